@@ -1,12 +1,13 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use nalgebra::MatrixXx3;
-use ndarray;
-use nifti_processing::{NearestNeighbor, ReSample, TriLinear};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use nalgebra::{Matrix4, Rotation3};
+use nifti_processing::{
+    resample_from_to_with, NearestNeighbor, ReSample, ResampleOptions, Tiling, TriLinear,
+};
 
 fn sampling_benchmark(c: &mut Criterion) {
-    let in_im = ndarray::Array::range(0., 1000000., 1.);
+    let in_im = ndarray::Array::<f32, _>::range(0., 1000000., 1.);
     let in_im = in_im.into_shape((100, 100, 100)).unwrap();
-    let in_im = in_im.mapv(|x| f32::from(x)).into_dyn();
+    let in_im = in_im.into_dyn();
 
     let mut in_coords: Vec<f32> = Vec::default();
     for x in 0..100 {
@@ -16,18 +17,62 @@ fn sampling_benchmark(c: &mut Criterion) {
             }
         }
     }
-    let mut in_coords = MatrixXx3::from_row_slice(&in_coords);
+    let mut in_coords = ndarray::Array2::from_shape_vec((1000000, 3), in_coords).unwrap();
 
     let sampler = NearestNeighbor::<f32>::default();
     c.bench_function("nearest neighbor resampling", |b| {
-        b.iter(|| sampler.sample(&in_im, &mut in_coords, &[100, 100, 100]))
+        b.iter(|| black_box(sampler.sample(&in_im, &mut in_coords, &[100, 100, 100])))
     });
 
     let sampler = TriLinear::<f32>::default();
     c.bench_function("trilinear resampling", |b| {
-        b.iter(|| sampler.sample(&in_im, &mut in_coords, &[100, 100, 100]))
+        b.iter(|| black_box(sampler.sample(&in_im, &mut in_coords, &[100, 100, 100])))
     });
 }
 
-criterion_group!(benches, sampling_benchmark);
+fn tiling_benchmark(c: &mut Criterion) {
+    let in_im = ndarray::Array::range(0., 8000000., 1.);
+    let in_im = in_im.into_shape((200, 200, 200)).unwrap().into_dyn();
+    let in_affine = Matrix4::<f64>::identity();
+    let out_affine = Matrix4::new_translation(&[100.0, 0.0, 100.0].into())
+        * Rotation3::from_euler_angles(0.0, 0.5, 0.0).to_homogeneous()
+        * Matrix4::new_translation(&[-100.0, 0.0, -100.0].into());
+    let sampler = TriLinear::<f64>::default();
+
+    let mut group = c.benchmark_group("rotated trilinear resampling");
+    group.sample_size(10);
+    let tilings = [
+        Tiling::RowMajor,
+        Tiling::Tiles([8, 8, 8]),
+        Tiling::Tiles([16, 16, 16]),
+        Tiling::Tiles([32, 32, 32]),
+        Tiling::Tiles([64, 64, 64]),
+    ];
+    for tiling in tilings {
+        let options = ResampleOptions {
+            anti_aliasing: false,
+            tiling,
+            ..Default::default()
+        };
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{:?}", tiling)),
+            &options,
+            |b, options| {
+                b.iter(|| {
+                    resample_from_to_with(
+                        &in_im,
+                        &in_affine,
+                        &[200, 200, 200],
+                        &out_affine,
+                        &sampler,
+                        options,
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, sampling_benchmark, tiling_benchmark);
 criterion_main!(benches);
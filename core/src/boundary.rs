@@ -0,0 +1,57 @@
+use num_traits::Num;
+
+/// A set of strategies a sampler may employ if a point is out of sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplingMode {
+    /// The input is expanded by replacing all numbers outside of the edge
+    /// with the same constant value determined by the cval parameter.
+    Constant,
+
+    /// The nearest pixel value is duplicated to expand the input.
+    Nearest,
+}
+
+/// Whether all components of the voxel coordinate `p` lie within
+/// [0, `upper`].
+///
+/// The comparisons are written such that NaN coordinates (e.g. from a
+/// degenerate transform or displacement) fail the check just like
+/// coordinates far outside of the field of view. Samplers hence only cast
+/// coordinates to indices after this check and return the constant value
+/// otherwise, instead of relying on saturating float to integer casts.
+pub fn within_bounds<T>(p: &[T; 3], upper: &[T; 3]) -> bool
+where
+    T: Num + PartialOrd + Copy,
+{
+    (0..3).all(|d| p[d] >= T::zero() && p[d] <= upper[d])
+}
+
+/// A voxel coordinate clamped to [0, `cap`], the coordinate of the nearest
+/// voxel of [`SamplingMode::Nearest`]. NaN stays NaN.
+pub fn clamp_coordinate<T>(x: T, cap: T) -> T
+where
+    T: Num + PartialOrd + Copy,
+{
+    if x < T::zero() {
+        T::zero()
+    } else if x > cap {
+        cap
+    } else {
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_within_bounds() {
+        let upper = [4.0, 4.0, 4.0];
+        assert!(within_bounds(&[0.0, 2.5, 4.0], &upper));
+        assert!(!within_bounds(&[-0.1, 2.5, 4.0], &upper));
+        assert!(!within_bounds(&[0.0, 2.5, 1e300], &upper));
+        assert!(!within_bounds(&[f64::NAN, 0.0, 0.0], &upper));
+        assert!(!within_bounds(&[0.0, f64::NEG_INFINITY, 0.0], &upper));
+    }
+}
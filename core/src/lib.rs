@@ -0,0 +1,18 @@
+//! The sampling math of `nifti_processing` without the standard library:
+//! boundary handling, interpolation weights and point sampling kernels on
+//! ndarray views. It only requires `alloc` (for [`sample_points`]) and can
+//! hence run in embedded, enclave or GPU host contexts. File I/O, affines
+//! and multithreading live in the std-only `nifti_processing` crate, which
+//! builds its samplers on top of this crate.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod boundary;
+pub mod sample;
+pub mod weights;
+
+pub use boundary::{clamp_coordinate, within_bounds, SamplingMode};
+pub use sample::{nearest, sample_points, trilinear, value_or, Interpolation};
+pub use weights::{corners, trilinear_weights};
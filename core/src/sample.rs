@@ -0,0 +1,134 @@
+use crate::boundary::{clamp_coordinate, within_bounds, SamplingMode};
+use crate::weights::{corners, trilinear_weights};
+use alloc::vec::Vec;
+use ndarray::ArrayView3;
+use num_traits::float::FloatCore;
+use num_traits::{AsPrimitive, Num};
+
+/// Interpolation kernels of [`sample_points`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Interpolation {
+    /// `order=0` in nibabel.
+    Nearest,
+
+    /// `order=1` in nibabel.
+    #[default]
+    Linear,
+}
+
+/// The voxel at idx, or cval outside of the image.
+pub fn value_or<U>(im: &ArrayView3<U>, idx: [usize; 3], cval: U) -> U
+where
+    U: Copy,
+{
+    im.get(idx).copied().unwrap_or(cval)
+}
+
+/// The voxel coordinate after the boundary handling of mode and the upper
+/// bounds coordinates have to lie within.
+fn bounded<T, U>(im: &ArrayView3<U>, p: [T; 3], mode: SamplingMode) -> ([T; 3], [T; 3])
+where
+    T: Num + PartialOrd + Copy + 'static,
+    usize: AsPrimitive<T>,
+{
+    let shape = im.shape();
+    let p = match mode {
+        SamplingMode::Constant => p,
+        SamplingMode::Nearest => {
+            core::array::from_fn(|d| clamp_coordinate(p[d], shape[d].saturating_sub(1).as_()))
+        }
+    };
+    (p, core::array::from_fn(|d| shape[d].as_()))
+}
+
+/// Nearest neighbor interpolation at the voxel coordinate p.
+pub fn nearest<T, U>(im: &ArrayView3<U>, p: [T; 3], mode: SamplingMode, cval: U) -> U
+where
+    T: FloatCore + AsPrimitive<usize>,
+    U: Copy,
+    usize: AsPrimitive<T>,
+{
+    let (p, upper) = bounded(im, p, mode);
+    let p = p.map(|x| x.round());
+    // check if index is out of bounds (or not a number)
+    if !within_bounds(&p, &upper) {
+        return cval;
+    }
+    value_or(im, p.map(|x| x.as_()), cval)
+}
+
+/// Trilinear interpolation at the voxel coordinate p. Neighbors beyond the
+/// last voxel take cval.
+pub fn trilinear<T, U>(im: &ArrayView3<U>, p: [T; 3], mode: SamplingMode, cval: U) -> U
+where
+    T: FloatCore + AsPrimitive<usize> + AsPrimitive<U>,
+    U: Num + Copy + 'static,
+    usize: AsPrimitive<T>,
+{
+    let (p, upper) = bounded(im, p, mode);
+    if !within_bounds(&p, &upper) {
+        return cval;
+    }
+    let p0 = p.map(|x| x.floor());
+    let weights = trilinear_weights(&p, &p0);
+    corners(p0.map(|x| x.as_()))
+        .iter()
+        .zip(weights)
+        .fold(U::zero(), |acc, (c, w)| {
+            let w: U = w.as_();
+            acc + w * value_or(im, *c, cval)
+        })
+}
+
+/// Interpolate the image at the voxel coordinates points.
+pub fn sample_points<T, U>(
+    im: &ArrayView3<U>,
+    points: &[[T; 3]],
+    interpolation: Interpolation,
+    mode: SamplingMode,
+    cval: U,
+) -> Vec<U>
+where
+    T: FloatCore + AsPrimitive<usize> + AsPrimitive<U>,
+    U: Num + Copy + 'static,
+    usize: AsPrimitive<T>,
+{
+    points
+        .iter()
+        .map(|p| match interpolation {
+            Interpolation::Nearest => nearest(im, *p, mode, cval),
+            Interpolation::Linear => trilinear(im, *p, mode, cval),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array3;
+
+    #[test]
+    fn test_sample_points() {
+        let im = Array3::from_shape_fn((4, 2, 2), |(x, y, z)| (x + y + z) as f64);
+        let points = [
+            [0.5, 1.0, 0.0],
+            [2.0, 0.25, 1.5],
+            [3.5, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+        ];
+        let (view, mode) = (im.view(), SamplingMode::Constant);
+        let linear = sample_points(&view, &points, Interpolation::Linear, mode, -1.0);
+        // neighbors beyond the last voxel take the constant value
+        assert_eq!(linear, [1.5, 1.125, 1.0, -1.0]);
+        let nearest = sample_points(&view, &points, Interpolation::Nearest, mode, -1.0);
+        assert_eq!(nearest, [2.0, -1.0, -1.0, -1.0]);
+        let clamped = sample_points(
+            &view,
+            &points,
+            Interpolation::Linear,
+            SamplingMode::Nearest,
+            -1.0,
+        );
+        assert_eq!(clamped, [1.5, 3.25, 3.0, 0.0]);
+    }
+}
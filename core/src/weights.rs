@@ -0,0 +1,38 @@
+use num_traits::Num;
+
+/// The 8 voxels of the cell with the lower corner `p0`, in the order of
+/// [`trilinear_weights`]: z varies fastest, x slowest.
+pub fn corners(p0: [usize; 3]) -> [[usize; 3]; 8] {
+    core::array::from_fn(|i| [p0[0] + (i >> 2 & 1), p0[1] + (i >> 1 & 1), p0[2] + (i & 1)])
+}
+
+/// Trilinear interpolation weights of the [`corners`] of the cell with the
+/// lower corner `p0` (the floor of `p`) at the voxel coordinate `p`.
+pub fn trilinear_weights<T>(p: &[T; 3], p0: &[T; 3]) -> [T; 8]
+where
+    T: Num + Copy,
+{
+    // distances to the opposite corner along axis d
+    let weight = |d: usize, upper: usize| {
+        if upper == 1 {
+            p[d] - p0[d]
+        } else {
+            p0[d] + T::one() - p[d]
+        }
+    };
+    core::array::from_fn(|i| weight(0, i >> 2 & 1) * weight(1, i >> 1 & 1) * weight(2, i & 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trilinear_weights() {
+        let weights = trilinear_weights(&[1.25, 2.5, 3.0], &[1.0, 2.0, 3.0]);
+        assert_eq!(weights.iter().sum::<f64>(), 1.0);
+        // all weight on the lower z corners, 3/4 on the lower x corners
+        assert_eq!(weights, [0.375, 0.0, 0.375, 0.0, 0.125, 0.0, 0.125, 0.0]);
+        assert_eq!(corners([1, 2, 3])[5], [2, 2, 4]);
+    }
+}
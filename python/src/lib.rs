@@ -0,0 +1,163 @@
+//! Python bindings of the resampling, smoothing and reorientation functions,
+//! on numpy arrays of float64. Parameters follow nibabel.processing: `order`
+//! 0 selects nearest neighbor and 1 trilinear interpolation, `mode` is
+//! "constant" or "nearest" and `cval` the value outside of the input.
+
+use nalgebra::Matrix4;
+use nifti_processing::lazy::LazyImage;
+use nifti_processing::{NearestNeighbor, ReSample, SamplerBuilder, SamplingMode, TriLinear};
+use numpy::{IntoPyArray, PyArray2, PyArrayDyn, PyReadonlyArray2, PyReadonlyArrayDyn};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+type Sampler = Box<dyn ReSample<f64, f64>>;
+
+fn sampler(order: usize, mode: &str, cval: f64) -> PyResult<Sampler> {
+    let mode = match mode {
+        "constant" => SamplingMode::Constant,
+        "nearest" => SamplingMode::Nearest,
+        _ => return Err(PyValueError::new_err("mode has to be constant or nearest")),
+    };
+    Ok(match order {
+        0 => Box::new(configured(NearestNeighbor::builder(), mode, cval)),
+        1 => Box::new(configured(TriLinear::builder(), mode, cval)),
+        _ => return Err(PyValueError::new_err("order has to be 0 or 1")),
+    })
+}
+
+fn configured<S>(builder: SamplerBuilder<S>, mode: SamplingMode, cval: f64) -> S
+where
+    S: nifti_processing::sampler::builder::SamplerSettings<f64>,
+{
+    builder.mode(mode).cval(cval).build()
+}
+
+fn affine_from(affine: &PyReadonlyArray2<f64>) -> PyResult<Matrix4<f64>> {
+    let affine = affine.as_array();
+    if affine.shape() != [4, 4] {
+        return Err(PyValueError::new_err("affine has to be of shape (4, 4)"));
+    }
+    Ok(Matrix4::from_fn(|i, j| affine[[i, j]]))
+}
+
+fn affine_to<'py>(py: Python<'py>, affine: &Matrix4<f64>) -> Bound<'py, PyArray2<f64>> {
+    ndarray::Array2::from_shape_fn((4, 4), |(i, j)| affine[(i, j)]).into_pyarray_bound(py)
+}
+
+/// Resample image (2D to 5D) to the voxel space defined by out_shape and
+/// out_affine.
+#[pyfunction]
+#[pyo3(signature = (image, affine, out_shape, out_affine, order = 1, mode = "constant", cval = 0.0))]
+#[allow(clippy::too_many_arguments)]
+fn resample_from_to<'py>(
+    py: Python<'py>,
+    image: PyReadonlyArrayDyn<'py, f64>,
+    affine: PyReadonlyArray2<'py, f64>,
+    out_shape: [usize; 3],
+    out_affine: PyReadonlyArray2<'py, f64>,
+    order: usize,
+    mode: &str,
+    cval: f64,
+) -> PyResult<Bound<'py, PyArrayDyn<f64>>> {
+    let sampler = sampler(order, mode, cval)?;
+    let (affine, out_affine) = (affine_from(&affine)?, affine_from(&out_affine)?);
+    let image = image.as_array().to_owned();
+    let out = py
+        .allow_threads(|| {
+            nifti_processing::resample_from_to(
+                &image,
+                &affine,
+                &out_shape,
+                &out_affine,
+                sampler.as_ref(),
+            )
+        })
+        .map_err(PyValueError::new_err)?;
+    Ok(out.into_pyarray_bound(py))
+}
+
+/// Resample image to world space with the given voxel sizes. Returns the
+/// image and its affine.
+#[pyfunction]
+#[pyo3(signature = (image, affine, voxel_sizes, order = 1, mode = "constant", cval = 0.0))]
+fn resample_to_output<'py>(
+    py: Python<'py>,
+    image: PyReadonlyArrayDyn<'py, f64>,
+    affine: PyReadonlyArray2<'py, f64>,
+    voxel_sizes: [f32; 3],
+    order: usize,
+    mode: &str,
+    cval: f64,
+) -> PyResult<(Bound<'py, PyArrayDyn<f64>>, Bound<'py, PyArray2<f64>>)> {
+    let sampler = sampler(order, mode, cval)?;
+    let affine = affine_from(&affine)?;
+    let image = image.as_array().to_owned();
+    let (out, out_affine) = py
+        .allow_threads(|| {
+            nifti_processing::resample_to_output(&image, &affine, &voxel_sizes, sampler.as_ref())
+        })
+        .map_err(PyValueError::new_err)?;
+    Ok((out.into_pyarray_bound(py), affine_to(py, &out_affine)))
+}
+
+/// Gaussian smoothing with a standard deviation of sigma voxels per axis.
+#[pyfunction]
+fn gaussian_filter<'py>(
+    py: Python<'py>,
+    image: PyReadonlyArrayDyn<'py, f64>,
+    sigma: [f64; 3],
+) -> PyResult<Bound<'py, PyArrayDyn<f64>>> {
+    let image = image.as_array().to_owned();
+    let out = py
+        .allow_threads(|| nifti_processing::filter::gaussian::gaussian_filter(&image, &sigma))
+        .map_err(PyValueError::new_err)?;
+    Ok(out.into_pyarray_bound(py))
+}
+
+/// Gaussian smoothing with a full width at half maximum of fwhm mm per axis.
+#[pyfunction]
+fn smooth_fwhm<'py>(
+    py: Python<'py>,
+    image: PyReadonlyArrayDyn<'py, f64>,
+    affine: PyReadonlyArray2<'py, f64>,
+    fwhm: [f64; 3],
+) -> PyResult<Bound<'py, PyArrayDyn<f64>>> {
+    let affine = affine_from(&affine)?;
+    let image = image.as_array().to_owned();
+    let out = py
+        .allow_threads(|| nifti_processing::filter::gaussian::smooth_fwhm(&image, &affine, &fwhm))
+        .map_err(PyValueError::new_err)?;
+    Ok(out.into_pyarray_bound(py))
+}
+
+/// Permute and flip the voxel axes to be closest to the world axes, in
+/// increasing order (as nibabel's as_closest_canonical). Returns the image
+/// and its affine.
+#[pyfunction]
+fn reorient<'py>(
+    py: Python<'py>,
+    image: PyReadonlyArrayDyn<'py, f64>,
+    affine: PyReadonlyArray2<'py, f64>,
+) -> PyResult<(Bound<'py, PyArrayDyn<f64>>, Bound<'py, PyArray2<f64>>)> {
+    let affine = affine_from(&affine)?;
+    let image = image.as_array().to_owned();
+    let (out, out_affine) = py
+        .allow_threads(|| {
+            LazyImage::new(&image, &affine)
+                .reorient()
+                .execute(&NearestNeighbor::default())
+        })
+        .map_err(PyValueError::new_err)?;
+    Ok((out.into_pyarray_bound(py), affine_to(py, &out_affine)))
+}
+
+#[pymodule]
+#[pyo3(name = "nifti_processing")]
+fn module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(resample_from_to, m)?)?;
+    m.add_function(wrap_pyfunction!(resample_to_output, m)?)?;
+    m.add_function(wrap_pyfunction!(gaussian_filter, m)?)?;
+    m.add_function(wrap_pyfunction!(smooth_fwhm, m)?)?;
+    m.add_function(wrap_pyfunction!(reorient, m)?)?;
+    Ok(())
+}
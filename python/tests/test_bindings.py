@@ -0,0 +1,33 @@
+import numpy as np
+
+import nifti_processing
+
+
+def test_resample_from_to_identity():
+    rng = np.random.default_rng(0)
+    image = rng.random((4, 5, 6))
+    affine = np.diag([2.0, 3.0, 4.0, 1.0])
+    affine[:3, 3] = [-10.0, 5.0, 1.5]
+    for order in (0, 1):
+        out = nifti_processing.resample_from_to(image, affine, image.shape, affine, order=order)
+        np.testing.assert_allclose(out, image)
+
+
+def test_reorient_round_trip():
+    rng = np.random.default_rng(1)
+    image = rng.random((4, 5, 6))
+    affine = np.diag([-2.0, 3.0, 4.0, 1.0])
+    affine[:3, 3] = [10.0, 5.0, 1.5]
+
+    out, out_affine = nifti_processing.reorient(image, affine)
+    np.testing.assert_array_equal(out, image[::-1])
+    expected = np.diag([2.0, 3.0, 4.0, 1.0])
+    expected[:3, 3] = [4.0, 5.0, 1.5]
+    np.testing.assert_allclose(out_affine, expected)
+
+    # canonical images stay as they are, and resampling undoes the flip
+    again, again_affine = nifti_processing.reorient(out, out_affine)
+    np.testing.assert_array_equal(again, out)
+    np.testing.assert_allclose(again_affine, out_affine)
+    back = nifti_processing.resample_from_to(out, out_affine, image.shape, affine, order=0)
+    np.testing.assert_array_equal(back, image)
@@ -0,0 +1,547 @@
+use crate::filter::gaussian::gaussian_filter;
+use crate::patches::{extract_patch, patch_offsets, Padding, PatchCenters};
+use crate::registration::fov_center;
+use crate::registration::transform::{affine_matrix, linear_about_center};
+use crate::rng::Rng;
+use crate::sampler::nearest_neighbor::NearestNeighbor;
+use crate::sampler::trilinear::TriLinear;
+use crate::{
+    resample_from_to, resample_with_transform, sample_world_points, sanitize_im_shape, shape3,
+    voxel_sizes,
+};
+use nalgebra::{Matrix3, Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::{AsPrimitive, Num, Zero};
+
+/// Elastic deformation by a random smooth displacement field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Elastic {
+    /// Standard deviation (mm) of the gaussian smoothing the white noise
+    /// the field is generated from; larger values give smoother fields.
+    pub sigma: f64,
+
+    /// Root mean square (mm) of each displacement component.
+    pub magnitude: f64,
+}
+
+impl Default for Elastic {
+    fn default() -> Self {
+        Self {
+            sigma: 8.0,
+            magnitude: 2.0,
+        }
+    }
+}
+
+/// Parameters of the random spatial augmentation, see [`augment`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Augmentation {
+    /// Probability to flip the image along each voxel axis.
+    pub flip_probability: f64,
+
+    /// Rotate by random multiples of 90° in each voxel plane with equal
+    /// extents along both axes (others would be cropped).
+    pub rotate90: bool,
+
+    /// Maximum rotation (radians) about each world axis.
+    pub max_rotation: f64,
+
+    /// Maximum relative scaling along each world axis.
+    pub max_scaling: f64,
+
+    /// Maximum translation (mm) along each world axis.
+    pub max_translation: f64,
+
+    /// Elastic deformation, if any.
+    pub elastic: Option<Elastic>,
+}
+
+impl Default for Augmentation {
+    fn default() -> Self {
+        Self {
+            flip_probability: 0.5,
+            rotate90: false,
+            max_rotation: 0.1,
+            max_scaling: 0.1,
+            max_translation: 0.0,
+            elastic: None,
+        }
+    }
+}
+
+/// Result of [`augment`].
+#[derive(Debug, Clone)]
+pub struct Augmented<L> {
+    /// The augmented image, interpolated trilinearly.
+    pub image: Array<f64, IxDyn>,
+
+    /// The augmented label map, if any, sampled by nearest neighbor.
+    pub labels: Option<Array<L, IxDyn>>,
+
+    /// World space transform of the flips, rotations and affine
+    /// perturbation, mapping augmented world coordinates to world
+    /// coordinates of the input. The elastic deformation is applied on top.
+    pub transform: Matrix4<f64>,
+}
+
+/// Random spatial augmentation of a 3D image and optionally its label map,
+/// e.g. to train a model; both are transformed identically.
+///
+/// The augmented volumes share the grid of the input. The transform is drawn
+/// from the random number generator initialized with seed, so that the same
+/// seed reproduces the same augmentation. Voxels mapped from outside of the
+/// image are 0. Flips and 90° rotations alone copy the voxels without
+/// interpolation.
+pub fn augment<T, U, L>(
+    image: &Array<U, IxDyn>,
+    labels: Option<&Array<L, IxDyn>>,
+    affine: &Matrix4<T>,
+    params: &Augmentation,
+    seed: u64,
+) -> Result<Augmented<L>, String>
+where
+    T: Scalar + AsPrimitive<f64>,
+    U: AsPrimitive<f64>,
+    L: Num + Copy + Send + Sync + AsPrimitive<f64> + 'static,
+    f64: AsPrimitive<L>,
+{
+    let image: Array<f64, IxDyn> = sanitize_im_shape(image)?.mapv(|x| x.as_());
+    let labels = labels.map(sanitize_im_shape).transpose()?;
+    if labels.as_ref().is_some_and(|l| l.shape() != image.shape()) {
+        return Err("label map shape does not match image shape".into());
+    }
+    let affine: Matrix4<f64> = affine.map(|x| x.as_());
+    let inv_affine = affine
+        .try_inverse()
+        .ok_or("no valid matrix inverse found for affine")?;
+    let shape = shape3(&image);
+    let mut rng = Rng::new(seed);
+
+    // flips and 90° rotations of the voxel grid about its center
+    let mut voxel = Matrix3::<f64>::identity();
+    for d in 0..3 {
+        if rng.uniform() < params.flip_probability {
+            voxel[(d, d)] = -1.0;
+        }
+    }
+    if params.rotate90 {
+        for (a, b) in [(0, 1), (0, 2), (1, 2)] {
+            if shape[a] == shape[b] {
+                let mut quarter = Matrix3::identity();
+                quarter[(a, a)] = 0.0;
+                quarter[(b, b)] = 0.0;
+                quarter[(a, b)] = -1.0;
+                quarter[(b, a)] = 1.0;
+                for _ in 0..rng.below(4) {
+                    voxel *= quarter;
+                }
+            }
+        }
+    }
+    let voxel_center = Vector3::from(shape.map(|n| (n as f64 - 1.0) / 2.0));
+    let voxel = linear_about_center(&voxel, &Vector3::zeros(), &voxel_center);
+
+    // small affine perturbation about the center of the field of view
+    let mut p = [0.0; 12];
+    for (i, p) in p.iter_mut().enumerate() {
+        *p = match i {
+            0..=2 => rng.range(-params.max_rotation, params.max_rotation),
+            3..=5 => rng.range(-params.max_translation, params.max_translation),
+            6..=8 => 1.0 + rng.range(-params.max_scaling, params.max_scaling),
+            _ => 0.0,
+        };
+    }
+    let perturbation = affine_matrix(&p, &fov_center(&shape, &affine));
+    let transform = affine * voxel * inv_affine * perturbation;
+
+    let trilinear = TriLinear::<f64>::default();
+    let nearest = NearestNeighbor::<L>::default();
+    let (image, labels) = match params.elastic {
+        None => (
+            resample_with_transform(&image, &affine, &transform, &shape, &affine, &trilinear)?,
+            labels
+                .map(|l| {
+                    resample_with_transform(&l, &affine, &transform, &shape, &affine, &nearest)
+                })
+                .transpose()?,
+        ),
+        Some(elastic) => {
+            let displacement = random_displacement(&shape, &affine, &elastic, &mut rng)?;
+            let points: Vec<Vector3<f64>> = ndarray::indices(shape)
+                .into_iter()
+                .zip(displacement)
+                .map(|((x, y, z), u)| {
+                    let p = affine * nalgebra::Vector4::new(x as f64, y as f64, z as f64, 1.0);
+                    (transform * (p.xyz() + u).push(1.0)).xyz()
+                })
+                .collect();
+            let image = voxels_from(
+                &shape,
+                sample_world_points(&image, &affine, &points, &trilinear)?,
+            );
+            let labels = labels
+                .map(|l| sample_world_points(&l, &affine, &points, &nearest))
+                .transpose()?
+                .map(|values| voxels_from(&shape, values));
+            (image, labels)
+        }
+    };
+    Ok(Augmented {
+        image,
+        labels,
+        transform,
+    })
+}
+
+/// Position of the region of [`random_crop`].
+#[derive(Debug, Clone, Copy, Default)]
+pub enum CropCenter<'a> {
+    /// The center of the image.
+    Center,
+
+    /// A uniformly random voxel.
+    #[default]
+    Random,
+
+    /// A random voxel of the mask (e.g. the foreground) with the given
+    /// probability, a uniformly random voxel otherwise, to oversample
+    /// regions of interest.
+    Foreground {
+        mask: &'a Array<bool, IxDyn>,
+        probability: f64,
+    },
+}
+
+/// Result of [`random_crop`].
+#[derive(Debug, Clone)]
+pub struct Cropped<T, U, L>
+where
+    T: Scalar,
+{
+    /// The cropped image, of the crop size followed by the non-spatial
+    /// dimensions of the image.
+    pub image: Array<U, IxDyn>,
+
+    /// The cropped label map, if any.
+    pub labels: Option<Array<L, IxDyn>>,
+
+    /// Voxel index of the first cropped voxel in the image.
+    pub offset: [isize; 3],
+
+    /// Affine of the cropped volumes, i.e. the image affine moved to the
+    /// offset.
+    pub affine: Matrix4<T>,
+}
+
+/// Crop a region of size from an image (3D, or with further dimensions such
+/// as channels) and optionally its label map, e.g. to train a model on.
+///
+/// The region is drawn from the random number generator initialized with
+/// seed. Voxels beyond the image are filled according to the padding, with
+/// 0 for [`Padding::Constant`]; [`Padding::Shift`] keeps the region inside
+/// of the image.
+pub fn random_crop<T, U, L>(
+    image: &Array<U, IxDyn>,
+    labels: Option<&Array<L, IxDyn>>,
+    affine: &Matrix4<T>,
+    size: &[usize; 3],
+    center: &CropCenter,
+    padding: Padding,
+    seed: u64,
+) -> Result<Cropped<T, U, L>, String>
+where
+    T: Scalar + RealField + Copy,
+    U: Zero + Clone,
+    L: Zero + Clone,
+    isize: AsPrimitive<T>,
+{
+    if image.ndim() < 3 {
+        return Err("invalid shape".into());
+    }
+    let shape = [image.shape()[0], image.shape()[1], image.shape()[2]];
+    if labels.is_some_and(|l| l.shape()[..] != image.shape()[..3]) {
+        return Err("label map shape does not match image shape".into());
+    }
+    let mut rng = Rng::new(seed);
+    let random = PatchCenters::Random {
+        n: 1,
+        seed: rng.next_u64(),
+    };
+    let centers = match *center {
+        CropCenter::Center => None,
+        CropCenter::Random => Some(random),
+        CropCenter::Foreground { mask, probability } => Some(if rng.uniform() < probability {
+            PatchCenters::Mask {
+                mask,
+                n: 1,
+                seed: rng.next_u64(),
+            }
+        } else {
+            random
+        }),
+    };
+    // validates the size, the shape and the mask
+    let mut offset = patch_offsets(&shape, size, &centers.unwrap_or(random), padding)?[0];
+    if centers.is_none() {
+        offset = [0, 1, 2].map(|d| (shape[d] as isize - size[d] as isize).div_euclid(2));
+    }
+    Ok(Cropped {
+        image: extract_patch(image, &offset, size, padding, U::zero()),
+        labels: labels.map(|l| extract_patch(l, &offset, size, padding, L::zero())),
+        affine: affine * Matrix4::new_translation(&Vector3::from(offset.map(|o| o.as_()))),
+        offset,
+    })
+}
+
+/// Parameters of [`random_zoom`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Zoom {
+    /// Smallest zoom factor; factors below 1 zoom out.
+    pub min_factor: f64,
+
+    /// Largest zoom factor; factors above 1 zoom in.
+    pub max_factor: f64,
+
+    /// Zoom by the same factor along all axes, by independent factors
+    /// otherwise.
+    pub isotropic: bool,
+}
+
+impl Default for Zoom {
+    fn default() -> Self {
+        Self {
+            min_factor: 0.9,
+            max_factor: 1.1,
+            isotropic: true,
+        }
+    }
+}
+
+/// Result of [`random_zoom`].
+#[derive(Debug, Clone)]
+pub struct Zoomed<L> {
+    /// The zoomed image, interpolated trilinearly.
+    pub image: Array<f64, IxDyn>,
+
+    /// The zoomed label map, if any, sampled by nearest neighbor.
+    pub labels: Option<Array<L, IxDyn>>,
+
+    /// Affine of the zoomed volumes, whose voxel sizes are those of the
+    /// image divided by the factors.
+    pub affine: Matrix4<f64>,
+
+    /// Zoom factor along each voxel axis.
+    pub factors: [f64; 3],
+}
+
+/// Zoom a 3D image and optionally its label map by random factors about the
+/// center of the image, keeping its shape.
+///
+/// The factors are drawn from the random number generator initialized with
+/// seed. Instead of moving anatomy in world space, the zoom shrinks (or
+/// grows) the voxels, so world coordinates stay valid in the returned
+/// affine. Voxels mapped from outside of the image are 0.
+pub fn random_zoom<T, U, L>(
+    image: &Array<U, IxDyn>,
+    labels: Option<&Array<L, IxDyn>>,
+    affine: &Matrix4<T>,
+    params: &Zoom,
+    seed: u64,
+) -> Result<Zoomed<L>, String>
+where
+    T: Scalar + AsPrimitive<f64>,
+    U: AsPrimitive<f64>,
+    L: Num + Copy + Send + Sync + AsPrimitive<f64> + 'static,
+    f64: AsPrimitive<L>,
+{
+    if !(params.min_factor > 0.0 && params.min_factor <= params.max_factor) {
+        return Err("zoom factors have to be positive and min_factor at most max_factor".into());
+    }
+    let image: Array<f64, IxDyn> = sanitize_im_shape(image)?.mapv(|x| x.as_());
+    let labels = labels.map(sanitize_im_shape).transpose()?;
+    if labels.as_ref().is_some_and(|l| l.shape() != image.shape()) {
+        return Err("label map shape does not match image shape".into());
+    }
+    let affine: Matrix4<f64> = affine.map(|x| x.as_());
+    let shape = shape3(&image);
+    let mut rng = Rng::new(seed);
+    let mut factors = [0.0; 3];
+    for f in factors.iter_mut() {
+        *f = rng.range(params.min_factor, params.max_factor);
+    }
+    if params.isotropic {
+        factors = [factors[0]; 3];
+    }
+
+    let scaling = Matrix3::from_diagonal(&Vector3::from(factors.map(|f| 1.0 / f)));
+    let voxel_center = Vector3::from(shape.map(|n| (n as f64 - 1.0) / 2.0));
+    let out_affine = affine * linear_about_center(&scaling, &Vector3::zeros(), &voxel_center);
+    let trilinear = TriLinear::<f64>::default();
+    let nearest = NearestNeighbor::<L>::default();
+    Ok(Zoomed {
+        image: resample_from_to(&image, &affine, &shape, &out_affine, &trilinear)?,
+        labels: labels
+            .map(|l| resample_from_to(&l, &affine, &shape, &out_affine, &nearest))
+            .transpose()?,
+        affine: out_affine,
+        factors,
+    })
+}
+
+fn voxels_from<U>(shape: &[usize; 3], values: Vec<U>) -> Array<U, IxDyn> {
+    Array::from_shape_vec(IxDyn(shape), values).expect("one value per voxel")
+}
+
+/// Random smooth displacements (mm) of all voxels, in row-major order.
+fn random_displacement(
+    shape: &[usize; 3],
+    affine: &Matrix4<f64>,
+    elastic: &Elastic,
+    rng: &mut Rng,
+) -> Result<Vec<Vector3<f64>>, String> {
+    if !(elastic.sigma > 0.0 && elastic.magnitude >= 0.0) {
+        return Err("elastic sigma has to be positive and magnitude non-negative".into());
+    }
+    let sizes = voxel_sizes(affine);
+    let sigma = [0, 1, 2].map(|d| elastic.sigma / sizes[d]);
+    let mut components = Vec::with_capacity(3);
+    for _ in 0..3 {
+        let noise = Array::from_shape_simple_fn(IxDyn(shape), || rng.normal());
+        let smooth = gaussian_filter(&noise, &sigma)?;
+        let rms = (smooth.iter().map(|x| x * x).sum::<f64>() / smooth.len() as f64).sqrt();
+        let scale = if rms > 0.0 {
+            elastic.magnitude / rms
+        } else {
+            0.0
+        };
+        components.push(smooth.mapv(|x| x * scale));
+    }
+    Ok(components[0]
+        .iter()
+        .zip(components[1].iter())
+        .zip(components[2].iter())
+        .map(|((x, y), z)| Vector3::new(*x, *y, *z))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_augment_flips() {
+        let image = Array::from_shape_fn(IxDyn(&[4, 4, 3]), |idx| {
+            (idx[0] + 4 * idx[1] + 16 * idx[2]) as f64
+        });
+        let labels = image.mapv(|x| x as u16);
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 1.0, 2.5));
+        let params = Augmentation {
+            flip_probability: 0.5,
+            rotate90: true,
+            max_rotation: 0.0,
+            max_scaling: 0.0,
+            ..Default::default()
+        };
+        let mut n_different = 0;
+        for seed in 0..8 {
+            let a = augment(&image, Some(&labels), &affine, &params, seed).unwrap();
+            // a permutation of the voxels, identical for image and labels
+            let mut values = a.image.iter().copied().collect::<Vec<_>>();
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(values, (0..48).map(|x| x as f64).collect::<Vec<_>>());
+            assert_eq!(a.labels.unwrap(), a.image.mapv(|x| x as u16));
+            n_different += (a.image != image) as usize;
+            let b = augment(&image, Some(&labels), &affine, &params, seed).unwrap();
+            assert_eq!(a.image, b.image);
+        }
+        assert!(n_different > 4);
+    }
+
+    #[test]
+    fn test_augment_elastic() {
+        let image = Array::from_shape_fn(IxDyn(&[12, 12, 12]), |idx| (idx[0] >= 6) as u8 as f64);
+        let labels = image.mapv(|x| x as u8);
+        let affine = Matrix4::<f64>::identity();
+        let params = Augmentation {
+            flip_probability: 0.0,
+            elastic: Some(Elastic {
+                sigma: 3.0,
+                magnitude: 1.0,
+            }),
+            ..Default::default()
+        };
+        let a = augment(&image, Some(&labels), &affine, &params, 42).unwrap();
+        let labels = a.labels.unwrap();
+        assert_ne!(labels, image.mapv(|x| x as u8));
+        // labels stay labels and follow the interpolated image
+        assert!(labels.iter().all(|l| *l <= 1));
+        let disagreement = labels
+            .iter()
+            .zip(a.image.iter())
+            .filter(|(l, x)| (**l == 1) != (**x > 0.5))
+            .count();
+        assert!(disagreement < labels.len() / 20);
+    }
+
+    #[test]
+    fn test_random_crop_and_zoom() {
+        let image = Array::from_shape_fn(IxDyn(&[10, 8, 6]), |idx| {
+            (idx[0] + 10 * idx[1] + 100 * idx[2]) as f64
+        });
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 1.0, 3.0));
+        let mut mask = Array::from_elem(IxDyn(&[10, 8, 6]), false);
+        mask[[8, 1, 4]] = true;
+        let center = CropCenter::Foreground {
+            mask: &mask,
+            probability: 1.0,
+        };
+        for seed in 0..4 {
+            let crop = random_crop(
+                &image,
+                Some(&image),
+                &affine,
+                &[3, 3, 3],
+                &center,
+                Padding::Constant,
+                seed,
+            )
+            .unwrap();
+            assert_eq!(crop.offset, [7, 0, 3]);
+            assert_eq!(crop.image[[1, 1, 1]], image[[8, 1, 4]]);
+            // the affine maps cropped voxels to their world position
+            let world = crop.affine * nalgebra::Vector4::new(1.0, 1.0, 1.0, 1.0);
+            assert_eq!(world, affine * nalgebra::Vector4::new(8.0, 1.0, 4.0, 1.0));
+        }
+        let crop: Cropped<f64, f64, u8> = random_crop(
+            &image,
+            None,
+            &affine,
+            &[4, 4, 8],
+            &CropCenter::Center,
+            Padding::Constant,
+            0,
+        )
+        .unwrap();
+        assert_eq!(crop.offset, [3, 2, -1]);
+        assert_eq!(crop.image[[0, 0, 0]], 0.0);
+        assert_eq!(crop.image[[0, 0, 1]], image[[3, 2, 0]]);
+
+        let labels = image.mapv(|x| (x as u16 % 7) as u8);
+        let params = Zoom {
+            min_factor: 1.2,
+            max_factor: 1.5,
+            isotropic: false,
+        };
+        let zoomed = random_zoom(&image, Some(&labels), &affine, &params, 3).unwrap();
+        assert!(zoomed.factors.iter().all(|f| (1.2..1.5).contains(f)));
+        let sizes = voxel_sizes(&zoomed.affine);
+        assert!((0..3).all(|d| (sizes[d] * zoomed.factors[d] - affine[(d, d)]).abs() < 1e-12));
+        // the zoomed voxels carry the values at their world positions
+        let points = [zoomed.affine * nalgebra::Vector4::new(4.0, 3.0, 2.0, 1.0)].map(|p| p.xyz());
+        let expected =
+            sample_world_points(&image, &affine, &points, &TriLinear::default()).unwrap();
+        assert!((zoomed.image[[4, 3, 2]] - expected[0]).abs() < 1e-9);
+        let labels = zoomed.labels.unwrap();
+        assert!(labels.iter().all(|l| *l < 7));
+    }
+}
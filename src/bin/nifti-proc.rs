@@ -0,0 +1,357 @@
+use clap::{Args, Parser, Subcommand};
+use nalgebra::{Matrix4, Vector3};
+use ndarray::prelude::*;
+use nifti::{writer::WriterOptions, IntoNdArray, NiftiHeader, NiftiObject, ReaderOptions};
+use nifti_processing::filter::gaussian::{gaussian_filter, smooth_fwhm};
+use nifti_processing::lazy::LazyImage;
+use nifti_processing::measure::stats::{stats, StatsParams};
+use nifti_processing::{
+    resample_from_to, resample_to_output, NearestNeighbor, ReSample, TriLinear,
+};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Resampling, reorientation, smoothing and statistics of NIFTI images.
+#[derive(Parser, Debug)]
+#[command(name = "nifti-proc", author, version, about, long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Resample to voxel sizes in world space, or to the grid of a reference.
+    Resample {
+        #[command(flatten)]
+        io: InOut,
+
+        /// Voxel sizes in mm, one value for isotropic voxels.
+        #[arg(short, long, value_delimiter = ',', num_args = 1..=3, conflicts_with = "like")]
+        voxel_size: Option<Vec<f32>>,
+
+        /// Reference image whose grid (shape and affine) to resample to.
+        #[arg(short, long, required_unless_present = "voxel_size")]
+        like: Option<PathBuf>,
+
+        #[command(flatten)]
+        sampling: Sampling,
+    },
+    /// Permute and flip the voxel axes closest to RAS.
+    Reorient {
+        #[command(flatten)]
+        io: InOut,
+    },
+    /// Gaussian smoothing.
+    Smooth {
+        #[command(flatten)]
+        io: InOut,
+
+        /// Full width at half maximum in mm.
+        #[arg(
+            short,
+            long,
+            required_unless_present = "sigma",
+            conflicts_with = "sigma"
+        )]
+        fwhm: Option<f64>,
+
+        /// Standard deviation in voxels.
+        #[arg(short, long)]
+        sigma: Option<f64>,
+    },
+    /// Resample to a RAS grid of the given shape and voxel size, centered on
+    /// the input (as nibabel.processing.conform).
+    Conform {
+        #[command(flatten)]
+        io: InOut,
+
+        /// Output shape, one value for a cube.
+        #[arg(long, value_delimiter = ',', num_args = 1..=3, default_value = "256")]
+        shape: Vec<usize>,
+
+        /// Voxel sizes in mm, one value for isotropic voxels.
+        #[arg(short, long, value_delimiter = ',', num_args = 1..=3, default_value = "1")]
+        voxel_size: Vec<f32>,
+
+        #[command(flatten)]
+        sampling: Sampling,
+    },
+    /// Print summary statistics of a 3D image.
+    Stats {
+        input: PathBuf,
+
+        /// Only voxels where the mask is non-zero.
+        #[arg(short, long)]
+        mask: Option<PathBuf>,
+    },
+}
+
+#[derive(Args, Debug)]
+struct InOut {
+    input: PathBuf,
+    output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct Sampling {
+    /// Interpolation order, 0 (nearest neighbor) or 1 (trilinear).
+    #[arg(short = 'n', long, default_value_t = 1)]
+    order: u8,
+
+    /// Value outside of the input.
+    #[arg(short, long, default_value_t = 0.0)]
+    cval: f32,
+}
+
+impl Sampling {
+    fn sampler(&self) -> Result<Box<dyn ReSample<f64, f32>>, String> {
+        Ok(match self.order {
+            0 => Box::new(NearestNeighbor::builder().cval(self.cval).build()),
+            1 => Box::new(TriLinear::builder().cval(self.cval).build()),
+            _ => return Err("order has to be 0 or 1".into()),
+        })
+    }
+}
+
+struct Image {
+    data: Array<f32, IxDyn>,
+    affine: Matrix4<f64>,
+    header: NiftiHeader,
+}
+
+fn read(path: &PathBuf) -> Result<Image, String> {
+    let nii = ReaderOptions::new()
+        .read_file(path)
+        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let header = nii.header().clone();
+    let data = nii
+        .into_volume()
+        .into_ndarray::<f32>()
+        .map_err(|e| format!("failed to read the voxels of {}: {e}", path.display()))?;
+    Ok(Image {
+        data,
+        affine: header.affine(),
+        header,
+    })
+}
+
+fn write(
+    path: &PathBuf,
+    data: &Array<f32, IxDyn>,
+    affine: &Matrix4<f64>,
+    reference: &NiftiHeader,
+) -> Result<(), String> {
+    let mut header = reference.clone();
+    header.set_affine(affine);
+    // the voxels are written as they are
+    header.scl_slope = 1.0;
+    header.scl_inter = 0.0;
+    WriterOptions::new(path)
+        .reference_header(&header)
+        .write_nifti(data)
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+fn triple<T: Copy>(values: &[T]) -> [T; 3] {
+    match values {
+        [x] => [*x; 3],
+        [x, y, z] => [*x, *y, *z],
+        _ => unreachable!("clap accepts one or three values"),
+    }
+}
+
+fn to_f32(im: Array<f64, IxDyn>) -> Array<f32, IxDyn> {
+    im.mapv(|x| x as f32)
+}
+
+/// RAS affine of voxel_sizes whose grid of shape is centered on the field of
+/// view of in_shape and in_affine.
+fn conform_affine(
+    in_shape: &[usize],
+    in_affine: &Matrix4<f64>,
+    shape: &[usize; 3],
+    voxel_sizes: &[f32; 3],
+) -> Matrix4<f64> {
+    let center = |shape: &[usize]| Vector3::from_fn(|d, _| (shape[d] as f64 - 1.0) / 2.0);
+    let in_center = (in_affine * center(in_shape).push(1.0)).xyz();
+    let zooms = Vector3::from(voxel_sizes.map(|v| v as f64));
+    let mut affine = Matrix4::from_diagonal(&zooms.push(1.0));
+    let translation = in_center - zooms.component_mul(&center(shape));
+    affine.fixed_slice_mut::<3, 1>(0, 3).copy_from(&translation);
+    affine
+}
+
+fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Resample {
+            io,
+            voxel_size,
+            like,
+            sampling,
+        } => {
+            let im = read(&io.input)?;
+            let sampler = sampling.sampler()?;
+            let (out, affine) = match (voxel_size, like) {
+                (Some(voxel_size), _) => resample_to_output(
+                    &im.data,
+                    &im.affine,
+                    &triple(&voxel_size),
+                    sampler.as_ref(),
+                )?,
+                (None, Some(like)) => {
+                    let like = read(&like)?;
+                    let shape = like.data.shape();
+                    if shape.len() < 3 {
+                        return Err("the reference has to be 3D".into());
+                    }
+                    let shape = [shape[0], shape[1], shape[2]];
+                    let out = resample_from_to(
+                        &im.data,
+                        &im.affine,
+                        &shape,
+                        &like.affine,
+                        sampler.as_ref(),
+                    )?;
+                    (out, like.affine)
+                }
+                (None, None) => unreachable!("clap requires either"),
+            };
+            write(&io.output, &out, &affine, &im.header)
+        }
+        Command::Reorient { io } => {
+            let im = read(&io.input)?;
+            let (out, affine) = LazyImage::new(&im.data, &im.affine)
+                .reorient()
+                .execute(&NearestNeighbor::default())?;
+            write(&io.output, &out, &affine, &im.header)
+        }
+        Command::Smooth { io, fwhm, sigma } => {
+            let im = read(&io.input)?;
+            let out = match (fwhm, sigma) {
+                (Some(fwhm), _) => smooth_fwhm(&im.data, &im.affine, &[fwhm; 3])?,
+                (None, Some(sigma)) => gaussian_filter(&im.data, &[sigma; 3])?,
+                (None, None) => unreachable!("clap requires either"),
+            };
+            write(&io.output, &to_f32(out), &im.affine, &im.header)
+        }
+        Command::Conform {
+            io,
+            shape,
+            voxel_size,
+            sampling,
+        } => {
+            let im = read(&io.input)?;
+            let shape = triple(&shape);
+            let affine = conform_affine(im.data.shape(), &im.affine, &shape, &triple(&voxel_size));
+            let sampler = sampling.sampler()?;
+            let out = resample_from_to(&im.data, &im.affine, &shape, &affine, sampler.as_ref())?;
+            write(&io.output, &out, &affine, &im.header)
+        }
+        Command::Stats { input, mask } => {
+            let im = read(&input)?;
+            let mask = match mask {
+                Some(mask) => Some(read(&mask)?.data.mapv(|x| x != 0.0)),
+                None => None,
+            };
+            let stats = stats(&im.data, &im.affine, mask.as_ref(), &StatsParams::default())?;
+            println!("voxels\t{}", stats.voxel_count);
+            println!("mean\t{}", stats.mean);
+            println!("std\t{}", stats.std);
+            println!("min\t{}", stats.min);
+            println!("median\t{}", stats.median);
+            println!("max\t{}", stats.max);
+            for (q, value) in &stats.percentiles {
+                println!("p{q}\t{value}");
+            }
+            println!("nonzero voxels\t{}", stats.nonzero_count);
+            println!("nonzero volume (mm³)\t{}", stats.nonzero_volume);
+            Ok(())
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("nifti-proc: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conform_affine() {
+        // LAS input, center voxel (4.5, 4.5, 2) at world (0.5, 0, 10)
+        let in_affine = Matrix4::new(
+            -2.0, 0.0, 0.0, 9.5, //
+            0.0, 2.0, 0.0, -9.0, //
+            0.0, 0.0, 4.0, 2.0, //
+            0.0, 0.0, 0.0, 1.0,
+        );
+        let affine = conform_affine(&[10, 10, 5], &in_affine, &[4, 4, 4], &[1.0; 3]);
+        let center = affine * nalgebra::Vector4::new(1.5, 1.5, 1.5, 1.0);
+        assert_eq!(center, nalgebra::Vector4::new(0.5, 0.0, 10.0, 1.0));
+        assert_eq!(
+            affine.fixed_slice::<3, 3>(0, 0),
+            nalgebra::Matrix3::identity()
+        );
+
+        let cli =
+            Cli::try_parse_from(["nifti-proc", "resample", "in.nii", "out.nii", "-v", "1,1,2"])
+                .unwrap();
+        match cli.command {
+            Command::Resample { voxel_size, .. } => {
+                assert_eq!(triple(&voxel_size.unwrap()), [1.0, 1.0, 2.0])
+            }
+            _ => panic!("expected the resample subcommand"),
+        }
+        assert!(Cli::try_parse_from(["nifti-proc", "smooth", "in.nii", "out.nii"]).is_err());
+    }
+
+    #[test]
+    fn test_run_end_to_end() {
+        let dir = std::env::temp_dir().join(format!("nifti-proc-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+
+        // 2 mm LAS voxels, the value increasing along the flipped x axis
+        let data = Array::from_shape_fn(IxDyn(&[4, 4, 4]), |idx| idx[0] as f32);
+        let mut affine = Matrix4::from_diagonal(&nalgebra::Vector4::new(-2.0, 2.0, 2.0, 1.0));
+        affine[(0, 3)] = 6.0;
+        write(&dir.join("in.nii"), &data, &affine, &NiftiHeader::default()).unwrap();
+
+        let command = |args: &[&str]| {
+            Cli::try_parse_from(["nifti-proc"].iter().chain(args))
+                .unwrap()
+                .command
+        };
+        run(command(&[
+            "resample",
+            &path("in.nii"),
+            &path("resampled.nii"),
+            "-v",
+            "1",
+        ]))
+        .unwrap();
+        let resampled = read(&dir.join("resampled.nii")).unwrap();
+        assert_eq!(resampled.data.shape(), &[7, 7, 7]);
+
+        run(command(&["reorient", &path("in.nii"), &path("ras.nii")])).unwrap();
+        let ras = read(&dir.join("ras.nii")).unwrap();
+        assert_eq!(ras.affine[(0, 0)], 2.0);
+        assert_eq!(ras.affine[(0, 3)], 0.0);
+        assert_eq!(ras.data[[0, 0, 0]], 3.0);
+        assert_eq!(ras.data[[3, 0, 0]], 0.0);
+
+        run(command(&["stats", &path("in.nii"), "-m", &path("ras.nii")])).unwrap();
+        assert!(run(command(&["stats", &path("missing.nii")])).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+use crate::filter::gaussian::{gaussian_filter, smooth_fwhm};
+use crate::lazy::LazyImage;
+use crate::{resample_from_to, NearestNeighbor, ReSample, TriLinear};
+use nalgebra::Matrix4;
+use ndarray::prelude::*;
+use ndarray::ShapeBuilder;
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Return code of the C API on success; on failure, functions return
+/// [`NP_ERROR`] and [`np_last_error`] describes the error.
+pub const NP_OK: i32 = 0;
+pub const NP_ERROR: i32 = -1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// Description of the last error of the C API on the calling thread, valid
+/// until the next call into the C API on that thread.
+#[no_mangle]
+pub extern "C" fn np_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ptr())
+}
+
+/// Run f, translating errors and panics into [`NP_ERROR`] and the last error.
+fn guarded<F>(f: F) -> i32
+where
+    F: FnOnce() -> Result<(), String>,
+{
+    let error = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => return NP_OK,
+        Ok(Err(e)) => e,
+        Err(_) => "panic in nifti_processing".to_string(),
+    };
+    let error = CString::new(error.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = error);
+    NP_ERROR
+}
+
+/// A 3D volume of dims (3 values) with the first axis varying fastest, as
+/// stored in NIFTI files and by ITK and VTK.
+unsafe fn volume(data: *const f32, dims: *const usize) -> Result<Array<f32, IxDyn>, String> {
+    let dims = array3(dims)?;
+    if data.is_null() {
+        return Err("null pointer".into());
+    }
+    let data = std::slice::from_raw_parts(data, dims.iter().product());
+    let view = ArrayView::from_shape(IxDyn(&dims).f(), data).map_err(|e| e.to_string())?;
+    Ok(view.to_owned())
+}
+
+/// Write a 3D volume with the first axis varying fastest.
+unsafe fn write_volume<U>(volume: &Array<U, IxDyn>, out: *mut f32) -> Result<(), String>
+where
+    U: Copy + num_traits::AsPrimitive<f32>,
+{
+    if out.is_null() {
+        return Err("null pointer".into());
+    }
+    let out = std::slice::from_raw_parts_mut(out, volume.len());
+    for (o, v) in out.iter_mut().zip(volume.t().iter()) {
+        *o = v.as_();
+    }
+    Ok(())
+}
+
+unsafe fn array3<T: Copy>(values: *const T) -> Result<[T; 3], String> {
+    if values.is_null() {
+        return Err("null pointer".into());
+    }
+    let values = std::slice::from_raw_parts(values, 3);
+    Ok([values[0], values[1], values[2]])
+}
+
+/// An affine of 16 row-major values.
+unsafe fn affine(values: *const f64) -> Result<Matrix4<f64>, String> {
+    if values.is_null() {
+        return Err("null pointer".into());
+    }
+    Ok(Matrix4::from_row_slice(std::slice::from_raw_parts(
+        values, 16,
+    )))
+}
+
+/// Resample the volume of in_dims and in_affine to the voxel space of
+/// out_dims and out_affine, by nearest neighbor (order 0) or trilinear
+/// (order 1) interpolation with cval outside of the volume. out has to hold
+/// the product of out_dims values.
+///
+/// # Safety
+///
+/// All pointers have to be valid for the number of values given above
+/// (dims: 3, affines: 16 row-major) and volumes are stored with the first
+/// axis varying fastest.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn np_resample_from_to(
+    in_data: *const f32,
+    in_dims: *const usize,
+    in_affine: *const f64,
+    out_data: *mut f32,
+    out_dims: *const usize,
+    out_affine: *const f64,
+    order: i32,
+    cval: f32,
+) -> i32 {
+    guarded(|| {
+        let im = volume(in_data, in_dims)?;
+        let (in_affine, out_affine) = (affine(in_affine)?, affine(out_affine)?);
+        let out_dims = array3(out_dims)?;
+        let mut sampler: Box<dyn ReSample<f64, f32>> = match order {
+            0 => Box::new(NearestNeighbor::default()),
+            1 => Box::new(TriLinear::default()),
+            _ => return Err("order has to be 0 or 1".into()),
+        };
+        sampler.set_cval(cval);
+        let out = resample_from_to(&im, &in_affine, &out_dims, &out_affine, sampler.as_ref())?;
+        write_volume(&out, out_data)
+    })
+}
+
+/// Gaussian smoothing of the volume of dims with a standard deviation of
+/// sigma (3 values) voxels per axis into out, which may be data.
+///
+/// # Safety
+///
+/// See [`np_resample_from_to`]; out has to hold as many values as data.
+#[no_mangle]
+pub unsafe extern "C" fn np_gaussian_filter(
+    data: *const f32,
+    dims: *const usize,
+    sigma: *const f64,
+    out: *mut f32,
+) -> i32 {
+    guarded(|| {
+        let im = volume(data, dims)?;
+        let smoothed = gaussian_filter(&im, &array3(sigma)?)?;
+        write_volume(&smoothed, out)
+    })
+}
+
+/// Gaussian smoothing of the volume of dims and affine (mm) with a full
+/// width at half maximum of fwhm (3 values) mm per axis into out, which may
+/// be data.
+///
+/// # Safety
+///
+/// See [`np_resample_from_to`]; out has to hold as many values as data.
+#[no_mangle]
+pub unsafe extern "C" fn np_smooth_fwhm(
+    data: *const f32,
+    dims: *const usize,
+    affine_values: *const f64,
+    fwhm: *const f64,
+    out: *mut f32,
+) -> i32 {
+    guarded(|| {
+        let im = volume(data, dims)?;
+        let smoothed = smooth_fwhm(&im, &affine(affine_values)?, &array3(fwhm)?)?;
+        write_volume(&smoothed, out)
+    })
+}
+
+/// Permute and flip the voxel axes of the volume of dims and affine to be
+/// closest to the world axes, in increasing order. Writes the voxels to out
+/// (as many as data), their dims to out_dims and their affine to out_affine.
+///
+/// # Safety
+///
+/// See [`np_resample_from_to`]; out must not overlap data.
+#[no_mangle]
+pub unsafe extern "C" fn np_reorient(
+    data: *const f32,
+    dims: *const usize,
+    affine_values: *const f64,
+    out: *mut f32,
+    out_dims: *mut usize,
+    out_affine: *mut f64,
+) -> i32 {
+    guarded(|| {
+        let im = volume(data, dims)?;
+        let affine = affine(affine_values)?;
+        let (reoriented, reoriented_affine) = LazyImage::new(&im, &affine)
+            .reorient()
+            .execute(&NearestNeighbor::default())?;
+        if out_dims.is_null() || out_affine.is_null() {
+            return Err("null pointer".into());
+        }
+        write_volume(&reoriented, out)?;
+        std::slice::from_raw_parts_mut(out_dims, 3).copy_from_slice(reoriented.shape());
+        let out_affine = std::slice::from_raw_parts_mut(out_affine, 16);
+        for (i, value) in out_affine.iter_mut().enumerate() {
+            *value = reoriented_affine[(i / 4, i % 4)];
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_capi() {
+        // x varies fastest, x flipped in world space
+        let data: Vec<f32> = (0..24).map(|x| x as f32).collect();
+        let dims = [2usize, 3, 4];
+        #[rustfmt::skip]
+        let affine = [
+            -1.0, 0.0, 0.0, 1.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        ];
+        let (mut out, mut out_dims, mut out_affine) = (vec![0.0; 24], [0; 3], [0.0; 16]);
+        let status = unsafe {
+            np_reorient(
+                data.as_ptr(),
+                dims.as_ptr(),
+                affine.as_ptr(),
+                out.as_mut_ptr(),
+                out_dims.as_mut_ptr(),
+                out_affine.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, NP_OK);
+        assert_eq!(out_dims, dims);
+        assert_eq!(out_affine[0], 1.0);
+        assert_eq!(&out[..4], &[1.0, 0.0, 3.0, 2.0]);
+
+        // resampling onto the reoriented grid undoes the reorientation
+        let mut resampled = vec![0.0; 24];
+        let status = unsafe {
+            np_resample_from_to(
+                out.as_ptr(),
+                out_dims.as_ptr(),
+                out_affine.as_ptr(),
+                resampled.as_mut_ptr(),
+                dims.as_ptr(),
+                affine.as_ptr(),
+                1,
+                0.0,
+            )
+        };
+        assert_eq!(status, NP_OK);
+        assert_eq!(resampled, data);
+
+        let sigma = [1.0, 0.0, 0.0];
+        let status = unsafe {
+            np_gaussian_filter(
+                data.as_ptr(),
+                dims.as_ptr(),
+                sigma.as_ptr(),
+                out.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, NP_OK);
+        // smoothed along x only, towards the mean of each pair
+        assert!(out[0] > 0.0 && out[1] < 1.0 && (out[0] + out[1] - 1.0).abs() < 1e-5);
+
+        let status = unsafe {
+            np_gaussian_filter(
+                data.as_ptr(),
+                dims.as_ptr(),
+                sigma.as_ptr(),
+                std::ptr::null_mut(),
+            )
+        };
+        assert_eq!(status, NP_ERROR);
+        let error = unsafe { CStr::from_ptr(np_last_error()) };
+        assert_eq!(error.to_str().unwrap(), "null pointer");
+    }
+}
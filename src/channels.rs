@@ -0,0 +1,196 @@
+use crate::sampler::nearest_neighbor::NearestNeighbor;
+use crate::sampler::trilinear::TriLinear;
+use crate::{resample_from_to, sanitize_im_shape};
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+// Multi-channel volumes are stored channel last, with shape (x, y, z, c).
+// Packed voxels such as NIFTI RGB24 ([u8; 3]) or RGBA32 ([u8; 4]) are
+// converted to and from this layout with `unpack_channels` and
+// `pack_channels`.
+
+/// Interpolation of a single channel, see [`resample_channels`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelInterpolation {
+    /// Trilinear interpolation, for continuous channels such as colors.
+    #[default]
+    Linear,
+
+    /// Nearest neighbor, for categorical channels such as labels.
+    Nearest,
+}
+
+/// Convert packed voxels (e.g. RGB24 `[u8; 3]`) of a 2D or 3D image into a
+/// multi-channel volume (x, y, z, N).
+pub fn unpack_channels<U, const N: usize>(
+    in_im: &Array<[U; N], IxDyn>,
+) -> Result<Array<U, IxDyn>, String>
+where
+    U: Copy,
+{
+    let im = sanitize_im_shape(in_im)?;
+    let s = im.shape();
+    Ok(Array::from_shape_fn(IxDyn(&[s[0], s[1], s[2], N]), |idx| {
+        im[[idx[0], idx[1], idx[2]]][idx[3]]
+    }))
+}
+
+/// Convert a multi-channel volume (x, y, z, N) into packed 8 bit voxels
+/// (e.g. RGB24 `[u8; 3]`), rounding and clamping each channel to [0, 255].
+pub fn pack_channels<U, const N: usize>(
+    in_im: &Array<U, IxDyn>,
+) -> Result<Array<[u8; N], IxDyn>, String>
+where
+    U: AsPrimitive<f64>,
+{
+    match in_im.shape() {
+        [x, y, z, c] if *c == N => Ok(Array::from_shape_fn(IxDyn(&[*x, *y, *z]), |idx| {
+            std::array::from_fn(|n| {
+                let v: f64 = in_im[[idx[0], idx[1], idx[2], n]].as_();
+                v.round().clamp(0.0, 255.0) as u8
+            })
+        })),
+        _ => Err(format!("expected a volume of shape (x, y, z, {N})")),
+    }
+}
+
+/// Apply a 3D operation, e.g. a filter, to each channel of a multi-channel
+/// volume (x, y, z, c).
+pub fn map_channels<U, V, F>(in_im: &Array<U, IxDyn>, f: F) -> Result<Array<V, IxDyn>, String>
+where
+    U: Clone,
+    V: Clone,
+    F: Fn(&Array<U, IxDyn>) -> Result<Array<V, IxDyn>, String>,
+{
+    if in_im.ndim() != 4 {
+        return Err("expected a multi-channel volume (x, y, z, c)".into());
+    }
+    let channels = in_im
+        .axis_iter(Axis(3))
+        .map(|channel| f(&channel.to_owned()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let views: Vec<_> = channels.iter().map(|c| c.view()).collect();
+    ndarray::stack(Axis(3), &views).map_err(|e| e.to_string())
+}
+
+/// Resample a multi-channel volume (x, y, z, c) onto the grid given by
+/// out_shape and out_affine, interpolating each channel at the same
+/// positions.
+///
+/// `interpolation` holds either a single entry used for all channels, or one
+/// entry per channel. Channels are interpolated in f64, see
+/// [`pack_channels`] for converting the result back to 8 bit colors.
+pub fn resample_channels<T, U>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    out_shape: &[usize; 3],
+    out_affine: &Matrix4<T>,
+    interpolation: &[ChannelInterpolation],
+) -> Result<Array<f64, IxDyn>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+{
+    if in_im.ndim() != 4 {
+        return Err("expected a multi-channel volume (x, y, z, c)".into());
+    }
+    let n_channels = in_im.shape()[3];
+    let interpolation = match interpolation.len() {
+        1 => vec![interpolation[0]; n_channels],
+        n if n == n_channels => interpolation.to_vec(),
+        _ => return Err("number of interpolations does not match the channels".into()),
+    };
+    let in_affine: Matrix4<f64> = in_affine.map(|x| x.as_());
+    let out_affine: Matrix4<f64> = out_affine.map(|x| x.as_());
+    let im: Array<f64, IxDyn> = in_im.mapv(|x| x.as_());
+
+    // channels sharing an interpolation are resampled together, reusing the
+    // sample coordinates
+    let mut out = Array::zeros(IxDyn(&[
+        out_shape[0],
+        out_shape[1],
+        out_shape[2],
+        n_channels,
+    ]));
+    for mode in [ChannelInterpolation::Linear, ChannelInterpolation::Nearest] {
+        let selected: Vec<usize> = (0..n_channels)
+            .filter(|c| interpolation[*c] == mode)
+            .collect();
+        if selected.is_empty() {
+            continue;
+        }
+        let channels = im.select(Axis(3), &selected);
+        let resampled = match mode {
+            ChannelInterpolation::Linear => resample_from_to(
+                &channels,
+                &in_affine,
+                out_shape,
+                &out_affine,
+                &TriLinear::<f64>::default(),
+            )?,
+            ChannelInterpolation::Nearest => resample_from_to(
+                &channels,
+                &in_affine,
+                out_shape,
+                &out_affine,
+                &NearestNeighbor::<f64>::default(),
+            )?,
+        };
+        for (i, c) in selected.iter().enumerate() {
+            out.index_axis_mut(Axis(3), *c)
+                .assign(&resampled.index_axis(Axis(3), i));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::gaussian::gaussian_filter;
+
+    #[test]
+    fn test_resample_rgb() {
+        // red gradient along x, labels in the second channel
+        let rgb = Array::from_shape_fn(IxDyn(&[3, 2, 2]), |idx| {
+            [(idx[0] * 100) as u8, idx[0] as u8, 255]
+        });
+        let channels = unpack_channels(&rgb).unwrap();
+        assert_eq!(channels.shape(), &[3, 2, 2, 3]);
+
+        let in_affine = Matrix4::<f64>::identity();
+        let out_affine = Matrix4::new_translation(&[0.4, 0.0, 0.0].into());
+        let interpolation = [
+            ChannelInterpolation::Linear,
+            ChannelInterpolation::Nearest,
+            ChannelInterpolation::Linear,
+        ];
+        let out = resample_channels(
+            &channels,
+            &in_affine,
+            &[2, 2, 2],
+            &out_affine,
+            &interpolation,
+        )
+        .unwrap();
+        let packed: Array<[u8; 3], IxDyn> = pack_channels(&out).unwrap();
+        assert_eq!(packed[[0, 0, 0]], [40, 0, 255]);
+        assert_eq!(packed[[1, 1, 1]], [140, 1, 255]);
+        assert!(resample_channels(
+            &channels,
+            &in_affine,
+            &[2, 2, 2],
+            &out_affine,
+            &interpolation[..2]
+        )
+        .is_err());
+
+        let smoothed = map_channels(&channels, |c| gaussian_filter(c, &[1.0; 3])).unwrap();
+        assert_eq!(smoothed.shape(), channels.shape());
+        assert!(smoothed
+            .index_axis(Axis(3), 2)
+            .iter()
+            .all(|x| (x - 255.0).abs() < 1e-9));
+    }
+}
@@ -0,0 +1,321 @@
+use crate::progress::Progress;
+use crate::resample_from_to;
+use crate::sampler::traits::ReSample;
+use crate::trace::{debug_event, timed_span};
+use nalgebra::{Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::{AsPrimitive, Num};
+
+/// A 2D or 3D volume whose voxels are read region by region, e.g. from a
+/// memory mapped or streamed file, such that it does not need to fit into
+/// memory as a whole.
+pub trait VolumeSource<U> {
+    /// Spatial shape of the volume; 2D volumes have a z extent of 1.
+    fn shape(&self) -> Result<[usize; 3], String>;
+
+    /// Read the box of voxels starting at start with the given shape as a 3D
+    /// image.
+    fn read_region(
+        &self,
+        start: &[usize; 3],
+        shape: &[usize; 3],
+    ) -> Result<Array<U, IxDyn>, String>;
+}
+
+impl<U> VolumeSource<U> for Array<U, IxDyn>
+where
+    U: Clone,
+{
+    fn shape(&self) -> Result<[usize; 3], String> {
+        match self.shape() {
+            [x, y] => Ok([*x, *y, 1]),
+            [x, y, z] => Ok([*x, *y, *z]),
+            _ => Err("invalid shape".into()),
+        }
+    }
+
+    fn read_region(
+        &self,
+        start: &[usize; 3],
+        shape: &[usize; 3],
+    ) -> Result<Array<U, IxDyn>, String> {
+        let full = VolumeSource::shape(self)?;
+        if (0..3).any(|d| start[d] + shape[d] > full[d]) {
+            return Err("region exceeds the volume".into());
+        }
+        let view = match self.ndim() {
+            2 => self.view().insert_axis(Axis(2)),
+            _ => self.view(),
+        };
+        Ok(view
+            .slice_each_axis(|ax| {
+                let d = ax.axis.index();
+                ndarray::Slice::from(start[d]..start[d] + shape[d])
+            })
+            .to_owned())
+    }
+}
+
+/// Parameters of [`resample_chunked`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkedParams {
+    /// Number of output z slices per slab.
+    pub slab_thickness: usize,
+
+    /// Voxels read around the bounding box of the input coordinates of a
+    /// slab, per side. 1 suffices for the nearest neighbor, trilinear and
+    /// label trilinear samplers; samplers with a wider support need more
+    /// to be exact.
+    pub margin: usize,
+}
+
+impl Default for ChunkedParams {
+    fn default() -> Self {
+        Self {
+            slab_thickness: 16,
+            margin: 1,
+        }
+    }
+}
+
+/// Resample the volume of source to the voxel space defined by out_shape
+/// and out_affine (see [`resample_from_to`]) slab by slab, holding only the
+/// input region required for the current slab and the slab itself in
+/// memory.
+///
+/// The output is split into slabs of consecutive z slices, matching the
+/// on-disk order of NIFTI images. Each slab is passed to sink together with
+/// the index of its first slice, in order, e.g. to be appended to an output
+/// file. The input region of a slab is the bounding box of the input voxel
+/// coordinates of its voxels, grown by the margin and clipped to the
+/// volume.
+pub fn resample_chunked<T, U, S, V, F>(
+    source: &V,
+    in_affine: &Matrix4<T>,
+    out_shape: &[usize; 3],
+    out_affine: &Matrix4<T>,
+    sampler: &S,
+    params: &ChunkedParams,
+    sink: F,
+) -> Result<(), String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<U> + AsPrimitive<f64> + Copy,
+    U: Num + Copy + Send + Sync + 'static,
+    S: ReSample<T, U> + ?Sized + 'static,
+    V: VolumeSource<U> + ?Sized,
+    F: FnMut(usize, Array<U, IxDyn>) -> Result<(), String>,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    resample_chunked_with_progress(
+        source,
+        in_affine,
+        out_shape,
+        out_affine,
+        sampler,
+        params,
+        sink,
+        &Progress::default(),
+    )
+}
+
+/// [`resample_chunked`] reporting the fraction of completed slices after
+/// each slab, which is also when cancellation is checked.
+#[allow(clippy::too_many_arguments)]
+pub fn resample_chunked_with_progress<T, U, S, V, F>(
+    source: &V,
+    in_affine: &Matrix4<T>,
+    out_shape: &[usize; 3],
+    out_affine: &Matrix4<T>,
+    sampler: &S,
+    params: &ChunkedParams,
+    mut sink: F,
+    progress: &Progress,
+) -> Result<(), String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<U> + AsPrimitive<f64> + Copy,
+    U: Num + Copy + Send + Sync + 'static,
+    S: ReSample<T, U> + ?Sized + 'static,
+    V: VolumeSource<U> + ?Sized,
+    F: FnMut(usize, Array<U, IxDyn>) -> Result<(), String>,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    timed_span!("resample_chunked", out_shape = ?out_shape, ?params);
+    if params.slab_thickness == 0 {
+        return Err("slab thickness has to be at least 1".into());
+    }
+    let in_shape = source.shape()?;
+    if in_shape.contains(&0) {
+        return Err("volume does not contain any voxels".into());
+    }
+    let compound = match in_affine.try_inverse() {
+        Some(val) => val * out_affine,
+        None => return Err("no valid matrix inverse found for in_affine".into()),
+    };
+
+    progress.update(0.0)?;
+    for z0 in (0..out_shape[2]).step_by(params.slab_thickness) {
+        let nz = params.slab_thickness.min(out_shape[2] - z0);
+        let slab_shape = [out_shape[0], out_shape[1], nz];
+        let (start, shape) = input_region(&compound, &in_shape, z0, &slab_shape, params.margin);
+        debug_event!(z0, region_start = ?start, region_shape = ?shape, "reading slab input");
+        let region = source.read_region(&start, &shape)?;
+
+        let offset: Vector3<T> = Vector3::from(start.map(|x| x.as_()));
+        let region_affine = in_affine * Matrix4::new_translation(&offset);
+        let z_offset = Vector3::new(T::zero(), T::zero(), z0.as_());
+        let slab_affine = out_affine * Matrix4::new_translation(&z_offset);
+        let slab = resample_from_to(&region, &region_affine, &slab_shape, &slab_affine, sampler)?;
+        sink(z0, slab)?;
+        progress.update((z0 + nz) as f64 / out_shape[2] as f64)?;
+    }
+    Ok(())
+}
+
+/// Start and shape of the input region of the output slab starting at slice
+/// z0.
+fn input_region<T>(
+    compound: &Matrix4<T>,
+    in_shape: &[usize; 3],
+    z0: usize,
+    slab_shape: &[usize; 3],
+    margin: usize,
+) -> ([usize; 3], [usize; 3])
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+{
+    let compound: Matrix4<f64> = compound.map(|x| x.as_());
+    let (mut lo, mut hi) = ([f64::INFINITY; 3], [f64::NEG_INFINITY; 3]);
+    let extent = slab_shape.map(|n| n.saturating_sub(1) as f64);
+    for corner in 0..8 {
+        let along = |d: usize| {
+            if corner & (1 << d) != 0 {
+                extent[d]
+            } else {
+                0.0
+            }
+        };
+        let p = nalgebra::Vector4::new(along(0), along(1), z0 as f64 + along(2), 1.0);
+        let q = compound * p;
+        for d in 0..3 {
+            lo[d] = lo[d].min(q[d]);
+            hi[d] = hi[d].max(q[d]);
+        }
+    }
+
+    let (mut start, mut shape) = ([0; 3], *in_shape);
+    for d in 0..3 {
+        // degenerate coordinates are resolved by the sampler on the full volume
+        if !(lo[d].is_finite() && hi[d].is_finite()) {
+            continue;
+        }
+        let n = in_shape[d] as f64;
+        // the clamping keeps the region non-empty, as required for samplers
+        // replicating the border
+        let first = (lo[d].floor() - margin as f64).clamp(0.0, n - 1.0) as usize;
+        let last = (hi[d].floor() + 1.0 + margin as f64).clamp(0.0, n - 1.0) as usize;
+        start[d] = first.min(last);
+        shape[d] = last.max(first) - start[d] + 1;
+    }
+    (start, shape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SamplingMode, TriLinear};
+
+    #[test]
+    fn test_resample_chunked() {
+        let im = Array::from_shape_fn(IxDyn(&[12, 10, 9]), |idx| {
+            (idx[0] * idx[1] + 7 * idx[2]) as f64
+        });
+        let in_affine = Matrix4::new_scaling(1.5);
+        let out_affine = Matrix4::new_translation(&Vector3::new(-2.0, 1.0, 0.5))
+            * nalgebra::Rotation3::from_euler_angles(0.3, 0.1, -0.2).to_homogeneous();
+        let out_shape = [14, 12, 11];
+
+        for mode in [SamplingMode::Constant, SamplingMode::Nearest] {
+            let mut sampler = TriLinear::<f64>::default();
+            ReSample::<f64, f64>::set_sampling_mode(&mut sampler, mode);
+            ReSample::<f64, f64>::set_cval(&mut sampler, -1.0);
+            let expected =
+                resample_from_to(&im, &in_affine, &out_shape, &out_affine, &sampler).unwrap();
+
+            let mut out = Array::zeros(IxDyn(&out_shape));
+            let mut slabs = Vec::new();
+            let params = ChunkedParams {
+                slab_thickness: 4,
+                ..Default::default()
+            };
+            resample_chunked(
+                &im,
+                &in_affine,
+                &out_shape,
+                &out_affine,
+                &sampler,
+                &params,
+                |z0, slab| {
+                    slabs.push((z0, slab.shape()[2]));
+                    out.slice_mut(s![.., .., z0..z0 + slab.shape()[2]])
+                        .assign(&slab);
+                    Ok(())
+                },
+            )
+            .unwrap();
+            assert_eq!(slabs, vec![(0, 4), (4, 4), (8, 3)]);
+            assert!(out
+                .iter()
+                .zip(expected.iter())
+                .all(|(a, b)| (a - b).abs() < 1e-9));
+        }
+    }
+
+    #[test]
+    fn test_resample_chunked_cancellation() {
+        let im = Array::from_elem(IxDyn(&[4, 4, 8]), 1.0);
+        let affine = Matrix4::<f64>::identity();
+        let token = crate::progress::CancelToken::new();
+        let fractions = std::cell::RefCell::new(Vec::new());
+        let callback = |f: f64| fractions.borrow_mut().push(f);
+        let progress = Progress {
+            callback: Some(&callback),
+            cancel: Some(token.clone()),
+        };
+        let params = ChunkedParams {
+            slab_thickness: 2,
+            ..Default::default()
+        };
+        let mut n_slabs = 0;
+        let result = resample_chunked_with_progress(
+            &im,
+            &affine,
+            &[4, 4, 8],
+            &(affine * Matrix4::new_scaling(0.9)),
+            &TriLinear::<f64>::default(),
+            &params,
+            |_, _| {
+                n_slabs += 1;
+                if n_slabs == 2 {
+                    token.cancel();
+                }
+                Ok(())
+            },
+            &progress,
+        );
+        assert_eq!(result, Err(crate::progress::CANCELLED.to_string()));
+        assert_eq!(n_slabs, 2);
+        assert_eq!(*fractions.borrow(), vec![0.0, 0.25, 0.5]);
+    }
+
+    #[test]
+    fn test_read_region() {
+        let im = Array::from_shape_fn(IxDyn(&[4, 3]), |idx| idx[0] + 10 * idx[1]);
+        assert_eq!(VolumeSource::shape(&im), Ok([4, 3, 1]));
+        let region = im.read_region(&[1, 1, 0], &[2, 2, 1]).unwrap();
+        assert_eq!(region.shape(), &[2, 2, 1]);
+        assert_eq!(region[[1, 0, 0]], 12);
+        assert!(im.read_region(&[3, 0, 0], &[2, 1, 1]).is_err());
+    }
+}
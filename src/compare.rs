@@ -0,0 +1,216 @@
+use crate::sampler::traits::ReSample;
+use crate::{resample_from_to, same_grid, sanitize_im_shape, shape3};
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::{AsPrimitive, Num};
+
+/// Interleave two co-registered images in a checkerboard pattern.
+///
+/// The volume is divided into `tiles` blocks per axis; blocks with an even
+/// sum of block indices are taken from `a`, the others from `b`. Edges of
+/// structures which continue smoothly across the block borders indicate a
+/// good alignment.
+pub fn checkerboard<U>(
+    a: &Array<U, IxDyn>,
+    b: &Array<U, IxDyn>,
+    tiles: &[usize; 3],
+) -> Result<Array<U, IxDyn>, String>
+where
+    U: Clone,
+{
+    let a = sanitize_im_shape(a)?;
+    let b = sanitize_im_shape(b)?;
+    if a.shape() != b.shape() {
+        return Err("image shapes do not match".into());
+    }
+    if tiles.contains(&0) {
+        return Err("number of tiles has to be at least 1".into());
+    }
+    let shape = a.shape().to_vec();
+    Ok(Array::from_shape_fn(a.raw_dim(), |idx| {
+        let parity: usize = (0..3).map(|i| idx[i] * tiles[i] / shape[i]).sum();
+        if parity % 2 == 0 {
+            a[&idx].clone()
+        } else {
+            b[&idx].clone()
+        }
+    }))
+}
+
+/// Summary statistics of a [`difference_maps`] comparison.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DifferenceStats {
+    /// Maximum absolute difference.
+    pub max_absolute: f64,
+
+    /// Mean absolute difference.
+    pub mean_absolute: f64,
+
+    /// Mean signed difference (test - reference), i.e. the bias.
+    pub mean_signed: f64,
+
+    /// Root mean squared difference.
+    pub rmse: f64,
+
+    /// Maximum finite relative error.
+    pub max_relative: f64,
+
+    /// Number of voxels whose values differ.
+    pub n_different: usize,
+}
+
+/// Result of [`difference_maps`].
+#[derive(Debug, Clone)]
+pub struct DifferenceMaps {
+    /// Absolute difference `|test - reference|`.
+    pub absolute: Array<f64, IxDyn>,
+
+    /// Signed difference `test - reference`.
+    pub signed: Array<f64, IxDyn>,
+
+    /// Relative error `|test - reference| / |reference|`. Voxels with a
+    /// reference value of 0 are 0 if the test value is 0 as well and
+    /// infinite otherwise.
+    pub relative: Array<f64, IxDyn>,
+
+    /// Summary statistics over all voxels with finite values.
+    pub stats: DifferenceStats,
+}
+
+/// Voxel-wise comparison of `test` against `reference`.
+///
+/// If the test image does not share the grid of the reference, it is first
+/// resampled onto the reference grid with `sampler`. Comparing the outputs of
+/// two resampling pipelines on a common grid involves no further resampling.
+pub fn difference_maps<T, U, V, S>(
+    reference: &Array<U, IxDyn>,
+    reference_affine: &Matrix4<T>,
+    test: &Array<V, IxDyn>,
+    test_affine: &Matrix4<T>,
+    sampler: &S,
+) -> Result<DifferenceMaps, String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<V> + Copy,
+    U: AsPrimitive<f64>,
+    V: Num + Copy + Send + Sync + AsPrimitive<f64> + 'static,
+    S: ReSample<T, V> + ?Sized + 'static,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    let reference: Array<f64, IxDyn> = sanitize_im_shape(reference)?.mapv(|x| x.as_());
+    let test = sanitize_im_shape(test)?;
+    let tolerance: T = 1e-4f32.as_();
+    let test = if same_grid(
+        reference.shape(),
+        reference_affine,
+        test.shape(),
+        test_affine,
+        tolerance,
+    ) {
+        test
+    } else {
+        resample_from_to(
+            &test,
+            test_affine,
+            &shape3(&reference),
+            reference_affine,
+            sampler,
+        )?
+    };
+    let test: Array<f64, IxDyn> = test.mapv(|x| x.as_());
+
+    let signed = &test - &reference;
+    let absolute = signed.mapv(f64::abs);
+    let mut relative = absolute.clone();
+    relative.zip_mut_with(&reference, |d, r| {
+        *d = if *d == 0.0 {
+            0.0
+        } else if *r == 0.0 {
+            f64::INFINITY
+        } else {
+            *d / r.abs()
+        }
+    });
+
+    let (mut sum_abs, mut sum_signed, mut sum_sq, mut n) = (0.0, 0.0, 0.0, 0usize);
+    let (mut max_absolute, mut n_different) = (0.0f64, 0);
+    for d in signed.iter().filter(|d| d.is_finite()) {
+        sum_abs += d.abs();
+        sum_signed += d;
+        sum_sq += d * d;
+        max_absolute = max_absolute.max(d.abs());
+        n_different += (*d != 0.0) as usize;
+        n += 1;
+    }
+    if n == 0 {
+        return Err("no voxels to compare".into());
+    }
+    let n_f = n as f64;
+    let stats = DifferenceStats {
+        max_absolute,
+        mean_absolute: sum_abs / n_f,
+        mean_signed: sum_signed / n_f,
+        rmse: (sum_sq / n_f).sqrt(),
+        max_relative: relative
+            .iter()
+            .copied()
+            .filter(|r| r.is_finite())
+            .fold(0.0, f64::max),
+        n_different,
+    };
+    Ok(DifferenceMaps {
+        absolute,
+        signed,
+        relative,
+        stats,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkerboard() {
+        let a = Array::from_elem(IxDyn(&[4, 6, 3]), 1u8);
+        let b = Array::from_elem(IxDyn(&[4, 6, 3]), 2u8);
+        let board = checkerboard(&a, &b, &[2, 3, 1]).unwrap();
+        assert_eq!(board[[0, 0, 0]], 1);
+        assert_eq!(board[[1, 1, 2]], 1);
+        assert_eq!(board[[2, 0, 0]], 2);
+        assert_eq!(board[[0, 2, 1]], 2);
+        assert_eq!(board[[3, 5, 0]], 2);
+        assert!(checkerboard(
+            &a,
+            &b.slice(s![.., ..5, ..]).to_owned().into_dyn(),
+            &[2, 2, 2]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_difference_maps() {
+        let reference = Array::from_shape_vec(IxDyn(&[4, 1, 1]), vec![0.0, 2.0, 4.0, 0.0]).unwrap();
+        let test = Array::from_shape_vec(IxDyn(&[4, 1, 1]), vec![0.0, 3.0, 2.0, 1.0]).unwrap();
+        let affine = Matrix4::<f64>::identity();
+        let sampler = crate::TriLinear::default();
+        let maps = difference_maps(&reference, &affine, &test, &affine, &sampler).unwrap();
+
+        assert_eq!(maps.signed.as_slice().unwrap(), &[0.0, 1.0, -2.0, 1.0]);
+        assert_eq!(
+            maps.relative.as_slice().unwrap(),
+            &[0.0, 0.5, 0.5, f64::INFINITY]
+        );
+        assert_eq!(maps.stats.max_absolute, 2.0);
+        assert_eq!(maps.stats.mean_absolute, 1.0);
+        assert_eq!(maps.stats.mean_signed, 0.0);
+        assert_eq!(maps.stats.rmse, 1.5f64.sqrt());
+        assert_eq!(maps.stats.max_relative, 0.5);
+        assert_eq!(maps.stats.n_different, 3);
+
+        // a test image on a shifted grid is resampled first
+        let shifted = Matrix4::new_translation(&nalgebra::Vector3::new(1.0, 0.0, 0.0));
+        let maps = difference_maps(&reference, &affine, &reference, &shifted, &sampler).unwrap();
+        assert_eq!(maps.signed[[2, 0, 0]], -2.0);
+    }
+}
@@ -0,0 +1,81 @@
+use ndarray::prelude::*;
+use num_traits::{AsPrimitive, Bounded, ToPrimitive};
+
+/// Rounding applied when converting to an integer voxel type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    /// Round to the nearest integer, halfway cases away from zero.
+    #[default]
+    Round,
+
+    /// Round towards negative infinity.
+    Floor,
+
+    /// Round towards zero, like a plain `as` cast.
+    Truncate,
+}
+
+impl Rounding {
+    pub fn apply(self, value: f64) -> f64 {
+        match self {
+            Rounding::Round => value.round(),
+            Rounding::Floor => value.floor(),
+            Rounding::Truncate => value.trunc(),
+        }
+    }
+}
+
+/// Convert an image, e.g. a float interpolation result, to the voxel type
+/// `V` of the original image.
+///
+/// Values are rounded according to `rounding` and saturate at the limits of
+/// `V` instead of wrapping around; NaN becomes 0. For a float `V` the
+/// rounding is applied as well, which is rarely what you want.
+pub fn convert_dtype<U, V>(in_im: &Array<U, IxDyn>, rounding: Rounding) -> Array<V, IxDyn>
+where
+    U: AsPrimitive<f64>,
+    V: Bounded + ToPrimitive + Copy + 'static,
+    f64: AsPrimitive<V>,
+{
+    let low = V::min_value().to_f64().unwrap_or(f64::NEG_INFINITY);
+    let high = V::max_value().to_f64().unwrap_or(f64::INFINITY);
+    in_im.mapv(|x| {
+        let value = rounding.apply(x.as_());
+        if value.is_nan() {
+            0.0.as_()
+        } else if value <= low {
+            V::min_value()
+        } else if value >= high {
+            V::max_value()
+        } else {
+            value.as_()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_dtype() {
+        let im = Array::from_vec(vec![-40000.0, -1.5, -0.5, 0.5, 2.7, f64::NAN, 1e300]).into_dyn();
+
+        let round: Array<i16, IxDyn> = convert_dtype(&im, Rounding::Round);
+        assert_eq!(
+            round.as_slice().unwrap(),
+            &[i16::MIN, -2, -1, 1, 3, 0, i16::MAX]
+        );
+        let floor: Array<i16, IxDyn> = convert_dtype(&im, Rounding::Floor);
+        assert_eq!(
+            floor.as_slice().unwrap(),
+            &[i16::MIN, -2, -1, 0, 2, 0, i16::MAX]
+        );
+        let trunc: Array<u8, IxDyn> = convert_dtype(&im, Rounding::Truncate);
+        assert_eq!(trunc.as_slice().unwrap(), &[0, 0, 0, 0, 2, 0, 255]);
+
+        // limits which are not exactly representable as f64 saturate as well
+        let big: Array<u64, IxDyn> = convert_dtype(&im, Rounding::Round);
+        assert_eq!(big[[6]], u64::MAX);
+    }
+}
@@ -0,0 +1,164 @@
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Linear mapping of stored voxel values to physical values, given by the
+/// NIFTI `scl_slope` and `scl_inter` header fields. For CT these are
+/// Hounsfield units (HU).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rescale {
+    pub slope: f64,
+    pub intercept: f64,
+}
+
+impl Default for Rescale {
+    fn default() -> Self {
+        Self {
+            slope: 1.0,
+            intercept: 0.0,
+        }
+    }
+}
+
+impl Rescale {
+    /// The mapping of the header fields. A slope of 0 (or a non-finite
+    /// slope) means that no scaling is applied, as specified by NIFTI.
+    pub fn from_header(scl_slope: f32, scl_inter: f32) -> Self {
+        if scl_slope == 0.0 || !scl_slope.is_finite() {
+            return Self::default();
+        }
+        Self {
+            slope: scl_slope as f64,
+            intercept: if scl_inter.is_finite() {
+                scl_inter as f64
+            } else {
+                0.0
+            },
+        }
+    }
+
+    /// Physical values (e.g. HU) of stored values.
+    pub fn apply<U>(&self, stored: &Array<U, IxDyn>) -> Array<f64, IxDyn>
+    where
+        U: AsPrimitive<f64>,
+    {
+        stored.mapv(|x| self.slope * x.as_() + self.intercept)
+    }
+
+    /// Stored values of physical values, e.g. before writing with the same
+    /// header fields. Convert to an integer type with
+    /// [`convert_dtype`](crate::convert::convert_dtype).
+    pub fn invert(&self, values: &Array<f64, IxDyn>) -> Array<f64, IxDyn> {
+        values.mapv(|x| (x - self.intercept) / self.slope)
+    }
+}
+
+/// A range of Hounsfield units, both ends inclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HuRange {
+    pub low: f64,
+    pub high: f64,
+}
+
+impl HuRange {
+    /// Air, e.g. to find the outside of the body.
+    pub const AIR: HuRange = HuRange {
+        low: f64::NEG_INFINITY,
+        high: -900.0,
+    };
+
+    /// Lung parenchyma.
+    pub const LUNG: HuRange = HuRange {
+        low: -950.0,
+        high: -500.0,
+    };
+
+    /// Adipose tissue.
+    pub const FAT: HuRange = HuRange {
+        low: -190.0,
+        high: -30.0,
+    };
+
+    /// Soft tissue including blood and muscle.
+    pub const SOFT_TISSUE: HuRange = HuRange {
+        low: -100.0,
+        high: 300.0,
+    };
+
+    /// Cancellous and cortical bone.
+    pub const BONE: HuRange = HuRange {
+        low: 300.0,
+        high: f64::INFINITY,
+    };
+
+    pub fn contains(&self, hu: f64) -> bool {
+        hu >= self.low && hu <= self.high
+    }
+}
+
+/// Mask of the voxels of a HU image within `range`, e.g. [`HuRange::BONE`].
+pub fn hu_mask(hu: &Array<f64, IxDyn>, range: HuRange) -> Array<bool, IxDyn> {
+    hu.mapv(|x| range.contains(x))
+}
+
+/// Clip a HU image to [low, high], e.g. to [-1024, 3071] to suppress metal
+/// artifacts and out of field of view padding before further processing.
+pub fn clip_hu(hu: &Array<f64, IxDyn>, low: f64, high: f64) -> Result<Array<f64, IxDyn>, String> {
+    if low.is_nan() || high.is_nan() || low > high {
+        return Err("lower clipping bound exceeds the upper bound".into());
+    }
+    Ok(hu.mapv(|x| x.clamp(low, high)))
+}
+
+/// Check that an image plausibly contains HU rather than raw stored values.
+///
+/// CT images always contain air or at least fat around and within the body,
+/// hence an image without any negative value most likely misses the
+/// rescale intercept (commonly -1024); values far below that of air (-1000)
+/// indicate a wrong slope or intercept. Padding values (e.g. -2048 or
+/// -3024) are within the accepted range.
+pub fn check_hu(hu: &Array<f64, IxDyn>) -> Result<(), String> {
+    let (min, max) = hu
+        .iter()
+        .filter(|x| x.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), x| {
+            (lo.min(*x), hi.max(*x))
+        });
+    if min > max {
+        return Err("image does not contain any finite values".into());
+    }
+    if min >= 0.0 {
+        return Err("no negative values, the rescale intercept is likely not applied".into());
+    }
+    if min < -4096.0 || max > 65535.0 {
+        return Err("values out of the HU range, check the rescale slope and intercept".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hounsfield_units() {
+        let stored = Array::from_vec(vec![0u16, 24, 984, 1524]).into_dyn();
+        assert!(check_hu(&stored.mapv(|x| x as f64)).is_err());
+
+        let rescale = Rescale::from_header(1.0, -1024.0);
+        let hu = rescale.apply(&stored);
+        assert_eq!(hu.as_slice().unwrap(), &[-1024.0, -1000.0, -40.0, 500.0]);
+        assert!(check_hu(&hu).is_ok());
+        assert_eq!(rescale.invert(&hu), stored.mapv(|x| x as f64));
+        assert_eq!(Rescale::from_header(0.0, 5.0), Rescale::default());
+
+        let air = hu_mask(&hu, HuRange::AIR);
+        assert_eq!(air.as_slice().unwrap(), &[true, true, false, false]);
+        let bone = hu_mask(&hu, HuRange::BONE);
+        assert_eq!(bone.as_slice().unwrap(), &[false, false, false, true]);
+        assert!(HuRange::FAT.contains(-40.0));
+
+        let clipped = clip_hu(&hu, -100.0, 100.0).unwrap();
+        assert_eq!(clipped.as_slice().unwrap(), &[-100.0, -100.0, -40.0, 100.0]);
+        assert!(clip_hu(&hu, 1.0, 0.0).is_err());
+    }
+}
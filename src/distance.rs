@@ -0,0 +1,109 @@
+use crate::sanitize_im_shape;
+use ndarray::prelude::*;
+
+/// Exact euclidean distance transform of a binary mask.
+///
+/// For every foreground (`true`) voxel the distance to the nearest background
+/// voxel is computed, background voxels are 0. Distances are given in the
+/// units of `voxel_sizes`, e.g. millimeters. If the mask does not contain any
+/// background voxel, all distances are infinite.
+///
+/// This is the separable algorithm of Felzenszwalb & Huttenlocher, which runs
+/// in linear time for any voxel spacing.
+pub fn euclidean_distance_transform(
+    mask: &Array<bool, IxDyn>,
+    voxel_sizes: &[f64; 3],
+) -> Result<Array<f64, IxDyn>, String> {
+    let mask = sanitize_im_shape(mask)?;
+    let init = mask.mapv(|x| if x { f64::INFINITY } else { 0.0 });
+    Ok(squared_distance(init, voxel_sizes).mapv(f64::sqrt))
+}
+
+/// Squared distance transform of the sampled function `f` (0 at the feature
+/// voxels, infinite elsewhere).
+pub(crate) fn squared_distance(
+    mut f: Array<f64, IxDyn>,
+    voxel_sizes: &[f64; 3],
+) -> Array<f64, IxDyn> {
+    let mut buffer = Vec::new();
+    for (axis, spacing) in voxel_sizes.iter().enumerate() {
+        for mut lane in f.lanes_mut(Axis(axis)) {
+            let line: Vec<f64> = lane.iter().copied().collect();
+            buffer.resize(line.len(), 0.0);
+            distance_1d(&line, *spacing, &mut buffer);
+            lane.iter_mut().zip(&buffer).for_each(|(x, d)| *x = *d);
+        }
+    }
+    f
+}
+
+/// One dimensional squared distance transform along a line with spacing `spacing`.
+fn distance_1d(f: &[f64], spacing: f64, out: &mut [f64]) {
+    // the lower envelope of the parabolas rooted at all finite samples
+    let mut roots: Vec<usize> = Vec::with_capacity(f.len());
+    let mut bounds: Vec<f64> = Vec::with_capacity(f.len());
+    for q in 0..f.len() {
+        if !f[q].is_finite() {
+            continue;
+        }
+        let xq = q as f64 * spacing;
+        while let Some(&p) = roots.last() {
+            let xp = p as f64 * spacing;
+            let s = ((f[q] + xq * xq) - (f[p] + xp * xp)) / (2.0 * (xq - xp));
+            if s <= *bounds.last().unwrap() {
+                roots.pop();
+                bounds.pop();
+            } else {
+                roots.push(q);
+                bounds.push(s);
+                break;
+            }
+        }
+        if roots.is_empty() {
+            roots.push(q);
+            bounds.push(f64::NEG_INFINITY);
+        }
+    }
+
+    if roots.is_empty() {
+        out.iter_mut().for_each(|x| *x = f64::INFINITY);
+        return;
+    }
+
+    let mut k = 0;
+    for (q, o) in out.iter_mut().enumerate() {
+        let xq = q as f64 * spacing;
+        while k + 1 < roots.len() && bounds[k + 1] < xq {
+            k += 1;
+        }
+        let xp = roots[k] as f64 * spacing;
+        *o = (xq - xp).powi(2) + f[roots[k]];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn test_euclidean_distance_transform() {
+        let mut mask = Array::from_elem(IxDyn(&[1, 1, 7]), true);
+        mask[[0, 0, 0]] = false;
+        mask[[0, 0, 6]] = false;
+        let dist = euclidean_distance_transform(&mask, &[1.0, 1.0, 0.5]).unwrap();
+        let expected = [0.0, 0.5, 1.0, 1.5, 1.0, 0.5, 0.0];
+        for (d, e) in dist.iter().zip(expected) {
+            assert_relative_eq!(*d, e);
+        }
+    }
+
+    #[test]
+    fn test_euclidean_distance_transform_diagonal() {
+        let mut mask = Array::from_elem(IxDyn(&[4, 4, 1]), true);
+        mask[[0, 0, 0]] = false;
+        let dist = euclidean_distance_transform(&mask, &[2.0, 1.0, 1.0]).unwrap();
+        assert_relative_eq!(dist[[3, 3, 0]], (36.0f64 + 9.0).sqrt());
+        assert_relative_eq!(dist[[1, 0, 0]], 2.0);
+    }
+}
@@ -0,0 +1,281 @@
+use super::tensor::finite_strain_rotation;
+use crate::afftra_to_aff_tra;
+use nalgebra::{Matrix3, Matrix4, RealField, Scalar, Vector3};
+use num_traits::AsPrimitive;
+#[cfg(feature = "io")]
+use std::fs::{self, File};
+#[cfg(feature = "io")]
+use std::io::BufWriter;
+use std::io::{self, Write};
+#[cfg(feature = "io")]
+use std::path::Path;
+
+/// Diffusion gradient table: b-values (s/mm²) and unit gradient directions,
+/// one per volume of a DWI series.
+///
+/// Directions are expressed with respect to the world axes, as are the
+/// transforms they are rotated by.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientTable {
+    /// b-value of each volume.
+    pub bvals: Vec<f64>,
+
+    /// Gradient direction of each volume, zero for b = 0 volumes.
+    pub bvecs: Vec<Vector3<f64>>,
+}
+
+impl GradientTable {
+    /// A table from matching b-values and b-vectors.
+    pub fn new(bvals: Vec<f64>, bvecs: Vec<Vector3<f64>>) -> Result<Self, String> {
+        if bvals.len() != bvecs.len() {
+            return Err("number of b-values and b-vectors do not match".into());
+        }
+        Ok(Self { bvals, bvecs })
+    }
+
+    /// Rotate all directions by the rotational part of the world space
+    /// `transform` applied to the DWI series with
+    /// [`resample_with_transform`](crate::resample_with_transform).
+    ///
+    /// As the transform maps output to input coordinates, the directions are
+    /// rotated by the (finite strain) rotation of its inverse. Zero vectors
+    /// (b = 0 volumes) remain zero.
+    pub fn rotate<T>(&self, transform: &Matrix4<T>) -> Result<Self, String>
+    where
+        T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    {
+        let rotation = inverse_rotation(transform)?;
+        Ok(Self {
+            bvals: self.bvals.clone(),
+            bvecs: self.bvecs.iter().map(|v| rotate(&rotation, v)).collect(),
+        })
+    }
+
+    /// Rotate each direction by the transform of its volume, e.g. the
+    /// [`transforms`](crate::registration::motion::MotionCorrected) of a
+    /// motion correction.
+    pub fn rotate_per_volume<T>(&self, transforms: &[Matrix4<T>]) -> Result<Self, String>
+    where
+        T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    {
+        if transforms.len() != self.bvecs.len() {
+            return Err("number of transforms and b-vectors do not match".into());
+        }
+        let bvecs = self
+            .bvecs
+            .iter()
+            .zip(transforms)
+            .map(|(v, t)| Ok(rotate(&inverse_rotation(t)?, v)))
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            bvals: self.bvals.clone(),
+            bvecs,
+        })
+    }
+
+    /// Parse the contents of FSL style `bval` (one row) and `bvec` (three
+    /// rows) files of the DWI series with `affine`.
+    ///
+    /// FSL gives the directions with respect to the voxel axes, with x
+    /// flipped if the affine has a positive determinant. They are converted
+    /// to world directions.
+    pub fn from_fsl<T>(bvals: &str, bvecs: &str, affine: &Matrix4<T>) -> Result<Self, String>
+    where
+        T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    {
+        let parse_row = |row: &str| {
+            row.split_whitespace()
+                .map(|x| {
+                    x.parse::<f64>()
+                        .map_err(|e| format!("invalid value {x}: {e}"))
+                })
+                .collect::<Result<Vec<f64>, String>>()
+        };
+        let bvals: Vec<f64> = bvals
+            .lines()
+            .map(parse_row)
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+        let rows = bvecs
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(parse_row)
+            .collect::<Result<Vec<_>, _>>()?;
+        if rows.len() != 3 || rows.iter().any(|r| r.len() != bvals.len()) {
+            return Err("bvec has to contain three rows with one entry per b-value".into());
+        }
+        let axes = fsl_axes(affine)?;
+        let bvecs = (0..bvals.len())
+            .map(|i| rotate(&axes, &Vector3::new(rows[0][i], rows[1][i], rows[2][i])))
+            .collect();
+        Self::new(bvals, bvecs)
+    }
+
+    /// Read FSL style `bval` and `bvec` files of the DWI series with `affine`,
+    /// see [`from_fsl`](Self::from_fsl).
+    #[cfg(feature = "io")]
+    pub fn load_fsl<P, T>(bval_path: P, bvec_path: P, affine: &Matrix4<T>) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+        T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    {
+        let bvals = fs::read_to_string(bval_path)?;
+        let bvecs = fs::read_to_string(bvec_path)?;
+        Self::from_fsl(&bvals, &bvecs, affine)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Write FSL style `bval` and `bvec` files of the DWI series with
+    /// `affine`, converting the world directions to the FSL voxel convention.
+    pub fn write_fsl<W, T>(&self, affine: &Matrix4<T>, bval: &mut W, bvec: &mut W) -> io::Result<()>
+    where
+        W: Write,
+        T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    {
+        let axes = fsl_axes(affine)
+            .and_then(|axes| {
+                axes.try_inverse()
+                    .ok_or_else(|| "no valid matrix inverse found for the affine".to_string())
+            })
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let bvecs: Vec<Vector3<f64>> = self.bvecs.iter().map(|v| rotate(&axes, v)).collect();
+        let row = |values: Vec<f64>| {
+            values
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        writeln!(bval, "{}", row(self.bvals.clone()))?;
+        for c in 0..3 {
+            writeln!(bvec, "{}", row(bvecs.iter().map(|v| v[c]).collect()))?;
+        }
+        Ok(())
+    }
+
+    /// Save FSL style `bval` and `bvec` files, e.g. alongside a resampled DWI
+    /// series with `affine`.
+    #[cfg(feature = "io")]
+    pub fn save_fsl<P, T>(&self, bval_path: P, bvec_path: P, affine: &Matrix4<T>) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    {
+        let mut bval = BufWriter::new(File::create(bval_path)?);
+        let mut bvec = BufWriter::new(File::create(bvec_path)?);
+        self.write_fsl(affine, &mut bval, &mut bvec)?;
+        bval.flush()?;
+        bvec.flush()
+    }
+}
+
+/// Rotation of the inverse of the linear part of `transform`.
+fn inverse_rotation<T>(transform: &Matrix4<T>) -> Result<Matrix3<f64>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+{
+    let (linear, _) = afftra_to_aff_tra(&transform.map(|x| x.as_()));
+    let inverse = linear
+        .try_inverse()
+        .ok_or("no valid matrix inverse found for the transform")?;
+    Ok(finite_strain_rotation(&inverse))
+}
+
+/// World directions of the FSL bvec axes of an image with `affine`: the voxel
+/// axes without their scaling, x flipped for a positive determinant.
+fn fsl_axes<T>(affine: &Matrix4<T>) -> Result<Matrix3<f64>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+{
+    let (linear, _) = afftra_to_aff_tra(&affine.map(|x| x.as_()));
+    let mut axes = linear;
+    for mut axis in axes.column_iter_mut() {
+        let norm = axis.norm();
+        if norm == 0.0 {
+            return Err("the affine has a zero voxel size".into());
+        }
+        axis /= norm;
+    }
+    if linear.determinant() > 0.0 {
+        axes.column_mut(0).neg_mut();
+    }
+    Ok(axes)
+}
+
+fn rotate(rotation: &Matrix3<f64>, v: &Vector3<f64>) -> Vector3<f64> {
+    (rotation * v).try_normalize(1e-12).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registration::transform::rigid_matrix;
+    use approx::*;
+
+    #[test]
+    fn test_gradient_table() {
+        // a RAS image, the FSL x axis is flipped
+        let affine = Matrix4::<f64>::identity();
+        let table =
+            GradientTable::from_fsl("0 1000 1000\n", "0 1 0\n0 0 1\n0 0 0\n", &affine).unwrap();
+        assert_eq!(table.bvecs[1], -Vector3::x());
+
+        // the image is rotated by 90 degrees about z
+        let transform = rigid_matrix(
+            &[0.0, 0.0, -std::f64::consts::FRAC_PI_2, 0.0, 0.0, 0.0],
+            &Vector3::zeros(),
+        );
+        let rotated = table.rotate(&transform).unwrap();
+        assert_eq!(rotated.bvecs[0], Vector3::zeros());
+        assert_relative_eq!(rotated.bvecs[1], -Vector3::y(), epsilon = 1e-12);
+        assert_relative_eq!(rotated.bvecs[2], -Vector3::x(), epsilon = 1e-12);
+        let per_volume = table
+            .rotate_per_volume(&[Matrix4::identity(), transform, Matrix4::identity()])
+            .unwrap();
+        assert_relative_eq!(per_volume.bvecs[1], -Vector3::y(), epsilon = 1e-12);
+        assert_eq!(per_volume.bvecs[2], table.bvecs[2]);
+
+        let (mut bval, mut bvec) = (Vec::new(), Vec::new());
+        table.write_fsl(&affine, &mut bval, &mut bvec).unwrap();
+        let parsed = GradientTable::from_fsl(
+            &String::from_utf8(bval).unwrap(),
+            &String::from_utf8(bvec).unwrap(),
+            &affine,
+        )
+        .unwrap();
+        assert_eq!(parsed, table);
+        assert!(GradientTable::from_fsl("0 1000", "0 1\n0 0\n", &affine).is_err());
+    }
+
+    #[test]
+    fn test_fsl_voxel_convention() {
+        let (bvals, bvecs) = ("1000 1000\n", "1 0\n0 1\n0 0\n");
+
+        // the same bvec file describes the same world directions for LAS and
+        // RAS storage of a series
+        let las = Matrix4::new_nonuniform_scaling(&Vector3::new(-2.0, 2.0, 2.0));
+        let ras = Matrix4::new_scaling(2.0);
+        let from_las = GradientTable::from_fsl(bvals, bvecs, &las).unwrap();
+        assert_eq!(from_las.bvecs, [-Vector3::x(), Vector3::y()]);
+        assert_eq!(
+            GradientTable::from_fsl(bvals, bvecs, &ras).unwrap(),
+            from_las
+        );
+
+        // voxel axes rotated by 90 degrees about z: x along world y, y along -x
+        let rotated = Matrix4::new(
+            0.0, -2.0, 0.0, 0.0, //
+            2.0, 0.0, 0.0, 0.0, //
+            0.0, 0.0, 2.0, 0.0, //
+            0.0, 0.0, 0.0, 1.0,
+        );
+        let table = GradientTable::from_fsl(bvals, bvecs, &rotated).unwrap();
+        assert_relative_eq!(table.bvecs[0], -Vector3::y(), epsilon = 1e-12);
+        assert_relative_eq!(table.bvecs[1], -Vector3::x(), epsilon = 1e-12);
+
+        // written for the LAS series, the world directions read back as FSL axes
+        let (mut bval, mut bvec) = (Vec::new(), Vec::new());
+        table.write_fsl(&las, &mut bval, &mut bvec).unwrap();
+        assert_eq!(String::from_utf8(bvec).unwrap(), "0 1\n-1 0\n0 0\n");
+    }
+}
@@ -0,0 +1,3 @@
+// diffusion tensor imaging:
+pub mod gradients;
+pub mod tensor;
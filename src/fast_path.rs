@@ -0,0 +1,194 @@
+use crate::sampler::common::SamplingMode;
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use ndarray::Slice;
+
+/// Maximum deviation (in input voxels) of a compound affine from an integer
+/// voxel mapping to still be treated as one.
+const TOLERANCE: f64 = 1e-6;
+
+/// A mapping of output voxels to input voxels by whole voxels: the index
+/// along input axis `axes[d]` is `steps[d] * o[d] + offsets[d]` for output
+/// voxel `o`, with steps of ±1. This covers identities, integer shifts and
+/// axis permutations and flips as in reorientation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AxisMapping {
+    pub axes: [usize; 3],
+    pub steps: [isize; 3],
+    pub offsets: [isize; 3],
+}
+
+impl AxisMapping {
+    /// The mapping of a compound affine (output voxel to input voxel), if it
+    /// is one.
+    pub fn from_affine<T>(compound: &Matrix4<T>) -> Option<Self>
+    where
+        T: Scalar + RealField + Copy,
+    {
+        let m = compound.map(|x| nalgebra::try_convert::<T, f64>(x).unwrap_or(f64::NAN));
+        let close = |x: f64, y: f64| (x - y).abs() <= TOLERANCE;
+        let bottom = [m[(3, 0)], m[(3, 1)], m[(3, 2)], m[(3, 3)] - 1.0];
+        if !bottom.iter().all(|x| close(*x, 0.0)) {
+            return None;
+        }
+
+        let mut mapping = AxisMapping {
+            axes: [0; 3],
+            steps: [1; 3],
+            offsets: [0; 3],
+        };
+        let mut used = [false; 3];
+        for d in 0..3 {
+            let mut axis = None;
+            for r in 0..3 {
+                let x = m[(r, d)];
+                if close(x.abs(), 1.0) && axis.is_none() && !used[r] {
+                    axis = Some(r);
+                    mapping.steps[d] = x.signum() as isize;
+                } else if !close(x, 0.0) {
+                    return None;
+                }
+            }
+            let r = axis?;
+            used[r] = true;
+            mapping.axes[d] = r;
+            let t = m[(r, 3)];
+            if !close(t, t.round()) {
+                return None;
+            }
+            mapping.offsets[d] = t.round() as isize;
+        }
+        Some(mapping)
+    }
+}
+
+/// Copy the voxels of in_im (of 3 or more dimensions) according to
+/// `mapping`. Voxels without a source voxel are set to `cval` or, in
+/// [`SamplingMode::Nearest`], to the nearest border voxel, as a sampler
+/// would. The values are copied bit-identically.
+pub(crate) fn copy_aligned<U>(
+    in_im: &Array<U, IxDyn>,
+    mapping: &AxisMapping,
+    out_shape: &[usize; 3],
+    mode: SamplingMode,
+    cval: U,
+) -> Array<U, IxDyn>
+where
+    U: Clone,
+{
+    // view the input such that its first axes correspond to the output axes
+    let mut perm: Vec<usize> = mapping.axes.to_vec();
+    perm.extend(3..in_im.ndim());
+    let view = in_im.view().permuted_axes(IxDyn(&perm));
+    let source = |d: usize, o: isize| (mapping.steps[d] * o).saturating_add(mapping.offsets[d]);
+
+    if mode == SamplingMode::Nearest {
+        let mut out = view.to_owned();
+        for (d, len) in out_shape.iter().enumerate() {
+            let n = view.shape()[d] as isize;
+            let clamped: Vec<usize> = (0..*len as isize)
+                .map(|o| source(d, o).clamp(0, n - 1) as usize)
+                .collect();
+            out = out.select(Axis(d), &clamped);
+        }
+        return out;
+    }
+
+    let mut shape = view.shape().to_vec();
+    shape[..3].copy_from_slice(out_shape);
+    let mut out = Array::from_elem(shape, cval);
+
+    // range of output voxels with a source voxel per axis
+    let mut valid = [(0, 0); 3];
+    for (d, range) in valid.iter_mut().enumerate() {
+        let n = view.shape()[d] as isize;
+        let inside: Vec<isize> = (0..out_shape[d] as isize)
+            .filter(|o| (0..n).contains(&source(d, *o)))
+            .collect();
+        match (inside.first(), inside.last()) {
+            (Some(lo), Some(hi)) => *range = (*lo, *hi + 1),
+            _ => return out,
+        }
+    }
+    let src = view.slice_each_axis(|ax| match ax.axis.index() {
+        d if d < 3 => {
+            let (lo, hi) = valid[d];
+            let (a, b) = (source(d, lo), source(d, hi - 1));
+            Slice::new(a.min(b), Some(a.max(b) + 1), mapping.steps[d])
+        }
+        _ => Slice::from(..),
+    });
+    out.slice_each_axis_mut(|ax| match ax.axis.index() {
+        d if d < 3 => Slice::from(valid[d].0..valid[d].1),
+        _ => Slice::from(..),
+    })
+    .assign(&src);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector3;
+
+    #[test]
+    #[rustfmt::skip] // do not mangle manual matrix format
+    fn test_axis_mapping() {
+        let shift = Matrix4::new_translation(&Vector3::new(2.0, -1.0, 1e-9));
+        let mapping = AxisMapping::from_affine(&shift).unwrap();
+        assert_eq!(mapping.axes, [0, 1, 2]);
+        assert_eq!(mapping.offsets, [2, -1, 0]);
+
+        // output x runs along -y of the input, output y along z, z along x
+        let permutation = Matrix4::new(
+            0.0,  0.0, 1.0, 0.0,
+           -1.0,  0.0, 0.0, 3.0,
+            0.0,  1.0, 0.0, 0.0,
+            0.0,  0.0, 0.0, 1.0,
+        );
+        let mapping = AxisMapping::from_affine(&permutation).unwrap();
+        assert_eq!(mapping.axes, [1, 2, 0]);
+        assert_eq!(mapping.steps, [-1, 1, 1]);
+        assert_eq!(mapping.offsets, [3, 0, 0]);
+
+        let half = Matrix4::new_translation(&Vector3::new(0.5, 0.0, 0.0));
+        assert_eq!(AxisMapping::from_affine(&half), None);
+        assert_eq!(AxisMapping::from_affine(&Matrix4::new_scaling(2.0)), None);
+        let rotation = nalgebra::Rotation3::from_euler_angles(0.0, 0.0, 0.3).to_homogeneous();
+        assert_eq!(AxisMapping::from_affine(&rotation), None);
+    }
+
+    #[test]
+    fn test_copy_aligned() {
+        let im = Array::from_shape_fn(IxDyn(&[3, 2, 1, 2]), |idx| {
+            (idx[0] + 10 * idx[1] + 100 * idx[3]) as i32
+        });
+        let shift = AxisMapping {
+            axes: [0, 1, 2],
+            steps: [1; 3],
+            offsets: [1, 0, 0],
+        };
+        let shifted = copy_aligned(&im, &shift, &[3, 2, 1], SamplingMode::Constant, -1);
+        assert_eq!(shifted.shape(), &[3, 2, 1, 2]);
+        assert_eq!(shifted.slice(s![.., 0, 0, 1]).to_vec(), vec![101, 102, -1]);
+        let nearest = AxisMapping {
+            offsets: [-2, 0, 0],
+            ..shift
+        };
+        let nearest = copy_aligned(&im, &nearest, &[4, 2, 1], SamplingMode::Nearest, -1);
+        assert_eq!(nearest.slice(s![.., 0, 0, 0]).to_vec(), vec![0, 0, 0, 1]);
+
+        // swap x and y, flipping the new y
+        let swap = AxisMapping {
+            axes: [1, 0, 2],
+            steps: [1, -1, 1],
+            offsets: [0, 3, 0],
+        };
+        let swapped = copy_aligned(&im, &swap, &[2, 4, 1], SamplingMode::Constant, -1);
+        assert_eq!(swapped.shape(), &[2, 4, 1, 2]);
+        assert_eq!(
+            swapped.slice(s![1, .., 0, 0]).to_vec(),
+            vec![-1, 12, 11, 10]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use crate::trace::timed_span;
+use crate::{sanitize_im_shape, voxel_sizes};
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Gaussian smoothing with a standard deviation of `sigma` voxels per axis.
+///
+/// The separable kernel is truncated at 4 standard deviations and voxels
+/// outside of the volume are replaced by the nearest border voxel. A sigma of
+/// 0 leaves the respective axis unchanged.
+pub fn gaussian_filter<U>(
+    in_im: &Array<U, IxDyn>,
+    sigma: &[f64; 3],
+) -> Result<Array<f64, IxDyn>, String>
+where
+    U: AsPrimitive<f64>,
+{
+    timed_span!("gaussian_filter", shape = ?in_im.shape(), ?sigma);
+    if sigma.iter().any(|s| !s.is_finite() || *s < 0.0) {
+        return Err("sigma has to be finite and non-negative".into());
+    }
+    let in_im = sanitize_im_shape(in_im)?;
+    let mut im: Array<f64, IxDyn> = in_im.mapv(|x| x.as_());
+
+    let mut buffer = Vec::new();
+    for (axis, sigma) in sigma.iter().enumerate() {
+        if *sigma == 0.0 {
+            continue;
+        }
+        let kernel = gaussian_kernel(*sigma);
+        let radius = (kernel.len() / 2) as isize;
+        for mut lane in im.lanes_mut(Axis(axis)) {
+            buffer.clear();
+            buffer.extend(lane.iter().copied());
+            let n = buffer.len() as isize;
+            for (i, x) in lane.iter_mut().enumerate() {
+                *x = kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, w)| {
+                        let j = (i as isize + k as isize - radius).clamp(0, n - 1);
+                        w * buffer[j as usize]
+                    })
+                    .sum();
+            }
+        }
+    }
+    Ok(im)
+}
+
+/// Gaussian smoothing with a full width at half maximum of `fwhm` mm per
+/// axis, see [`gaussian_filter`].
+///
+/// The affine has to be in mm, see
+/// [`Units::affine_to_mm`](crate::header::Units::affine_to_mm) for images
+/// stored in other units.
+pub fn smooth_fwhm<T, U>(
+    in_im: &Array<U, IxDyn>,
+    affine: &Matrix4<T>,
+    fwhm: &[f64; 3],
+) -> Result<Array<f64, IxDyn>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+{
+    let sizes = voxel_sizes(affine);
+    let fwhm_per_sigma = (8.0 * 2f64.ln()).sqrt();
+    let mut sigma = [0.0; 3];
+    for d in 0..3 {
+        sigma[d] = fwhm[d] / fwhm_per_sigma / sizes[d].as_();
+    }
+    gaussian_filter(in_im, &sigma)
+}
+
+/// Normalized gaussian kernel truncated at 4 standard deviations.
+fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+    let radius = (4.0 * sigma).ceil() as isize;
+    let kernel: Vec<f64> = (-radius..=radius)
+        .map(|x| (-0.5 * (x as f64 / sigma).powi(2)).exp())
+        .collect();
+    let sum: f64 = kernel.iter().sum();
+    kernel.iter().map(|w| w / sum).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn test_gaussian_filter() {
+        let mut im = Array::zeros(IxDyn(&[21, 21, 21]));
+        im[[10, 10, 10]] = 1.0;
+        let smoothed = gaussian_filter(&im, &[1.5, 1.5, 0.0]).unwrap();
+
+        // mass is preserved and the impulse response is separable
+        assert_relative_eq!(smoothed.sum(), 1.0, epsilon = 1e-12);
+        let g = gaussian_kernel(1.5);
+        assert_relative_eq!(smoothed[[10, 11, 10]], g[6] * g[7], epsilon = 1e-12);
+        assert_eq!(smoothed[[10, 10, 11]], 0.0);
+
+        // half precision volumes are filtered without an up-casting copy
+        let half_im = im.mapv(half::f16::from_f64);
+        assert_eq!(
+            gaussian_filter(&half_im, &[1.5, 1.5, 0.0]).unwrap(),
+            smoothed
+        );
+    }
+
+    #[test]
+    fn test_smooth_fwhm() {
+        let mut im = Array::zeros(IxDyn(&[9, 9, 9]));
+        im[[4, 4, 4]] = 1.0;
+        let affine = Matrix4::new_nonuniform_scaling(&nalgebra::Vector3::new(2.0, 2.0, 2.0));
+        let fwhm = 3.0 * (8.0 * 2f64.ln()).sqrt();
+        let smoothed = smooth_fwhm(&im, &affine, &[fwhm, fwhm, 0.0]).unwrap();
+        let expected = gaussian_filter(&im, &[1.5, 1.5, 0.0]).unwrap();
+        assert_relative_eq!(smoothed, expected, epsilon = 1e-12);
+    }
+}
@@ -0,0 +1,36 @@
+use crate::{sanitize_im_shape, shape3};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Magnitude of the intensity gradient of in_im.
+///
+/// The gradient is approximated by central differences (one-sided at the
+/// image border) and scaled by `voxel_sizes`, i.e. it is given in intensity
+/// units per millimeter if the voxel sizes are given in millimeters.
+pub fn gradient_magnitude<U>(
+    in_im: &Array<U, IxDyn>,
+    voxel_sizes: &[f64; 3],
+) -> Result<Array<f64, IxDyn>, String>
+where
+    U: AsPrimitive<f64>,
+{
+    let in_im = sanitize_im_shape(in_im)?;
+    let im: Array<f64, IxDyn> = in_im.mapv(|x| x.as_());
+    let shape = shape3(&im);
+
+    Ok(Array::from_shape_fn(IxDyn(&shape), |idx| {
+        let mut sum_sq = 0.0;
+        for axis in 0..3 {
+            if shape[axis] < 2 {
+                continue;
+            }
+            let (mut lo, mut hi) = ([idx[0], idx[1], idx[2]], [idx[0], idx[1], idx[2]]);
+            lo[axis] = idx[axis].saturating_sub(1);
+            hi[axis] = (idx[axis] + 1).min(shape[axis] - 1);
+            let d = (im[IxDyn(&hi)] - im[IxDyn(&lo)])
+                / ((hi[axis] - lo[axis]) as f64 * voxel_sizes[axis]);
+            sum_sq += d * d;
+        }
+        sum_sq.sqrt()
+    }))
+}
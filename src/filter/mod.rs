@@ -0,0 +1,4 @@
+// filter implementations:
+pub mod gaussian;
+pub mod gradient;
+pub mod uniform;
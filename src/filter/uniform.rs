@@ -0,0 +1,42 @@
+use crate::sanitize_im_shape;
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Moving average over a box of `size` voxels (per axis) centered at each voxel.
+///
+/// Even sizes are rounded up to the next odd size. At the volume border the
+/// box is truncated, i.e. only voxels inside the volume are averaged.
+pub fn uniform_filter<U>(
+    in_im: &Array<U, IxDyn>,
+    size: &[usize; 3],
+) -> Result<Array<f64, IxDyn>, String>
+where
+    U: AsPrimitive<f64>,
+{
+    let in_im = sanitize_im_shape(in_im)?;
+    let mut im: Array<f64, IxDyn> = in_im.mapv(|x| x.as_());
+
+    let mut prefix = Vec::new();
+    for (axis, size) in size.iter().enumerate() {
+        let radius = size / 2;
+        if radius == 0 {
+            continue;
+        }
+        for mut lane in im.lanes_mut(Axis(axis)) {
+            prefix.clear();
+            prefix.push(0.0);
+            let mut acc = 0.0;
+            for x in lane.iter() {
+                acc += x;
+                prefix.push(acc);
+            }
+            let n = lane.len();
+            for (i, x) in lane.iter_mut().enumerate() {
+                let lo = i.saturating_sub(radius);
+                let hi = (i + radius + 1).min(n);
+                *x = (prefix[hi] - prefix[lo]) / (hi - lo) as f64;
+            }
+        }
+    }
+    Ok(im)
+}
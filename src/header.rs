@@ -0,0 +1,270 @@
+use crate::voxel_sizes;
+use nalgebra::{Matrix4, RealField, Scalar};
+use num_traits::AsPrimitive;
+
+/// Spatial unit of a NIFTI header (`xyzt_units & 0x07`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpatialUnit {
+    /// Not specified; treated as millimeters.
+    #[default]
+    Unknown,
+    Meter,
+    Millimeter,
+    Micron,
+}
+
+impl SpatialUnit {
+    /// NIFTI unit code.
+    pub fn code(self) -> u8 {
+        match self {
+            SpatialUnit::Unknown => 0,
+            SpatialUnit::Meter => 1,
+            SpatialUnit::Millimeter => 2,
+            SpatialUnit::Micron => 3,
+        }
+    }
+
+    /// Length of one unit in mm.
+    pub fn in_mm(self) -> f64 {
+        match self {
+            SpatialUnit::Unknown | SpatialUnit::Millimeter => 1.0,
+            SpatialUnit::Meter => 1000.0,
+            SpatialUnit::Micron => 0.001,
+        }
+    }
+}
+
+/// Temporal unit of a NIFTI header (`xyzt_units & 0x38`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TemporalUnit {
+    /// Not specified; treated as seconds.
+    #[default]
+    Unknown,
+    Second,
+    Millisecond,
+    Microsecond,
+    Hertz,
+    Ppm,
+    RadiansPerSecond,
+}
+
+impl TemporalUnit {
+    /// NIFTI unit code.
+    pub fn code(self) -> u8 {
+        match self {
+            TemporalUnit::Unknown => 0,
+            TemporalUnit::Second => 8,
+            TemporalUnit::Millisecond => 16,
+            TemporalUnit::Microsecond => 24,
+            TemporalUnit::Hertz => 32,
+            TemporalUnit::Ppm => 40,
+            TemporalUnit::RadiansPerSecond => 48,
+        }
+    }
+
+    /// Duration of one unit in seconds, `None` for the spectral units.
+    pub fn in_seconds(self) -> Option<f64> {
+        match self {
+            TemporalUnit::Unknown | TemporalUnit::Second => Some(1.0),
+            TemporalUnit::Millisecond => Some(1e-3),
+            TemporalUnit::Microsecond => Some(1e-6),
+            TemporalUnit::Hertz | TemporalUnit::Ppm | TemporalUnit::RadiansPerSecond => None,
+        }
+    }
+}
+
+/// The units of a NIFTI header as stored in its `xyzt_units` field.
+///
+/// All functions of this crate expect spacings and affines in mm and times
+/// in seconds; convert on read with [`Units::affine_to_mm`] and
+/// [`Units::tr_to_seconds`] and on write with [`Units::affine_from_mm`] and
+/// [`nifti_pixdim_with_units`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Units {
+    pub spatial: SpatialUnit,
+    pub temporal: TemporalUnit,
+}
+
+impl Units {
+    /// Millimeters and seconds.
+    pub const MM_SEC: Units = Units {
+        spatial: SpatialUnit::Millimeter,
+        temporal: TemporalUnit::Second,
+    };
+
+    /// Parse the `xyzt_units` header field.
+    pub fn from_xyzt(xyzt_units: u8) -> Result<Self, String> {
+        let spatial = match xyzt_units & 0x07 {
+            0 => SpatialUnit::Unknown,
+            1 => SpatialUnit::Meter,
+            2 => SpatialUnit::Millimeter,
+            3 => SpatialUnit::Micron,
+            code => return Err(format!("invalid spatial unit code {code}")),
+        };
+        let temporal = match xyzt_units & 0x38 {
+            0 => TemporalUnit::Unknown,
+            8 => TemporalUnit::Second,
+            16 => TemporalUnit::Millisecond,
+            24 => TemporalUnit::Microsecond,
+            32 => TemporalUnit::Hertz,
+            40 => TemporalUnit::Ppm,
+            48 => TemporalUnit::RadiansPerSecond,
+            code => return Err(format!("invalid temporal unit code {code}")),
+        };
+        Ok(Self { spatial, temporal })
+    }
+
+    /// The `xyzt_units` header field.
+    pub fn xyzt(self) -> u8 {
+        self.spatial.code() | self.temporal.code()
+    }
+
+    /// Scale an affine (voxel to world in the stored unit) to mm.
+    pub fn affine_to_mm<T>(self, affine: &Matrix4<T>) -> Matrix4<T>
+    where
+        T: Scalar + RealField + Copy,
+        f64: AsPrimitive<T>,
+    {
+        scale_world(affine, self.spatial.in_mm().as_())
+    }
+
+    /// Scale an affine in mm to the stored unit.
+    pub fn affine_from_mm<T>(self, affine: &Matrix4<T>) -> Matrix4<T>
+    where
+        T: Scalar + RealField + Copy,
+        f64: AsPrimitive<T>,
+    {
+        scale_world(affine, (1.0 / self.spatial.in_mm()).as_())
+    }
+
+    /// The repetition time (`pixdim[4]`) in seconds.
+    pub fn tr_to_seconds(self, tr: f64) -> Result<f64, String> {
+        self.temporal
+            .in_seconds()
+            .map(|s| tr * s)
+            .ok_or_else(|| "temporal unit is not a time".into())
+    }
+
+    /// A repetition time in seconds in the stored unit.
+    pub fn tr_from_seconds(self, tr: f64) -> Result<f64, String> {
+        self.temporal
+            .in_seconds()
+            .map(|s| tr / s)
+            .ok_or_else(|| "temporal unit is not a time".into())
+    }
+}
+
+/// Scale the world coordinates (rows 0 to 2) of an affine.
+fn scale_world<T>(affine: &Matrix4<T>, factor: T) -> Matrix4<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    let mut scaled = *affine;
+    for mut row in scaled.row_iter_mut().take(3) {
+        row *= factor;
+    }
+    scaled
+}
+
+/// The NIFTI `dim` header field of an image of the given shape: the number
+/// of dimensions followed by the size of each, unused entries set to 1.
+pub fn nifti_dim(shape: &[usize]) -> Result<[u16; 8], String> {
+    if shape.is_empty() || shape.len() > 7 {
+        return Err("NIFTI images have 1 to 7 dimensions".into());
+    }
+    let mut dim = [1u16; 8];
+    dim[0] = shape.len() as u16;
+    for (d, n) in dim[1..].iter_mut().zip(shape) {
+        *d = u16::try_from(*n).map_err(|_| "dimension too large for a NIFTI header")?;
+    }
+    Ok(dim)
+}
+
+/// The NIFTI `pixdim` header field for an affine and a repetition time `tr`
+/// (s, `pixdim[4]`): the qfac (sign of the affine determinant) followed by
+/// the voxel sizes. Further entries are set to 1.
+pub fn nifti_pixdim<T>(affine: &Matrix4<T>, tr: f64) -> [f32; 8]
+where
+    T: Scalar + RealField + AsPrimitive<f32> + Copy,
+{
+    let sizes = voxel_sizes(affine);
+    let mut pixdim = [1f32; 8];
+    pixdim[0] = if affine.fixed_slice::<3, 3>(0, 0).determinant() < T::zero() {
+        -1.0
+    } else {
+        1.0
+    };
+    for (p, s) in pixdim[1..4].iter_mut().zip(sizes.iter()) {
+        *p = s.as_();
+    }
+    pixdim[4] = tr as f32;
+    pixdim
+}
+
+/// The NIFTI `pixdim` header field for an affine in mm and a repetition time
+/// in seconds, stored in `units`, see [`nifti_pixdim`].
+pub fn nifti_pixdim_with_units<T>(
+    affine: &Matrix4<T>,
+    tr: f64,
+    units: Units,
+) -> Result<[f32; 8], String>
+where
+    T: Scalar + RealField + AsPrimitive<f32> + Copy,
+    f64: AsPrimitive<T>,
+{
+    Ok(nifti_pixdim(
+        &units.affine_from_mm(affine),
+        units.tr_from_seconds(tr)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn test_nifti_dim_pixdim() {
+        assert_eq!(
+            nifti_dim(&[64, 64, 30, 120]).unwrap(),
+            [4, 64, 64, 30, 120, 1, 1, 1]
+        );
+        assert!(nifti_dim(&[70_000]).is_err());
+
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(-2.0, 2.0, 3.0));
+        assert_eq!(
+            nifti_pixdim(&affine, 1.5),
+            [-1.0, 2.0, 2.0, 3.0, 1.5, 1.0, 1.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn test_units() {
+        // microns and milliseconds
+        let units = Units::from_xyzt(3 | 16).unwrap();
+        assert_eq!(units.spatial, SpatialUnit::Micron);
+        assert_eq!(units.temporal, TemporalUnit::Millisecond);
+        assert_eq!(units.xyzt(), 19);
+        assert_eq!(Units::from_xyzt(0).unwrap(), Units::default());
+        assert!(Units::from_xyzt(5).is_err());
+        assert!(Units::from_xyzt(2 | 56).is_err());
+        assert_eq!(
+            Units::from_xyzt(2 | 48).unwrap().temporal,
+            TemporalUnit::RadiansPerSecond
+        );
+
+        let stored = Matrix4::new_nonuniform_scaling(&Vector3::new(500.0, 500.0, 1000.0))
+            .append_translation(&Vector3::new(-1000.0, 0.0, 2000.0));
+        let mm = units.affine_to_mm(&stored);
+        assert_eq!(crate::voxel_sizes(&mm), Vector3::new(0.5, 0.5, 1.0));
+        assert_eq!(mm[(0, 3)], -1.0);
+        assert_eq!(mm[(3, 3)], 1.0);
+        assert!((units.affine_from_mm(&mm) - stored).abs().max() < 1e-9);
+
+        assert_eq!(units.tr_to_seconds(2000.0).unwrap(), 2.0);
+        let pixdim = nifti_pixdim_with_units(&mm, 2.0, units).unwrap();
+        assert_eq!(&pixdim[1..5], &[500.0, 500.0, 1000.0, 2000.0]);
+        let spectral = Units::from_xyzt(2 | 32).unwrap();
+        assert!(spectral.tr_to_seconds(1.0).is_err());
+    }
+}
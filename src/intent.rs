@@ -0,0 +1,286 @@
+use crate::sampler::label_trilinear::LabelTriLinear;
+use crate::sampler::nearest_neighbor::NearestNeighbor;
+use crate::sampler::traits::ReSample;
+use crate::sampler::trilinear::TriLinear;
+use crate::{resample_from_to, sanitize_im_shape};
+use nalgebra::{Matrix4, Scalar};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// The meaning of the voxel values of a NIFTI image, determined by its
+/// `intent_code` and `intent_name` header fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intent {
+    /// No intent, e.g. an anatomical image.
+    None,
+
+    /// A label map or parcellation (`NIFTI_INTENT_LABEL`,
+    /// `NIFTI_INTENT_NEURONAME`).
+    Label,
+
+    /// A statistical map, e.g. t, F or z statistics and p values (intent
+    /// codes 2 to 24). The code is retained.
+    Statistic(i16),
+
+    /// Parameter estimates (`NIFTI_INTENT_ESTIMATE`).
+    Estimate,
+
+    /// A displacement field (`NIFTI_INTENT_DISPVECT`), stored as a 5D vector
+    /// image (x, y, z, 1, 3).
+    Displacement,
+
+    /// A vector image (`NIFTI_INTENT_VECTOR`).
+    Vector,
+
+    /// Any other intent code.
+    Other(i16),
+}
+
+impl Intent {
+    /// Determine the intent from the header fields. Label maps written
+    /// without an intent code are recognized by an `intent_name` of
+    /// "label" or "labels".
+    pub fn from_header(intent_code: i16, intent_name: &str) -> Self {
+        match intent_code {
+            0 => match intent_name.trim().to_ascii_lowercase().as_str() {
+                "label" | "labels" => Intent::Label,
+                _ => Intent::None,
+            },
+            2..=24 => Intent::Statistic(intent_code),
+            1001 => Intent::Estimate,
+            1002 | 1003 => Intent::Label,
+            1006 => Intent::Displacement,
+            1007 => Intent::Vector,
+            code => Intent::Other(code),
+        }
+    }
+
+    /// Processing defaults matching the intent.
+    pub fn defaults(self) -> IntentDefaults {
+        match self {
+            Intent::Label => IntentDefaults {
+                interpolation: Interpolation::Label,
+                ..Default::default()
+            },
+            Intent::Statistic(_) | Intent::Estimate => IntentDefaults {
+                cval: f64::NAN,
+                nan_aware: true,
+                ..Default::default()
+            },
+            _ => IntentDefaults::default(),
+        }
+    }
+
+    /// Check that an image of the given shape is valid for the intent.
+    pub fn check_shape(self, shape: &[usize]) -> Result<(), String> {
+        match self {
+            Intent::Displacement if shape.len() != 5 || shape[3] != 1 || shape[4] != 3 => {
+                Err("displacement fields have to be of shape (x, y, z, 1, 3)".into())
+            }
+            Intent::Vector if shape.len() != 5 => {
+                Err("vector images have to be 5D (x, y, z, t, c)".into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Interpolation of a [`resample_by_intent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Nearest,
+    Linear,
+
+    /// One-hot trilinear interpolation of label maps, see [`LabelTriLinear`].
+    Label,
+}
+
+/// Processing defaults derived from an [`Intent`].
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntentDefaults {
+    pub interpolation: Interpolation,
+
+    /// Value of samples outside of the input field of view.
+    pub cval: f64,
+
+    /// Interpolate ignoring NaN voxels, which statistical maps commonly use to
+    /// mark voxels outside of the analysis mask: the weights of the finite
+    /// neighbors are renormalized and only samples without any finite
+    /// neighbor become NaN.
+    pub nan_aware: bool,
+}
+
+impl Default for IntentDefaults {
+    fn default() -> Self {
+        Self {
+            interpolation: Interpolation::Linear,
+            cval: 0.0,
+            nan_aware: false,
+        }
+    }
+}
+
+/// Explicit choices overriding the [`IntentDefaults`] of an intent.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Overrides {
+    pub interpolation: Option<Interpolation>,
+    pub cval: Option<f64>,
+    pub nan_aware: Option<bool>,
+}
+
+impl Overrides {
+    /// The defaults with all set overrides applied.
+    pub fn apply(&self, defaults: IntentDefaults) -> IntentDefaults {
+        IntentDefaults {
+            interpolation: self.interpolation.unwrap_or(defaults.interpolation),
+            cval: self.cval.unwrap_or(defaults.cval),
+            nan_aware: self.nan_aware.unwrap_or(defaults.nan_aware),
+        }
+    }
+}
+
+/// Resample in_im to the voxel space defined by out_shape and out_affine,
+/// see [`resample_from_to`], with the sampler and out of field of view
+/// handling chosen by the intent of the image unless overridden.
+///
+/// This prevents e.g. label maps from being interpolated trilinearly. The
+/// shape of the image is checked against the intent.
+pub fn resample_by_intent<T, U>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    out_shape: &[usize; 3],
+    out_affine: &Matrix4<T>,
+    intent: Intent,
+    overrides: &Overrides,
+) -> Result<Array<f64, IxDyn>, String>
+where
+    T: Scalar + AsPrimitive<f64>,
+    U: AsPrimitive<f64>,
+{
+    intent.check_shape(in_im.shape())?;
+    let params = overrides.apply(intent.defaults());
+    let in_im: Array<f64, IxDyn> = if in_im.ndim() > 3 {
+        in_im.mapv(|x| x.as_())
+    } else {
+        sanitize_im_shape(in_im)?.mapv(|x| x.as_())
+    };
+    let in_affine: Matrix4<f64> = in_affine.map(|x| x.as_());
+    let out_affine: Matrix4<f64> = out_affine.map(|x| x.as_());
+
+    let resample = |im: &Array<f64, IxDyn>, cval: f64| match params.interpolation {
+        Interpolation::Nearest => {
+            let mut sampler = NearestNeighbor::default();
+            ReSample::<f64, f64>::set_cval(&mut sampler, cval);
+            resample_from_to(im, &in_affine, out_shape, &out_affine, &sampler)
+        }
+        Interpolation::Linear => {
+            let mut sampler = TriLinear::default();
+            ReSample::<f64, f64>::set_cval(&mut sampler, cval);
+            resample_from_to(im, &in_affine, out_shape, &out_affine, &sampler)
+        }
+        Interpolation::Label => {
+            let mut sampler = LabelTriLinear::default();
+            ReSample::<f64, f64>::set_cval(&mut sampler, cval);
+            resample_from_to(im, &in_affine, out_shape, &out_affine, &sampler)
+        }
+    };
+
+    if !params.nan_aware || params.interpolation != Interpolation::Linear {
+        return resample(&in_im, params.cval);
+    }
+
+    // interpolate the finite values and their weights separately
+    let values = resample(&in_im.mapv(|x| if x.is_finite() { x } else { 0.0 }), 0.0)?;
+    let weights = resample(&in_im.mapv(|x| x.is_finite() as u8 as f64), 0.0)?;
+    let inside = resample(&Array::ones(in_im.raw_dim()), 0.0)?;
+    Ok(ndarray::Zip::from(&values)
+        .and(&weights)
+        .and(&inside)
+        .map_collect(|v, w, i| {
+            if *i <= 0.0 {
+                params.cval
+            } else if *w > 1e-12 {
+                v / w
+            } else {
+                f64::NAN
+            }
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn test_intent_defaults() {
+        assert_eq!(Intent::from_header(1002, ""), Intent::Label);
+        assert_eq!(Intent::from_header(0, "Labels"), Intent::Label);
+        assert_eq!(Intent::from_header(3, "t-stat"), Intent::Statistic(3));
+        assert_eq!(Intent::from_header(0, ""), Intent::None);
+        assert_eq!(Intent::Label.defaults().interpolation, Interpolation::Label);
+        assert!(Intent::Statistic(5).defaults().nan_aware);
+        assert!(Intent::Displacement.check_shape(&[4, 4, 4, 3]).is_err());
+        assert!(Intent::Displacement.check_shape(&[4, 4, 4, 1, 3]).is_ok());
+
+        let overrides = Overrides {
+            interpolation: Some(Interpolation::Nearest),
+            ..Default::default()
+        };
+        let params = overrides.apply(Intent::Label.defaults());
+        assert_eq!(params.interpolation, Interpolation::Nearest);
+        assert_eq!(params.cval, 0.0);
+    }
+
+    #[test]
+    fn test_resample_by_intent() {
+        let affine = Matrix4::<f64>::identity();
+        let out_affine = Matrix4::new_translation(&Vector3::new(0.5, 0.0, 0.0));
+
+        // labels are never blended
+        let labels = Array::from_shape_fn(IxDyn(&[2, 2, 2]), |idx| (idx[0] * 4) as u8);
+        let out = resample_by_intent(
+            &labels,
+            &affine,
+            &[2, 2, 2],
+            &out_affine,
+            Intent::Label,
+            &Overrides::default(),
+        )
+        .unwrap();
+        assert!(out.iter().all(|x| *x == 0.0 || *x == 4.0));
+
+        // NaN voxels do not spread into their neighbors
+        let mut stat = Array::from_shape_fn(IxDyn(&[4, 1, 1]), |idx| idx[0] as f64);
+        stat[[2, 0, 0]] = f64::NAN;
+        stat[[3, 0, 0]] = f64::NAN;
+        let out = resample_by_intent(
+            &stat,
+            &affine,
+            &[4, 1, 1],
+            &out_affine,
+            Intent::Statistic(3),
+            &Overrides::default(),
+        )
+        .unwrap();
+        assert_eq!(out[[0, 0, 0]], 0.5);
+        assert_eq!(out[[1, 0, 0]], 1.0);
+        assert!(out[[2, 0, 0]].is_nan() && out[[3, 0, 0]].is_nan());
+
+        let plain = resample_by_intent(
+            &stat,
+            &affine,
+            &[4, 1, 1],
+            &out_affine,
+            Intent::Statistic(3),
+            &Overrides {
+                nan_aware: Some(false),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(plain[[1, 0, 0]].is_nan());
+    }
+}
@@ -0,0 +1,276 @@
+use crate::sampler::traits::ReSample;
+use crate::trace::timed_span;
+use crate::{resample_with_transform, sanitize_im_shape, vox2out_vox};
+use nalgebra::{Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::{AsPrimitive, Num};
+use std::fmt::Display;
+
+/// An operation recorded by a [`LazyImage`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum LazyOp<T>
+where
+    T: Scalar,
+{
+    /// Permute and flip the voxel axes such that they are closest to the
+    /// world axes, in increasing order.
+    Reorient,
+
+    /// Crop (or pad, beyond the borders) to shape, starting at the voxel
+    /// index start.
+    Crop {
+        start: [isize; 3],
+        shape: [usize; 3],
+    },
+
+    /// Resample to the voxel space defined by shape and affine.
+    Resample {
+        shape: [usize; 3],
+        affine: Matrix4<T>,
+    },
+
+    /// Resample to a world aligned grid with the voxel sizes, covering the
+    /// current field of view, see [`resample_to_output`](crate::resample_to_output).
+    Spacing { voxel_sizes: [f32; 3] },
+
+    /// Move the image by a world space transform, mapping new world
+    /// coordinates to current world coordinates, see
+    /// [`resample_with_transform`].
+    Transform(Matrix4<T>),
+}
+
+/// Result of fusing the operations of a [`LazyImage`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fused<T>
+where
+    T: Scalar,
+{
+    /// Shape of the output grid.
+    pub shape: [usize; 3],
+
+    /// Affine of the output grid.
+    pub affine: Matrix4<T>,
+
+    /// Composite world space transform, mapping output world coordinates to
+    /// world coordinates of the source image.
+    pub transform: Matrix4<T>,
+}
+
+/// Lazily transformed image.
+///
+/// Reorientations, crops, resamplings and transforms are only recorded. On
+/// [`execute`](LazyImage::execute), they are fused into a single output grid
+/// and a single composite transform, such that the image is interpolated
+/// once, without the losses (and intermediate images) of interpolating after
+/// each operation. Operations which only permute, flip or shift the voxel
+/// grid also fuse into a bit-identical copy.
+#[derive(Debug, Clone)]
+pub struct LazyImage<'a, T, U>
+where
+    T: Scalar,
+{
+    image: &'a Array<U, IxDyn>,
+    affine: Matrix4<T>,
+    ops: Vec<LazyOp<T>>,
+}
+
+impl<'a, T, U> LazyImage<'a, T, U>
+where
+    T: Scalar + RealField + AsPrimitive<usize> + Copy + Display,
+    f32: AsPrimitive<T>,
+    isize: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    pub fn new(image: &'a Array<U, IxDyn>, affine: &Matrix4<T>) -> Self {
+        Self {
+            image,
+            affine: *affine,
+            ops: Vec::new(),
+        }
+    }
+
+    /// The operations recorded so far, in order.
+    pub fn ops(&self) -> &[LazyOp<T>] {
+        &self.ops
+    }
+
+    /// Record an operation.
+    pub fn then(mut self, op: LazyOp<T>) -> Self {
+        self.ops.push(op);
+        self
+    }
+
+    pub fn reorient(self) -> Self {
+        self.then(LazyOp::Reorient)
+    }
+
+    pub fn crop(self, start: [isize; 3], shape: [usize; 3]) -> Self {
+        self.then(LazyOp::Crop { start, shape })
+    }
+
+    pub fn resample(self, shape: [usize; 3], affine: Matrix4<T>) -> Self {
+        self.then(LazyOp::Resample { shape, affine })
+    }
+
+    pub fn spacing(self, voxel_sizes: [f32; 3]) -> Self {
+        self.then(LazyOp::Spacing { voxel_sizes })
+    }
+
+    pub fn transform(self, transform: Matrix4<T>) -> Self {
+        self.then(LazyOp::Transform(transform))
+    }
+
+    /// Fuse the operations into the output grid and the composite
+    /// transform, without touching the voxels.
+    pub fn fuse(&self) -> Result<Fused<T>, String> {
+        let shape = self.image.shape();
+        if !(2..=5).contains(&shape.len()) {
+            return Err("invalid shape".into());
+        }
+        let mut fused = Fused {
+            shape: [shape[0], shape[1], *shape.get(2).unwrap_or(&1)],
+            affine: self.affine,
+            transform: Matrix4::identity(),
+        };
+        for op in &self.ops {
+            match op {
+                LazyOp::Reorient => {
+                    (fused.shape, fused.affine) = reoriented(&fused.shape, &fused.affine)
+                }
+                LazyOp::Crop { start, shape } => {
+                    let start = Vector3::from(start.map(|s| s.as_()));
+                    fused.affine *= Matrix4::new_translation(&start);
+                    fused.shape = *shape;
+                }
+                LazyOp::Resample { shape, affine } => {
+                    fused.shape = *shape;
+                    fused.affine = *affine;
+                }
+                LazyOp::Spacing { voxel_sizes } => {
+                    let voxel_sizes = Vector3::from(voxel_sizes.map(|x| x.as_()));
+                    let (shape, affine) =
+                        vox2out_vox(&Vector3::from(fused.shape), &fused.affine, &voxel_sizes)?;
+                    fused.shape = shape.into();
+                    fused.affine = affine;
+                }
+                LazyOp::Transform(transform) => {
+                    // the grid stays, the image moves
+                    fused.transform *= transform;
+                }
+            }
+        }
+        if fused.shape.contains(&0) {
+            return Err("output shape has to be at least 1 along each axis".into());
+        }
+        Ok(fused)
+    }
+
+    /// Fuse the operations and resample the image once, see
+    /// [`resample_with_transform`]. Returns the image and its affine.
+    pub fn execute<S>(&self, sampler: &S) -> Result<(Array<U, IxDyn>, Matrix4<T>), String>
+    where
+        T: Num + AsPrimitive<U>,
+        U: Num + Copy + Send + Sync + 'static,
+        S: ReSample<T, U> + ?Sized + 'static,
+    {
+        timed_span!("lazy", n_ops = self.ops.len());
+        let fused = self.fuse()?;
+        let sanitized;
+        let image = if self.image.ndim() == 2 {
+            sanitized = sanitize_im_shape(self.image)?;
+            &sanitized
+        } else {
+            self.image
+        };
+        let out_im = resample_with_transform(
+            image,
+            &self.affine,
+            &fused.transform,
+            &fused.shape,
+            &fused.affine,
+            sampler,
+        )?;
+        Ok((out_im, fused.affine))
+    }
+}
+
+/// Grid with the voxel axes of shape and affine permuted and flipped to be
+/// closest to the world axes, in increasing order.
+fn reoriented<T>(shape: &[usize; 3], affine: &Matrix4<T>) -> ([usize; 3], Matrix4<T>)
+where
+    T: Scalar + RealField + Copy,
+    usize: AsPrimitive<T>,
+{
+    // assign voxel axes to world axes greedily, largest component first
+    let mut pairs: Vec<(usize, usize)> = itertools::iproduct!(0..3, 0..3).collect();
+    pairs.sort_by(|a, b| {
+        let (a, b) = (affine[*a].abs(), affine[*b].abs());
+        b.partial_cmp(&a).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let mut axes = [usize::MAX; 3];
+    let mut used = [false; 3];
+    for (world, voxel) in pairs {
+        if axes[world] == usize::MAX && !used[voxel] {
+            axes[world] = voxel;
+            used[voxel] = true;
+        }
+    }
+    // new voxel index -> old voxel index
+    let mut permutation = Matrix4::zeros();
+    permutation[(3, 3)] = T::one();
+    for (new, old) in axes.into_iter().enumerate() {
+        if affine[(new, old)] < T::zero() {
+            permutation[(old, new)] = -T::one();
+            permutation[(old, 3)] = (shape[old] - 1).as_();
+        } else {
+            permutation[(old, new)] = T::one();
+        }
+    }
+    (axes.map(|old| shape[old]), affine * permutation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registration::transform::rigid_matrix;
+    use crate::TriLinear;
+
+    #[test]
+    fn test_lazy_fusion() {
+        let image = Array::from_shape_fn(IxDyn(&[8, 6, 5]), |idx| {
+            ((idx[0] * 7 + idx[1] * 3 + idx[2] * 11) % 13) as f64
+        });
+        // flipped x, swapped y and z
+        #[rustfmt::skip]
+        let affine = Matrix4::new(
+            -2.0, 0.0, 0.0, 20.0,
+            0.0, 0.0, 1.5, -4.0,
+            0.0, 1.0, 0.0, 3.0,
+            0.0, 0.0, 0.0, 1.0,
+        );
+        let sampler = TriLinear::<f64>::default();
+
+        // reorientation and cropping copy voxels
+        let (out, out_affine) = LazyImage::new(&image, &affine)
+            .reorient()
+            .crop([1, 1, 0], [6, 4, 6])
+            .execute(&sampler)
+            .unwrap();
+        assert_eq!(out.shape(), &[6, 4, 6]);
+        let diag = Vector3::new(out_affine[(0, 0)], out_affine[(1, 1)], out_affine[(2, 2)]);
+        assert_eq!(diag, Vector3::new(2.0, 1.5, 1.0));
+        assert_eq!(out[[0, 0, 0]], image[[6, 0, 1]]);
+        assert_eq!(out[[5, 3, 4]], image[[1, 4, 4]]);
+
+        // a transform and its inverse cancel exactly, instead of blurring
+        let rotation = rigid_matrix(&[0.3, -0.2, 0.5, 1.0, 2.0, 0.0], &Vector3::zeros());
+        let lazy = LazyImage::new(&image, &affine)
+            .transform(rotation)
+            .spacing([2.0, 1.5, 1.0])
+            .transform(rotation.try_inverse().unwrap())
+            .resample([8, 6, 5], affine);
+        let fused = lazy.fuse().unwrap();
+        assert!((fused.transform - Matrix4::identity()).abs().max() < 1e-12);
+        assert_eq!(lazy.execute(&sampler).unwrap().0, image);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{afftra_to_aff_tra, sanitize_im_shape};
+use nalgebra::{Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+use std::collections::BTreeMap;
+
+/// Statistics of a single label, see [`label_stats`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelStats {
+    /// The label value.
+    pub label: usize,
+
+    /// Number of voxels carrying the label.
+    pub voxel_count: usize,
+
+    /// Volume in mm³ (assuming the affine is given in mm).
+    pub volume: f64,
+
+    /// Centroid in world coordinates.
+    pub centroid: Vector3<f64>,
+
+    /// Lower corner of the bounding box (voxel indices, inclusive).
+    pub bbox_min: [usize; 3],
+
+    /// Upper corner of the bounding box (voxel indices, inclusive).
+    pub bbox_max: [usize; 3],
+
+    /// Mean intensity.
+    pub mean: f64,
+
+    /// Standard deviation of the intensity.
+    pub std: f64,
+
+    /// Minimum intensity.
+    pub min: f64,
+
+    /// Maximum intensity.
+    pub max: f64,
+}
+
+struct Accumulator {
+    count: usize,
+    idx_sum: [f64; 3],
+    bbox_min: [usize; 3],
+    bbox_max: [usize; 3],
+    sum: f64,
+    sum_sq: f64,
+    min: f64,
+    max: f64,
+}
+
+/// Compute statistics for each non-zero label of label_img.
+///
+/// The intensities are taken from intensity_img, which has to be on the same
+/// grid as label_img. The result is sorted by label. This is the equivalent of
+/// `fslstats -K`.
+pub fn label_stats<T, U>(
+    label_img: &Array<usize, IxDyn>,
+    intensity_img: &Array<U, IxDyn>,
+    affine: &Matrix4<T>,
+) -> Result<Vec<LabelStats>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+{
+    let label_img = sanitize_im_shape(label_img)?;
+    let intensity_img = sanitize_im_shape(intensity_img)?;
+    if label_img.shape() != intensity_img.shape() {
+        return Err("label image and intensity image shapes do not match".into());
+    }
+
+    let affine: Matrix4<f64> = affine.map(|x| x.as_());
+    let (aff, _) = afftra_to_aff_tra(&affine);
+    let voxel_volume = aff.determinant().abs();
+
+    let mut acc: BTreeMap<usize, Accumulator> = BTreeMap::new();
+    for ((idx, label), val) in label_img.indexed_iter().zip(intensity_img.iter()) {
+        if *label == 0 {
+            continue;
+        }
+        let idx = [idx[0], idx[1], idx[2]];
+        let val: f64 = val.as_();
+        let a = acc.entry(*label).or_insert(Accumulator {
+            count: 0,
+            idx_sum: [0.0; 3],
+            bbox_min: idx,
+            bbox_max: idx,
+            sum: 0.0,
+            sum_sq: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        });
+        a.count += 1;
+        for (i, x) in idx.iter().enumerate() {
+            a.idx_sum[i] += *x as f64;
+            a.bbox_min[i] = a.bbox_min[i].min(*x);
+            a.bbox_max[i] = a.bbox_max[i].max(*x);
+        }
+        a.sum += val;
+        a.sum_sq += val * val;
+        a.min = a.min.min(val);
+        a.max = a.max.max(val);
+    }
+
+    Ok(acc
+        .into_iter()
+        .map(|(label, a)| {
+            let n = a.count as f64;
+            let mean = a.sum / n;
+            let centroid = Vector3::from(a.idx_sum.map(|x| x / n));
+            let centroid = (affine * centroid.push(1.0)).xyz();
+            LabelStats {
+                label,
+                voxel_count: a.count,
+                volume: n * voxel_volume,
+                centroid,
+                bbox_min: a.bbox_min,
+                bbox_max: a.bbox_max,
+                mean,
+                std: (a.sum_sq / n - mean * mean).max(0.0).sqrt(),
+                min: a.min,
+                max: a.max,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    #[rustfmt::skip] // do not mangle manual matrix format
+    fn test_label_stats() {
+        let labels = Array::from_shape_fn(IxDyn(&[4, 4, 4]), |idx| {
+            if idx[0] < 2 && idx[1] < 2 && idx[2] < 2 { 3 } else { 0 }
+        });
+        let intensities = Array::from_shape_fn(IxDyn(&[4, 4, 4]), |idx| idx[0] as f32);
+        let affine: Matrix4<f32> = Matrix4::from_row_slice(&[
+            2.0, 0.0, 0.0, 10.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, -5.0,
+            0.0, 0.0, 0.0, 1.0,
+        ]);
+        let stats = label_stats(&labels, &intensities, &affine).unwrap();
+        assert_eq!(stats.len(), 1);
+        let s = &stats[0];
+        assert_eq!((s.label, s.voxel_count), (3, 8));
+        assert_relative_eq!(s.volume, 16.0);
+        assert_relative_eq!(s.centroid, Vector3::new(11.0, 0.5, -4.5));
+        assert_eq!((s.bbox_min, s.bbox_max), ([0, 0, 0], [1, 1, 1]));
+        assert_relative_eq!(s.mean, 0.5);
+        assert_relative_eq!(s.std, 0.5);
+        assert_eq!((s.min, s.max), (0.0, 1.0));
+    }
+}
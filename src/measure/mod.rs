@@ -0,0 +1,4 @@
+// measurement implementations:
+pub mod label_stats;
+pub mod region_props;
+pub mod stats;
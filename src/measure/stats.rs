@@ -0,0 +1,187 @@
+use crate::{afftra_to_aff_tra, percentile_sorted, sanitize_im_shape, sanitize_mask};
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Parameters of the image [`stats`].
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatsParams {
+    /// Percentiles (in [0, 100]) to compute.
+    pub percentiles: Vec<f64>,
+
+    /// Number of bins of the intensity histogram between min and max.
+    pub n_bins: usize,
+}
+
+impl Default for StatsParams {
+    fn default() -> Self {
+        Self {
+            percentiles: vec![2.0, 5.0, 25.0, 75.0, 95.0, 98.0],
+            n_bins: 64,
+        }
+    }
+}
+
+/// An intensity histogram with equally wide bins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// Bin edges, one more than there are bins. The last bin includes its
+    /// upper edge.
+    pub edges: Vec<f64>,
+
+    /// Number of voxels per bin.
+    pub counts: Vec<usize>,
+}
+
+/// Summary statistics of an image, see [`stats`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageStats {
+    /// Number of (masked, finite) voxels the statistics are computed over.
+    pub voxel_count: usize,
+
+    /// Mean intensity.
+    pub mean: f64,
+
+    /// Sample standard deviation of the intensity.
+    pub std: f64,
+
+    /// Median intensity.
+    pub median: f64,
+
+    /// The requested percentiles as (percentile, value).
+    pub percentiles: Vec<(f64, f64)>,
+
+    /// Minimum intensity.
+    pub min: f64,
+
+    /// Maximum intensity.
+    pub max: f64,
+
+    /// Number of voxels with a non-zero intensity.
+    pub nonzero_count: usize,
+
+    /// Volume of the voxels with a non-zero intensity in mm³ (assuming the
+    /// affine is given in mm).
+    pub nonzero_volume: f64,
+
+    pub histogram: Histogram,
+}
+
+/// Summary statistics of the voxels of a 3D image within the optional mask,
+/// the numbers of a typical QC report (like `fslstats -m -s -p 50 -R -V`).
+///
+/// Non-finite voxels are ignored.
+pub fn stats<T, U>(
+    in_im: &Array<U, IxDyn>,
+    affine: &Matrix4<T>,
+    mask: Option<&Array<bool, IxDyn>>,
+    params: &StatsParams,
+) -> Result<ImageStats, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+{
+    let in_im = sanitize_im_shape(in_im)?;
+    let mask = sanitize_mask(mask, in_im.shape())?;
+    if params.n_bins == 0 {
+        return Err("histogram requires at least one bin".into());
+    }
+    if params
+        .percentiles
+        .iter()
+        .any(|q| !(0.0..=100.0).contains(q))
+    {
+        return Err("percentiles have to be within [0, 100]".into());
+    }
+
+    let mut values = Vec::new();
+    let (mut sum, mut nonzero_count) = (0.0, 0);
+    for (idx, x) in in_im.indexed_iter() {
+        let x: f64 = x.as_();
+        if x.is_finite() && mask.as_ref().map_or(true, |m| m[idx.slice()]) {
+            sum += x;
+            nonzero_count += (x != 0.0) as usize;
+            values.push(x);
+        }
+    }
+    if values.is_empty() {
+        return Err("no voxels to compute statistics over".into());
+    }
+    values.sort_unstable_by(f64::total_cmp);
+
+    let n = values.len() as f64;
+    let mean = sum / n;
+    let std = if values.len() > 1 {
+        (values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+    } else {
+        0.0
+    };
+    let (min, max) = (values[0], values[values.len() - 1]);
+
+    let width = (max - min) / params.n_bins as f64;
+    let edges = (0..=params.n_bins)
+        .map(|i| min + i as f64 * width)
+        .collect();
+    let mut counts = vec![0; params.n_bins];
+    for x in &values {
+        let bin = if width > 0.0 {
+            (((x - min) / width) as usize).min(params.n_bins - 1)
+        } else {
+            0
+        };
+        counts[bin] += 1;
+    }
+
+    let affine: Matrix4<f64> = affine.map(|x| x.as_());
+    let voxel_volume = afftra_to_aff_tra(&affine).0.determinant().abs();
+    Ok(ImageStats {
+        voxel_count: values.len(),
+        mean,
+        std,
+        median: percentile_sorted(&values, 50.0),
+        percentiles: params
+            .percentiles
+            .iter()
+            .map(|q| (*q, percentile_sorted(&values, *q)))
+            .collect(),
+        min,
+        max,
+        nonzero_count,
+        nonzero_volume: nonzero_count as f64 * voxel_volume,
+        histogram: Histogram { edges, counts },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn test_stats() {
+        let im =
+            Array::from_shape_vec(IxDyn(&[5, 1, 2]), vec![0, 9, 1, 9, 2, 9, 3, 9, 4, 9]).unwrap();
+        let mask = im.mapv(|x| x != 9);
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 1.0, 1.5));
+        let params = StatsParams {
+            percentiles: vec![25.0],
+            n_bins: 2,
+        };
+        let s = stats(&im, &affine, Some(&mask), &params).unwrap();
+
+        assert_eq!(s.voxel_count, 5);
+        assert_eq!((s.mean, s.median, s.min, s.max), (2.0, 2.0, 0.0, 4.0));
+        assert_relative_eq!(s.std, 2.5f64.sqrt());
+        assert_eq!(s.percentiles, vec![(25.0, 1.0)]);
+        assert_eq!(s.nonzero_count, 4);
+        assert_relative_eq!(s.nonzero_volume, 12.0);
+        assert_eq!(s.histogram.edges, vec![0.0, 2.0, 4.0]);
+        assert_eq!(s.histogram.counts, vec![2, 3]);
+
+        let unmasked = stats(&im, &affine, None, &params).unwrap();
+        assert_eq!(unmasked.voxel_count, 10);
+        assert!(stats(&im, &affine, Some(&im.mapv(|_| false)), &params).is_err());
+    }
+}
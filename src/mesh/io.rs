@@ -0,0 +1,180 @@
+use super::Mesh;
+#[cfg(feature = "io")]
+use std::fs::File;
+#[cfg(feature = "io")]
+use std::io::BufWriter;
+use std::io::{self, Write};
+#[cfg(feature = "io")]
+use std::path::Path;
+
+/// Write a mesh as binary STL.
+///
+/// STL stores coordinates without units; they are written in mm.
+pub fn write_stl<W: Write>(mesh: &Mesh, writer: &mut W) -> io::Result<()> {
+    let mut header = [0u8; 80];
+    let text = b"binary STL written by nifti_processing";
+    header[..text.len()].copy_from_slice(text);
+    writer.write_all(&header)?;
+    let n_faces = u32::try_from(mesh.faces.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many faces for STL"))?;
+    writer.write_all(&n_faces.to_le_bytes())?;
+    for face in &mesh.faces {
+        let normal = mesh
+            .face_normal(face)
+            .try_normalize(0.0)
+            .unwrap_or_default();
+        let points = face.iter().map(|i| &mesh.vertices[*i]);
+        for p in std::iter::once(&normal).chain(points) {
+            for x in p.iter() {
+                writer.write_all(&(*x as f32).to_le_bytes())?;
+            }
+        }
+        // attribute byte count
+        writer.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+/// Write a mesh as Wavefront OBJ.
+pub fn write_obj<W: Write>(mesh: &Mesh, writer: &mut W) -> io::Result<()> {
+    for v in &mesh.vertices {
+        writeln!(writer, "v {} {} {}", v.x, v.y, v.z)?;
+    }
+    for f in &mesh.faces {
+        // OBJ indices are 1-based
+        writeln!(writer, "f {} {} {}", f[0] + 1, f[1] + 1, f[2] + 1)?;
+    }
+    Ok(())
+}
+
+/// Write a mesh as GIFTI surface (`.surf.gii`) with ASCII encoded data
+/// arrays. The vertices are declared to be in scanner space.
+pub fn write_gifti<W: Write>(mesh: &Mesh, writer: &mut W) -> io::Result<()> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<!DOCTYPE GIFTI SYSTEM "http://www.nitrc.org/frs/download.php/115/gifti.dtd">"#
+    )?;
+    writeln!(writer, r#"<GIFTI Version="1.0" NumberOfDataArrays="2">"#)?;
+    writeln!(writer, "<MetaData/>\n<LabelTable/>")?;
+
+    write_data_array_start(
+        writer,
+        "NIFTI_INTENT_POINTSET",
+        "NIFTI_TYPE_FLOAT32",
+        mesh.vertices.len(),
+    )?;
+    writeln!(writer, "<CoordinateSystemTransformMatrix>")?;
+    writeln!(
+        writer,
+        "<DataSpace><![CDATA[NIFTI_XFORM_SCANNER_ANAT]]></DataSpace>"
+    )?;
+    writeln!(
+        writer,
+        "<TransformedSpace><![CDATA[NIFTI_XFORM_SCANNER_ANAT]]></TransformedSpace>"
+    )?;
+    writeln!(
+        writer,
+        "<MatrixData>1 0 0 0 0 1 0 0 0 0 1 0 0 0 0 1</MatrixData>"
+    )?;
+    writeln!(writer, "</CoordinateSystemTransformMatrix>")?;
+    writeln!(writer, "<Data>")?;
+    for v in &mesh.vertices {
+        writeln!(writer, "{} {} {}", v.x as f32, v.y as f32, v.z as f32)?;
+    }
+    writeln!(writer, "</Data>\n</DataArray>")?;
+
+    write_data_array_start(
+        writer,
+        "NIFTI_INTENT_TRIANGLE",
+        "NIFTI_TYPE_INT32",
+        mesh.faces.len(),
+    )?;
+    writeln!(writer, "<Data>")?;
+    for f in &mesh.faces {
+        writeln!(writer, "{} {} {}", f[0], f[1], f[2])?;
+    }
+    writeln!(writer, "</Data>\n</DataArray>")?;
+    writeln!(writer, "</GIFTI>")
+}
+
+fn write_data_array_start<W: Write>(
+    writer: &mut W,
+    intent: &str,
+    data_type: &str,
+    rows: usize,
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        r#"<DataArray Intent="{intent}" DataType="{data_type}" ArrayIndexingOrder="RowMajorOrder" Dimensionality="2" Dim0="{rows}" Dim1="3" Encoding="ASCII" Endian="LittleEndian" ExternalFileName="" ExternalFileOffset="">"#
+    )?;
+    writeln!(writer, "<MetaData/>")
+}
+
+/// Save a mesh to `path`, choosing the format from the file extension:
+/// `.stl`, `.obj` or `.gii` (e.g. `.surf.gii`).
+#[cfg(feature = "io")]
+pub fn save_mesh<P: AsRef<Path>>(mesh: &Mesh, path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let write = match extension.as_deref() {
+        Some("stl") => write_stl,
+        Some("obj") => write_obj,
+        Some("gii") => write_gifti,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "unknown mesh format, expected .stl, .obj or .gii",
+            ))
+        }
+    };
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(mesh, &mut writer)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector3;
+
+    fn tetrahedron() -> Mesh {
+        Mesh {
+            vertices: vec![Vector3::zeros(), Vector3::x(), Vector3::y(), Vector3::z()],
+            faces: vec![[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]],
+        }
+    }
+
+    #[test]
+    fn test_write_stl() {
+        let mut buffer = Vec::new();
+        write_stl(&tetrahedron(), &mut buffer).unwrap();
+        assert_eq!(buffer.len(), 84 + 4 * 50);
+        assert_eq!(buffer[80..84], 4u32.to_le_bytes());
+        // normal of the first face points along -z
+        let nz = f32::from_le_bytes(buffer[92..96].try_into().unwrap());
+        assert_eq!(nz, -1.0);
+    }
+
+    #[test]
+    fn test_write_obj_and_gifti() {
+        let mut buffer = Vec::new();
+        write_obj(&tetrahedron(), &mut buffer).unwrap();
+        let obj = String::from_utf8(buffer).unwrap();
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 4);
+        assert_eq!(obj.lines().last(), Some("f 2 3 4"));
+
+        let mut buffer = Vec::new();
+        write_gifti(&tetrahedron(), &mut buffer).unwrap();
+        let gifti = String::from_utf8(buffer).unwrap();
+        assert!(gifti.contains(r#"Intent="NIFTI_INTENT_TRIANGLE""#));
+        assert_eq!(gifti.matches(r#"Dim0="4""#).count(), 2);
+        assert!(gifti.trim_end().ends_with("</GIFTI>"));
+
+        #[cfg(feature = "io")]
+        assert!(save_mesh(&tetrahedron(), "mesh.ply").is_err());
+    }
+}
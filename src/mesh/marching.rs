@@ -0,0 +1,191 @@
+use super::Mesh;
+use crate::{sanitize_im_shape, shape3};
+use nalgebra::{Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+use std::collections::HashMap;
+
+/// Corners of the six tetrahedra each cube is split into. Every tetrahedron
+/// follows a path along the cube edges from corner (0, 0, 0) to (1, 1, 1)
+/// (Freudenthal / Kuhn triangulation), so that neighbouring cubes share the
+/// diagonals of their common faces.
+const TETRAHEDRA: [[[usize; 3]; 4]; 6] = [
+    [[0, 0, 0], [1, 0, 0], [1, 1, 0], [1, 1, 1]],
+    [[0, 0, 0], [1, 0, 0], [1, 0, 1], [1, 1, 1]],
+    [[0, 0, 0], [0, 1, 0], [1, 1, 0], [1, 1, 1]],
+    [[0, 0, 0], [0, 1, 0], [0, 1, 1], [1, 1, 1]],
+    [[0, 0, 0], [0, 0, 1], [1, 0, 1], [1, 1, 1]],
+    [[0, 0, 0], [0, 0, 1], [0, 1, 1], [1, 1, 1]],
+];
+
+/// Extract the isosurface of in_im at `iso` as a triangle mesh in world
+/// coordinates.
+///
+/// This is a marching cubes variant which splits each cube of 8 voxels into
+/// six tetrahedra, avoiding the ambiguous configurations of the classic case
+/// tables. The resulting surface is free of holes and separates voxels with
+/// values above `iso` (inside) from the remaining voxels. Vertices are placed
+/// by linear interpolation along the grid edges and shared between adjacent
+/// faces. Surfaces of objects touching the volume border are open there;
+/// cubes containing non-finite values are skipped.
+pub fn marching_cubes<T, U>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    iso: f64,
+) -> Result<Mesh, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+{
+    let im: Array<f64, IxDyn> = sanitize_im_shape(in_im)?.mapv(|x| x.as_());
+    let affine: Matrix4<f64> = in_affine.map(|x| x.as_());
+    let shape = shape3(&im);
+    let to_world = |p: Vector3<f64>| (affine * p.push(1.0)).xyz();
+    let flat = |p: &[usize; 3]| (p[0] * shape[1] + p[1]) * shape[2] + p[2];
+
+    let mut mesh = Mesh::default();
+    let mut edge_vertices: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut vertex = |a: [usize; 3], b: [usize; 3], mesh: &mut Mesh| -> usize {
+        let (fa, fb) = (flat(&a), flat(&b));
+        let key = (fa.min(fb), fa.max(fb));
+        *edge_vertices.entry(key).or_insert_with(|| {
+            let (va, vb) = (im[IxDyn(&a)], im[IxDyn(&b)]);
+            let t = (iso - va) / (vb - va);
+            let pa = Vector3::from(a.map(|x| x as f64));
+            let pb = Vector3::from(b.map(|x| x as f64));
+            mesh.vertices.push(to_world(pa + (pb - pa) * t));
+            mesh.vertices.len() - 1
+        })
+    };
+
+    for i in 0..shape[0].saturating_sub(1) {
+        for j in 0..shape[1].saturating_sub(1) {
+            for k in 0..shape[2].saturating_sub(1) {
+                for tetrahedron in &TETRAHEDRA {
+                    let corners = tetrahedron.map(|c| [i + c[0], j + c[1], k + c[2]]);
+                    let values = corners.map(|c| im[IxDyn(&c)]);
+                    if values.iter().any(|v| !v.is_finite()) {
+                        continue;
+                    }
+                    let (inside, outside): (Vec<usize>, Vec<usize>) =
+                        (0..4).partition(|n| values[*n] > iso);
+                    if inside.is_empty() || outside.is_empty() {
+                        continue;
+                    }
+
+                    // vertices on the edges between inside and outside corners
+                    let mut polygon = Vec::with_capacity(4);
+                    match (inside.len(), outside.len()) {
+                        (1, _) | (_, 1) => {
+                            let (apex, others) = if inside.len() == 1 {
+                                (inside[0], &outside)
+                            } else {
+                                (outside[0], &inside)
+                            };
+                            for o in others {
+                                polygon.push(vertex(corners[apex], corners[*o], &mut mesh));
+                            }
+                        }
+                        _ => {
+                            // quadrilateral, ordered around its boundary
+                            let (a, b) = (inside[0], inside[1]);
+                            let (c, d) = (outside[0], outside[1]);
+                            for (p, q) in [(a, c), (a, d), (b, d), (b, c)] {
+                                polygon.push(vertex(corners[p], corners[q], &mut mesh));
+                            }
+                        }
+                    }
+
+                    // orient the faces from the inside towards the outside
+                    let centroid = |ns: &[usize]| {
+                        ns.iter()
+                            .map(|n| to_world(Vector3::from(corners[*n].map(|x| x as f64))))
+                            .sum::<Vector3<f64>>()
+                            / ns.len() as f64
+                    };
+                    let direction = centroid(&outside) - centroid(&inside);
+                    for t in 1..polygon.len() - 1 {
+                        let mut face = [polygon[0], polygon[t], polygon[t + 1]];
+                        let normal = mesh.face_normal(&face);
+                        if normal.norm_squared() < 1e-24 {
+                            continue;
+                        }
+                        if normal.dot(&direction) < 0.0 {
+                            face.swap(1, 2);
+                        }
+                        mesh.faces.push(face);
+                    }
+                }
+            }
+        }
+    }
+    Ok(mesh)
+}
+
+/// Closed surface of a binary mask in world coordinates.
+///
+/// The mask is padded by one background voxel, so that objects touching the
+/// volume border are closed as well. The surface passes halfway between
+/// foreground and background voxel centers.
+pub fn mask_surface<T>(mask: &Array<bool, IxDyn>, affine: &Matrix4<T>) -> Result<Mesh, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+{
+    let mask = sanitize_im_shape(mask)?;
+    let shape = shape3(&mask);
+    let padded = Array::from_shape_fn(IxDyn(&shape.map(|n| n + 2)), |idx| {
+        let inside = (0..3).all(|i| idx[i] >= 1 && idx[i] <= shape[i]);
+        if inside && mask[[idx[0] - 1, idx[1] - 1, idx[2] - 1]] {
+            1.0
+        } else {
+            0.0
+        }
+    });
+    let affine: Matrix4<f64> = affine.map(|x| x.as_());
+    let padded_affine = affine * Matrix4::new_translation(&Vector3::new(-1.0, -1.0, -1.0));
+    marching_cubes(&padded, &padded_affine, 0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_mask_surface_sphere() {
+        let r = 6.0;
+        let mask = Array::from_shape_fn(IxDyn(&[15, 15, 15]), |idx| {
+            (0..3).map(|i| (idx[i] as f64 - 7.0).powi(2)).sum::<f64>() <= r * r
+        });
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 2.0, 1.0));
+        let mesh = mask_surface(&mask, &affine).unwrap();
+
+        // closed and consistently oriented: each directed edge occurs once
+        // and its reverse occurs as well
+        let mut edges = HashMap::new();
+        for f in &mesh.faces {
+            for e in 0..3 {
+                *edges.entry((f[e], f[(e + 1) % 3])).or_insert(0) += 1;
+            }
+        }
+        assert!(edges
+            .iter()
+            .all(|((a, b), n)| *n == 1 && edges.get(&(*b, *a)) == Some(&1)));
+
+        let voxel_volume = mask.iter().filter(|m| **m).count() as f64 * 2.0;
+        assert!((mesh.volume() - voxel_volume).abs() / voxel_volume < 0.1);
+        let sphere_volume = 4.0 / 3.0 * PI * r.powi(3) * 2.0;
+        assert!((mesh.volume() - sphere_volume).abs() / sphere_volume < 0.1);
+    }
+
+    #[test]
+    fn test_marching_cubes_plane() {
+        // linear ramp along x: the iso surface is the plane x = 2.5
+        let im = Array::from_shape_fn(IxDyn(&[6, 3, 3]), |idx| idx[0] as f64);
+        let mesh = marching_cubes(&im, &Matrix4::<f64>::identity(), 2.5).unwrap();
+        assert!(mesh.vertices.iter().all(|v| (v.x - 2.5).abs() < 1e-12));
+        assert!((mesh.surface_area() - 4.0).abs() < 1e-9);
+        // normals point towards lower values
+        assert!(mesh.faces.iter().all(|f| mesh.face_normal(f).x < 0.0));
+    }
+}
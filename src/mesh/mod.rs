@@ -0,0 +1,43 @@
+use nalgebra::Vector3;
+
+// mesh implementations:
+pub mod io;
+pub mod marching;
+
+/// A triangle mesh in world coordinates.
+///
+/// Faces are oriented counter-clockwise when viewed from outside, i.e. their
+/// normals point away from the enclosed volume.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mesh {
+    /// Vertex positions (mm).
+    pub vertices: Vec<Vector3<f64>>,
+
+    /// Vertex indices of each triangle.
+    pub faces: Vec<[usize; 3]>,
+}
+
+impl Mesh {
+    /// (Unnormalized) normal of a face, with a length of twice its area.
+    pub fn face_normal(&self, face: &[usize; 3]) -> Vector3<f64> {
+        let [a, b, c] = face.map(|i| self.vertices[i]);
+        (b - a).cross(&(c - a))
+    }
+
+    /// Total surface area (mm²).
+    pub fn surface_area(&self) -> f64 {
+        self.faces
+            .iter()
+            .map(|f| 0.5 * self.face_normal(f).norm())
+            .sum()
+    }
+
+    /// Enclosed volume (mm³), evaluated by the divergence theorem. Only
+    /// meaningful for closed meshes.
+    pub fn volume(&self) -> f64 {
+        self.faces
+            .iter()
+            .map(|f| self.vertices[f[0]].dot(&self.face_normal(f)) / 6.0)
+            .sum()
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{afftra_to_aff_tra, sanitize_im_shape};
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+use std::collections::BTreeMap;
+
+/// Label-vs-label confusion matrix of two segmentations, see
+/// [`confusion_matrix`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfusionMatrix {
+    /// All labels present in either segmentation (including 0), sorted.
+    pub labels: Vec<usize>,
+
+    /// Number of voxels with reference label `labels[i]` (row) and predicted
+    /// label `labels[j]` (column).
+    pub counts: Array2<usize>,
+
+    /// Volume of a single voxel (mm³).
+    pub voxel_volume: f64,
+}
+
+impl ConfusionMatrix {
+    /// Number of voxels labeled `reference` in the reference and `prediction`
+    /// in the prediction; 0 for labels not present in either.
+    pub fn count(&self, reference: usize, prediction: usize) -> usize {
+        match (self.index(reference), self.index(prediction)) {
+            (Some(i), Some(j)) => self.counts[[i, j]],
+            _ => 0,
+        }
+    }
+
+    /// Volume (mm³) labeled `reference` in the reference and `prediction` in
+    /// the prediction.
+    pub fn volume(&self, reference: usize, prediction: usize) -> f64 {
+        self.count(reference, prediction) as f64 * self.voxel_volume
+    }
+
+    /// Confusion matrix in mm³.
+    pub fn volumes(&self) -> Array2<f64> {
+        self.counts.mapv(|n| n as f64 * self.voxel_volume)
+    }
+
+    /// Row (and column) of `label`.
+    pub fn index(&self, label: usize) -> Option<usize> {
+        self.labels.binary_search(&label).ok()
+    }
+}
+
+/// Confusion matrix between a reference and a predicted label map on a
+/// common grid with the given affine.
+///
+/// Unlike the [`overlap`](super::overlap) metrics the background label 0 is
+/// included, so that e.g. `count(l, 0)` gives the voxels of label `l` missed
+/// by the prediction.
+pub fn confusion_matrix<T>(
+    reference: &Array<usize, IxDyn>,
+    prediction: &Array<usize, IxDyn>,
+    affine: &Matrix4<T>,
+) -> Result<ConfusionMatrix, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+{
+    let reference = sanitize_im_shape(reference)?;
+    let prediction = sanitize_im_shape(prediction)?;
+    if reference.shape() != prediction.shape() {
+        return Err("reference and prediction shapes do not match".into());
+    }
+
+    let mut pairs: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+    for (r, p) in reference.iter().zip(prediction.iter()) {
+        *pairs.entry((*r, *p)).or_insert(0) += 1;
+    }
+    let mut labels: Vec<usize> = pairs.keys().flat_map(|(r, p)| [*r, *p]).collect();
+    labels.sort_unstable();
+    labels.dedup();
+
+    let mut counts = Array2::zeros((labels.len(), labels.len()));
+    let index = |label: &usize| labels.binary_search(label).expect("label was collected");
+    for ((r, p), n) in &pairs {
+        counts[[index(r), index(p)]] = *n;
+    }
+
+    let (aff, _) = afftra_to_aff_tra(&affine.map(|x| x.as_()));
+    Ok(ConfusionMatrix {
+        labels,
+        counts,
+        voxel_volume: aff.determinant().abs(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn test_confusion_matrix() {
+        let reference = Array::from_shape_vec(IxDyn(&[6, 1, 1]), vec![0, 1, 1, 2, 2, 2]).unwrap();
+        let prediction = Array::from_shape_vec(IxDyn(&[6, 1, 1]), vec![0, 1, 2, 2, 2, 4]).unwrap();
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 2.0, 0.5));
+        let cm = confusion_matrix(&reference, &prediction, &affine).unwrap();
+
+        assert_eq!(cm.labels, vec![0, 1, 2, 4]);
+        assert_eq!(cm.counts.sum(), 6);
+        assert_eq!(cm.count(1, 2), 1);
+        assert_eq!(cm.count(2, 2), 2);
+        assert_eq!(cm.count(2, 4), 1);
+        assert_eq!(cm.count(4, 2), 0);
+        assert_eq!(cm.count(3, 3), 0);
+        assert_relative_eq!(cm.volume(2, 2), 2.0);
+        assert_relative_eq!(cm.volumes().sum(), 6.0);
+    }
+}
@@ -0,0 +1,27 @@
+use crate::sanitize_im_shape;
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+// metric implementations:
+pub mod confusion;
+pub mod overlap;
+pub mod quality;
+pub mod similarity;
+pub mod surface_distance;
+
+/// Sanitize two images which are compared voxel-wise and convert them to f64.
+pub(crate) fn sanitize_pair<U, V>(
+    a: &Array<U, IxDyn>,
+    b: &Array<V, IxDyn>,
+) -> Result<[Array<f64, IxDyn>; 2], String>
+where
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+{
+    let a = sanitize_im_shape(a)?;
+    let b = sanitize_im_shape(b)?;
+    if a.shape() != b.shape() {
+        return Err("image shapes do not match".into());
+    }
+    Ok([a.mapv(|x| x.as_()), b.mapv(|x| x.as_())])
+}
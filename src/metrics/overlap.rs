@@ -0,0 +1,264 @@
+use crate::sampler::nearest_neighbor::NearestNeighbor;
+use crate::{resample_from_to, same_grid, sanitize_im_shape, shape3};
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::float::FloatCore;
+use num_traits::AsPrimitive;
+use std::collections::BTreeSet;
+
+/// Overlap between a reference and a predicted segmentation.
+///
+/// Ratios with a zero denominator are defined as 1, e.g. the Dice coefficient
+/// of two empty segmentations is 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlapMetrics {
+    /// Dice coefficient `2·TP / (2·TP + FP + FN)`.
+    pub dice: f64,
+
+    /// Jaccard index / intersection over union `TP / (TP + FP + FN)`.
+    pub jaccard: f64,
+
+    /// Sensitivity / recall `TP / (TP + FN)`.
+    pub sensitivity: f64,
+
+    /// Precision / positive predictive value `TP / (TP + FP)`.
+    pub precision: f64,
+}
+
+impl OverlapMetrics {
+    fn from_counts(tp: usize, fp: usize, fn_: usize) -> Self {
+        let ratio = |num: usize, den: usize| {
+            if den == 0 {
+                1.0
+            } else {
+                num as f64 / den as f64
+            }
+        };
+        Self {
+            dice: ratio(2 * tp, 2 * tp + fp + fn_),
+            jaccard: ratio(tp, tp + fp + fn_),
+            sensitivity: ratio(tp, tp + fn_),
+            precision: ratio(tp, tp + fp),
+        }
+    }
+}
+
+/// Per-label and macro-averaged overlap, see [`label_overlap`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelOverlap {
+    /// Overlap of each label present in either segmentation, sorted by label.
+    pub per_label: Vec<(usize, OverlapMetrics)>,
+
+    /// Unweighted mean of the per-label metrics.
+    pub macro_average: OverlapMetrics,
+}
+
+/// Overlap between two binary segmentations on the same grid.
+pub fn binary_overlap(
+    reference: &Array<bool, IxDyn>,
+    prediction: &Array<bool, IxDyn>,
+) -> Result<OverlapMetrics, String> {
+    let reference = sanitize_im_shape(reference)?;
+    let prediction = sanitize_im_shape(prediction)?;
+    if reference.shape() != prediction.shape() {
+        return Err("reference and prediction shapes do not match".into());
+    }
+    let (mut tp, mut fp, mut fn_) = (0, 0, 0);
+    for (r, p) in reference.iter().zip(prediction.iter()) {
+        match (r, p) {
+            (true, true) => tp += 1,
+            (false, true) => fp += 1,
+            (true, false) => fn_ += 1,
+            (false, false) => (),
+        }
+    }
+    Ok(OverlapMetrics::from_counts(tp, fp, fn_))
+}
+
+/// Overlap of all non-zero labels between two label maps on the same grid.
+pub fn label_overlap(
+    reference: &Array<usize, IxDyn>,
+    prediction: &Array<usize, IxDyn>,
+) -> Result<LabelOverlap, String> {
+    let reference = sanitize_im_shape(reference)?;
+    let prediction = sanitize_im_shape(prediction)?;
+    if reference.shape() != prediction.shape() {
+        return Err("reference and prediction shapes do not match".into());
+    }
+
+    let labels: BTreeSet<usize> = reference
+        .iter()
+        .chain(prediction.iter())
+        .copied()
+        .filter(|l| *l != 0)
+        .collect();
+    let per_label: Vec<(usize, OverlapMetrics)> = labels
+        .into_iter()
+        .map(|label| {
+            let (mut tp, mut fp, mut fn_) = (0, 0, 0);
+            for (r, p) in reference.iter().zip(prediction.iter()) {
+                match (*r == label, *p == label) {
+                    (true, true) => tp += 1,
+                    (false, true) => fp += 1,
+                    (true, false) => fn_ += 1,
+                    (false, false) => (),
+                }
+            }
+            (label, OverlapMetrics::from_counts(tp, fp, fn_))
+        })
+        .collect();
+
+    let n = per_label.len().max(1) as f64;
+    let mean = |f: fn(&OverlapMetrics) -> f64| {
+        if per_label.is_empty() {
+            1.0
+        } else {
+            per_label.iter().map(|(_, m)| f(m)).sum::<f64>() / n
+        }
+    };
+    let macro_average = OverlapMetrics {
+        dice: mean(|m| m.dice),
+        jaccard: mean(|m| m.jaccard),
+        sensitivity: mean(|m| m.sensitivity),
+        precision: mean(|m| m.precision),
+    };
+    Ok(LabelOverlap {
+        per_label,
+        macro_average,
+    })
+}
+
+/// Overlap of two binary segmentations which may be defined on different
+/// grids, see [`label_overlap_resampled`].
+pub fn binary_overlap_resampled<T>(
+    reference: &Array<bool, IxDyn>,
+    reference_affine: &Matrix4<T>,
+    prediction: &Array<bool, IxDyn>,
+    prediction_affine: &Matrix4<T>,
+) -> Result<OverlapMetrics, String>
+where
+    T: Scalar + RealField + FloatCore + AsPrimitive<usize> + Copy,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    let reference = sanitize_im_shape(reference)?;
+    let prediction = sanitize_im_shape(prediction)?;
+    let tolerance: T = 1e-4f32.as_();
+    if same_grid(
+        reference.shape(),
+        reference_affine,
+        prediction.shape(),
+        prediction_affine,
+        tolerance,
+    ) {
+        return binary_overlap(&reference, &prediction);
+    }
+    let resampled = resample_from_to(
+        &prediction.mapv(|x| x as usize),
+        prediction_affine,
+        &shape3(&reference),
+        reference_affine,
+        &NearestNeighbor::<usize>::default(),
+    )?;
+    binary_overlap(&reference, &resampled.mapv(|x| x != 0))
+}
+
+/// Overlap of two label maps which may be defined on different grids.
+///
+/// If the grids differ, the prediction is resampled onto the reference grid
+/// with a nearest neighbor sampler before comparison.
+pub fn label_overlap_resampled<T>(
+    reference: &Array<usize, IxDyn>,
+    reference_affine: &Matrix4<T>,
+    prediction: &Array<usize, IxDyn>,
+    prediction_affine: &Matrix4<T>,
+) -> Result<LabelOverlap, String>
+where
+    T: Scalar + RealField + FloatCore + AsPrimitive<usize> + Copy,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    let reference = sanitize_im_shape(reference)?;
+    let prediction = sanitize_im_shape(prediction)?;
+    let tolerance: T = 1e-4f32.as_();
+    if same_grid(
+        reference.shape(),
+        reference_affine,
+        prediction.shape(),
+        prediction_affine,
+        tolerance,
+    ) {
+        return label_overlap(&reference, &prediction);
+    }
+    let resampled = resample_from_to(
+        &prediction,
+        prediction_affine,
+        &shape3(&reference),
+        reference_affine,
+        &NearestNeighbor::<usize>::default(),
+    )?;
+    label_overlap(&reference, &resampled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn test_binary_overlap() {
+        let reference = Array::from_shape_fn(IxDyn(&[4, 1, 1]), |idx| idx[0] < 2);
+        let prediction = Array::from_shape_fn(IxDyn(&[4, 1, 1]), |idx| idx[0] >= 1 && idx[0] < 4);
+        let m = binary_overlap(&reference, &prediction).unwrap();
+        assert_relative_eq!(m.dice, 0.4);
+        assert_relative_eq!(m.jaccard, 0.25);
+        assert_relative_eq!(m.sensitivity, 0.5);
+        assert_relative_eq!(m.precision, 1.0 / 3.0);
+    }
+
+    #[test]
+    fn test_binary_overlap_resampled() {
+        // the prediction covers the same half space on a 2 mm grid
+        let reference = Array::from_shape_fn(IxDyn(&[4, 4, 4]), |idx| idx[0] >= 2);
+        let prediction = Array::from_shape_fn(IxDyn(&[2, 2, 2]), |idx| idx[0] == 1);
+        let reference_affine = Matrix4::<f32>::identity();
+        let mut prediction_affine = Matrix4::<f32>::new_nonuniform_scaling(&[2.0, 2.0, 2.0].into());
+        prediction_affine[(0, 3)] = 0.5;
+        prediction_affine[(1, 3)] = 0.5;
+        prediction_affine[(2, 3)] = 0.5;
+
+        let m = binary_overlap_resampled(
+            &reference,
+            &reference_affine,
+            &prediction,
+            &prediction_affine,
+        )
+        .unwrap();
+        assert_relative_eq!(m.dice, 1.0);
+        let same =
+            binary_overlap_resampled(&reference, &reference_affine, &reference, &reference_affine)
+                .unwrap();
+        assert_eq!(same, binary_overlap(&reference, &reference).unwrap());
+    }
+
+    #[test]
+    fn test_label_overlap_resampled() {
+        let reference = Array::from_shape_fn(IxDyn(&[4, 4, 4]), |idx| idx[0] / 2 + 1);
+        let prediction = Array::from_shape_fn(IxDyn(&[2, 2, 2]), |idx| idx[0] + 1);
+        let reference_affine = Matrix4::<f32>::identity();
+        let mut prediction_affine = Matrix4::<f32>::new_nonuniform_scaling(&[2.0, 2.0, 2.0].into());
+        prediction_affine[(0, 3)] = 0.5;
+        prediction_affine[(1, 3)] = 0.5;
+        prediction_affine[(2, 3)] = 0.5;
+
+        let overlap = label_overlap_resampled(
+            &reference,
+            &reference_affine,
+            &prediction,
+            &prediction_affine,
+        )
+        .unwrap();
+        assert_eq!(overlap.per_label.len(), 2);
+        assert_relative_eq!(overlap.macro_average.dice, 1.0);
+    }
+}
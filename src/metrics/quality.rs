@@ -0,0 +1,150 @@
+use super::sanitize_pair;
+use crate::filter::uniform::uniform_filter;
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Parameters of the structural similarity index, see [`ssim`].
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ssim {
+    /// Edge length (voxels) of the cubic window used for the local statistics.
+    pub window: usize,
+
+    /// Dynamic range of the intensities. `None` uses the range of the
+    /// reference, which is an error for constant references.
+    pub data_range: Option<f64>,
+
+    /// Stabilization constant of the luminance term.
+    pub k1: f64,
+
+    /// Stabilization constant of the contrast / structure term.
+    pub k2: f64,
+}
+
+impl Default for Ssim {
+    fn default() -> Self {
+        Self {
+            window: 7,
+            data_range: None,
+            k1: 0.01,
+            k2: 0.03,
+        }
+    }
+}
+
+/// The given data range, or the intensity range of the reference. A constant
+/// reference has no range to derive the metrics from.
+fn data_range(reference: &Array<f64, IxDyn>, data_range: Option<f64>) -> Result<f64, String> {
+    if let Some(range) = data_range {
+        return Ok(range);
+    }
+    let (mn, mx) = reference
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(mn, mx), x| {
+            (mn.min(*x), mx.max(*x))
+        });
+    let range = mx - mn;
+    if range > 0.0 && range.is_finite() {
+        Ok(range)
+    } else {
+        Err("the reference has no intensity range, data_range has to be given".into())
+    }
+}
+
+/// Peak signal-to-noise ratio (dB) of test with respect to reference.
+///
+/// `data_range` defaults to the intensity range of the reference, which is
+/// an error for constant references. Identical images yield an infinite PSNR.
+pub fn psnr<U, V>(
+    reference: &Array<U, IxDyn>,
+    test: &Array<V, IxDyn>,
+    data_range: Option<f64>,
+) -> Result<f64, String>
+where
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+{
+    let [reference, test] = sanitize_pair(reference, test)?;
+    let range = self::data_range(&reference, data_range)?;
+    let mse = reference
+        .iter()
+        .zip(test.iter())
+        .map(|(r, t)| (r - t).powi(2))
+        .sum::<f64>()
+        / reference.len() as f64;
+    Ok(10.0 * (range * range / mse).log10())
+}
+
+/// Mean structural similarity index (SSIM) of test with respect to reference,
+/// together with the voxel-wise SSIM map.
+///
+/// The local statistics are computed over a cubic window, truncated at the
+/// volume border.
+pub fn ssim<U, V>(
+    reference: &Array<U, IxDyn>,
+    test: &Array<V, IxDyn>,
+    params: &Ssim,
+) -> Result<(f64, Array<f64, IxDyn>), String>
+where
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+{
+    let [x, y] = sanitize_pair(reference, test)?;
+    let range = data_range(&x, params.data_range)?;
+    let c1 = (params.k1 * range).powi(2);
+    let c2 = (params.k2 * range).powi(2);
+    let size = [params.window; 3];
+
+    let mu_x = uniform_filter(&x, &size)?;
+    let mu_y = uniform_filter(&y, &size)?;
+    let xx = uniform_filter(&(&x * &x), &size)?;
+    let yy = uniform_filter(&(&y * &y), &size)?;
+    let xy = uniform_filter(&(&x * &y), &size)?;
+
+    let mut map = Array::zeros(x.raw_dim());
+    ndarray::Zip::from(&mut map)
+        .and(&mu_x)
+        .and(&mu_y)
+        .and(&xx)
+        .and(&yy)
+        .and(&xy)
+        .for_each(|s, mx, my, xx, yy, xy| {
+            let var_x = xx - mx * mx;
+            let var_y = yy - my * my;
+            let cov = xy - mx * my;
+            *s = ((2.0 * mx * my + c1) * (2.0 * cov + c2))
+                / ((mx * mx + my * my + c1) * (var_x + var_y + c2));
+        });
+    let mean = map.mean().unwrap_or(f64::NAN);
+    Ok((mean, map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn test_psnr_ssim() {
+        let reference = Array::from_shape_fn(IxDyn(&[8, 8, 8]), |idx| {
+            ((idx[0] * 3 + idx[1] * 5 + idx[2] * 7) % 11) as f32
+        });
+        let (mean, _) = ssim(&reference, &reference, &Ssim::default()).unwrap();
+        assert_relative_eq!(mean, 1.0, epsilon = 1e-12);
+        assert!(psnr(&reference, &reference, None).unwrap().is_infinite());
+
+        let noisy = reference.mapv(|x| x + 1.0);
+        assert_relative_eq!(psnr(&reference, &noisy, Some(10.0)).unwrap(), 20.0);
+        let (mean, _) = ssim(&reference, &noisy, &Ssim::default()).unwrap();
+        assert!(mean < 1.0 && mean > 0.5);
+    }
+
+    #[test]
+    fn test_constant_reference() {
+        let reference = Array::from_elem(IxDyn(&[8, 8, 8]), 3.0f32);
+        let test = reference.mapv(|x| x + 1.0);
+        assert!(psnr(&reference, &test, None).is_err());
+        assert!(ssim(&reference, &reference, &Ssim::default()).is_err());
+        assert_relative_eq!(psnr(&reference, &test, Some(10.0)).unwrap(), 20.0);
+    }
+}
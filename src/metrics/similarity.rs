@@ -0,0 +1,233 @@
+use super::sanitize_pair;
+use crate::filter::uniform::uniform_filter;
+use crate::sanitize_mask;
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Collect the voxel pairs within mask, skipping pairs with non-finite values.
+fn masked_pairs<U, V>(
+    a: &Array<U, IxDyn>,
+    b: &Array<V, IxDyn>,
+    mask: Option<&Array<bool, IxDyn>>,
+) -> Result<Vec<(f64, f64)>, String>
+where
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+{
+    let [a, b] = sanitize_pair(a, b)?;
+    let mask = sanitize_mask(mask, a.shape())?;
+    let pairs: Vec<(f64, f64)> = match &mask {
+        Some(m) => a
+            .iter()
+            .zip(b.iter())
+            .zip(m.iter())
+            .filter(|(_, m)| **m)
+            .map(|(p, _)| (*p.0, *p.1))
+            .collect(),
+        None => a.iter().copied().zip(b.iter().copied()).collect(),
+    };
+    let pairs: Vec<(f64, f64)> = pairs
+        .into_iter()
+        .filter(|(x, y)| x.is_finite() && y.is_finite())
+        .collect();
+    if pairs.is_empty() {
+        return Err("no voxels to compare".into());
+    }
+    Ok(pairs)
+}
+
+/// Marginal and joint entropies (in nats) of the voxel pairs, estimated from
+/// a joint histogram with `bins` equally sized bins per image.
+fn entropies(pairs: &[(f64, f64)], bins: usize) -> Result<(f64, f64, f64), String> {
+    if bins == 0 {
+        return Err("number of bins has to be at least 1".into());
+    }
+    let range = |f: fn(&(f64, f64)) -> f64| {
+        pairs
+            .iter()
+            .map(f)
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(mn, mx), x| {
+                (mn.min(x), mx.max(x))
+            })
+    };
+    let (a_min, a_max) = range(|p| p.0);
+    let (b_min, b_max) = range(|p| p.1);
+    let bin = |x: f64, mn: f64, mx: f64| {
+        if mx > mn {
+            (((x - mn) / (mx - mn) * bins as f64) as usize).min(bins - 1)
+        } else {
+            0
+        }
+    };
+
+    let mut joint = vec![0.0; bins * bins];
+    for (x, y) in pairs {
+        joint[bin(*x, a_min, a_max) * bins + bin(*y, b_min, b_max)] += 1.0;
+    }
+    let n = pairs.len() as f64;
+    let mut p_a = vec![0.0; bins];
+    let mut p_b = vec![0.0; bins];
+    for i in 0..bins {
+        for j in 0..bins {
+            p_a[i] += joint[i * bins + j] / n;
+            p_b[j] += joint[i * bins + j] / n;
+        }
+    }
+    Ok((
+        entropy(p_a.into_iter()),
+        entropy(p_b.into_iter()),
+        entropy(joint.into_iter().map(|x| x / n)),
+    ))
+}
+
+fn entropy(p: impl Iterator<Item = f64>) -> f64 {
+    p.filter(|x| *x > 0.0).map(|x| -x * x.ln()).sum()
+}
+
+/// Mutual information (in nats) between two images on a common grid.
+pub fn mutual_information<U, V>(
+    a: &Array<U, IxDyn>,
+    b: &Array<V, IxDyn>,
+    bins: usize,
+    mask: Option<&Array<bool, IxDyn>>,
+) -> Result<f64, String>
+where
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+{
+    let (h_a, h_b, h_ab) = entropies(&masked_pairs(a, b, mask)?, bins)?;
+    Ok(h_a + h_b - h_ab)
+}
+
+/// Normalized mutual information `(H(A) + H(B)) / H(A, B)` (Studholme), which
+/// ranges from 1 (independent) to 2 (identical).
+pub fn normalized_mutual_information<U, V>(
+    a: &Array<U, IxDyn>,
+    b: &Array<V, IxDyn>,
+    bins: usize,
+    mask: Option<&Array<bool, IxDyn>>,
+) -> Result<f64, String>
+where
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+{
+    let (h_a, h_b, h_ab) = entropies(&masked_pairs(a, b, mask)?, bins)?;
+    if h_ab == 0.0 {
+        return Ok(2.0);
+    }
+    Ok((h_a + h_b) / h_ab)
+}
+
+/// Normalized cross-correlation (Pearson correlation) between two images.
+/// Returns 0 if either image is constant.
+pub fn normalized_cross_correlation<U, V>(
+    a: &Array<U, IxDyn>,
+    b: &Array<V, IxDyn>,
+    mask: Option<&Array<bool, IxDyn>>,
+) -> Result<f64, String>
+where
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+{
+    let pairs = masked_pairs(a, b, mask)?;
+    let n = pairs.len() as f64;
+    let mean_a = pairs.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_b = pairs.iter().map(|p| p.1).sum::<f64>() / n;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (x, y) in &pairs {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+    if var_a == 0.0 || var_b == 0.0 {
+        return Ok(0.0);
+    }
+    Ok(cov / (var_a * var_b).sqrt())
+}
+
+/// Local normalized cross-correlation: the mean (within mask) of the
+/// correlation computed over a cubic window of `window` voxels around each
+/// voxel. Windows with constant intensity contribute 0.
+pub fn local_normalized_cross_correlation<U, V>(
+    a: &Array<U, IxDyn>,
+    b: &Array<V, IxDyn>,
+    window: usize,
+    mask: Option<&Array<bool, IxDyn>>,
+) -> Result<f64, String>
+where
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+{
+    let [x, y] = sanitize_pair(a, b)?;
+    let mask = sanitize_mask(mask, x.shape())?;
+    let size = [window; 3];
+    let mu_x = uniform_filter(&x, &size)?;
+    let mu_y = uniform_filter(&y, &size)?;
+    let xx = uniform_filter(&(&x * &x), &size)?;
+    let yy = uniform_filter(&(&y * &y), &size)?;
+    let xy = uniform_filter(&(&x * &y), &size)?;
+
+    let mut local = Array::zeros(x.raw_dim());
+    ndarray::Zip::from(&mut local)
+        .and(&mu_x)
+        .and(&mu_y)
+        .and(&xx)
+        .and(&yy)
+        .and(&xy)
+        .for_each(|c, mx, my, xx, yy, xy| {
+            let var = (xx - mx * mx) * (yy - my * my);
+            *c = if var > 1e-12 {
+                (xy - mx * my) / var.sqrt()
+            } else {
+                0.0
+            };
+        });
+
+    let values: Vec<f64> = match &mask {
+        Some(m) => local
+            .iter()
+            .zip(m.iter())
+            .filter(|(_, m)| **m)
+            .map(|(c, _)| *c)
+            .collect(),
+        None => local.iter().copied().collect(),
+    };
+    if values.is_empty() {
+        return Err("no voxels to compare".into());
+    }
+    Ok(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn test_similarity_metrics() {
+        let a = Array::from_shape_fn(IxDyn(&[8, 8, 8]), |idx| {
+            ((idx[0] * 3 + idx[1] * 5 + idx[2] * 7) % 11) as f32
+        });
+        // an inverted intensity mapping is perfectly (anti-)correlated
+        let b = a.mapv(|x| 100.0 - 2.0 * x);
+
+        assert_relative_eq!(
+            normalized_cross_correlation(&a, &b, None).unwrap(),
+            -1.0,
+            epsilon = 1e-12
+        );
+        assert_relative_eq!(
+            normalized_mutual_information(&a, &b, 11, None).unwrap(),
+            2.0,
+            epsilon = 1e-12
+        );
+        let mi = mutual_information(&a, &b, 11, None).unwrap();
+        let mi_self = mutual_information(&a, &a, 11, None).unwrap();
+        assert_relative_eq!(mi, mi_self, epsilon = 1e-12);
+        let lncc = local_normalized_cross_correlation(&a, &a, 3, None).unwrap();
+        assert_relative_eq!(lncc, 1.0, epsilon = 1e-9);
+
+        let mask = Array::from_shape_fn(IxDyn(&[8, 8, 8]), |idx| idx[0] < 4);
+        assert!(normalized_cross_correlation(&a, &b, Some(&mask)).unwrap() < -0.99);
+    }
+}
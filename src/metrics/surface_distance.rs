@@ -0,0 +1,121 @@
+use crate::distance::euclidean_distance_transform;
+use crate::neighborhood::{offset_index, Connectivity};
+use crate::{percentile_sorted, sanitize_im_shape, shape3, voxel_sizes};
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Surface distances between two segmentations in mm, see [`surface_distances`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceDistances {
+    /// Maximum distance between the surfaces (Hausdorff distance).
+    pub hausdorff: f64,
+
+    /// 95th percentile of the distances pooled over both directions.
+    pub hausdorff_95: f64,
+
+    /// Mean of the two directed mean surface distances.
+    pub mean_surface_distance: f64,
+
+    /// Mean of the distances pooled over both directions (ASSD).
+    pub average_symmetric_surface_distance: f64,
+}
+
+/// Compute surface distance metrics between two binary segmentations.
+///
+/// The surface of a segmentation consists of its foreground voxels with at
+/// least one 6-connected background neighbor. Distances are computed with the
+/// spacing aware distance transform, hence they are given in the units of the
+/// affine (usually mm). Both segmentations have to be non-empty.
+pub fn surface_distances<T>(
+    reference: &Array<bool, IxDyn>,
+    prediction: &Array<bool, IxDyn>,
+    affine: &Matrix4<T>,
+) -> Result<SurfaceDistances, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+{
+    let reference = sanitize_im_shape(reference)?;
+    let prediction = sanitize_im_shape(prediction)?;
+    if reference.shape() != prediction.shape() {
+        return Err("reference and prediction shapes do not match".into());
+    }
+    let vs = voxel_sizes(affine);
+    let vs: [f64; 3] = [vs[0].as_(), vs[1].as_(), vs[2].as_()];
+
+    let surface_ref = surface(&reference);
+    let surface_pred = surface(&prediction);
+    if !surface_ref.iter().any(|x| *x) || !surface_pred.iter().any(|x| *x) {
+        return Err("surface distances are undefined for empty segmentations".into());
+    }
+
+    let d_ref_pred = directed_distances(&surface_ref, &surface_pred, &vs)?;
+    let d_pred_ref = directed_distances(&surface_pred, &surface_ref, &vs)?;
+    let mean = |d: &[f64]| d.iter().sum::<f64>() / d.len() as f64;
+
+    let mut pooled: Vec<f64> = d_ref_pred.iter().chain(&d_pred_ref).copied().collect();
+    pooled.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    Ok(SurfaceDistances {
+        hausdorff: *pooled.last().unwrap(),
+        hausdorff_95: percentile_sorted(&pooled, 95.0),
+        mean_surface_distance: 0.5 * (mean(&d_ref_pred) + mean(&d_pred_ref)),
+        average_symmetric_surface_distance: mean(&pooled),
+    })
+}
+
+/// Foreground voxels with a background voxel (or the volume border) as 6-neighbor.
+fn surface(mask: &Array<bool, IxDyn>) -> Array<bool, IxDyn> {
+    let shape = shape3(mask);
+    let offsets = Connectivity::Six.offsets();
+    Array::from_shape_fn(mask.raw_dim(), |idx| {
+        let idx = [idx[0], idx[1], idx[2]];
+        mask[IxDyn(&idx)]
+            && offsets
+                .iter()
+                .any(|o| offset_index(idx, o, &shape).map_or(true, |nb| !mask[IxDyn(&nb)]))
+    })
+}
+
+/// Distances from every `from` surface voxel to the nearest `to` surface voxel.
+fn directed_distances(
+    from: &Array<bool, IxDyn>,
+    to: &Array<bool, IxDyn>,
+    voxel_sizes: &[f64; 3],
+) -> Result<Vec<f64>, String> {
+    let distance = euclidean_distance_transform(&to.mapv(|x| !x), voxel_sizes)?;
+    Ok(distance
+        .iter()
+        .zip(from.iter())
+        .filter(|(_, f)| **f)
+        .map(|(d, _)| *d)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn test_surface_distances() {
+        let cube = |x0: usize| {
+            Array::from_shape_fn(IxDyn(&[10, 8, 8]), move |idx| {
+                (x0..x0 + 4).contains(&idx[0])
+                    && (2..6).contains(&idx[1])
+                    && (2..6).contains(&idx[2])
+            })
+        };
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(1.5, 1.0, 1.0));
+
+        let same = surface_distances(&cube(2), &cube(2), &affine).unwrap();
+        assert_relative_eq!(same.hausdorff, 0.0);
+        assert_relative_eq!(same.average_symmetric_surface_distance, 0.0);
+
+        let shifted = surface_distances(&cube(2), &cube(4), &affine).unwrap();
+        assert_relative_eq!(shifted.hausdorff, 3.0);
+        assert!(shifted.hausdorff_95 <= shifted.hausdorff);
+        assert!(shifted.mean_surface_distance > 0.0);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::distance::euclidean_distance_transform;
+use crate::neighborhood::{offset_index, Connectivity};
+use crate::{sanitize_im_shape, shape3};
+use ndarray::prelude::*;
+use std::collections::VecDeque;
+
+/// Binary dilation with a ball of `radius` (in the units of `voxel_sizes`).
+///
+/// The ball is evaluated exactly for anisotropic voxels by thresholding the
+/// distance transform.
+pub fn binary_dilation(
+    mask: &Array<bool, IxDyn>,
+    radius: f64,
+    voxel_sizes: &[f64; 3],
+) -> Result<Array<bool, IxDyn>, String> {
+    let background = mask.mapv(|x| !x);
+    let distance = euclidean_distance_transform(&background, voxel_sizes)?;
+    Ok(distance.mapv(|d| d <= radius))
+}
+
+/// Binary erosion with a ball of `radius` (in the units of `voxel_sizes`).
+///
+/// Voxels outside of the volume are treated as foreground, i.e. objects
+/// touching the border of the volume are not eroded from the border.
+pub fn binary_erosion(
+    mask: &Array<bool, IxDyn>,
+    radius: f64,
+    voxel_sizes: &[f64; 3],
+) -> Result<Array<bool, IxDyn>, String> {
+    let distance = euclidean_distance_transform(mask, voxel_sizes)?;
+    Ok(distance.mapv(|d| d > radius))
+}
+
+/// Binary opening (erosion followed by dilation) with a ball of `radius`.
+pub fn binary_opening(
+    mask: &Array<bool, IxDyn>,
+    radius: f64,
+    voxel_sizes: &[f64; 3],
+) -> Result<Array<bool, IxDyn>, String> {
+    binary_dilation(
+        &binary_erosion(mask, radius, voxel_sizes)?,
+        radius,
+        voxel_sizes,
+    )
+}
+
+/// Binary closing (dilation followed by erosion) with a ball of `radius`.
+pub fn binary_closing(
+    mask: &Array<bool, IxDyn>,
+    radius: f64,
+    voxel_sizes: &[f64; 3],
+) -> Result<Array<bool, IxDyn>, String> {
+    binary_erosion(
+        &binary_dilation(mask, radius, voxel_sizes)?,
+        radius,
+        voxel_sizes,
+    )
+}
+
+/// Fill all background regions which are not connected to the volume border.
+pub fn fill_holes(mask: &Array<bool, IxDyn>) -> Result<Array<bool, IxDyn>, String> {
+    let mask = sanitize_im_shape(mask)?;
+    let shape = shape3(&mask);
+    let offsets = Connectivity::Six.offsets();
+
+    // flood the background starting at the border
+    let mut outside = Array::from_elem(mask.raw_dim(), false);
+    let mut queue = VecDeque::new();
+    for (idx, m) in mask.indexed_iter() {
+        let on_border = (0..3).any(|i| idx[i] == 0 || idx[i] == shape[i] - 1);
+        if on_border && !m {
+            outside[&idx] = true;
+            queue.push_back([idx[0], idx[1], idx[2]]);
+        }
+    }
+    while let Some(idx) = queue.pop_front() {
+        for offset in &offsets {
+            if let Some(nb) = offset_index(idx, offset, &shape) {
+                if !mask[IxDyn(&nb)] && !outside[IxDyn(&nb)] {
+                    outside[IxDyn(&nb)] = true;
+                    queue.push_back(nb);
+                }
+            }
+        }
+    }
+    Ok(outside.mapv(|x| !x))
+}
+
+/// Label the connected components of a binary mask.
+///
+/// Returns the label image (background 0, components numbered from 1 in
+/// scan order) and the number of components.
+pub fn label_components(
+    mask: &Array<bool, IxDyn>,
+    connectivity: Connectivity,
+) -> Result<(Array<usize, IxDyn>, usize), String> {
+    let mask = sanitize_im_shape(mask)?;
+    let shape = shape3(&mask);
+    let offsets = connectivity.offsets();
+
+    let mut labels = Array::<usize, _>::zeros(mask.raw_dim());
+    let mut n_labels = 0;
+    let mut queue = VecDeque::new();
+    for (idx, m) in mask.indexed_iter() {
+        if !m || labels[&idx] != 0 {
+            continue;
+        }
+        n_labels += 1;
+        labels[&idx] = n_labels;
+        queue.push_back([idx[0], idx[1], idx[2]]);
+        while let Some(idx) = queue.pop_front() {
+            for offset in &offsets {
+                if let Some(nb) = offset_index(idx, offset, &shape) {
+                    if mask[IxDyn(&nb)] && labels[IxDyn(&nb)] == 0 {
+                        labels[IxDyn(&nb)] = n_labels;
+                        queue.push_back(nb);
+                    }
+                }
+            }
+        }
+    }
+    Ok((labels, n_labels))
+}
+
+/// Keep only the largest connected component of a binary mask.
+pub fn largest_component(
+    mask: &Array<bool, IxDyn>,
+    connectivity: Connectivity,
+) -> Result<Array<bool, IxDyn>, String> {
+    let (labels, n_labels) = label_components(mask, connectivity)?;
+    let mut counts = vec![0usize; n_labels + 1];
+    labels.iter().for_each(|l| counts[*l] += 1);
+    let largest = (1..=n_labels).max_by_key(|l| counts[*l]).unwrap_or(0);
+    Ok(labels.mapv(|l| l != 0 && l == largest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_dilation_erosion() {
+        let mut mask = Array::from_elem(IxDyn(&[5, 5, 5]), false);
+        mask[[2, 2, 2]] = true;
+        let dilated = binary_dilation(&mask, 1.0, &[1.0; 3]).unwrap();
+        assert_eq!(dilated.iter().filter(|x| **x).count(), 7);
+        let eroded = binary_erosion(&dilated, 1.0, &[1.0; 3]).unwrap();
+        assert_eq!(eroded, mask);
+    }
+
+    #[test]
+    fn test_fill_holes_and_components() {
+        // hollow cube and a separate voxel
+        let mut mask = Array::from_shape_fn(IxDyn(&[7, 5, 5]), |idx| {
+            (0..3).all(|i| (1..4).contains(&idx[i])) && idx != IxDyn(&[2, 2, 2])
+        });
+        mask[[5, 2, 2]] = true;
+        let filled = fill_holes(&mask).unwrap();
+        assert!(filled[[2, 2, 2]]);
+        assert_eq!(filled.iter().filter(|x| **x).count(), 28);
+
+        let (_, n) = label_components(&mask, Connectivity::Six).unwrap();
+        assert_eq!(n, 2);
+        let largest = largest_component(&mask, Connectivity::Six).unwrap();
+        assert!(!largest[[5, 2, 2]] && largest[[1, 1, 1]]);
+    }
+}
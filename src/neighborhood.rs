@@ -0,0 +1,67 @@
+/// Voxel connectivity used by neighborhood based operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Voxels sharing a face (6 neighbors).
+    Six,
+
+    /// Voxels sharing a face or an edge (18 neighbors).
+    Eighteen,
+
+    /// Voxels sharing a face, an edge or a corner (26 neighbors).
+    TwentySix,
+}
+
+impl Connectivity {
+    /// Relative voxel offsets of all neighbors, excluding the center voxel.
+    pub fn offsets(&self) -> Vec<[isize; 3]> {
+        let max_manhattan = match self {
+            Connectivity::Six => 1,
+            Connectivity::Eighteen => 2,
+            Connectivity::TwentySix => 3,
+        };
+        let mut offsets = Vec::new();
+        for dx in -1isize..=1 {
+            for dy in -1isize..=1 {
+                for dz in -1isize..=1 {
+                    let manhattan = dx.abs() + dy.abs() + dz.abs();
+                    if manhattan > 0 && manhattan <= max_manhattan {
+                        offsets.push([dx, dy, dz]);
+                    }
+                }
+            }
+        }
+        offsets
+    }
+}
+
+/// Shift `idx` by `offset`, returning `None` if the result leaves `shape`.
+pub(crate) fn offset_index(
+    idx: [usize; 3],
+    offset: &[isize; 3],
+    shape: &[usize; 3],
+) -> Option<[usize; 3]> {
+    let mut r = [0; 3];
+    for i in 0..3 {
+        let v = idx[i] as isize + offset[i];
+        if v < 0 || v >= shape[i] as isize {
+            return None;
+        }
+        r[i] = v as usize;
+    }
+    Some(r)
+}
+
+/// Row-major (C order) flat index of a voxel in a volume of shape `shape`.
+#[cfg_attr(not(feature = "nalgebra"), allow(dead_code))]
+pub(crate) fn flat_index(idx: [usize; 3], shape: &[usize; 3]) -> usize {
+    (idx[0] * shape[1] + idx[1]) * shape[2] + idx[2]
+}
+
+/// Inverse of [`flat_index`].
+#[cfg_attr(not(feature = "nalgebra"), allow(dead_code))]
+pub(crate) fn unflat_index(i: usize, shape: &[usize; 3]) -> [usize; 3] {
+    let z = i % shape[2];
+    let y = (i / shape[2]) % shape[1];
+    let x = i / (shape[1] * shape[2]);
+    [x, y, z]
+}
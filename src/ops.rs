@@ -0,0 +1,155 @@
+use crate::sampler::traits::ReSample;
+use crate::{resample_from_to, same_grid, sanitize_im_shape, shape3};
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::{AsPrimitive, Num};
+
+/// A voxelwise binary operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+
+    /// Division, with a result of 0 where the divisor is 0 (as in fslmaths).
+    Div,
+    Min,
+    Max,
+}
+
+impl Op {
+    pub fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            Op::Add => a + b,
+            Op::Sub => a - b,
+            Op::Mul => a * b,
+            Op::Div if b == 0.0 => 0.0,
+            Op::Div => a / b,
+            Op::Min => a.min(b),
+            Op::Max => a.max(b),
+        }
+    }
+}
+
+/// Apply `op` to each voxel of `a` and a scalar, e.g. `a - 100`.
+pub fn scalar_op<U>(op: Op, a: &Array<U, IxDyn>, value: f64) -> Array<f64, IxDyn>
+where
+    U: AsPrimitive<f64>,
+{
+    a.mapv(|x| op.apply(x.as_(), value))
+}
+
+/// Apply `op` voxelwise to two images, e.g. `a - b`.
+///
+/// Both images have to share the same grid: shapes have to be equal and the
+/// affines equal within a tolerance of 1e-4. Images of more than three
+/// dimensions (e.g. 4D series) are supported. See [`image_op_resampled`] for
+/// images on different grids.
+pub fn image_op<T, U, V>(
+    op: Op,
+    a: &Array<U, IxDyn>,
+    a_affine: &Matrix4<T>,
+    b: &Array<V, IxDyn>,
+    b_affine: &Matrix4<T>,
+) -> Result<Array<f64, IxDyn>, String>
+where
+    T: Scalar + RealField + Copy,
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+    f32: AsPrimitive<T>,
+{
+    let (a, b) = (sanitize(a)?, sanitize(b)?);
+    if !same_grid(a.shape(), a_affine, b.shape(), b_affine, 1e-4f32.as_()) {
+        return Err("images do not share the same grid".into());
+    }
+    Ok(combine(op, &a, &b))
+}
+
+/// Apply `op` voxelwise to two images, resampling `b` onto the grid of `a`
+/// with `sampler` if the grids differ, see [`image_op`].
+///
+/// Images of more than three dimensions only have to share the spatial
+/// grid; the further dimensions have to match.
+pub fn image_op_resampled<T, U, V, S>(
+    op: Op,
+    a: &Array<U, IxDyn>,
+    a_affine: &Matrix4<T>,
+    b: &Array<V, IxDyn>,
+    b_affine: &Matrix4<T>,
+    sampler: &S,
+) -> Result<Array<f64, IxDyn>, String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<V> + Copy,
+    U: AsPrimitive<f64>,
+    V: Num + Copy + Send + Sync + AsPrimitive<f64> + 'static,
+    S: ReSample<T, V> + ?Sized + 'static,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    let (a, b) = (sanitize(a)?, sanitize(b)?);
+    if a.shape()[3..] != b.shape()[3..] {
+        return Err("non-spatial dimensions of the images do not match".into());
+    }
+    let b = if same_grid(a.shape(), a_affine, b.shape(), b_affine, 1e-4f32.as_()) {
+        b
+    } else {
+        resample_from_to(&b, b_affine, &shape3(&a), a_affine, sampler)?
+    };
+    Ok(combine(op, &a, &b))
+}
+
+/// Voxel data of at least three dimensions, 2D images get a singleton third
+/// dimension.
+fn sanitize<U: Clone>(im: &Array<U, IxDyn>) -> Result<Array<U, IxDyn>, String> {
+    if im.ndim() > 3 {
+        Ok(im.clone())
+    } else {
+        sanitize_im_shape(im)
+    }
+}
+
+fn combine<U, V>(op: Op, a: &Array<U, IxDyn>, b: &Array<V, IxDyn>) -> Array<f64, IxDyn>
+where
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+{
+    ndarray::Zip::from(a)
+        .and(b)
+        .map_collect(|a, b| op.apply(a.as_(), b.as_()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NearestNeighbor;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn test_image_op() {
+        let a = Array::from_shape_vec(IxDyn(&[4, 1, 1]), vec![1u8, 2, 3, 4]).unwrap();
+        let b = Array::from_shape_vec(IxDyn(&[4, 1, 1]), vec![2.0, 0.0, 1.0, 8.0]).unwrap();
+        let affine = Matrix4::<f64>::identity();
+        let div = image_op(Op::Div, &a, &affine, &b, &affine).unwrap();
+        assert_eq!(div.as_slice().unwrap(), &[0.5, 0.0, 3.0, 0.5]);
+        let max = image_op(Op::Max, &a, &affine, &b, &affine).unwrap();
+        assert_eq!(max.as_slice().unwrap(), &[2.0, 2.0, 3.0, 8.0]);
+        assert_eq!(
+            scalar_op(Op::Sub, &a, 1.0).as_slice().unwrap(),
+            &[0.0, 1.0, 2.0, 3.0]
+        );
+
+        // a shifted grid is refused unless resampling is requested
+        let shifted = Matrix4::new_translation(&Vector3::new(1.0, 0.0, 0.0));
+        assert!(image_op(Op::Add, &a, &affine, &b, &shifted).is_err());
+        let sum = image_op_resampled(
+            Op::Add,
+            &a,
+            &affine,
+            &b,
+            &shifted,
+            &NearestNeighbor::default(),
+        )
+        .unwrap();
+        assert_eq!(sum.as_slice().unwrap(), &[1.0, 4.0, 3.0, 5.0]);
+    }
+}
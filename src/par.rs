@@ -0,0 +1,53 @@
+/// Parallel iteration with rayon, or its sequential stand-ins without the
+/// `parallel` feature, e.g. on `wasm32-unknown-unknown`. The stand-ins have
+/// the names of the rayon methods and return the corresponding std
+/// iterators, so call sites compile either way.
+#[cfg(feature = "parallel")]
+pub(crate) use rayon::prelude::*;
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) use sequential::*;
+
+#[cfg(not(feature = "parallel"))]
+#[allow(dead_code)] // sampling on its own only iterates in parallel
+mod sequential {
+    use std::slice::{Chunks, ChunksMut, IterMut, Windows};
+
+    pub(crate) trait IntoParallelIterator: IntoIterator + Sized {
+        fn into_par_iter(self) -> Self::IntoIter {
+            self.into_iter()
+        }
+    }
+
+    impl<I> IntoParallelIterator for I where I: IntoIterator {}
+
+    pub(crate) trait ParallelSlice<T> {
+        fn par_chunks(&self, chunk_size: usize) -> Chunks<'_, T>;
+        fn par_windows(&self, window_size: usize) -> Windows<'_, T>;
+    }
+
+    impl<T> ParallelSlice<T> for [T] {
+        fn par_chunks(&self, chunk_size: usize) -> Chunks<'_, T> {
+            self.chunks(chunk_size)
+        }
+
+        fn par_windows(&self, window_size: usize) -> Windows<'_, T> {
+            self.windows(window_size)
+        }
+    }
+
+    pub(crate) trait ParallelSliceMut<T> {
+        fn par_iter_mut(&mut self) -> IterMut<'_, T>;
+        fn par_chunks_mut(&mut self, chunk_size: usize) -> ChunksMut<'_, T>;
+    }
+
+    impl<T> ParallelSliceMut<T> for [T] {
+        fn par_iter_mut(&mut self) -> IterMut<'_, T> {
+            self.iter_mut()
+        }
+
+        fn par_chunks_mut(&mut self, chunk_size: usize) -> ChunksMut<'_, T> {
+            self.chunks_mut(chunk_size)
+        }
+    }
+}
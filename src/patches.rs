@@ -0,0 +1,495 @@
+use crate::rng::Rng;
+use nalgebra::{Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Handling of patches extending beyond the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    /// Voxels outside of the image take the constant value.
+    Constant,
+
+    /// The nearest border voxel is duplicated.
+    Nearest,
+
+    /// The image is mirrored at its borders, without repeating the border
+    /// voxels.
+    Reflect,
+
+    /// Patches are shifted into the image, which has to be at least as
+    /// large as a patch.
+    Shift,
+}
+
+/// Positions of the patches of [`extract_patches`].
+#[derive(Debug, Clone, Copy)]
+pub enum PatchCenters<'a> {
+    /// Patches on a regular grid with the given stride (voxels), starting at
+    /// the first voxel and covering the whole image. The last patch along an
+    /// axis ends at the border, unless the image is smaller than a patch.
+    Grid { stride: [usize; 3] },
+
+    /// n patches centered at uniformly random voxels.
+    Random { n: usize, seed: u64 },
+
+    /// n patches centered at random voxels of the mask (e.g. the
+    /// foreground), drawn with replacement.
+    Mask {
+        mask: &'a Array<bool, IxDyn>,
+        n: usize,
+        seed: u64,
+    },
+}
+
+/// A patch of [`extract_patches`].
+#[derive(Debug, Clone)]
+pub struct Patch<T, U>
+where
+    T: Scalar,
+{
+    /// The voxels of the patch, of the patch size followed by the
+    /// non-spatial dimensions of the image.
+    pub data: Array<U, IxDyn>,
+
+    /// Voxel index of the first patch voxel in the image; negative or
+    /// beyond the image for patches extending over the border.
+    pub offset: [isize; 3],
+
+    /// Affine of the patch, i.e. the image affine moved to the offset. Its
+    /// translation is the world position of the first patch voxel.
+    pub affine: Matrix4<T>,
+}
+
+/// Voxel offsets (first voxels) of the patches of size at the given centers
+/// in an image of in_shape.
+pub fn patch_offsets(
+    in_shape: &[usize; 3],
+    size: &[usize; 3],
+    centers: &PatchCenters,
+    padding: Padding,
+) -> Result<Vec<[isize; 3]>, String> {
+    if size.contains(&0) || in_shape.contains(&0) {
+        return Err("image and patch size have to be at least 1 along each axis".into());
+    }
+    if padding == Padding::Shift && (0..3).any(|d| size[d] > in_shape[d]) {
+        return Err("patch is larger than the image".into());
+    }
+    let half = size.map(|s| (s / 2) as isize);
+    let offsets: Vec<[isize; 3]> = match centers {
+        PatchCenters::Grid { stride } => {
+            if stride.contains(&0) {
+                return Err("stride has to be at least 1 along each axis".into());
+            }
+            let starts: Vec<Vec<isize>> = (0..3)
+                .map(|d| {
+                    let last = in_shape[d].saturating_sub(size[d]);
+                    let mut starts: Vec<usize> = (0..=last).step_by(stride[d]).collect();
+                    if starts.last() != Some(&last) {
+                        starts.push(last);
+                    }
+                    starts.into_iter().map(|s| s as isize).collect()
+                })
+                .collect();
+            itertools::iproduct!(&starts[0], &starts[1], &starts[2])
+                .map(|(x, y, z)| [*x, *y, *z])
+                .collect()
+        }
+        PatchCenters::Random { n, seed } => {
+            let mut rng = Rng::new(*seed);
+            (0..*n)
+                .map(|_| [0, 1, 2].map(|d| rng.below(in_shape[d]) as isize - half[d]))
+                .collect()
+        }
+        PatchCenters::Mask { mask, n, seed } => {
+            if mask.shape() != in_shape {
+                return Err("mask shape does not match image shape".into());
+            }
+            let voxels: Vec<[isize; 3]> = mask
+                .indexed_iter()
+                .filter(|(_, m)| **m)
+                .map(|(idx, _)| [0, 1, 2].map(|d| idx[d] as isize))
+                .collect();
+            if voxels.is_empty() && *n > 0 {
+                return Err("mask is empty".into());
+            }
+            let mut rng = Rng::new(*seed);
+            (0..*n)
+                .map(|_| {
+                    let center = voxels[rng.below(voxels.len())];
+                    [0, 1, 2].map(|d| center[d] - half[d])
+                })
+                .collect()
+        }
+    };
+    Ok(match padding {
+        Padding::Shift => offsets
+            .into_iter()
+            .map(|o| [0, 1, 2].map(|d| o[d].clamp(0, (in_shape[d] - size[d]) as isize)))
+            .collect(),
+        _ => offsets,
+    })
+}
+
+/// Extract patches of size from in_im (3D, or with further dimensions such
+/// as channels, which are preserved), e.g. to train a model on.
+///
+/// Voxels of patches extending beyond the image are filled according to the
+/// padding, with cval for [`Padding::Constant`].
+pub fn extract_patches<T, U>(
+    in_im: &Array<U, IxDyn>,
+    affine: &Matrix4<T>,
+    size: &[usize; 3],
+    centers: &PatchCenters,
+    padding: Padding,
+    cval: U,
+) -> Result<Vec<Patch<T, U>>, String>
+where
+    T: Scalar + RealField + Copy,
+    U: Clone,
+    isize: AsPrimitive<T>,
+{
+    if in_im.ndim() < 3 {
+        return Err("invalid shape".into());
+    }
+    let in_shape = [in_im.shape()[0], in_im.shape()[1], in_im.shape()[2]];
+    let offsets = patch_offsets(&in_shape, size, centers, padding)?;
+    Ok(offsets
+        .into_iter()
+        .map(|offset| Patch {
+            data: extract_patch(in_im, &offset, size, padding, cval.clone()),
+            affine: affine * Matrix4::new_translation(&Vector3::from(offset.map(|o| o.as_()))),
+            offset,
+        })
+        .collect())
+}
+
+/// Source index along an axis of length n, if any.
+fn source_index(i: isize, n: usize, padding: Padding) -> Option<usize> {
+    let n = n as isize;
+    match padding {
+        Padding::Constant | Padding::Shift => (0..n).contains(&i).then_some(i as usize),
+        Padding::Nearest => Some(i.clamp(0, n - 1) as usize),
+        Padding::Reflect if n == 1 => Some(0),
+        Padding::Reflect => {
+            let period = 2 * (n - 1);
+            let i = i.rem_euclid(period);
+            Some(if i < n { i } else { period - i } as usize)
+        }
+    }
+}
+
+pub(crate) fn extract_patch<U>(
+    in_im: &Array<U, IxDyn>,
+    offset: &[isize; 3],
+    size: &[usize; 3],
+    padding: Padding,
+    cval: U,
+) -> Array<U, IxDyn>
+where
+    U: Clone,
+{
+    let sources: Vec<Vec<Option<usize>>> = (0..3)
+        .map(|d| {
+            (0..size[d] as isize)
+                .map(|i| source_index(offset[d] + i, in_im.shape()[d], padding))
+                .collect()
+        })
+        .collect();
+    // gather from the box of source voxels only
+    let bounds: Vec<(usize, usize)> = sources
+        .iter()
+        .map(|source| {
+            let inside = source.iter().flatten();
+            match (inside.clone().min(), inside.max()) {
+                (Some(lo), Some(hi)) => (*lo, *hi + 1),
+                _ => (0, 1),
+            }
+        })
+        .collect();
+    let region = in_im.slice_each_axis(|ax| match ax.axis.index() {
+        d if d < 3 => ndarray::Slice::from(bounds[d].0..bounds[d].1),
+        _ => ndarray::Slice::from(..),
+    });
+    let local = |d: usize| -> Vec<usize> {
+        sources[d]
+            .iter()
+            .map(|s| s.map_or(0, |i| i - bounds[d].0))
+            .collect()
+    };
+    let mut patch = region.select(Axis(0), &local(0));
+    for d in 1..3 {
+        patch = patch.select(Axis(d), &local(d));
+    }
+    for (d, source) in sources.iter().enumerate() {
+        for (i, _) in source.iter().enumerate().filter(|(_, s)| s.is_none()) {
+            patch.index_axis_mut(Axis(d), i).fill(cval.clone());
+        }
+    }
+    patch
+}
+
+/// Weighting of overlapping patch predictions in a [`PatchAggregator`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Blending {
+    /// All voxels of a patch weigh the same.
+    Uniform,
+
+    /// Voxels are weighted by a gaussian centered on the patch, with a sigma
+    /// of sigma_scale times the patch size per axis, so predictions near
+    /// patch borders (with less context) weigh less. A sigma_scale of 0.125
+    /// is common.
+    Gaussian { sigma_scale: f64 },
+}
+
+impl Default for Blending {
+    fn default() -> Self {
+        Blending::Gaussian { sigma_scale: 0.125 }
+    }
+}
+
+/// Accumulates per-patch predictions (e.g. of a segmentation model run on
+/// the patches of [`extract_patches`]) into a full volume, as in sliding
+/// window inference.
+///
+/// Overlapping predictions are averaged with the weights of the blending,
+/// voxels not covered by any patch are NaN. Patch voxels outside of the
+/// volume are ignored.
+#[derive(Debug, Clone)]
+pub struct PatchAggregator {
+    out_shape: [usize; 3],
+    size: [usize; 3],
+    window: Array<f64, IxDyn>,
+    sum: Option<Array<f64, IxDyn>>,
+    weight: Array<f64, IxDyn>,
+}
+
+impl PatchAggregator {
+    pub fn new(
+        out_shape: &[usize; 3],
+        size: &[usize; 3],
+        blending: Blending,
+    ) -> Result<Self, String> {
+        if size.contains(&0) {
+            return Err("patch size has to be at least 1 along each axis".into());
+        }
+        let window = match blending {
+            Blending::Uniform => Array::ones(IxDyn(size)),
+            Blending::Gaussian { sigma_scale } => {
+                if !(sigma_scale.is_finite() && sigma_scale > 0.0) {
+                    return Err("sigma_scale has to be positive".into());
+                }
+                let profile = |d: usize, i: usize| {
+                    let sigma = sigma_scale * size[d] as f64;
+                    let x = i as f64 - (size[d] as f64 - 1.0) / 2.0;
+                    (-x * x / (2.0 * sigma * sigma)).exp()
+                };
+                // a floor keeps voxels only covered by patch borders defined
+                Array::from_shape_fn(IxDyn(size), |idx| {
+                    (profile(0, idx[0]) * profile(1, idx[1]) * profile(2, idx[2])).max(1e-3)
+                })
+            }
+        };
+        Ok(Self {
+            out_shape: *out_shape,
+            size: *size,
+            window,
+            sum: None,
+            weight: Array::zeros(IxDyn(out_shape)),
+        })
+    }
+
+    /// Add the prediction of the patch with the given offset (see
+    /// [`Patch`]). The prediction has the patch size followed by any
+    /// non-spatial dimensions (e.g. classes), which have to be the same for
+    /// all patches.
+    pub fn add<U>(
+        &mut self,
+        offset: &[isize; 3],
+        prediction: &Array<U, IxDyn>,
+    ) -> Result<(), String>
+    where
+        U: AsPrimitive<f64>,
+    {
+        if prediction.ndim() < 3 || prediction.shape()[..3] != self.size {
+            return Err("prediction shape does not match the patch size".into());
+        }
+        let extra = &prediction.shape()[3..];
+        let mut full_shape = self.out_shape.to_vec();
+        full_shape.extend_from_slice(extra);
+        let sum = self
+            .sum
+            .get_or_insert_with(|| Array::zeros(IxDyn(&full_shape)));
+        if sum.shape() != full_shape.as_slice() {
+            return Err("non-spatial dimensions of the predictions differ".into());
+        }
+
+        // overlap of the patch with the volume, in patch voxels
+        let mut ranges = [(0, 0); 3];
+        for (d, range) in ranges.iter_mut().enumerate() {
+            let lo = (-offset[d]).clamp(0, self.size[d] as isize);
+            let hi = (self.out_shape[d] as isize - offset[d]).clamp(lo, self.size[d] as isize);
+            if lo == hi {
+                return Ok(());
+            }
+            *range = (lo, hi);
+        }
+        let patch_slice = |ax: ndarray::AxisDescription| match ax.axis.index() {
+            d if d < 3 => ndarray::Slice::from(ranges[d].0..ranges[d].1),
+            _ => ndarray::Slice::from(..),
+        };
+        let out_slice = |ax: ndarray::AxisDescription| match ax.axis.index() {
+            d if d < 3 => ndarray::Slice::from(offset[d] + ranges[d].0..offset[d] + ranges[d].1),
+            _ => ndarray::Slice::from(..),
+        };
+
+        let window = self.window.slice_each_axis(patch_slice);
+        let mut window_shape = window.shape().to_vec();
+        window_shape.extend(extra.iter().map(|_| 1));
+        let broadcast_window = window
+            .to_shape(IxDyn(&window_shape))
+            .expect("only unit axes are added");
+        let weighted =
+            prediction.slice_each_axis(patch_slice).mapv(|x| x.as_()) * &broadcast_window;
+        let mut target = sum.slice_each_axis_mut(out_slice);
+        target += &weighted;
+        self.weight
+            .slice_each_axis_mut(out_slice)
+            .zip_mut_with(&window, |w, x| *w += x);
+        Ok(())
+    }
+
+    /// The blended volume of the out_shape followed by the non-spatial
+    /// dimensions of the predictions.
+    pub fn finish(self) -> Array<f64, IxDyn> {
+        let mut sum = match self.sum {
+            Some(sum) => sum,
+            None => return Array::from_elem(IxDyn(&self.out_shape), f64::NAN),
+        };
+        let mut weight_shape = self.out_shape.to_vec();
+        weight_shape.extend((3..sum.ndim()).map(|_| 1));
+        let weight = self
+            .weight
+            .into_shape(IxDyn(&weight_shape))
+            .expect("only unit axes are added");
+        ndarray::Zip::from(&mut sum)
+            .and_broadcast(&weight)
+            .for_each(|s, w| *s = if *w > 0.0 { *s / w } else { f64::NAN });
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_offsets() {
+        let grid = PatchCenters::Grid { stride: [4, 4, 4] };
+        let offsets = patch_offsets(&[10, 4, 3], &[4, 4, 4], &grid, Padding::Constant).unwrap();
+        let x: Vec<isize> = offsets.iter().map(|o| o[0]).collect();
+        assert_eq!(x, vec![0, 4, 6]);
+        assert!(offsets.iter().all(|o| o[1] == 0 && o[2] == 0));
+        assert!(patch_offsets(&[10, 4, 3], &[4, 4, 4], &grid, Padding::Shift).is_err());
+
+        let random = PatchCenters::Random { n: 20, seed: 3 };
+        let a = patch_offsets(&[10, 8, 6], &[4, 4, 4], &random, Padding::Shift).unwrap();
+        let b = patch_offsets(&[10, 8, 6], &[4, 4, 4], &random, Padding::Shift).unwrap();
+        assert_eq!(a, b);
+        assert!(a.iter().all(|o| o[0] <= 6 && o[1] <= 4 && o[2] <= 2));
+
+        let mut mask = Array::from_elem(IxDyn(&[10, 8, 6]), false);
+        mask[[7, 2, 3]] = true;
+        let masked = PatchCenters::Mask {
+            mask: &mask,
+            n: 3,
+            seed: 0,
+        };
+        let offsets = patch_offsets(&[10, 8, 6], &[3, 3, 3], &masked, Padding::Nearest).unwrap();
+        assert_eq!(offsets, vec![[6, 1, 2]; 3]);
+    }
+
+    #[test]
+    fn test_extract_patches() {
+        let im = Array::from_shape_fn(IxDyn(&[4, 3, 2, 2]), |idx| {
+            (idx[0] + 10 * idx[1] + 100 * idx[2] + 1000 * idx[3]) as i32
+        });
+        let affine = Matrix4::new_scaling(2.0);
+        let mut mask = Array::from_elem(IxDyn(&[4, 3, 2]), false);
+        mask[[0, 0, 0]] = true;
+        let centers = PatchCenters::Mask {
+            mask: &mask,
+            n: 1,
+            seed: 1,
+        };
+        let patch = |padding| {
+            extract_patches(&im, &affine, &[3, 3, 1], &centers, padding, -1)
+                .unwrap()
+                .remove(0)
+        };
+
+        let constant = patch(Padding::Constant);
+        assert_eq!(constant.offset, [-1, -1, 0]);
+        assert_eq!(constant.data.shape(), &[3, 3, 1, 2]);
+        assert_eq!(
+            constant.data.slice(s![.., 1, 0, 1]).to_vec(),
+            vec![-1, 1000, 1001]
+        );
+        assert_eq!(constant.data[[0, 0, 0, 0]], -1);
+        assert_eq!(constant.affine[(0, 3)], -2.0);
+
+        let nearest = patch(Padding::Nearest);
+        assert_eq!(nearest.data.slice(s![.., 0, 0, 0]).to_vec(), vec![0, 0, 1]);
+        let reflect = patch(Padding::Reflect);
+        assert_eq!(
+            reflect.data.slice(s![.., 0, 0, 0]).to_vec(),
+            vec![11, 10, 11]
+        );
+        let shift = patch(Padding::Shift);
+        assert_eq!(shift.offset, [0, 0, 0]);
+        assert_eq!(shift.data.slice(s![.., 2, 0, 0]).to_vec(), vec![20, 21, 22]);
+    }
+
+    #[test]
+    fn test_patch_aggregator() {
+        let im = Array::from_shape_fn(IxDyn(&[9, 7, 5]), |idx| {
+            (idx[0] + 2 * idx[1] + idx[2]) as f64
+        });
+        let affine = Matrix4::<f64>::identity();
+        let grid = PatchCenters::Grid { stride: [3, 3, 3] };
+        let patches =
+            extract_patches(&im, &affine, &[4, 4, 4], &grid, Padding::Constant, 0.0).unwrap();
+        for blending in [Blending::Uniform, Blending::default()] {
+            let mut aggregator = PatchAggregator::new(&[9, 7, 5], &[4, 4, 4], blending).unwrap();
+            for patch in &patches {
+                // two "classes": the identity and a constant
+                let prediction = ndarray::stack(
+                    Axis(3),
+                    &[patch.data.view(), Array::ones(IxDyn(&[4, 4, 4])).view()],
+                )
+                .unwrap();
+                aggregator.add(&patch.offset, &prediction).unwrap();
+            }
+            let out = aggregator.finish();
+            assert_eq!(out.shape(), &[9, 7, 5, 2]);
+            let identity = out.index_axis(Axis(3), 0);
+            assert!(identity
+                .iter()
+                .zip(im.iter())
+                .all(|(a, b)| (a - b).abs() < 1e-9));
+            assert!(out
+                .index_axis(Axis(3), 1)
+                .iter()
+                .all(|x| (x - 1.0).abs() < 1e-12));
+        }
+
+        let mut aggregator =
+            PatchAggregator::new(&[4, 4, 4], &[2, 2, 2], Blending::Uniform).unwrap();
+        let prediction = Array::from_elem(IxDyn(&[2, 2, 2]), 3u8);
+        aggregator.add(&[-1, 0, 3], &prediction).unwrap();
+        assert!(aggregator.add(&[0, 0, 0], &im).is_err());
+        let out = aggregator.finish();
+        assert_eq!(out[[0, 1, 3]], 3.0);
+        assert!(out[[1, 0, 3]].is_nan());
+        assert_eq!(out.iter().filter(|x| !x.is_nan()).count(), 2);
+    }
+}
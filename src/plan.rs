@@ -0,0 +1,263 @@
+use crate::out_grid_coords;
+use crate::par::*;
+use crate::sampler::common::{within_bounds, SamplingMode};
+use crate::trace::timed_span;
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::{AsPrimitive, Num};
+
+/// Index of a neighbor outside of the input, which takes the constant value.
+const OUTSIDE: usize = usize::MAX;
+
+/// Interpolation of a [`ResamplePlan`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlanInterpolation {
+    /// Nearest neighbor, as [`NearestNeighbor`](crate::NearestNeighbor).
+    Nearest,
+
+    /// Trilinear, as [`TriLinear`](crate::TriLinear).
+    #[default]
+    Linear,
+}
+
+/// Precomputed resampling from a fixed input grid to a fixed output grid.
+///
+/// The input voxels (neighbors) each output voxel is interpolated from and
+/// their interpolation weights are computed once, such that resampling a
+/// volume reduces to a weighted gather. This pays off whenever the same
+/// resampling is applied repeatedly, e.g. to every frame of a 4D series or
+/// to a stream of images on the same grid.
+///
+/// Neighbors with a weight of 0 are dropped, hence non-finite voxel values
+/// only spread to the output voxels they contribute to. Otherwise, the
+/// results match the corresponding sampler.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResamplePlan {
+    in_shape: [usize; 3],
+    out_shape: [usize; 3],
+
+    /// Start of the neighbors of each output voxel in indices and weights,
+    /// followed by the total number of neighbors.
+    offsets: Vec<usize>,
+
+    /// Row-major indices of the neighbors in the input volume, or OUTSIDE.
+    indices: Vec<usize>,
+    weights: Vec<f64>,
+}
+
+impl ResamplePlan {
+    /// Plan the resampling of volumes of in_shape and in_affine to the voxel
+    /// space defined by out_shape and out_affine (see
+    /// [`resample_from_to`](crate::resample_from_to)).
+    pub fn new<T>(
+        in_shape: &[usize; 3],
+        in_affine: &Matrix4<T>,
+        out_shape: &[usize; 3],
+        out_affine: &Matrix4<T>,
+        interpolation: PlanInterpolation,
+        mode: SamplingMode,
+    ) -> Result<Self, String>
+    where
+        T: Num + Scalar + RealField + AsPrimitive<f64> + Copy,
+        usize: AsPrimitive<T>,
+    {
+        timed_span!(
+            "plan_resampling",
+            in_shape = ?in_shape,
+            out_shape = ?out_shape,
+            ?interpolation
+        );
+        let (coords, _) = out_grid_coords(in_affine, out_shape, out_affine, None)?;
+        let upper = in_shape.map(|x| x as f64);
+        let caps = in_shape.map(|x| x.saturating_sub(1) as f64);
+        let flat = |p: [usize; 3]| {
+            if (0..3).all(|d| p[d] < in_shape[d]) {
+                (p[0] * in_shape[1] + p[1]) * in_shape[2] + p[2]
+            } else {
+                OUTSIDE
+            }
+        };
+
+        let neighbors: Vec<Vec<(usize, f64)>> = (0..coords.nrows())
+            .into_par_iter()
+            .map(|i| {
+                let mut p: [f64; 3] = [0, 1, 2].map(|d| coords[[i, d]].as_());
+                if mode == SamplingMode::Nearest {
+                    p = [0, 1, 2].map(|d| p[d].clamp(0.0, caps[d]));
+                }
+                if interpolation == PlanInterpolation::Nearest {
+                    p = p.map(|x| x.round());
+                }
+                // check if index is out of bounds (or not a number)
+                if !within_bounds(&p, &upper) {
+                    return vec![(OUTSIDE, 1.0)];
+                }
+                match interpolation {
+                    PlanInterpolation::Nearest => vec![(flat(p.map(|x| x as usize)), 1.0)],
+                    PlanInterpolation::Linear => {
+                        let p0 = p.map(|x| x.floor());
+                        let p1 = p0.map(|x| x + 1.0);
+                        let (i0, i1) = (p0.map(|x| x as usize), p1.map(|x| x as usize));
+                        let mut neighbors = Vec::with_capacity(8);
+                        for corner in 0..8 {
+                            let upper_half = [corner & 4 != 0, corner & 2 != 0, corner & 1 != 0];
+                            let mut idx = [0; 3];
+                            let mut weight = 1.0;
+                            for d in 0..3 {
+                                if upper_half[d] {
+                                    idx[d] = i1[d];
+                                    weight *= p[d] - p0[d];
+                                } else {
+                                    idx[d] = i0[d];
+                                    weight *= p1[d] - p[d];
+                                }
+                            }
+                            if weight != 0.0 {
+                                neighbors.push((flat(idx), weight));
+                            }
+                        }
+                        neighbors
+                    }
+                }
+            })
+            .collect();
+
+        let mut offsets = Vec::with_capacity(neighbors.len() + 1);
+        let (mut indices, mut weights) = (Vec::new(), Vec::new());
+        for voxel in neighbors {
+            offsets.push(indices.len());
+            for (index, weight) in voxel {
+                indices.push(index);
+                weights.push(weight);
+            }
+        }
+        offsets.push(indices.len());
+
+        Ok(Self {
+            in_shape: *in_shape,
+            out_shape: *out_shape,
+            offsets,
+            indices,
+            weights,
+        })
+    }
+
+    /// Spatial shape of the input volumes.
+    pub fn in_shape(&self) -> [usize; 3] {
+        self.in_shape
+    }
+
+    /// Spatial shape of the output volumes.
+    pub fn out_shape(&self) -> [usize; 3] {
+        self.out_shape
+    }
+
+    /// Total number of stored neighbors, a measure of the memory footprint.
+    pub fn n_neighbors(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Resample in_im (2D, 3D or with further dimensions, which are
+    /// preserved, e.g. a 4D series) according to the plan. Outside neighbors
+    /// take the value cval.
+    pub fn apply<U>(&self, in_im: &Array<U, IxDyn>, cval: f64) -> Result<Array<f64, IxDyn>, String>
+    where
+        U: AsPrimitive<f64> + Sync,
+    {
+        timed_span!("apply_plan", in_shape = ?in_im.shape());
+        let shape = in_im.shape();
+        let spatial = match shape {
+            [x, y] => [*x, *y, 1],
+            [x, y, z, ..] => [*x, *y, *z],
+            _ => return Err("invalid shape".into()),
+        };
+        if spatial != self.in_shape {
+            return Err("image shape does not match the shape of the plan".into());
+        }
+        let extra = if shape.len() > 3 { &shape[3..] } else { &[] };
+        let n_volumes: usize = extra.iter().product();
+        if n_volumes == 0 {
+            return Err("image does not contain any volumes".into());
+        }
+
+        // in standard layout, the volumes of a voxel are contiguous
+        let in_im = in_im.as_standard_layout();
+        let values = in_im.as_slice().expect("standard layout is contiguous");
+        let mut out = vec![0.0; (self.offsets.len() - 1) * n_volumes];
+        out.par_chunks_mut(n_volumes)
+            .zip(self.offsets.par_windows(2))
+            .for_each(|(out, range)| {
+                for (t, out) in out.iter_mut().enumerate() {
+                    *out = (range[0]..range[1])
+                        .map(|j| match self.indices[j] {
+                            OUTSIDE => self.weights[j] * cval,
+                            index => self.weights[j] * values[index * n_volumes + t].as_(),
+                        })
+                        .sum();
+                }
+            });
+
+        let mut out_shape = self.out_shape.to_vec();
+        out_shape.extend_from_slice(extra);
+        Ok(Array::from_shape_vec(IxDyn(&out_shape), out).expect("one value per output voxel"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{resample_from_to, NearestNeighbor, ReSample, TriLinear};
+    use approx::*;
+
+    #[test]
+    fn test_resample_plan() {
+        let series = Array::from_shape_fn(IxDyn(&[6, 5, 4, 3]), |idx| {
+            (idx[0] * idx[1] + 2 * idx[2] + 10 * idx[3]) as f64
+        });
+        let in_affine = Matrix4::new_scaling(2.0);
+        let out_affine = in_affine
+            * nalgebra::Rotation3::from_euler_angles(0.1, -0.2, 0.3).to_homogeneous()
+            * Matrix4::new_scaling(0.7);
+
+        let mut trilinear = TriLinear::<f64>::default();
+        ReSample::<f64, f64>::set_cval(&mut trilinear, -5.0);
+        let plan = ResamplePlan::new(
+            &[6, 5, 4],
+            &in_affine,
+            &[7, 6, 5],
+            &out_affine,
+            PlanInterpolation::Linear,
+            SamplingMode::Constant,
+        )
+        .unwrap();
+        let planned = plan.apply(&series, -5.0).unwrap();
+        let expected =
+            resample_from_to(&series, &in_affine, &[7, 6, 5], &out_affine, &trilinear).unwrap();
+        assert_eq!(planned.shape(), &[7, 6, 5, 3]);
+        assert!(planned
+            .iter()
+            .zip(expected.iter())
+            .all(|(a, b)| relative_eq!(a, b, epsilon = 1e-9)));
+        assert!(plan.n_neighbors() <= 8 * 7 * 6 * 5);
+
+        let mut nearest = NearestNeighbor::<f64>::default();
+        ReSample::<f64, f64>::set_sampling_mode(&mut nearest, SamplingMode::Nearest);
+        let plan = ResamplePlan::new(
+            &[6, 5, 4],
+            &in_affine,
+            &[7, 6, 5],
+            &out_affine,
+            PlanInterpolation::Nearest,
+            SamplingMode::Nearest,
+        )
+        .unwrap();
+        let volume = series.index_axis(Axis(3), 1).to_owned();
+        let expected =
+            resample_from_to(&volume, &in_affine, &[7, 6, 5], &out_affine, &nearest).unwrap();
+        assert_eq!(plan.apply(&volume, 0.0).unwrap(), expected);
+        assert_eq!(plan.n_neighbors(), 7 * 6 * 5);
+        assert!(plan
+            .apply(&series.index_axis(Axis(0), 0).to_owned(), 0.0)
+            .is_err());
+    }
+}
@@ -0,0 +1,1217 @@
+use crate::par::*;
+use crate::trace::{debug_event, timed_span};
+use crate::{fast_path, filter, sanitize_im_shape, IntoCoords, ReSample};
+use itertools::Itertools;
+use nalgebra::{ClosedAdd, ClosedMul, Matrix3, Matrix4, MatrixXx3, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::{AsPrimitive, Num};
+use std::fmt::Display;
+
+/// Corners could be calculated, e.g. using itertools. As we only need to cover the 3D
+/// usecase, we simply do it "manually".
+#[rustfmt::skip] // do not mangle manual matrix format
+fn get_corners(in_shape: &Vector3<usize>) -> MatrixXx3<usize>
+where
+{
+    let is = in_shape;
+    MatrixXx3::<usize>::from_row_slice(&[
+        0,         0,        0,
+        0,         0,        is[2] - 1,
+        0,         is[1] -1, 0,
+        0,         is[1] -1, is[2] - 1,
+        is[0] - 1, 0,        0,
+        is[0] - 1, 0,        is[2] - 1,
+        is[0] - 1, is[1] -1, 0,
+        is[0] - 1, is[1] -1, is[2] - 1,
+    ])
+}
+
+/// Transform the point matrix by applying the affine transform + translation.
+fn apply_affine<T>(affine: &Matrix4<T>, pts: &MatrixXx3<T>) -> MatrixXx3<T>
+where
+    T: Num + Scalar + ClosedAdd + ClosedMul + Copy + Display,
+{
+    let (aff, tra) = afftra_to_aff_tra(affine);
+    let mut r = pts * aff.transpose();
+    let _tra = tra.transpose();
+
+    for mut row in r.row_iter_mut() {
+        row += &_tra;
+    }
+    r
+}
+
+/// output-aligned shape, affine for input implied by `in_shape` & `in_affine`
+///
+/// The input (voxel) space, and the affine mapping to output space, are given
+/// in `in_shape` & `in_affine`.
+///
+/// The output space is implied by the affine, we don't need to know what that
+/// is, we just return something with the same (implied) output space.
+///
+/// Our job is to work out another voxel space where the voxel array axes and
+/// the output axes are aligned (top left 3 x 3 of affine is diagonal with all
+/// positive entries) and which contains all the voxels of the implied input
+/// image at their correct output space positions, once resampled into the
+/// output voxel space.
+///
+/// (from the nibabel documentation)
+///
+pub(crate) fn vox2out_vox<T>(
+    in_shape: &Vector3<usize>,
+    in_affine: &Matrix4<T>,
+    voxel_sizes: &Vector3<T>,
+) -> Result<(Vector3<usize>, Matrix4<T>), String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + Copy + Display,
+    usize: AsPrimitive<T>,
+{
+    let in_corners: MatrixXx3<usize> = get_corners(in_shape);
+    // convert MatrixXx3<usize> -> MatrixXx3<T>
+    let in_corners: MatrixXx3<T> =
+        MatrixXx3::from_iterator(in_corners.nrows(), in_corners.iter().map(|x| x.as_()));
+
+    let out_corners: MatrixXx3<T> = apply_affine(in_affine, &in_corners);
+
+    // ToDo make pretty
+    let out_mn = out_corners
+        .column_iter()
+        .map(|x| x.min())
+        .collect::<Vec<T>>();
+    let out_mn = Vector3::from_vec(out_mn);
+    let out_mx = out_corners
+        .column_iter()
+        .map(|x| x.max())
+        .collect::<Vec<T>>();
+    let out_mx = Vector3::from_vec(out_mx);
+
+    let out_shape: Vector3<T> = (out_mx - out_mn)
+        .component_div(voxel_sizes)
+        .map(|x| x.ceil())
+        .add_scalar(T::one());
+    // checked, as the float to integer cast saturates
+    let max_len: T = (isize::MAX as usize).as_();
+    if out_shape.iter().any(|x| !(x.is_finite() && *x < max_len)) {
+        return Err("output shape overflows; check the affine and voxel sizes".into());
+    }
+    let out_shape: Vector3<usize> = Vector3::from_iterator(out_shape.iter().map(|x| x.as_()));
+
+    let out_aff = Matrix3::from_diagonal(voxel_sizes);
+    let out_tra = out_mn;
+    let out_affine = aff_tra_to_afftra(&out_aff, &out_tra);
+
+    Ok((out_shape, out_affine))
+}
+
+/// Voxel sizes (the norms of the columns of the linear part) of an affine.
+pub fn voxel_sizes<T>(affine: &Matrix4<T>) -> Vector3<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    let (aff, _) = afftra_to_aff_tra(affine);
+    Vector3::from_iterator(aff.column_iter().map(|col| col.norm()))
+}
+
+fn aff_tra_to_afftra<T>(aff: &Matrix3<T>, tra: &Vector3<T>) -> Matrix4<T>
+where
+    T: Num + Scalar + Copy,
+{
+    let r = *aff;
+    let r = r.insert_column(3, T::zero());
+    let mut r = r.insert_row(3, T::zero());
+    r[(0, 3)] = tra.x;
+    r[(1, 3)] = tra.y;
+    r[(2, 3)] = tra.z;
+    r[(3, 3)] = T::one();
+    r
+}
+
+pub(crate) fn afftra_to_aff_tra<T>(affine: &Matrix4<T>) -> (Matrix3<T>, Vector3<T>)
+where
+    T: Num + Scalar + Copy,
+{
+    let aff = affine.fixed_slice::<3, 3>(0, 0);
+    let tra = affine.fixed_slice::<3, 1>(0, 3);
+    (aff.into(), tra.into())
+}
+
+/// Validate an optional mask against the (spatial) shape of an image.
+pub(crate) fn sanitize_mask(
+    mask: Option<&Array<bool, IxDyn>>,
+    shape: &[usize],
+) -> Result<Option<Array<bool, IxDyn>>, String> {
+    match mask {
+        None => Ok(None),
+        Some(mask) => {
+            let mask = sanitize_im_shape(mask)?;
+            if mask.shape() != shape {
+                return Err("mask shape does not match image shape".into());
+            }
+            Ok(Some(mask))
+        }
+    }
+}
+
+/// Linearly interpolated percentile `q` (in [0, 100]) of sorted values.
+pub(crate) fn percentile_sorted(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let pos = (q / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (pos - lo as f64)
+}
+
+/// Check whether two images share the same voxel grid, comparing the affines
+/// element-wise with an absolute tolerance.
+pub(crate) fn same_grid<T>(
+    shape_a: &[usize],
+    affine_a: &Matrix4<T>,
+    shape_b: &[usize],
+    affine_b: &Matrix4<T>,
+    tolerance: T,
+) -> bool
+where
+    T: Scalar + RealField + Copy,
+{
+    shape_a == shape_b
+        && affine_a
+            .iter()
+            .zip(affine_b.iter())
+            .all(|(a, b)| (*a - *b).abs() <= tolerance)
+}
+
+/// Resample in_im to world space with a given voxel size.
+///
+/// 4D and 5D images are resampled volume by volume, see
+/// [`resample_from_to`].
+pub fn resample_to_output<T, U, S>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    voxel_sizes: &[f32; 3],
+    sampler: &S,
+) -> Result<(Array<U, IxDyn>, Matrix4<T>), String>
+where
+    T: Scalar + RealField + AsPrimitive<usize> + AsPrimitive<U> + Copy,
+    U: Num + Copy + Send + Sync + 'static,
+    S: ReSample<T, U> + ?Sized + 'static,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    // ToDo make pretty
+    let sanitized;
+    let in_im = if in_im.ndim() >= 4 {
+        in_im
+    } else {
+        sanitized = sanitize_im_shape(in_im)?;
+        &sanitized
+    };
+    let in_shape = in_im.shape();
+    let in_shape = Vector3::from_row_slice(&[in_shape[0], in_shape[1], in_shape[2]]);
+
+    let voxel_sizes: Vector3<T> = Vector3::from_row_slice(&voxel_sizes.map(|x| x.as_()));
+
+    let (out_shape, out_affine) = vox2out_vox(&in_shape, in_affine, &voxel_sizes)?;
+    let out_shape: [usize; 3] = out_shape.into();
+    match resample_from_to(in_im, in_affine, &out_shape, &out_affine, sampler) {
+        Ok(out_im) => Ok((out_im, out_affine)),
+        Err(err) => Err(err),
+    }
+}
+
+/// Resample in_im to mapped voxel space defined by out_affine and out_shape.
+///
+/// For 4D images (x, y, z, t) the same spatial resampling is applied to each
+/// volume. The sample coordinates are computed once and the volumes are
+/// processed in parallel; the output has shape (out_shape, t). Likewise for
+/// 5D vector images (x, y, z, t, c), following NIFTI `dim[5]`, each vector
+/// component is resampled separately and all non-spatial dimensions are
+/// preserved untouched.
+///
+/// If the output grid is aligned with the input grid, i.e. it is the same
+/// grid up to shifts by whole voxels and axis permutations and flips (as in
+/// reorientation), the voxels are copied bit-identically without sampling
+/// (for samplers which [interpolate](ReSample::interpolates)).
+pub fn resample_from_to<T, U, S>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    out_shape: &[usize; 3],
+    out_affine: &Matrix4<T>,
+    sampler: &S,
+) -> Result<Array<U, IxDyn>, String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<U> + Copy,
+    U: Num + Copy + Send + Sync + 'static,
+    S: ReSample<T, U> + ?Sized + 'static,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    resample_tiled(
+        in_im,
+        in_affine,
+        out_shape,
+        out_affine,
+        sampler,
+        Tiling::Auto,
+    )
+}
+
+/// [`resample_from_to`] sampling the output voxels in the order given by
+/// tiling.
+pub(crate) fn resample_tiled<T, U, S>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    out_shape: &[usize; 3],
+    out_affine: &Matrix4<T>,
+    sampler: &S,
+    tiling: Tiling,
+) -> Result<Array<U, IxDyn>, String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<U> + Copy,
+    U: Num + Copy + Send + Sync + 'static,
+    S: ReSample<T, U> + ?Sized + 'static,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    timed_span!(
+        "resample",
+        in_shape = ?in_im.shape(),
+        out_shape = ?out_shape,
+        ?tiling
+    );
+    let compound = match in_affine.try_inverse() {
+        Some(val) => val * out_affine,
+        None => return Err("no valid matrix inverse found for in_affine".into()),
+    };
+    if sampler.interpolates() && in_im.ndim() >= 3 {
+        if let Some(mapping) = fast_path::AxisMapping::from_affine(&compound) {
+            debug_event!(?mapping, "aligned grids, copying voxels");
+            return Ok(fast_path::copy_aligned(
+                in_im,
+                &mapping,
+                out_shape,
+                sampler.get_sampling_mode(),
+                sampler.get_cval(),
+            ));
+        }
+    }
+
+    let tile = tiling.tile_shape(in_im.shape(), &compound)?;
+    let (out_coords, order) = out_grid_coords(in_affine, out_shape, out_affine, tile)?;
+    debug_event!(?tile, "computed sample coordinates");
+    sample_grid(
+        in_im,
+        out_coords.into(),
+        out_shape,
+        order.as_deref(),
+        sampler,
+    )
+}
+
+/// Sample in_im of any supported dimensionality at the coordinates of
+/// out_grid_coords. Borrowed coordinates are shared without copies.
+fn sample_grid<T, U, S>(
+    in_im: &Array<U, IxDyn>,
+    out_coords: CowArray<T, Ix2>,
+    out_shape: &[usize; 3],
+    order: Option<&[usize]>,
+    sampler: &S,
+) -> Result<Array<U, IxDyn>, String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<U> + Copy,
+    U: Num + Copy + Send + Sync + 'static,
+    S: ReSample<T, U> + ?Sized,
+    usize: AsPrimitive<T>,
+{
+    match in_im.ndim() {
+        3 => sample_ordered(sampler, in_im, out_coords, out_shape, order),
+        n if n > 3 => resample_volumes(in_im, out_coords.view(), out_shape, order, sampler),
+        _ => sample_ordered(
+            sampler,
+            &sanitize_im_shape(in_im)?,
+            out_coords,
+            out_shape,
+            order,
+        ),
+    }
+}
+
+/// Resample several co-registered images of the same grid (in_affine) to
+/// the voxel space defined by out_shape and out_affine, each with its own
+/// sampler, e.g. T1, T2 and FLAIR with a trilinear and the label map with a
+/// label-aware sampler.
+///
+/// The sample coordinates, the most expensive part of resampling, are
+/// computed once and shared by all images. The images may differ in their
+/// non-spatial dimensions, see [`resample_from_to`]; voxel types may be
+/// unified beforehand, e.g. with [`convert`].
+pub fn resample_many<T, U>(
+    in_ims: &[&Array<U, IxDyn>],
+    in_affine: &Matrix4<T>,
+    out_shape: &[usize; 3],
+    out_affine: &Matrix4<T>,
+    samplers: &[&dyn ReSample<T, U>],
+) -> Result<Vec<Array<U, IxDyn>>, String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<U> + Copy,
+    U: Num + Copy + Send + Sync + 'static,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    timed_span!(
+        "resample_many",
+        n_images = in_ims.len(),
+        out_shape = ?out_shape
+    );
+    if in_ims.len() != samplers.len() {
+        return Err("number of images and samplers do not match".into());
+    }
+    let spatial_shape = |im: &Array<U, IxDyn>| match im.shape() {
+        [x, y] => Ok([*x, *y, 1]),
+        [x, y, z, ..] => Ok([*x, *y, *z]),
+        _ => Err("invalid shape".to_string()),
+    };
+    let in_shape = match in_ims.first() {
+        Some(im) => spatial_shape(im)?,
+        None => return Ok(Vec::new()),
+    };
+    for im in in_ims {
+        if spatial_shape(im)? != in_shape {
+            return Err("spatial shapes of the images do not match".into());
+        }
+    }
+
+    let compound = match in_affine.try_inverse() {
+        Some(val) => val * out_affine,
+        None => return Err("no valid matrix inverse found for in_affine".into()),
+    };
+    let mapping = fast_path::AxisMapping::from_affine(&compound);
+    let mut grid = None;
+    let mut out_ims = Vec::with_capacity(in_ims.len());
+    for (in_im, sampler) in in_ims.iter().zip(samplers) {
+        if let Some(mapping) = mapping.filter(|_| sampler.interpolates() && in_im.ndim() >= 3) {
+            out_ims.push(fast_path::copy_aligned(
+                in_im,
+                &mapping,
+                out_shape,
+                sampler.get_sampling_mode(),
+                sampler.get_cval(),
+            ));
+            continue;
+        }
+        if grid.is_none() {
+            let tile = Tiling::Auto.tile_shape(&in_shape, &compound)?;
+            grid = Some(out_grid_coords(in_affine, out_shape, out_affine, tile)?);
+        }
+        let (out_coords, order) = grid.as_ref().expect("computed above");
+        out_ims.push(sample_grid(
+            in_im,
+            out_coords.view().into(),
+            out_shape,
+            order.as_deref(),
+            *sampler,
+        )?);
+    }
+    Ok(out_ims)
+}
+
+/// Order in which the voxels of the output grid are sampled.
+///
+/// Sampling visits the output voxels one after the other. With transforms
+/// which rotate or permute the axes, neighboring voxels of an output row lie
+/// far apart in the input, such that row-major iteration over large volumes
+/// thrashes the cache. Iterating over small 3D tiles of the output keeps the
+/// input voxels accessed in succession close together. The results do not
+/// depend on the order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tiling {
+    /// Row-major order, as the output is stored.
+    RowMajor,
+
+    /// Tiles of the given shape (in output voxels) in row-major order, each
+    /// of them in row-major order.
+    Tiles([usize; 3]),
+
+    /// Tiles of [`Tiling::AUTO_TILE`] voxels for transforms which do not
+    /// preserve the axes of inputs with more than [`Tiling::AUTO_MIN_VOXELS`]
+    /// voxels per volume, row-major order otherwise.
+    #[default]
+    Auto,
+}
+
+impl Tiling {
+    /// Tile shape of [`Tiling::Auto`], chosen with the tiling benchmark of
+    /// rotated volumes (`cargo bench`).
+    pub const AUTO_TILE: [usize; 3] = [16, 16, 16];
+
+    /// Smallest input volume for which [`Tiling::Auto`] tiles; smaller ones
+    /// fit into the cache anyway.
+    pub const AUTO_MIN_VOXELS: usize = 64 * 64 * 64;
+
+    /// The tile shape to sample in_im with for the compound affine (output
+    /// voxel to input voxel), if any.
+    fn tile_shape<T>(
+        &self,
+        in_shape: &[usize],
+        compound: &Matrix4<T>,
+    ) -> Result<Option<[usize; 3]>, String>
+    where
+        T: Scalar + RealField + Copy,
+    {
+        match *self {
+            Tiling::RowMajor => Ok(None),
+            Tiling::Tiles(tile) if tile.contains(&0) => {
+                Err("tile shape has to be at least 1 along each axis".into())
+            }
+            Tiling::Tiles(tile) => Ok(Some(tile)),
+            Tiling::Auto => {
+                let n_voxels: usize = in_shape.iter().take(3).product();
+                let (aff, _) = afftra_to_aff_tra(compound);
+                let preserves_axes =
+                    (0..3).all(|r| (0..3).all(|c| r == c || aff[(r, c)] == T::zero()));
+                if n_voxels < Self::AUTO_MIN_VOXELS || preserves_axes {
+                    Ok(None)
+                } else {
+                    Ok(Some(Self::AUTO_TILE))
+                }
+            }
+        }
+    }
+}
+
+/// Sample in_im at in_coords, given in the order of out_grid_coords, into an
+/// image of out_shape.
+fn sample_ordered<T, U, S>(
+    sampler: &S,
+    in_im: &Array<U, IxDyn>,
+    in_coords: CowArray<T, Ix2>,
+    out_shape: &[usize; 3],
+    order: Option<&[usize]>,
+) -> Result<Array<U, IxDyn>, String>
+where
+    T: Num + AsPrimitive<usize> + AsPrimitive<U> + PartialOrd + Copy,
+    U: Num + Copy + 'static,
+    S: ReSample<T, U> + ?Sized,
+    usize: AsPrimitive<T>,
+{
+    let shape = match order {
+        Some(order) => vec![order.len()],
+        None => out_shape.to_vec(),
+    };
+    let values = if in_coords.is_view() {
+        sampler.sample_view(in_im, in_coords.view(), &shape)?
+    } else {
+        sampler.sample(in_im, &mut in_coords.into_owned(), &shape)?
+    };
+    let order = match order {
+        Some(order) => order,
+        None => return Ok(values),
+    };
+    let mut out = vec![U::zero(); order.len()];
+    for (value, i) in values.iter().zip(order) {
+        out[*i] = *value;
+    }
+    Ok(Array::from_shape_vec(IxDyn(out_shape), out).expect("one value per output voxel"))
+}
+
+/// Voxel coordinates of in_im of all voxels of the output grid, in row-major
+/// order or tile by tile. For tiles, the row-major index of each voxel is
+/// returned as well.
+#[allow(clippy::type_complexity)]
+pub(crate) fn out_grid_coords<T>(
+    in_affine: &Matrix4<T>,
+    out_shape: &[usize; 3],
+    out_affine: &Matrix4<T>,
+    tile: Option<[usize; 3]>,
+) -> Result<(Array2<T>, Option<Vec<usize>>), String>
+where
+    T: Num + Scalar + RealField + Copy,
+    usize: AsPrimitive<T>,
+{
+    let inv_in_affine = match in_affine.try_inverse() {
+        Some(val) => val,
+        None => return Err("no valid matrix inverse found for in_affine".into()),
+    };
+
+    let compound_affine = inv_in_affine * out_affine;
+
+    let n_voxels = out_shape
+        .iter()
+        .try_fold(3usize, |n, x| n.checked_mul(*x))
+        .ok_or("number of output voxels overflows")?;
+    if n_voxels > isize::MAX as usize {
+        return Err("number of output voxels overflows".into());
+    }
+
+    // ToDo: generation of all coords is not very fast
+    let (in_coords, order) = match tile {
+        None => {
+            let in_coord_iter = out_shape.iter().map(|x| 0..*x).multi_cartesian_product();
+            (in_coord_iter.flatten().collect_vec(), None)
+        }
+        Some(tile) => {
+            let n = out_shape.iter().product();
+            let (mut in_coords, mut order) = (Vec::with_capacity(3 * n), Vec::with_capacity(n));
+            let starts = (0..3).map(|d| (0..out_shape[d]).step_by(tile[d]));
+            for start in starts.multi_cartesian_product() {
+                let ranges = (0..3).map(|d| start[d]..(start[d] + tile[d]).min(out_shape[d]));
+                for idx in ranges.multi_cartesian_product() {
+                    order.push((idx[0] * out_shape[1] + idx[1]) * out_shape[2] + idx[2]);
+                    in_coords.extend_from_slice(&idx);
+                }
+            }
+            (in_coords, Some(order))
+        }
+    };
+
+    // the iterator yields row-major order, nalgebra uses column-major order
+    // ToDo: this is slow. Possibly replace with Matrix3N::from_vec,
+    //       however, this operation expects column-major order
+    let in_coords = MatrixXx3::from_row_slice(&in_coords);
+    // convert MatrixXx3<usize> -> MatrixXx3<T>
+    let in_coords: MatrixXx3<T> =
+        MatrixXx3::from_iterator(in_coords.nrows(), in_coords.iter().map(|x| x.as_()));
+
+    Ok((
+        apply_affine(&compound_affine, &in_coords)
+            .into_coords()?
+            .into_owned(),
+        order,
+    ))
+}
+
+/// Resample each volume of in_im (x, y, z, ...) at the same coordinates, in
+/// parallel. All dimensions beyond the third (e.g. time or vector
+/// components) are preserved.
+fn resample_volumes<T, U, S>(
+    in_im: &Array<U, IxDyn>,
+    out_coords: ArrayView2<T>,
+    out_shape: &[usize; 3],
+    order: Option<&[usize]>,
+    sampler: &S,
+) -> Result<Array<U, IxDyn>, String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<U> + Copy,
+    U: Num + Copy + Send + Sync + 'static,
+    S: ReSample<T, U> + ?Sized,
+    usize: AsPrimitive<T>,
+{
+    let extra = &in_im.shape()[3..];
+    let n_volumes: usize = extra.iter().product();
+    if n_volumes == 0 {
+        return Err("image does not contain any volumes".into());
+    }
+    let s = in_im.shape();
+    let in_im = in_im.as_standard_layout();
+    let volumes = in_im
+        .view()
+        .into_shape(IxDyn(&[s[0], s[1], s[2], n_volumes]))
+        .expect("standard layout can be reshaped");
+
+    // each volume is written into its slot of the output directly
+    let mut out = Array::zeros(IxDyn(&[
+        out_shape[0],
+        out_shape[1],
+        out_shape[2],
+        n_volumes,
+    ]));
+    out.axis_iter_mut(Axis(3))
+        .into_par_iter()
+        .enumerate()
+        .try_for_each(|(t, mut slot)| {
+            let volume = volumes.index_axis(Axis(3), t).to_owned();
+            let resampled = sample_ordered(sampler, &volume, out_coords.into(), out_shape, order)?;
+            slot.assign(&resampled);
+            Ok::<_, String>(())
+        })?;
+    let mut shape = out_shape.to_vec();
+    shape.extend_from_slice(extra);
+    Ok(out
+        .into_shape(IxDyn(&shape))
+        .expect("number of elements is preserved"))
+}
+
+/// Options of [`resample_from_to_with`].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResampleOptions {
+    /// Number of sub-voxel sample positions per output voxel and axis, whose
+    /// average is the output value. 1 samples the voxel center only.
+    pub supersampling: usize,
+
+    /// Smooth the input with a gaussian before downsampling, with a sigma of
+    /// (zoom - 1) / 2 input voxels per axis (see [`anti_aliasing_sigma`]).
+    /// Axes which are not downsampled remain untouched.
+    pub anti_aliasing: bool,
+
+    /// Order in which the output voxels are sampled.
+    pub tiling: Tiling,
+}
+
+impl Default for ResampleOptions {
+    fn default() -> Self {
+        Self {
+            supersampling: 1,
+            anti_aliasing: true,
+            tiling: Tiling::Auto,
+        }
+    }
+}
+
+/// Gaussian sigma (in input voxels, per input axis) to suppress aliasing
+/// when resampling from in_affine to out_affine.
+///
+/// The zoom of an input axis is the largest step along it between
+/// neighboring output voxels; for zooms of at most 1 (upsampling) no
+/// smoothing is required.
+pub fn anti_aliasing_sigma<T>(
+    in_affine: &Matrix4<T>,
+    out_affine: &Matrix4<T>,
+) -> Result<[f64; 3], String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+{
+    let inv_in_affine = match in_affine.try_inverse() {
+        Some(val) => val,
+        None => return Err("no valid matrix inverse found for in_affine".into()),
+    };
+    let (compound, _) = afftra_to_aff_tra(&(inv_in_affine * out_affine));
+    Ok([0, 1, 2].map(|d| {
+        let zoom: f64 = compound.row(d).abs().max().as_();
+        ((zoom - 1.0) / 2.0).max(0.0)
+    }))
+}
+
+/// Resample in_im to the voxel space defined by out_shape and out_affine
+/// like [`resample_from_to`], with the accuracy [`ResampleOptions`] applied.
+///
+/// Unless disabled, the input is smoothed to avoid aliasing if the output
+/// grid is coarser than the input grid.
+///
+/// A supersampling factor k evaluates k³ positions evenly spread over each
+/// output voxel, which reduces aliasing when heavily downsampling high
+/// frequency data (e.g. distance maps). The image is processed in f64, hence
+/// the sampler has to operate on f64; averaging label maps is not meaningful.
+pub fn resample_from_to_with<T, U, S>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    out_shape: &[usize; 3],
+    out_affine: &Matrix4<T>,
+    sampler: &S,
+    options: &ResampleOptions,
+) -> Result<Array<f64, IxDyn>, String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+    S: ReSample<T, f64> + ?Sized + 'static,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    let k = options.supersampling;
+    if k == 0 {
+        return Err("supersampling factor has to be at least 1".into());
+    }
+    let mut in_im: Array<f64, IxDyn> = in_im.mapv(|x| x.as_());
+    if options.anti_aliasing {
+        let sigma = anti_aliasing_sigma(in_affine, out_affine)?;
+        if sigma.iter().any(|s| *s > 0.0) {
+            in_im = smooth_volumes(&in_im, &sigma)?;
+        }
+    }
+    if k == 1 {
+        return resample_tiled(
+            &in_im,
+            in_affine,
+            out_shape,
+            out_affine,
+            sampler,
+            options.tiling,
+        );
+    }
+
+    let offsets: Vec<T> = (0..k)
+        .map(|i| ((i as f32 + 0.5) / k as f32 - 0.5).as_())
+        .collect();
+    let mut sum: Option<Array<f64, IxDyn>> = None;
+    for offset in itertools::iproduct!(&offsets, &offsets, &offsets) {
+        let shift = Vector3::new(*offset.0, *offset.1, *offset.2);
+        let shifted = out_affine * Matrix4::new_translation(&shift);
+        let values = resample_tiled(
+            &in_im,
+            in_affine,
+            out_shape,
+            &shifted,
+            sampler,
+            options.tiling,
+        )?;
+        match sum.as_mut() {
+            Some(sum) => *sum += &values,
+            None => sum = Some(values),
+        }
+    }
+    let n = (k * k * k) as f64;
+    Ok(sum.expect("at least one sample position").mapv(|x| x / n))
+}
+
+/// Resample in_im to world space with a given voxel size like
+/// [`resample_to_output`], with the accuracy [`ResampleOptions`] applied, see
+/// [`resample_from_to_with`].
+pub fn resample_to_output_with<T, U, S>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    voxel_sizes: &[f32; 3],
+    sampler: &S,
+    options: &ResampleOptions,
+) -> Result<(Array<f64, IxDyn>, Matrix4<T>), String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64> + Clone,
+    S: ReSample<T, f64> + ?Sized + 'static,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    let in_shape = if in_im.ndim() >= 4 {
+        in_im.shape().to_vec()
+    } else {
+        sanitize_im_shape(in_im)?.shape().to_vec()
+    };
+    let in_shape = Vector3::new(in_shape[0], in_shape[1], in_shape[2]);
+    let voxel_sizes: Vector3<T> = Vector3::from_row_slice(&voxel_sizes.map(|x| x.as_()));
+    let (out_shape, out_affine) = vox2out_vox(&in_shape, in_affine, &voxel_sizes)?;
+    let out_im = resample_from_to_with(
+        in_im,
+        in_affine,
+        &out_shape.into(),
+        &out_affine,
+        sampler,
+        options,
+    )?;
+    Ok((out_im, out_affine))
+}
+
+/// Gaussian smoothing of each 3D volume of an image of any dimensionality.
+fn smooth_volumes(
+    in_im: &Array<f64, IxDyn>,
+    sigma: &[f64; 3],
+) -> Result<Array<f64, IxDyn>, String> {
+    if in_im.ndim() <= 3 {
+        return filter::gaussian::gaussian_filter(in_im, sigma);
+    }
+    let shape = in_im.shape().to_vec();
+    let n_volumes = shape[3..].iter().product();
+    let volumes = in_im
+        .as_standard_layout()
+        .into_owned()
+        .into_shape((shape[0], shape[1], shape[2], n_volumes))
+        .expect("standard layout can be reshaped");
+    let mut smoothed = Array::zeros(volumes.raw_dim());
+    for (volume, mut out) in volumes
+        .axis_iter(Axis(3))
+        .zip(smoothed.axis_iter_mut(Axis(3)))
+    {
+        out.assign(&filter::gaussian::gaussian_filter(
+            &volume.to_owned().into_dyn(),
+            sigma,
+        )?);
+    }
+    Ok(smoothed
+        .into_shape(shape)
+        .expect("number of elements is preserved"))
+}
+
+/// Resample in_im through a world space transform onto the voxel space defined
+/// by out_affine and out_shape.
+///
+/// `transform` maps output world coordinates to input world coordinates, i.e.
+/// it is the transform that aligns the input image to the output grid. 4D
+/// and 5D images are resampled volume by volume, see [`resample_from_to`].
+pub fn resample_with_transform<T, U, S>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    transform: &Matrix4<T>,
+    out_shape: &[usize; 3],
+    out_affine: &Matrix4<T>,
+    sampler: &S,
+) -> Result<Array<U, IxDyn>, String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<U> + Copy,
+    U: Num + Copy + Send + Sync + 'static,
+    S: ReSample<T, U> + ?Sized + 'static,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    resample_from_to(
+        in_im,
+        in_affine,
+        out_shape,
+        &(transform * out_affine),
+        sampler,
+    )
+}
+
+/// Sample in_im at arbitrary world coordinates, e.g. along a path or on an
+/// oblique plane, instead of on a regular output grid.
+///
+/// Returns one value per point, in order. Points outside of the field of
+/// view are handled by the sampling mode of the sampler.
+pub fn sample_world_points<T, U, S>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    points: &[Vector3<T>],
+    sampler: &S,
+) -> Result<Vec<U>, String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<U> + Copy,
+    U: Num + Copy + Send + Sync + 'static,
+    S: ReSample<T, U> + ?Sized + 'static,
+    usize: AsPrimitive<T>,
+{
+    let in_im = sanitize_im_shape(in_im)?;
+    let inv_in_affine = match in_affine.try_inverse() {
+        Some(val) => val,
+        None => return Err("no valid matrix inverse found for in_affine".into()),
+    };
+    let (aff, tra) = afftra_to_aff_tra(&inv_in_affine);
+    let points: Vec<Vector3<T>> = points.iter().map(|p| aff * p + tra).collect();
+    let mut in_coords = Array2::from_shape_fn((points.len(), 3).f(), |(i, d)| points[i][d]);
+    let values = sampler.sample(&in_im, &mut in_coords, &[points.len()])?;
+    Ok(values.into_raw_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NearestNeighbor, SamplingMode, TriLinear};
+    use approx::*;
+
+    #[test]
+    #[rustfmt::skip] // do not mangle manual matrix format
+    fn test_get_corners() {
+        // normal use case:
+        let in_shape: Vector3<usize> = Vector3::from_vec(vec![256, 230, 16]);
+        let corners: MatrixXx3<usize> = MatrixXx3::from_row_slice(&[
+            0,   0,   0,
+            0,   0,   15,
+            0,   229, 0,
+            0,   229, 15,
+            255, 0,   0,
+            255, 0,   15,
+            255, 229, 0,
+            255, 229, 15,
+        ]);
+        assert_eq!(get_corners(&in_shape), corners);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_get_corners_invalid_shape() {
+        // edge case:
+        // shape dim cannot be 0
+        let in_shape: Vector3<usize> = Vector3::from_vec(vec![0, 1, 2]);
+        get_corners(&in_shape);
+    }
+
+    #[test]
+    #[rustfmt::skip] // do not mangle manual matrix format
+    fn test_apply_affine() {
+        let affine: Matrix4<f32> = Matrix4::from_row_slice(&[
+            0.0,  0.0, 6.7, 50.2,
+            0.0,  1.6, 0.0, -192.0,
+            -1.6, 0.0, 0.0, 177.9,
+            0.0,  0.0, 0.0, 1.0
+        ]);
+        let pts: MatrixXx3<f32> = MatrixXx3::from_row_slice(&[
+            12.0,    11.0,   0.0,
+            -113.1,  0.0,    555.5,
+            -1001.8, 3695.7, -0.01,
+        ]);
+        let exptected_result: MatrixXx3<f32> = MatrixXx3::from_row_slice(&[
+            50.2,      -174.4,  158.7,
+            3772.0498, -192.0,  358.86,
+            50.133,    5721.12, 1780.78,
+        ]);
+        assert_relative_eq!(apply_affine(&affine, &pts), exptected_result);
+    }
+
+    #[test]
+    #[rustfmt::skip] // do not mangle manual matrix format
+    fn test_vox2out_vox () {
+        let in_shape: Vector3<usize> = Vector3::from_vec(vec![256, 230, 16]);
+        let in_affine: Matrix4<f32> = Matrix4::from_row_slice(&[
+            0.0,  0.0,  6.7, 50.2,
+            0.0,  -1.6, 0.0, -192.0,
+            -1.6, 0.0,  0.0, 177.9,
+            0.0,  0.0,  0.0, 1.0
+        ]);
+        let voxel_sizes: Vector3<f32> = Vector3::from_vec(vec![1.5, 3.0, 6.0]);
+        let expected_out_shape: Vector3<usize> = Vector3::from_vec(vec![68, 124, 69]);
+        let expected_out_affine: Matrix4<f32> = Matrix4::from_row_slice(&[
+            1.5, 0.0, 0.0, 50.2,
+            0.0, 3.0, 0.0, -558.4,
+            0.0, 0.0, 6.0, -230.1,
+            0.0, 0.0, 0.0, 1.0
+        ]);
+        let (out_shape, out_affine) = vox2out_vox(&in_shape, &in_affine, &voxel_sizes).unwrap();
+        assert_eq!(out_shape, expected_out_shape);
+        assert_relative_eq!(out_affine, expected_out_affine);
+    }
+
+    #[test]
+    fn test_resample_from_to_4d() {
+        let series = Array::from_shape_fn(IxDyn(&[4, 4, 4, 3]), |idx| {
+            (idx[0] + 2 * idx[1] + 3 * idx[2] + 10 * idx[3]) as f64
+        });
+        let in_affine = Matrix4::<f64>::identity();
+        let out_affine = Matrix4::new_translation(&Vector3::new(0.5, 0.0, 0.25));
+        let sampler = TriLinear::<f64>::default();
+        let out = resample_from_to(&series, &in_affine, &[3, 3, 3], &out_affine, &sampler).unwrap();
+        assert_eq!(out.shape(), &[3, 3, 3, 3]);
+        for t in 0..3 {
+            let volume = series.index_axis(Axis(3), t).to_owned();
+            let expected =
+                resample_from_to(&volume, &in_affine, &[3, 3, 3], &out_affine, &sampler).unwrap();
+            assert_eq!(out.index_axis(Axis(3), t), expected);
+        }
+        assert_relative_eq!(out[[0, 0, 0, 1]], 10.0 + 0.5 + 0.75);
+    }
+
+    #[test]
+    fn test_resample_from_to_5d() {
+        // vector image (x, y, z, 1, 3)
+        let im = Array::from_shape_fn(IxDyn(&[4, 4, 4, 1, 3]), |idx| (idx[0] + 10 * idx[4]) as f64);
+        let out_affine = Matrix4::new_translation(&Vector3::new(0.5, 0.0, 0.0));
+        let out = resample_from_to(
+            &im,
+            &Matrix4::identity(),
+            &[2, 2, 2],
+            &out_affine,
+            &TriLinear::<f64>::default(),
+        )
+        .unwrap();
+        assert_eq!(out.shape(), &[2, 2, 2, 1, 3]);
+        assert_relative_eq!(out[[1, 0, 0, 0, 2]], 21.5);
+    }
+
+    #[test]
+    fn test_resample_invalid_coordinates() {
+        let im = Array::from_elem(IxDyn(&[4, 4, 4]), 1.0f64);
+        let in_affine = Matrix4::<f64>::identity();
+        let mut trilinear = TriLinear::<f64>::default();
+        ReSample::<f64, f64>::set_cval(&mut trilinear, -1.0);
+        let mut nearest = NearestNeighbor::<f64>::default();
+        ReSample::<f64, f64>::set_cval(&mut nearest, -1.0);
+
+        // coordinates far outside of the field of view or not a number
+        // (on a finer grid, which is not aligned with the input grid)
+        for offset in [1e30, f64::NAN, f64::INFINITY] {
+            let out_affine = Matrix4::new_translation(&Vector3::new(offset, 0.0, 0.0))
+                * Matrix4::new_scaling(0.5);
+            for sampler in [&trilinear as &dyn ReSample<f64, f64>, &nearest] {
+                let out =
+                    resample_from_to(&im, &in_affine, &[2, 2, 2], &out_affine, sampler).unwrap();
+                assert!(out.iter().all(|x| *x == -1.0));
+            }
+        }
+
+        // output shapes which do not fit into memory are rejected
+        assert!(resample_to_output(&im, &(in_affine * 1e30), &[1.0, 1.0, 1.0], &nearest).is_err());
+        assert!(out_grid_coords(&in_affine, &[usize::MAX, 2, 1], &in_affine, None).is_err());
+    }
+
+    #[test]
+    fn test_sample_world_points() {
+        let im = Array::from_shape_fn(IxDyn(&[4, 4, 4]), |idx| (idx[0] + 10 * idx[2]) as f64);
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 1.0, 1.0));
+        let points = [
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(4.0, 2.0, 1.5),
+            Vector3::new(-10.0, 0.0, 0.0),
+        ];
+        let values =
+            sample_world_points(&im, &affine, &points, &TriLinear::<f64>::default()).unwrap();
+        assert_eq!(values, vec![0.5, 17.0, 0.0]);
+    }
+
+    #[test]
+    fn test_resample_from_to_supersampled() {
+        // a one voxel checkerboard aliases to a constant when downsampling by
+        // 2, supersampling recovers its mean
+        let im = Array::from_shape_fn(IxDyn(&[8, 8, 8]), |idx| {
+            ((idx[0] + idx[1] + idx[2]) % 2) as f64
+        });
+        let in_affine = Matrix4::<f64>::identity();
+        let out_affine = Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 2.0, 2.0))
+            .append_translation(&Vector3::new(0.5, 0.5, 0.5));
+        let sampler = NearestNeighbor::<f64>::default();
+        let naive = resample_from_to_with(
+            &im,
+            &in_affine,
+            &[4, 4, 4],
+            &out_affine,
+            &sampler,
+            &ResampleOptions {
+                anti_aliasing: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(naive.iter().all(|x| *x == 1.0));
+
+        let options = ResampleOptions {
+            supersampling: 2,
+            anti_aliasing: false,
+            ..Default::default()
+        };
+        let supersampled =
+            resample_from_to_with(&im, &in_affine, &[4, 4, 4], &out_affine, &sampler, &options)
+                .unwrap();
+        assert!(supersampled.iter().all(|x| *x == 0.5));
+        assert!(resample_from_to_with(
+            &im,
+            &in_affine,
+            &[4, 4, 4],
+            &out_affine,
+            &sampler,
+            &ResampleOptions {
+                supersampling: 0,
+                ..Default::default()
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_resample_many() {
+        let t1 = Array::from_shape_fn(IxDyn(&[6, 5, 4]), |idx| (idx[0] + 2 * idx[1]) as f64);
+        let series = Array::from_shape_fn(IxDyn(&[6, 5, 4, 2]), |idx| (idx[2] * idx[3]) as f64);
+        let labels = t1.mapv(|x| (x > 5.0) as u8 as f64);
+        let affine = Matrix4::new_scaling(2.0);
+        let rotation = nalgebra::Rotation3::from_euler_angles(0.1, 0.0, 0.3).to_homogeneous();
+        let out_affine = affine * rotation;
+        let trilinear = TriLinear::<f64>::default();
+        let mut nearest = NearestNeighbor::<f64>::default();
+        ReSample::<f64, f64>::set_sampling_mode(&mut nearest, SamplingMode::Nearest);
+        let samplers: [&dyn ReSample<f64, f64>; 3] = [&trilinear, &trilinear, &nearest];
+
+        let out = resample_many(
+            &[&t1, &series, &labels],
+            &affine,
+            &[5, 5, 3],
+            &out_affine,
+            &samplers,
+        )
+        .unwrap();
+        for (im, (out, sampler)) in [&t1, &series, &labels].iter().zip(out.iter().zip(samplers)) {
+            let single = resample_from_to(im, &affine, &[5, 5, 3], &out_affine, sampler).unwrap();
+            assert_eq!(out, &single);
+        }
+
+        let shifted = affine * Matrix4::new_translation(&Vector3::new(1.0, 0.0, 0.0));
+        let out = resample_many(&[&t1], &affine, &[6, 5, 4], &shifted, &samplers[..1]).unwrap();
+        assert_eq!(out[0][[0, 1, 0]], 3.0);
+
+        let small = t1.slice(s![..5, .., ..]).to_owned().into_dyn();
+        assert!(resample_many(
+            &[&t1, &small],
+            &affine,
+            &[5, 5, 3],
+            &out_affine,
+            &samplers[..2]
+        )
+        .is_err());
+        assert!(resample_many(&[&t1], &affine, &[5, 5, 3], &out_affine, &samplers).is_err());
+    }
+
+    #[test]
+    fn test_resample_tiled() {
+        let im = Array::from_shape_fn(IxDyn(&[9, 7, 5, 2]), |idx| {
+            (idx[0] * idx[1] + 3 * idx[2] + 100 * idx[3]) as f64
+        });
+        let rotation = nalgebra::Rotation3::from_euler_angles(0.2, -0.1, 0.4).to_homogeneous();
+        let affine = Matrix4::<f64>::identity();
+        let sampler = TriLinear::<f64>::default();
+        let resample =
+            |tiling| resample_tiled(&im, &affine, &[8, 6, 7], &rotation, &sampler, tiling).unwrap();
+        let row_major = resample(Tiling::RowMajor);
+        assert_eq!(row_major.shape(), &[8, 6, 7, 2]);
+        assert_eq!(resample(Tiling::Tiles([3, 4, 2])), row_major);
+        assert_eq!(resample(Tiling::Tiles([1, 1, 1])), row_major);
+        assert_eq!(resample(Tiling::Auto), row_major);
+        assert!(Tiling::Tiles([4, 0, 4])
+            .tile_shape(im.shape(), &rotation)
+            .is_err());
+    }
+
+    #[test]
+    fn test_resample_from_to_anti_aliasing() {
+        let in_affine = Matrix4::<f64>::identity();
+        let out_affine = Matrix4::new_nonuniform_scaling(&Vector3::new(3.0, 1.0, 0.5));
+        assert_eq!(
+            anti_aliasing_sigma(&in_affine, &out_affine).unwrap(),
+            [1.0, 0.0, 0.0]
+        );
+
+        // smoothing removes the aliased checkerboard, also of 4D series
+        let im = Array::from_shape_fn(IxDyn(&[12, 4, 4, 2]), |idx| (idx[0] % 2) as f64);
+        let out_affine = Matrix4::new_nonuniform_scaling(&Vector3::new(3.0, 1.0, 1.0));
+        let sampler = TriLinear::<f64>::default();
+        let options = ResampleOptions::default();
+        let out =
+            resample_from_to_with(&im, &in_affine, &[4, 4, 4], &out_affine, &sampler, &options)
+                .unwrap();
+        assert_eq!(out.shape(), &[4, 4, 4, 2]);
+        assert!(out
+            .slice(s![1..3, .., .., ..])
+            .iter()
+            .all(|x| (x - 0.5).abs() < 0.1));
+        let naive = resample_from_to(&im, &in_affine, &[4, 4, 4], &out_affine, &sampler).unwrap();
+        assert_eq!(naive[[1, 0, 0, 0]], 1.0);
+
+        let (out, out_affine) =
+            resample_to_output_with(&im, &in_affine, &[3.0, 1.0, 1.0], &sampler, &options).unwrap();
+        assert_eq!(out.shape(), &[5, 4, 4, 2]);
+        assert_relative_eq!(out_affine[(0, 0)], 3.0);
+        assert!(out
+            .slice(s![1..3, .., .., ..])
+            .iter()
+            .all(|x| (x - 0.5).abs() < 0.1));
+    }
+
+    #[test]
+    fn test_resample_from_to_aligned_grid() {
+        let mut im = Array::from_shape_fn(IxDyn(&[4, 3, 2]), |idx| (idx[0] + 10 * idx[1]) as f64);
+        im[[1, 1, 1]] = f64::NAN;
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 1.0, 1.0));
+        let mut sampler = TriLinear::<f64>::default();
+        ReSample::<f64, f64>::set_cval(&mut sampler, -1.0);
+
+        // the NaN voxel does not spread into its neighbors
+        let same = resample_from_to(&im, &affine, &[4, 3, 2], &affine, &sampler).unwrap();
+        assert_eq!(same.iter().filter(|x| x.is_nan()).count(), 1);
+
+        let shifted_affine = affine * Matrix4::new_translation(&Vector3::new(-1.0, 1.0, 0.0));
+        let shifted =
+            resample_from_to(&im, &affine, &[4, 3, 2], &shifted_affine, &sampler).unwrap();
+        assert_eq!(
+            shifted.slice(s![.., 0, 0]).to_vec(),
+            vec![-1.0, 10.0, 11.0, 12.0]
+        );
+        assert!(shifted.slice(s![.., 2, ..]).iter().all(|x| *x == -1.0));
+
+        // reorientation: x flipped and swapped with y
+        #[rustfmt::skip]
+        let reoriented_affine = affine * Matrix4::new(
+            0.0, -1.0, 0.0, 3.0,
+            1.0,  0.0, 0.0, 0.0,
+            0.0,  0.0, 1.0, 0.0,
+            0.0,  0.0, 0.0, 1.0,
+        );
+        let reoriented =
+            resample_from_to(&im, &affine, &[3, 4, 2], &reoriented_affine, &sampler).unwrap();
+        assert_eq!(
+            reoriented.slice(s![1, .., 0]).to_vec(),
+            vec![13.0, 12.0, 11.0, 10.0]
+        );
+        assert!(reoriented[[1, 2, 1]].is_nan());
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Error returned by operations which have been cancelled.
+pub const CANCELLED: &str = "operation cancelled";
+
+/// Token to cancel a long-running operation from another thread, e.g. of a
+/// GUI or of a server request handler. Clones share the cancellation state.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request the cancellation of all operations observing this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Progress reporting and cancellation of a long-running operation.
+///
+/// Operations report the completed fraction (in [0, 1]) to the callback and
+/// check the token between their chunks of work, e.g. slabs, resolution
+/// levels, iterations or volumes. A cancelled operation stops at the next
+/// check and returns the error [`CANCELLED`]. The default reports nothing
+/// and is never cancelled.
+#[derive(Clone, Default)]
+pub struct Progress<'a> {
+    /// Called with the completed fraction, from the calling thread.
+    pub callback: Option<&'a dyn Fn(f64)>,
+
+    pub cancel: Option<CancelToken>,
+}
+
+impl Progress<'_> {
+    /// Report the completed fraction and fail if the operation has been
+    /// cancelled.
+    pub(crate) fn update(&self, fraction: f64) -> Result<(), String> {
+        if let Some(callback) = self.callback {
+            callback(fraction.clamp(0.0, 1.0));
+        }
+        match &self.cancel {
+            Some(token) if token.is_cancelled() => Err(CANCELLED.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_progress() {
+        let reported = RefCell::new(Vec::new());
+        let callback = |f: f64| reported.borrow_mut().push(f);
+        let token = CancelToken::new();
+        let progress = Progress {
+            callback: Some(&callback),
+            cancel: Some(token.clone()),
+        };
+        assert!(progress.update(0.5).is_ok());
+        token.cancel();
+        assert_eq!(progress.update(1.5), Err(CANCELLED.to_string()));
+        assert_eq!(*reported.borrow(), vec![0.5, 1.0]);
+        assert!(Progress::default().update(0.0).is_ok());
+    }
+}
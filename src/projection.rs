@@ -0,0 +1,230 @@
+use crate::reslice::orthonormal_frame;
+use crate::sampler::common::SamplingMode;
+use crate::sampler::traits::ReSample;
+use crate::sampler::trilinear::TriLinear;
+use crate::{resample_from_to, sanitize_im_shape, shape3, voxel_sizes};
+use nalgebra::{Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Intensity projection along an axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    /// Maximum intensity projection (MIP), e.g. for angiography.
+    Maximum,
+
+    /// Minimum intensity projection (MinIP), e.g. for airways.
+    Minimum,
+
+    /// Mean intensity projection.
+    Mean,
+}
+
+impl Projection {
+    /// Projection of `values`, ignoring NaN; NaN if all values are NaN.
+    fn reduce<'a>(self, values: impl Iterator<Item = &'a f64>) -> f64 {
+        let (mut acc, mut n) = (f64::NAN, 0usize);
+        for x in values.filter(|x| !x.is_nan()) {
+            acc = match (self, n) {
+                (_, 0) => *x,
+                (Projection::Maximum, _) => acc.max(*x),
+                (Projection::Minimum, _) => acc.min(*x),
+                (Projection::Mean, _) => acc + x,
+            };
+            n += 1;
+        }
+        match self {
+            Projection::Mean if n > 0 => acc / n as f64,
+            _ => acc,
+        }
+    }
+}
+
+/// Project a 3D image along `axis` over its full extent.
+///
+/// The result keeps the projected axis with a length of 1 and the affine
+/// places it at the center of the projected extent, so a slice of it can be
+/// rendered (see [`extract_slice`](crate::render::extract_slice)) or
+/// resampled like any other image. NaN voxels (e.g. outside of the field of
+/// view after [`align_to_direction`]) are ignored.
+pub fn project<T, U>(
+    in_im: &Array<U, IxDyn>,
+    affine: &Matrix4<T>,
+    axis: usize,
+    projection: Projection,
+) -> Result<(Array<f64, IxDyn>, Matrix4<T>), String>
+where
+    T: Scalar + RealField + Copy,
+    U: AsPrimitive<f64>,
+    f64: AsPrimitive<T>,
+{
+    let im = as_f64(in_im, axis)?;
+    let projected = im
+        .map_axis(Axis(axis), |lane| projection.reduce(lane.iter()))
+        .insert_axis(Axis(axis));
+
+    let center = (im.shape()[axis] as f64 - 1.0) / 2.0;
+    let mut offset = Vector3::zeros();
+    offset[axis] = center.as_();
+    Ok((projected, affine * Matrix4::new_translation(&offset)))
+}
+
+/// Sliding thick slab projection: every slice along `axis` is replaced by
+/// the projection of the slab of `thickness` mm centered on it (at least the
+/// slice itself), as used for thin slab MIP reading. The grid is unchanged.
+pub fn slab_project<T, U>(
+    in_im: &Array<U, IxDyn>,
+    affine: &Matrix4<T>,
+    axis: usize,
+    projection: Projection,
+    thickness: f64,
+) -> Result<Array<f64, IxDyn>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+{
+    let im = as_f64(in_im, axis)?;
+    if !(thickness.is_finite() && thickness >= 0.0) {
+        return Err("slab thickness has to be finite and non-negative".into());
+    }
+    let spacing: f64 = voxel_sizes(affine)[axis].as_();
+    let half = ((thickness / spacing).round() as usize).saturating_sub(1) / 2;
+
+    let n = im.shape()[axis];
+    let mut out = Array::zeros(im.raw_dim());
+    for (lane, mut out_lane) in im
+        .lanes(Axis(axis))
+        .into_iter()
+        .zip(out.lanes_mut(Axis(axis)))
+    {
+        for (i, x) in out_lane.iter_mut().enumerate() {
+            let (lo, hi) = (i.saturating_sub(half), (i + half).min(n - 1));
+            *x = projection.reduce(lane.slice(s![lo..=hi]).iter());
+        }
+    }
+    Ok(out)
+}
+
+/// Resample a 3D image onto a grid whose third axis points along
+/// `direction` (world coordinates) with isotropic `voxel_size` mm, so that
+/// it can be projected along an arbitrary direction with [`project`] or
+/// [`slab_project`] along axis 2.
+///
+/// The grid covers the whole input; samples outside of the input are NaN.
+pub fn align_to_direction<T, U>(
+    in_im: &Array<U, IxDyn>,
+    affine: &Matrix4<T>,
+    direction: &Vector3<f64>,
+    voxel_size: f64,
+) -> Result<(Array<f64, IxDyn>, Matrix4<f64>), String>
+where
+    T: Scalar + AsPrimitive<f64>,
+    U: AsPrimitive<f64>,
+{
+    let im = as_f64(in_im, 2)?;
+    if !(voxel_size.is_finite() && voxel_size > 0.0) {
+        return Err("voxel size has to be positive".into());
+    }
+    let frame = orthonormal_frame(direction).ok_or("projection direction must not be zero")?;
+
+    // bounding box of the input corners in the frame
+    let affine: Matrix4<f64> = affine.map(|x| x.as_());
+    let shape = shape3(&im);
+    let (mut lo, mut hi) = (
+        Vector3::repeat(f64::INFINITY),
+        Vector3::repeat(f64::NEG_INFINITY),
+    );
+    for corner in 0..8 {
+        let idx = Vector3::from_fn(|d, _| ((corner >> d & 1) * (shape[d] - 1)) as f64);
+        let p = frame.transpose() * (affine * idx.push(1.0)).xyz();
+        lo = lo.inf(&p);
+        hi = hi.sup(&p);
+    }
+    let extent = (hi - lo) / voxel_size;
+    let out_shape = [0, 1, 2].map(|d| extent[d].ceil() as usize + 1);
+
+    let mut out_affine = Matrix4::identity();
+    out_affine
+        .fixed_slice_mut::<3, 3>(0, 0)
+        .copy_from(&(frame * voxel_size));
+    out_affine
+        .fixed_slice_mut::<3, 1>(0, 3)
+        .copy_from(&(frame * lo));
+
+    // a NaN cval would spread into samples on the border of the input,
+    // hence the field of view is determined separately
+    let mut sampler = TriLinear::default();
+    ReSample::<f64, f64>::set_sampling_mode(&mut sampler, SamplingMode::Nearest);
+    let mut aligned = resample_from_to(&im, &affine, &out_shape, &out_affine, &sampler)?;
+    let coverage = resample_from_to(
+        &Array::<f64, _>::ones(im.raw_dim()),
+        &affine,
+        &out_shape,
+        &out_affine,
+        &TriLinear::default(),
+    )?;
+    aligned.zip_mut_with(&coverage, |x, c| {
+        if *c < 1.0 - 1e-6 {
+            *x = f64::NAN
+        }
+    });
+    Ok((aligned, out_affine))
+}
+
+/// A 3D f64 copy of the image after checking the axis.
+fn as_f64<U>(in_im: &Array<U, IxDyn>, axis: usize) -> Result<Array<f64, IxDyn>, String>
+where
+    U: AsPrimitive<f64>,
+{
+    if axis > 2 {
+        return Err("projection axis has to be 0, 1 or 2".into());
+    }
+    Ok(sanitize_im_shape(in_im)?.mapv(|x| x.as_()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector4;
+
+    #[test]
+    fn test_project() {
+        let mut im = Array::from_shape_fn(IxDyn(&[2, 2, 4]), |idx| idx[2] as f64);
+        im[[0, 0, 1]] = f64::NAN;
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 1.0, 2.0));
+
+        let (mip, mip_affine) = project(&im, &affine, 2, Projection::Maximum).unwrap();
+        assert_eq!(mip.shape(), &[2, 2, 1]);
+        assert!(mip.iter().all(|x| *x == 3.0));
+        assert_eq!(mip_affine[(2, 3)], 3.0);
+        let (mean, _) = project(&im, &affine, 2, Projection::Mean).unwrap();
+        assert_eq!(mean[[0, 0, 0]], 5.0 / 3.0);
+        assert_eq!(mean[[1, 1, 0]], 1.5);
+
+        // 4 mm slabs span two slices, 6 mm three
+        let slab = slab_project(&im, &affine, 2, Projection::Minimum, 6.0).unwrap();
+        assert_eq!(slab.shape(), im.shape());
+        assert_eq!(slab.slice(s![1, 1, ..]).to_vec(), vec![0.0, 0.0, 1.0, 2.0]);
+        assert_eq!(slab.slice(s![0, 0, ..]).to_vec(), vec![0.0, 0.0, 2.0, 2.0]);
+        let thin = slab_project(&im, &affine, 2, Projection::Minimum, 0.0).unwrap();
+        assert_eq!(thin[[1, 1, 3]], 3.0);
+    }
+
+    #[test]
+    fn test_align_to_direction() {
+        let im = Array::from_shape_fn(IxDyn(&[5, 3, 3]), |idx| idx[0] as f64);
+        let affine = Matrix4::<f64>::identity();
+        let (aligned, aligned_affine) =
+            align_to_direction(&im, &affine, &Vector3::new(-2.0, 0.0, 0.0), 1.0).unwrap();
+        // the third axis runs along -x
+        assert_eq!(aligned.shape()[2], 5);
+        assert!(
+            (aligned_affine * Vector4::new(0.0, 0.0, 1.0, 0.0) - Vector4::new(-1.0, 0.0, 0.0, 0.0))
+                .norm()
+                < 1e-12
+        );
+        let (mip, _) = project(&aligned, &aligned_affine, 2, Projection::Maximum).unwrap();
+        assert!(mip.iter().all(|x| (*x - 4.0).abs() < 1e-9));
+        assert!(align_to_direction(&im, &affine, &Vector3::zeros(), 1.0).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::filter::gaussian::gaussian_filter;
+use crate::sampler::common::SamplingMode;
+use crate::sampler::traits::ReSample;
+use crate::sampler::trilinear::TriLinear;
+use crate::{resample_from_to, sanitize_im_shape, shape3};
+use nalgebra::{Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// One level of a [`gaussian_pyramid`].
+#[derive(Debug, Clone)]
+pub struct PyramidLevel<T>
+where
+    T: Scalar,
+{
+    /// Smoothed and downsampled image.
+    pub image: Array<f64, IxDyn>,
+
+    /// Affine of the downsampled image. It covers the same field of view as
+    /// the original image, with voxels enlarged by `factor`.
+    pub affine: Matrix4<T>,
+
+    /// Downsampling factor with respect to the original image.
+    pub factor: usize,
+}
+
+impl<T> PyramidLevel<T>
+where
+    T: Scalar,
+{
+    /// The shape of the downsampled image.
+    pub fn shape(&self) -> [usize; 3] {
+        shape3(&self.image)
+    }
+}
+
+/// Standard deviation (voxels of the original image) of the gaussian applied
+/// before downsampling by `factor`.
+pub fn pyramid_sigma(factor: usize) -> f64 {
+    if factor > 1 {
+        0.5 * factor as f64
+    } else {
+        0.0
+    }
+}
+
+/// Build a multi-resolution pyramid of in_im, e.g. for coarse-to-fine
+/// registration or fast previews.
+///
+/// For each of the `shrink_factors` the image is smoothed with a gaussian of
+/// [`pyramid_sigma`] voxels and sampled at the centers of blocks of `factor`
+/// voxels per axis. The levels are returned in the order of the factors; a
+/// factor of 1 yields the original image.
+pub fn gaussian_pyramid<T, U>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    shrink_factors: &[usize],
+) -> Result<Vec<PyramidLevel<T>>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+    f64: AsPrimitive<T>,
+{
+    if shrink_factors.is_empty() || shrink_factors.contains(&0) {
+        return Err("shrink factors have to be at least 1".into());
+    }
+    let in_im = sanitize_im_shape(in_im)?;
+    let in_shape = shape3(&in_im);
+    let in_affine: Matrix4<f64> = in_affine.map(|x| x.as_());
+
+    let mut sampler = TriLinear::<f64>::default();
+    ReSample::<f64, f64>::set_sampling_mode(&mut sampler, SamplingMode::Nearest);
+
+    shrink_factors
+        .iter()
+        .map(|&factor| {
+            let f = factor as f64;
+            let offset = (f - 1.0) / 2.0;
+            let affine = in_affine
+                * Matrix4::new_nonuniform_scaling(&Vector3::new(f, f, f))
+                    .append_translation(&Vector3::new(offset, offset, offset));
+            let sigma = pyramid_sigma(factor);
+            let smoothed = gaussian_filter(&in_im, &[sigma; 3])?;
+            let image = if factor == 1 {
+                smoothed
+            } else {
+                let shape = in_shape.map(|n| n.div_ceil(factor));
+                resample_from_to(&smoothed, &in_affine, &shape, &affine, &sampler)?
+            };
+            Ok(PyramidLevel {
+                image,
+                affine: affine.map(|x| x.as_()),
+                factor,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn test_gaussian_pyramid() {
+        let im = Array::from_shape_fn(IxDyn(&[24, 9, 4]), |idx| idx[0] as f64);
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 1.0, 3.0))
+            .append_translation(&Vector3::new(-5.0, 1.0, 2.0));
+        let levels = gaussian_pyramid(&im, &affine, &[4, 2, 1]).unwrap();
+
+        assert_eq!(levels[0].shape(), [6, 3, 1]);
+        assert_eq!(levels[2].image, im);
+        // the first voxel of each level is centered on its block
+        let center = |l: &PyramidLevel<f64>| (l.affine * Vector3::zeros().push(1.0)).xyz();
+        let block = Vector3::new(1.5 * 2.0 - 5.0, 1.5 + 1.0, 1.5 * 3.0 + 2.0);
+        assert_relative_eq!(center(&levels[0]), block);
+        // linear ramps are preserved away from the border
+        assert_relative_eq!(levels[1].image[[5, 1, 0]], 10.5, epsilon = 1e-9);
+    }
+}
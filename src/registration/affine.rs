@@ -0,0 +1,177 @@
+use super::optimizer::Optimizer;
+use super::transform::affine_matrix;
+use super::{Initialization, Metric, Problem, RegistrationResult};
+use crate::progress::Progress;
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Degrees of freedom of an affine registration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DegreesOfFreedom {
+    /// Rotations, translations and scaling along the three axes.
+    Nine,
+
+    /// Additionally three shears.
+    Twelve,
+}
+
+/// Parameters of the [`affine_registration`].
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct AffineRegistration {
+    /// Degrees of freedom of the transform.
+    pub dof: DegreesOfFreedom,
+
+    /// Similarity metric to optimize.
+    pub metric: Metric,
+
+    /// Optimizer and its step sizes (mm) at the finest resolution level.
+    pub optimizer: Optimizer,
+
+    /// Downsampling factors of the fixed image, from coarse to fine.
+    pub shrink_factors: Vec<usize>,
+
+    /// Initial alignment of the moving image.
+    pub initialization: Initialization,
+}
+
+impl Default for AffineRegistration {
+    fn default() -> Self {
+        Self {
+            dof: DegreesOfFreedom::Twelve,
+            metric: Metric::default(),
+            optimizer: Optimizer::default(),
+            shrink_factors: vec![4, 2, 1],
+            initialization: Initialization::CenterOfMass,
+        }
+    }
+}
+
+/// Affine (9 or 12 DOF) registration of `moving` to `fixed`.
+///
+/// The transform is parameterized as in
+/// [`affine_matrix`](super::transform::affine_matrix) about the center of
+/// mass of the fixed image (or the center of its field of view, see
+/// [`Initialization`]). Rotations, scales and shears are scaled by the radius
+/// of the fixed field of view, so that a unit step of the optimizer moves
+/// voxels by about 1 mm for every parameter.
+///
+/// The returned parameters always hold all 12 values, with the shears set to
+/// zero for 9 DOF. The transform maps fixed world coordinates to moving world
+/// coordinates, as for the [`rigid_registration`](super::rigid::rigid_registration).
+pub fn affine_registration<T, U, V>(
+    fixed: &Array<U, IxDyn>,
+    fixed_affine: &Matrix4<T>,
+    moving: &Array<V, IxDyn>,
+    moving_affine: &Matrix4<T>,
+    params: &AffineRegistration,
+) -> Result<RegistrationResult<T>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+    f64: AsPrimitive<T>,
+{
+    affine_registration_with_progress(
+        fixed,
+        fixed_affine,
+        moving,
+        moving_affine,
+        params,
+        &Progress::default(),
+    )
+}
+
+/// [`affine_registration`] reporting progress after each resolution level, which
+/// is also when cancellation is checked.
+pub fn affine_registration_with_progress<T, U, V>(
+    fixed: &Array<U, IxDyn>,
+    fixed_affine: &Matrix4<T>,
+    moving: &Array<V, IxDyn>,
+    moving_affine: &Matrix4<T>,
+    params: &AffineRegistration,
+    progress: &Progress,
+) -> Result<RegistrationResult<T>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+    f64: AsPrimitive<T>,
+{
+    let problem = Problem::new(
+        fixed,
+        fixed_affine,
+        moving,
+        moving_affine,
+        params.initialization,
+    )?;
+    let radius = problem.radius;
+
+    // scaled parameters -> transform parameters
+    let unscale = |u: &[f64]| {
+        let mut p = [0.0; 12];
+        for (i, p) in p.iter_mut().enumerate() {
+            *p = match i {
+                3..=5 => u[i],
+                6..=8 => 1.0 + u[i] / radius,
+                _ => u.get(i).map_or(0.0, |u| u / radius),
+            };
+        }
+        p
+    };
+    let to_matrix = |u: &[f64]| affine_matrix(&unscale(u), &problem.center);
+
+    let n_params = match params.dof {
+        DegreesOfFreedom::Nine => 9,
+        DegreesOfFreedom::Twelve => 12,
+    };
+    let mut u = vec![0.0; n_params];
+    u[3..6].copy_from_slice(problem.translation.as_slice());
+    let (u, metric_value, n_iter) = problem.optimize(
+        u,
+        &to_matrix,
+        &params.metric,
+        &params.optimizer,
+        &params.shrink_factors,
+        progress,
+    )?;
+
+    Ok(RegistrationResult {
+        transform: to_matrix(&u).map(|x| x.as_()),
+        parameters: unscale(&u).to_vec(),
+        metric_value,
+        n_iter,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registration::{blob_phantom, center_of_mass};
+    use nalgebra::Vector3;
+
+    #[test]
+    fn test_affine_registration() {
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(1.5, 1.5, 1.5));
+        let fixed = blob_phantom(&Matrix4::identity());
+        let center = center_of_mass(&fixed, &affine).unwrap();
+        let mut truth = [0.0; 12];
+        truth[..6].copy_from_slice(&[0.0, 0.0, 0.05, 2.0, -1.0, 0.5]);
+        truth[6..9].copy_from_slice(&[1.08, 0.95, 1.0]);
+        let expected = affine_matrix(&truth, &center);
+        let moving = blob_phantom(&expected);
+
+        let params = AffineRegistration {
+            dof: DegreesOfFreedom::Nine,
+            metric: Metric::NormalizedCrossCorrelation,
+            shrink_factors: vec![2, 1],
+            ..Default::default()
+        };
+        let result = affine_registration(&fixed, &affine, &moving, &affine, &params).unwrap();
+        assert!((result.transform - expected).abs().max() < 0.3);
+        for (p, t) in result.parameters.iter().zip(&truth).skip(6) {
+            assert!((p - t).abs() < 0.02);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use crate::filter::gaussian::gaussian_filter;
+use crate::progress::Progress;
+use crate::pyramid::gaussian_pyramid;
+use crate::sampler::traits::ReSample;
+use crate::sampler::trilinear::TriLinear;
+use crate::trace::{debug_event, timed_span};
+use crate::warp::{
+    compose_displacements, exponentiate, field_from_vectors, field_vectors, grid_points,
+    sample_field, warp_image,
+};
+use crate::{afftra_to_aff_tra, sanitize_im_shape, shape3};
+use nalgebra::{Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Parameters of the [`demons_registration`].
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Demons {
+    /// Number of iterations per resolution level.
+    pub iterations: usize,
+
+    /// Downsampling factors of the fixed image, from coarse to fine.
+    pub shrink_factors: Vec<usize>,
+
+    /// Standard deviation (voxels) of the gaussian smoothing of each update
+    /// (fluid-like regularization). 0 disables the smoothing.
+    pub update_sigma: f64,
+
+    /// Standard deviation (voxels) of the gaussian smoothing of the
+    /// displacement field after each update (diffusion-like regularization).
+    pub field_sigma: f64,
+
+    /// Maximum length (voxels) of the demons force of a single iteration.
+    pub max_step: f64,
+
+    /// Compose the exponential of each update (diffeomorphic demons) instead
+    /// of the update itself.
+    pub diffeomorphic: bool,
+}
+
+impl Default for Demons {
+    fn default() -> Self {
+        Self {
+            iterations: 50,
+            shrink_factors: vec![4, 2, 1],
+            update_sigma: 1.0,
+            field_sigma: 1.5,
+            max_step: 1.0,
+            diffeomorphic: true,
+        }
+    }
+}
+
+/// Result of a [`demons_registration`].
+#[derive(Debug, Clone)]
+pub struct DemonsResult {
+    /// Displacement field (mm) on the grid of the fixed image, see
+    /// [`warp`](crate::warp). Warping the moving image with it aligns the
+    /// moving image to the fixed image.
+    pub displacement: Array<f64, IxDyn>,
+
+    /// Mean squared intensity difference between the fixed and the warped
+    /// moving image.
+    pub mean_squared_error: f64,
+
+    /// Total number of iterations over all resolution levels.
+    pub n_iter: usize,
+}
+
+/// Demons deformable registration of `moving` to `fixed`.
+///
+/// The demons force is derived from the intensity difference and the
+/// symmetric (fixed and warped moving) image gradient, as for intra-modality
+/// images with comparable intensities. With `diffeomorphic` set, the smoothed
+/// force is treated as a velocity field and composed through its exponential
+/// (Vercauteren et al.), which keeps the transform invertible.
+///
+/// The moving image is expected to be roughly aligned, e.g. by resampling it
+/// with the result of an [`affine_registration`](super::affine::affine_registration).
+pub fn demons_registration<T, U, V>(
+    fixed: &Array<U, IxDyn>,
+    fixed_affine: &Matrix4<T>,
+    moving: &Array<V, IxDyn>,
+    moving_affine: &Matrix4<T>,
+    params: &Demons,
+) -> Result<DemonsResult, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+{
+    demons_registration_with_progress(
+        fixed,
+        fixed_affine,
+        moving,
+        moving_affine,
+        params,
+        &Progress::default(),
+    )
+}
+
+/// [`demons_registration`] reporting progress after each iteration, which is
+/// also when cancellation is checked.
+pub fn demons_registration_with_progress<T, U, V>(
+    fixed: &Array<U, IxDyn>,
+    fixed_affine: &Matrix4<T>,
+    moving: &Array<V, IxDyn>,
+    moving_affine: &Matrix4<T>,
+    params: &Demons,
+    progress: &Progress,
+) -> Result<DemonsResult, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+{
+    let fixed: Array<f64, IxDyn> = sanitize_im_shape(fixed)?.mapv(|x| x.as_());
+    let moving: Array<f64, IxDyn> = sanitize_im_shape(moving)?.mapv(|x| x.as_());
+    let fixed_affine: Matrix4<f64> = fixed_affine.map(|x| x.as_());
+    let moving_affine: Matrix4<f64> = moving_affine.map(|x| x.as_());
+    if params.max_step <= 0.0 {
+        return Err("max_step has to be positive".into());
+    }
+
+    timed_span!(
+        "demons_registration",
+        fixed_shape = ?fixed.shape(),
+        moving_shape = ?moving.shape()
+    );
+    let mut sampler = TriLinear::<f64>::default();
+    ReSample::<f64, f64>::set_cval(&mut sampler, f64::NAN);
+
+    let levels = gaussian_pyramid(&fixed, &fixed_affine, &params.shrink_factors)?;
+    let mut field: Option<(Array<f64, IxDyn>, Matrix4<f64>)> = None;
+    let mut n_iter = 0;
+    let total_iter = (levels.len() * params.iterations).max(1) as f64;
+    progress.update(0.0)?;
+    for level in &levels {
+        let shape = level.shape();
+        let mut displacement = match &field {
+            None => Array::zeros(IxDyn(&[shape[0], shape[1], shape[2], 3])),
+            Some((previous, previous_affine)) => {
+                resample_field(previous, previous_affine, &shape, &level.affine)?
+            }
+        };
+
+        let (aff, _) = afftra_to_aff_tra(&level.affine);
+        let voxel_size = (0..3).map(|i| aff.column(i).norm()).sum::<f64>() / 3.0;
+        let sigma_x = 2.0 * params.max_step * voxel_size;
+        let fixed_gradient = image_gradient(&level.image, &level.affine)?;
+
+        for _ in 0..params.iterations {
+            n_iter += 1;
+            let warped = warp_image(
+                &moving,
+                &moving_affine,
+                &displacement,
+                &level.affine,
+                &sampler,
+            )?;
+            let warped_gradient = image_gradient(&warped, &level.affine)?;
+
+            let force: Vec<Vector3<f64>> = level
+                .image
+                .iter()
+                .zip(warped.iter())
+                .zip(fixed_gradient.iter().zip(&warped_gradient))
+                .map(|((f, w), (gf, gw))| {
+                    let diff = w - f;
+                    let g = 0.5 * (gf + gw);
+                    let denominator = g.norm_squared() + diff * diff / (sigma_x * sigma_x);
+                    let u = -diff * g / denominator;
+                    if denominator > 1e-12 && u.iter().all(|x| x.is_finite()) {
+                        u
+                    } else {
+                        Vector3::zeros()
+                    }
+                })
+                .collect();
+
+            let mut update =
+                smooth_field(&field_from_vectors(&shape, &force), params.update_sigma)?;
+            if params.diffeomorphic {
+                // enough squaring steps to keep each step below half a voxel
+                let max_norm = field_vectors(&update)
+                    .iter()
+                    .map(|u| u.norm())
+                    .fold(0.0, f64::max);
+                let steps = (max_norm / (0.5 * voxel_size)).log2().ceil().max(0.0) as usize;
+                update = exponentiate(&update, &level.affine, steps)?;
+            }
+            displacement = compose_displacements(&update, &displacement, &level.affine)?;
+            displacement = smooth_field(&displacement, params.field_sigma)?;
+            progress.update(n_iter as f64 / total_iter)?;
+        }
+        debug_event!(factor = level.factor, n_iter, "registered resolution level");
+        field = Some((displacement, level.affine));
+    }
+
+    let (field, field_affine) = field.expect("at least one resolution level");
+    let shape = shape3(&fixed);
+    let displacement = resample_field(&field, &field_affine, &shape, &fixed_affine)?;
+    let warped = warp_image(
+        &moving,
+        &moving_affine,
+        &displacement,
+        &fixed_affine,
+        &sampler,
+    )?;
+    Ok(DemonsResult {
+        mean_squared_error: mean_squared_error(&fixed, &warped),
+        displacement,
+        n_iter,
+    })
+}
+
+/// Resample a displacement field onto another grid.
+fn resample_field(
+    field: &Array<f64, IxDyn>,
+    field_affine: &Matrix4<f64>,
+    shape: &[usize; 3],
+    affine: &Matrix4<f64>,
+) -> Result<Array<f64, IxDyn>, String> {
+    let vectors = sample_field(field, field_affine, &grid_points(shape, affine))?;
+    Ok(field_from_vectors(shape, &vectors))
+}
+
+/// Smooth each component of a displacement field.
+fn smooth_field(field: &Array<f64, IxDyn>, sigma: f64) -> Result<Array<f64, IxDyn>, String> {
+    if sigma == 0.0 {
+        return Ok(field.clone());
+    }
+    let mut smoothed = field.clone();
+    for c in 0..3 {
+        let component = gaussian_filter(&field.index_axis(Axis(3), c).to_owned(), &[sigma; 3])?;
+        smoothed.index_axis_mut(Axis(3), c).assign(&component);
+    }
+    Ok(smoothed)
+}
+
+/// World space intensity gradient of an image in row-major voxel order.
+/// Gradients next to non-finite voxels are non-finite.
+fn image_gradient(
+    im: &Array<f64, IxDyn>,
+    affine: &Matrix4<f64>,
+) -> Result<Vec<Vector3<f64>>, String> {
+    let shape = shape3(im);
+    let (aff, _) = afftra_to_aff_tra(affine);
+    let inv_t = aff
+        .try_inverse()
+        .ok_or("no valid matrix inverse found for the affine")?
+        .transpose();
+    Ok(im
+        .indexed_iter()
+        .map(|(idx, _)| {
+            let mut g = Vector3::zeros();
+            for axis in 0..3 {
+                if shape[axis] < 2 {
+                    continue;
+                }
+                let (mut lo, mut hi) = ([idx[0], idx[1], idx[2]], [idx[0], idx[1], idx[2]]);
+                lo[axis] = idx[axis].saturating_sub(1);
+                hi[axis] = (idx[axis] + 1).min(shape[axis] - 1);
+                g[axis] = (im[IxDyn(&hi)] - im[IxDyn(&lo)]) / (hi[axis] - lo[axis]) as f64;
+            }
+            inv_t * g
+        })
+        .collect())
+}
+
+/// Mean squared difference over all voxels where both images are finite.
+fn mean_squared_error(a: &Array<f64, IxDyn>, b: &Array<f64, IxDyn>) -> f64 {
+    let (sum, n) = a
+        .iter()
+        .zip(b.iter())
+        .map(|(a, b)| a - b)
+        .filter(|d| d.is_finite())
+        .fold((0.0, 0usize), |(sum, n), d| (sum + d * d, n + 1));
+    sum / n as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registration::blob_phantom;
+    use crate::warp::jacobian_determinant;
+
+    #[test]
+    fn test_demons_registration() {
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(1.5, 1.5, 1.5));
+        let fixed = blob_phantom(&Matrix4::identity());
+
+        // smooth bump displacement of up to 1.5 mm along x
+        let truth = Array::from_shape_fn(IxDyn(&[16, 16, 16, 3]), |idx| {
+            let r2 = (0..3).map(|i| (idx[i] as f64 - 7.5).powi(2)).sum::<f64>();
+            if idx[3] == 0 {
+                1.5 * (-r2 / 50.0).exp()
+            } else {
+                0.0
+            }
+        });
+        let moving = warp_image(&fixed, &affine, &truth, &affine, &TriLinear::default()).unwrap();
+        let initial = mean_squared_error(&fixed, &moving);
+
+        let params = Demons {
+            iterations: 30,
+            shrink_factors: vec![2, 1],
+            ..Default::default()
+        };
+        let result = demons_registration(&fixed, &affine, &moving, &affine, &params).unwrap();
+        assert!(result.mean_squared_error < 0.2 * initial);
+        let det = jacobian_determinant(&result.displacement, &affine).unwrap();
+        assert!(det.iter().all(|d| *d > 0.0));
+    }
+}
@@ -0,0 +1,342 @@
+use crate::metrics::similarity::normalized_cross_correlation;
+use crate::progress::Progress;
+use crate::pyramid::{gaussian_pyramid, PyramidLevel};
+use crate::sampler::traits::ReSample;
+use crate::sampler::trilinear::TriLinear;
+use crate::trace::{debug_event, timed_span};
+use crate::{resample_with_transform, sanitize_im_shape, shape3};
+use nalgebra::{Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+use optimizer::minimize;
+
+// registration implementations:
+pub mod affine;
+pub mod demons;
+pub mod motion;
+pub mod optimizer;
+pub mod rigid;
+pub mod transform;
+
+pub use optimizer::Optimizer;
+
+/// Similarity metric driving an intensity based registration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    /// Mutual information estimated from a joint histogram with `bins` bins
+    /// per image. Samples are distributed linearly between neighbouring bins
+    /// (partial volume interpolation), which keeps the metric smooth with
+    /// respect to the transform. Suited for inter-modality registration.
+    MutualInformation { bins: usize },
+
+    /// Normalized cross-correlation. Suited for intra-modality registration.
+    NormalizedCrossCorrelation,
+}
+
+impl Default for Metric {
+    fn default() -> Self {
+        Metric::MutualInformation { bins: 32 }
+    }
+}
+
+impl Metric {
+    /// Cost to be minimized, evaluated over all voxels where both images are
+    /// finite. Returns infinity if the images do not overlap.
+    pub(crate) fn cost(&self, fixed: &Array<f64, IxDyn>, moving: &Array<f64, IxDyn>) -> f64 {
+        let similarity = match *self {
+            Metric::MutualInformation { bins } => smooth_mutual_information(fixed, moving, bins),
+            Metric::NormalizedCrossCorrelation => normalized_cross_correlation(fixed, moving, None),
+        };
+        similarity.map_or(f64::INFINITY, |s| -s)
+    }
+}
+
+/// Mutual information with partial volume interpolation of the joint
+/// histogram, over all voxels where both images are finite.
+fn smooth_mutual_information(
+    fixed: &Array<f64, IxDyn>,
+    moving: &Array<f64, IxDyn>,
+    bins: usize,
+) -> Result<f64, String> {
+    if bins < 2 {
+        return Err("number of bins has to be at least 2".into());
+    }
+    let pairs: Vec<(f64, f64)> = fixed
+        .iter()
+        .zip(moving.iter())
+        .filter(|(f, m)| f.is_finite() && m.is_finite())
+        .map(|(f, m)| (*f, *m))
+        .collect();
+    if pairs.is_empty() {
+        return Err("no voxels to compare".into());
+    }
+    let range = |values: &mut dyn Iterator<Item = f64>| {
+        values.fold((f64::INFINITY, f64::NEG_INFINITY), |(mn, mx), x| {
+            (mn.min(x), mx.max(x))
+        })
+    };
+    let (f_min, f_max) = range(&mut pairs.iter().map(|p| p.0));
+    let (m_min, m_max) = range(&mut pairs.iter().map(|p| p.1));
+    // continuous bin coordinate, split into the lower bin and its weight
+    let split = |x: f64, mn: f64, mx: f64| {
+        let t = if mx > mn {
+            (x - mn) / (mx - mn) * (bins - 1) as f64
+        } else {
+            0.0
+        };
+        let lower = (t.floor() as usize).min(bins - 2);
+        (lower, t - lower as f64)
+    };
+
+    let mut joint = vec![0.0; bins * bins];
+    for (f, m) in &pairs {
+        let (i, wi) = split(*f, f_min, f_max);
+        let (j, wj) = split(*m, m_min, m_max);
+        joint[i * bins + j] += (1.0 - wi) * (1.0 - wj);
+        joint[i * bins + j + 1] += (1.0 - wi) * wj;
+        joint[(i + 1) * bins + j] += wi * (1.0 - wj);
+        joint[(i + 1) * bins + j + 1] += wi * wj;
+    }
+    let n = pairs.len() as f64;
+    let mut p_f = vec![0.0; bins];
+    let mut p_m = vec![0.0; bins];
+    for i in 0..bins {
+        for j in 0..bins {
+            p_f[i] += joint[i * bins + j] / n;
+            p_m[j] += joint[i * bins + j] / n;
+        }
+    }
+    let entropy = |p: &mut dyn Iterator<Item = f64>| -> f64 {
+        p.filter(|x| *x > 0.0).map(|x| -x * x.ln()).sum()
+    };
+    Ok(
+        entropy(&mut p_f.into_iter()) + entropy(&mut p_m.into_iter())
+            - entropy(&mut joint.into_iter().map(|x| x / n)),
+    )
+}
+
+/// Initial alignment of the moving image, before optimization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Initialization {
+    /// Start from the identity transform.
+    Identity,
+
+    /// Align the centers of the fields of view.
+    Geometry,
+
+    /// Align the intensity weighted centers of mass. Transforms are further
+    /// parameterized about the center of mass of the fixed image.
+    CenterOfMass,
+}
+
+/// Result of a registration.
+#[derive(Debug, Clone)]
+pub struct RegistrationResult<T> {
+    /// World space transform mapping fixed world coordinates to moving world
+    /// coordinates, as expected by [`resample_with_transform`](crate::resample_with_transform).
+    pub transform: Matrix4<T>,
+
+    /// Optimized transform parameters.
+    pub parameters: Vec<f64>,
+
+    /// Value of the similarity metric at the finest resolution level.
+    pub metric_value: f64,
+
+    /// Total number of optimizer iterations over all resolution levels.
+    pub n_iter: usize,
+}
+
+/// A registration problem: both images converted to f64, together with the
+/// center of rotation and the initial translation.
+pub(crate) struct Problem {
+    pub(crate) fixed: Array<f64, IxDyn>,
+    pub(crate) fixed_affine: Matrix4<f64>,
+    pub(crate) moving: Array<f64, IxDyn>,
+    pub(crate) moving_affine: Matrix4<f64>,
+
+    /// Center (world coordinates) about which the transform is parameterized.
+    pub(crate) center: Vector3<f64>,
+
+    /// Radius (mm) of the fixed field of view, used for parameter scaling.
+    pub(crate) radius: f64,
+
+    /// Initial translation (mm).
+    pub(crate) translation: Vector3<f64>,
+}
+
+impl Problem {
+    pub(crate) fn new<T, U, V>(
+        fixed: &Array<U, IxDyn>,
+        fixed_affine: &Matrix4<T>,
+        moving: &Array<V, IxDyn>,
+        moving_affine: &Matrix4<T>,
+        initialization: Initialization,
+    ) -> Result<Self, String>
+    where
+        T: Scalar + RealField + AsPrimitive<f64> + Copy,
+        U: AsPrimitive<f64>,
+        V: AsPrimitive<f64>,
+    {
+        let fixed: Array<f64, IxDyn> = sanitize_im_shape(fixed)?.mapv(|x| x.as_());
+        let moving: Array<f64, IxDyn> = sanitize_im_shape(moving)?.mapv(|x| x.as_());
+        let fixed_affine: Matrix4<f64> = fixed_affine.map(|x| x.as_());
+        let moving_affine: Matrix4<f64> = moving_affine.map(|x| x.as_());
+        if fixed_affine.try_inverse().is_none() || moving_affine.try_inverse().is_none() {
+            return Err("no valid matrix inverse found for the image affines".into());
+        }
+
+        let fixed_shape = shape3(&fixed);
+        let moving_shape = shape3(&moving);
+        let fixed_center = fov_center(&fixed_shape, &fixed_affine);
+        let (center, translation) = match initialization {
+            Initialization::Identity => (fixed_center, Vector3::zeros()),
+            Initialization::Geometry => (
+                fixed_center,
+                fov_center(&moving_shape, &moving_affine) - fixed_center,
+            ),
+            Initialization::CenterOfMass => {
+                let fixed_com = center_of_mass(&fixed, &fixed_affine)?;
+                let moving_com = center_of_mass(&moving, &moving_affine)?;
+                (fixed_com, moving_com - fixed_com)
+            }
+        };
+        Ok(Self {
+            radius: fov_radius(&fixed_shape, &fixed_affine),
+            fixed,
+            fixed_affine,
+            moving,
+            moving_affine,
+            center,
+            translation,
+        })
+    }
+
+    /// Optimize the scaled parameters `u` from coarse to fine resolution
+    /// levels, reporting progress after each level. Returns the parameters,
+    /// the similarity at the finest level and the total number of
+    /// iterations.
+    pub(crate) fn optimize(
+        &self,
+        mut u: Vec<f64>,
+        to_matrix: &dyn Fn(&[f64]) -> Matrix4<f64>,
+        metric: &Metric,
+        optimizer: &Optimizer,
+        shrink_factors: &[usize],
+        progress: &Progress,
+    ) -> Result<(Vec<f64>, f64, usize), String> {
+        timed_span!(
+            "register",
+            fixed_shape = ?self.fixed.shape(),
+            moving_shape = ?self.moving.shape(),
+            n_params = u.len()
+        );
+        let levels = gaussian_pyramid(&self.fixed, &self.fixed_affine, shrink_factors)?;
+        let mut metric_value = f64::INFINITY;
+        let mut n_iter = 0;
+        progress.update(0.0)?;
+        for (i, level) in levels.iter().enumerate() {
+            let cost = |u: &[f64]| {
+                transform_cost(
+                    level,
+                    &self.moving,
+                    &self.moving_affine,
+                    &to_matrix(u),
+                    metric,
+                )
+            };
+            let optimizer = optimizer.scaled(level.factor as f64);
+            let (u_level, cost_level, n_level) = minimize(&cost, &u, &optimizer);
+            u = u_level;
+            metric_value = -cost_level;
+            n_iter += n_level;
+            debug_event!(
+                factor = level.factor,
+                metric_value,
+                n_iter = n_level,
+                "registered resolution level"
+            );
+            progress.update((i + 1) as f64 / levels.len() as f64)?;
+        }
+        Ok((u, metric_value, n_iter))
+    }
+}
+
+/// Cost of `transform` on a resolution level. The moving image is sampled
+/// trilinearly; samples outside of the moving image are excluded.
+pub(crate) fn transform_cost(
+    level: &PyramidLevel<f64>,
+    moving: &Array<f64, IxDyn>,
+    moving_affine: &Matrix4<f64>,
+    transform: &Matrix4<f64>,
+    metric: &Metric,
+) -> f64 {
+    let mut sampler = TriLinear::<f64>::default();
+    ReSample::<f64, f64>::set_cval(&mut sampler, f64::NAN);
+    match resample_with_transform(
+        moving,
+        moving_affine,
+        transform,
+        &level.shape(),
+        &level.affine,
+        &sampler,
+    ) {
+        Ok(warped) => metric.cost(&level.image, &warped),
+        Err(_) => f64::INFINITY,
+    }
+}
+
+/// World coordinates of the center of the field of view of an image.
+pub(crate) fn fov_center(shape: &[usize; 3], affine: &Matrix4<f64>) -> Vector3<f64> {
+    let center = Vector3::from(shape.map(|n| (n as f64 - 1.0) / 2.0));
+    (affine * center.push(1.0)).xyz()
+}
+
+/// Intensity weighted center of mass (world coordinates) of an image.
+/// Intensities are taken relative to the image minimum.
+pub(crate) fn center_of_mass(
+    im: &Array<f64, IxDyn>,
+    affine: &Matrix4<f64>,
+) -> Result<Vector3<f64>, String> {
+    let min = im
+        .iter()
+        .copied()
+        .filter(|x| x.is_finite())
+        .fold(f64::INFINITY, f64::min);
+    let mut com = Vector3::zeros();
+    let mut total = 0.0;
+    for (idx, x) in im.indexed_iter() {
+        if x.is_finite() {
+            let w = x - min;
+            com += Vector3::new(idx[0] as f64, idx[1] as f64, idx[2] as f64) * w;
+            total += w;
+        }
+    }
+    if total == 0.0 {
+        return Err("center of mass of a constant image is undefined".into());
+    }
+    Ok((affine * (com / total).push(1.0)).xyz())
+}
+
+/// Half of the diagonal (mm) of the field of view of an image.
+pub(crate) fn fov_radius(shape: &[usize; 3], affine: &Matrix4<f64>) -> f64 {
+    let extent = Vector3::from(shape.map(|n| n as f64));
+    let extent: Vector3<f64> = affine.fixed_slice::<3, 3>(0, 0) * extent;
+    (0.5 * extent.norm()).max(1.0)
+}
+
+/// Gaussian blobs on a grid of 16^3 voxels of 1.5 mm, moved by the world
+/// space transform `m`.
+#[cfg(test)]
+pub(crate) fn blob_phantom(m: &Matrix4<f64>) -> Array<f64, IxDyn> {
+    let inv = m.try_inverse().unwrap();
+    Array::from_shape_fn(IxDyn(&[16, 16, 16]), |idx| {
+        let p = Vector3::new(idx[0] as f64, idx[1] as f64, idx[2] as f64) * 1.5;
+        let p = (inv * p.push(1.0)).xyz();
+        let blob = |c: Vector3<f64>, s: f64| (-(p - c).norm_squared() / (2.0 * s * s)).exp();
+        0.3 * blob(Vector3::new(12.0, 12.0, 12.0), 6.0)
+            + blob(Vector3::new(5.0, 12.0, 12.0), 2.0)
+            + 0.7 * blob(Vector3::new(17.0, 8.0, 13.0), 2.0)
+            + 0.5 * blob(Vector3::new(12.0, 18.0, 7.0), 2.0)
+            + 0.85 * blob(Vector3::new(13.0, 10.0, 18.0), 2.0)
+    })
+}
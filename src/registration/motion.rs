@@ -0,0 +1,235 @@
+use super::rigid::{rigid_registration, RigidRegistration};
+use super::{Initialization, Metric};
+use crate::progress::Progress;
+use crate::resample_with_transform;
+use crate::sampler::trilinear::TriLinear;
+use crate::trace::{debug_event, timed_span};
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Reference volume of a [`motion_correction`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reference {
+    /// The mean of all volumes.
+    Mean,
+
+    /// The first volume.
+    First,
+
+    /// The volume with the given index.
+    Volume(usize),
+}
+
+/// Parameters of the [`motion_correction`].
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct MotionCorrection {
+    /// Volume all other volumes are aligned to.
+    pub reference: Reference,
+
+    /// Registration of each volume to the reference.
+    pub registration: RigidRegistration,
+
+    /// Radius (mm) of the sphere on which rotations are converted to
+    /// displacements for the framewise displacement.
+    pub head_radius: f64,
+}
+
+impl Default for MotionCorrection {
+    fn default() -> Self {
+        Self {
+            reference: Reference::Mean,
+            registration: RigidRegistration {
+                metric: Metric::NormalizedCrossCorrelation,
+                shrink_factors: vec![2, 1],
+                initialization: Initialization::Identity,
+                ..Default::default()
+            },
+            head_radius: 50.0,
+        }
+    }
+}
+
+/// Result of a [`motion_correction`].
+#[derive(Debug, Clone)]
+pub struct MotionCorrected<T>
+where
+    T: Scalar,
+{
+    /// The realigned series, resampled trilinearly onto the reference grid.
+    pub corrected: Array<f64, IxDyn>,
+
+    /// Transform of each volume, mapping reference world coordinates to
+    /// world coordinates of the volume.
+    pub transforms: Vec<Matrix4<T>>,
+
+    /// Rigid parameters of each volume: rotations about x, y and z (radians)
+    /// followed by translations (mm), see
+    /// [`rigid_matrix`](super::transform::rigid_matrix).
+    pub parameters: Vec<[f64; 6]>,
+
+    /// Framewise displacement (mm) of each volume, 0 for the first volume.
+    pub framewise_displacement: Vec<f64>,
+}
+
+/// Rigid motion correction of a 4D series (e.g. fMRI or DWI) of shape
+/// (x, y, z, t).
+///
+/// Each volume is registered to the reference and resampled exactly once.
+/// All volumes share `in_affine`.
+pub fn motion_correction<T, U>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    params: &MotionCorrection,
+) -> Result<MotionCorrected<T>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+    f64: AsPrimitive<T>,
+{
+    motion_correction_with_progress(in_im, in_affine, params, &Progress::default())
+}
+
+/// [`motion_correction`] reporting progress after each volume, which is
+/// also when cancellation is checked.
+pub fn motion_correction_with_progress<T, U>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    params: &MotionCorrection,
+    progress: &Progress,
+) -> Result<MotionCorrected<T>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+    f64: AsPrimitive<T>,
+{
+    if in_im.ndim() != 4 {
+        return Err("motion correction expects a 4D series".into());
+    }
+    let series: Array<f64, IxDyn> = in_im.mapv(|x| x.as_());
+    let n_volumes = series.shape()[3];
+    if n_volumes == 0 {
+        return Err("series does not contain any volumes".into());
+    }
+    let reference = match params.reference {
+        Reference::Mean => series
+            .mean_axis(Axis(3))
+            .expect("series contains at least one volume"),
+        Reference::First => series.index_axis(Axis(3), 0).to_owned(),
+        Reference::Volume(t) if t < n_volumes => series.index_axis(Axis(3), t).to_owned(),
+        Reference::Volume(_) => return Err("reference volume index out of bounds".into()),
+    };
+
+    timed_span!("motion_correction", shape = ?series.shape());
+    let affine: Matrix4<f64> = in_affine.map(|x| x.as_());
+    let shape = [series.shape()[0], series.shape()[1], series.shape()[2]];
+    let sampler = TriLinear::<f64>::default();
+    let mut corrected = Array::zeros(series.raw_dim());
+    let mut transforms = Vec::with_capacity(n_volumes);
+    let mut parameters = Vec::with_capacity(n_volumes);
+    progress.update(0.0)?;
+    for t in 0..n_volumes {
+        let volume = series.index_axis(Axis(3), t).to_owned();
+        let result = rigid_registration::<f64, f64, f64>(
+            &reference,
+            &affine,
+            &volume,
+            &affine,
+            &params.registration,
+        )?;
+        let realigned = resample_with_transform(
+            &volume,
+            &affine,
+            &result.transform,
+            &shape,
+            &affine,
+            &sampler,
+        )?;
+        corrected.index_axis_mut(Axis(3), t).assign(&realigned);
+        transforms.push(result.transform.map(|x| x.as_()));
+        let mut p = [0.0; 6];
+        p.copy_from_slice(&result.parameters);
+        debug_event!(t, parameters = ?p, "realigned volume");
+        parameters.push(p);
+        progress.update((t + 1) as f64 / n_volumes as f64)?;
+    }
+
+    Ok(MotionCorrected {
+        corrected,
+        framewise_displacement: framewise_displacement(&parameters, params.head_radius),
+        transforms,
+        parameters,
+    })
+}
+
+/// Framewise displacement (Power et al. 2012): the sum of the absolute
+/// changes of the rigid parameters between consecutive volumes, with
+/// rotations converted to arc lengths on a sphere of `radius` mm.
+pub fn framewise_displacement(parameters: &[[f64; 6]], radius: f64) -> Vec<f64> {
+    let mut fd = vec![0.0; parameters.len().min(1)];
+    fd.extend(parameters.windows(2).map(|w| {
+        (0..6)
+            .map(|i| {
+                let d = (w[1][i] - w[0][i]).abs();
+                if i < 3 {
+                    d * radius
+                } else {
+                    d
+                }
+            })
+            .sum::<f64>()
+    }));
+    fd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registration::blob_phantom;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn test_motion_correction() {
+        let shifts = [
+            Vector3::zeros(),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.5),
+        ];
+        let mut series = Array::zeros(IxDyn(&[16, 16, 16, 3]));
+        for (t, shift) in shifts.iter().enumerate() {
+            series
+                .index_axis_mut(Axis(3), t)
+                .assign(&blob_phantom(&Matrix4::new_translation(shift)));
+        }
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(1.5, 1.5, 1.5));
+        let params = MotionCorrection {
+            reference: Reference::First,
+            ..Default::default()
+        };
+        let result = motion_correction(&series, &affine, &params).unwrap();
+
+        for (p, shift) in result.parameters.iter().zip(&shifts) {
+            assert!((Vector3::from_column_slice(&p[3..]) - shift).norm() < 0.3);
+        }
+        let fd = &result.framewise_displacement;
+        assert_eq!(fd[0], 0.0);
+        assert!((fd[1] - 1.0).abs() < 0.5 && (fd[2] - 2.5).abs() < 0.5);
+
+        // the corrected volume is much closer to the reference than the original
+        let reference = series.index_axis(Axis(3), 0);
+        let mean_error = |v: ArrayViewD<f64>| (&v - &reference).mapv(f64::abs).mean().unwrap();
+        let before = mean_error(series.index_axis(Axis(3), 2));
+        let after = mean_error(result.corrected.index_axis(Axis(3), 2));
+        assert!(after < 0.3 * before);
+    }
+
+    #[test]
+    fn test_framewise_displacement() {
+        let params = [[0.0; 6], [0.01, 0.0, 0.0, 0.5, -0.5, 0.0]];
+        let fd = framewise_displacement(&params, 50.0);
+        assert_eq!(fd.len(), 2);
+        assert!((fd[1] - 1.5).abs() < 1e-12);
+        assert!(framewise_displacement(&[], 50.0).is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+/// Optimization strategy used by the registration routines.
+///
+/// All step sizes refer to the scaled parameter space of the respective
+/// registration, in which a unit step moves voxels by roughly 1 mm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Optimizer {
+    /// Regular step gradient descent with finite difference gradients. The
+    /// step is halved every time it fails to improve the metric.
+    GradientDescent {
+        step: f64,
+        min_step: f64,
+        max_iter: usize,
+    },
+
+    /// Powell's conjugate direction method with golden section line searches.
+    Powell {
+        step: f64,
+        tolerance: f64,
+        max_iter: usize,
+    },
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Optimizer::GradientDescent {
+            step: 2.0,
+            min_step: 0.01,
+            max_iter: 200,
+        }
+    }
+}
+
+impl Optimizer {
+    /// The same optimizer with its step sizes scaled by `factor`.
+    pub(crate) fn scaled(&self, factor: f64) -> Self {
+        match *self {
+            Optimizer::GradientDescent {
+                step,
+                min_step,
+                max_iter,
+            } => Optimizer::GradientDescent {
+                step: step * factor,
+                min_step: min_step * factor,
+                max_iter,
+            },
+            Optimizer::Powell {
+                step,
+                tolerance,
+                max_iter,
+            } => Optimizer::Powell {
+                step: step * factor,
+                tolerance,
+                max_iter,
+            },
+        }
+    }
+}
+
+/// Minimize `f` starting at `x0`. Returns the minimizer, the minimum and the
+/// number of iterations performed.
+pub(crate) fn minimize(
+    f: &dyn Fn(&[f64]) -> f64,
+    x0: &[f64],
+    optimizer: &Optimizer,
+) -> (Vec<f64>, f64, usize) {
+    match *optimizer {
+        Optimizer::GradientDescent {
+            step,
+            min_step,
+            max_iter,
+        } => gradient_descent(f, x0, step, min_step, max_iter),
+        Optimizer::Powell {
+            step,
+            tolerance,
+            max_iter,
+        } => powell(f, x0, step, tolerance, max_iter),
+    }
+}
+
+fn gradient_descent(
+    f: &dyn Fn(&[f64]) -> f64,
+    x0: &[f64],
+    mut step: f64,
+    min_step: f64,
+    max_iter: usize,
+) -> (Vec<f64>, f64, usize) {
+    let mut x = x0.to_vec();
+    let mut fx = f(&x);
+    let mut n_iter = 0;
+    while n_iter < max_iter && step >= min_step {
+        n_iter += 1;
+
+        // central finite differences
+        let h = (0.5 * step).max(min_step);
+        let gradient: Vec<f64> = (0..x.len())
+            .map(|i| {
+                let mut xp = x.clone();
+                let mut xm = x.clone();
+                xp[i] += h;
+                xm[i] -= h;
+                (f(&xp) - f(&xm)) / (2.0 * h)
+            })
+            .collect();
+        let norm = gradient.iter().map(|g| g * g).sum::<f64>().sqrt();
+        if !norm.is_finite() || norm == 0.0 {
+            step *= 0.5;
+            continue;
+        }
+
+        let candidate: Vec<f64> = x
+            .iter()
+            .zip(&gradient)
+            .map(|(x, g)| x - step * g / norm)
+            .collect();
+        let f_candidate = f(&candidate);
+        if f_candidate < fx {
+            x = candidate;
+            fx = f_candidate;
+        } else {
+            step *= 0.5;
+        }
+    }
+    (x, fx, n_iter)
+}
+
+fn powell(
+    f: &dyn Fn(&[f64]) -> f64,
+    x0: &[f64],
+    step: f64,
+    tolerance: f64,
+    max_iter: usize,
+) -> (Vec<f64>, f64, usize) {
+    let n = x0.len();
+    let mut directions: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+    let mut x = x0.to_vec();
+    let mut fx = f(&x);
+    let mut n_iter = 0;
+
+    while n_iter < max_iter {
+        n_iter += 1;
+        let (x_start, f_start) = (x.clone(), fx);
+        let mut largest = (0, 0.0);
+        for (i, d) in directions.iter().enumerate() {
+            let (x_new, f_new) = line_search(f, &x, d, step, fx);
+            if fx - f_new > largest.1 {
+                largest = (i, fx - f_new);
+            }
+            x = x_new;
+            fx = f_new;
+        }
+        if f_start - fx <= tolerance * 0.5 * (f_start.abs() + fx.abs()) + 1e-12 {
+            break;
+        }
+
+        // replace the direction of largest decrease by the overall displacement
+        let displacement: Vec<f64> = x.iter().zip(&x_start).map(|(a, b)| a - b).collect();
+        let norm = displacement.iter().map(|d| d * d).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            let d: Vec<f64> = displacement.iter().map(|d| d / norm).collect();
+            let (x_new, f_new) = line_search(f, &x, &d, step, fx);
+            x = x_new;
+            fx = f_new;
+            directions.remove(largest.0);
+            directions.push(d);
+        }
+    }
+    (x, fx, n_iter)
+}
+
+/// Golden section line search along `d` within a bracket found by stepping
+/// outwards from `x` in multiples of `step`.
+fn line_search(
+    f: &dyn Fn(&[f64]) -> f64,
+    x: &[f64],
+    d: &[f64],
+    step: f64,
+    fx: f64,
+) -> (Vec<f64>, f64) {
+    let at = |alpha: f64| -> Vec<f64> { x.iter().zip(d).map(|(x, d)| x + alpha * d).collect() };
+    let eval = |alpha: f64| f(&at(alpha));
+
+    // bracket the minimum
+    let (f_minus, f_plus) = (eval(-step), eval(step));
+    let (mut best, mut f_best) = (0.0, fx);
+    let direction = if f_plus < f_minus { 1.0 } else { -1.0 };
+    if f_plus.min(f_minus) < fx {
+        best = direction * step;
+        f_best = f_plus.min(f_minus);
+        let mut delta = step;
+        for _ in 0..10 {
+            delta *= 2.0;
+            let f_next = eval(best + direction * delta);
+            if f_next < f_best {
+                best += direction * delta;
+                f_best = f_next;
+            } else {
+                break;
+            }
+        }
+    }
+
+    // refine by golden section search
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    let (mut a, mut b) = (best - step, best + step);
+    let mut c = b - ratio * (b - a);
+    let mut e = a + ratio * (b - a);
+    let (mut fc, mut fe) = (eval(c), eval(e));
+    for _ in 0..12 {
+        if fc < fe {
+            b = e;
+            e = c;
+            fe = fc;
+            c = b - ratio * (b - a);
+            fc = eval(c);
+        } else {
+            a = c;
+            c = e;
+            fc = fe;
+            e = a + ratio * (b - a);
+            fe = eval(e);
+        }
+    }
+    let (alpha, f_alpha) = if fc < fe { (c, fc) } else { (e, fe) };
+    if f_alpha < f_best {
+        (at(alpha), f_alpha)
+    } else {
+        (at(best), f_best)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quadratic(x: &[f64]) -> f64 {
+        (x[0] - 1.0).powi(2) + 4.0 * (x[1] + 2.0).powi(2) + x[0] * x[1]
+    }
+
+    #[test]
+    fn test_minimize() {
+        // minimum of the quadratic form at (32 / 15, -34 / 15)
+        for optimizer in [
+            Optimizer::default(),
+            Optimizer::Powell {
+                step: 1.0,
+                tolerance: 1e-10,
+                max_iter: 100,
+            },
+        ] {
+            let (x, _, _) = minimize(&quadratic, &[0.0, 0.0], &optimizer);
+            assert!((x[0] - 32.0 / 15.0).abs() < 0.05, "{optimizer:?}");
+            assert!((x[1] + 34.0 / 15.0).abs() < 0.05, "{optimizer:?}");
+        }
+    }
+}
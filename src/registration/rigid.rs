@@ -0,0 +1,180 @@
+use super::optimizer::Optimizer;
+use super::transform::rigid_matrix;
+use super::{Initialization, Metric, Problem, RegistrationResult};
+use crate::progress::Progress;
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Parameters of the [`rigid_registration`].
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct RigidRegistration {
+    /// Similarity metric to optimize.
+    pub metric: Metric,
+
+    /// Optimizer and its step sizes (mm) at the finest resolution level.
+    pub optimizer: Optimizer,
+
+    /// Downsampling factors of the fixed image, from coarse to fine.
+    pub shrink_factors: Vec<usize>,
+
+    /// Initial alignment of the moving image.
+    pub initialization: Initialization,
+}
+
+impl Default for RigidRegistration {
+    fn default() -> Self {
+        Self {
+            metric: Metric::default(),
+            optimizer: Optimizer::default(),
+            shrink_factors: vec![4, 2, 1],
+            initialization: Initialization::Geometry,
+        }
+    }
+}
+
+/// Rigid (6 DOF) registration of `moving` to `fixed`.
+///
+/// The transform is parameterized by three rotations about the center of the
+/// fixed field of view (or the fixed center of mass, see [`Initialization`])
+/// and a translation, see
+/// [`rigid_matrix`](super::transform::rigid_matrix). Rotations are scaled by
+/// the radius of the fixed field of view, so that a unit step of the
+/// optimizer moves voxels by about 1 mm. The registration proceeds from coarse
+/// to fine resolution levels of the fixed image, each initialized with the
+/// result of the previous one.
+///
+/// The returned transform maps fixed world coordinates to moving world
+/// coordinates, i.e. `resample_with_transform(moving, moving_affine,
+/// &result.transform, fixed_shape, fixed_affine, sampler)` aligns the moving
+/// image to the fixed image.
+pub fn rigid_registration<T, U, V>(
+    fixed: &Array<U, IxDyn>,
+    fixed_affine: &Matrix4<T>,
+    moving: &Array<V, IxDyn>,
+    moving_affine: &Matrix4<T>,
+    params: &RigidRegistration,
+) -> Result<RegistrationResult<T>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+    f64: AsPrimitive<T>,
+{
+    rigid_registration_with_progress(
+        fixed,
+        fixed_affine,
+        moving,
+        moving_affine,
+        params,
+        &Progress::default(),
+    )
+}
+
+/// [`rigid_registration`] reporting progress after each resolution level, which
+/// is also when cancellation is checked.
+pub fn rigid_registration_with_progress<T, U, V>(
+    fixed: &Array<U, IxDyn>,
+    fixed_affine: &Matrix4<T>,
+    moving: &Array<V, IxDyn>,
+    moving_affine: &Matrix4<T>,
+    params: &RigidRegistration,
+    progress: &Progress,
+) -> Result<RegistrationResult<T>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+    U: AsPrimitive<f64>,
+    V: AsPrimitive<f64>,
+    f64: AsPrimitive<T>,
+{
+    let problem = Problem::new(
+        fixed,
+        fixed_affine,
+        moving,
+        moving_affine,
+        params.initialization,
+    )?;
+    let radius = problem.radius;
+    let to_matrix = |u: &[f64]| {
+        rigid_matrix(
+            &[
+                u[0] / radius,
+                u[1] / radius,
+                u[2] / radius,
+                u[3],
+                u[4],
+                u[5],
+            ],
+            &problem.center,
+        )
+    };
+
+    let mut u = vec![0.0; 6];
+    u[3..].copy_from_slice(problem.translation.as_slice());
+    let (u, metric_value, n_iter) = problem.optimize(
+        u,
+        &to_matrix,
+        &params.metric,
+        &params.optimizer,
+        &params.shrink_factors,
+        progress,
+    )?;
+
+    let mut parameters = u.clone();
+    parameters[..3].iter_mut().for_each(|x| *x /= radius);
+    Ok(RegistrationResult {
+        transform: to_matrix(&u).map(|x| x.as_()),
+        parameters,
+        metric_value,
+        n_iter,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registration::blob_phantom;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn test_rigid_registration() {
+        let center = Vector3::new(11.25, 11.25, 11.25);
+        let expected = rigid_matrix(&[0.0, 0.0, 0.08, 1.5, -1.0, 0.5], &center);
+        let fixed = blob_phantom(&Matrix4::identity());
+        let moving = blob_phantom(&expected);
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(1.5, 1.5, 1.5));
+        let params = RigidRegistration {
+            metric: Metric::NormalizedCrossCorrelation,
+            shrink_factors: vec![2, 1],
+            ..Default::default()
+        };
+        let result = rigid_registration(&fixed, &affine, &moving, &affine, &params).unwrap();
+        assert!((result.transform - expected).abs().max() < 0.3);
+        assert!((result.parameters[2] - 0.08).abs() < 0.01);
+        assert!(result.metric_value > 0.99);
+    }
+
+    #[test]
+    fn test_rigid_registration_mutual_information() {
+        let shift = Vector3::new(1.3, -0.8, 2.1);
+        let fixed = blob_phantom(&Matrix4::identity());
+        // inverted contrast
+        let moving = blob_phantom(&Matrix4::new_translation(&shift)).mapv(|x| 2.0 - x);
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(1.5, 1.5, 1.5));
+        let params = RigidRegistration {
+            metric: Metric::MutualInformation { bins: 16 },
+            optimizer: Optimizer::Powell {
+                step: 1.0,
+                tolerance: 1e-6,
+                max_iter: 20,
+            },
+            shrink_factors: vec![2, 1],
+            ..Default::default()
+        };
+        let result = rigid_registration(&fixed, &affine, &moving, &affine, &params).unwrap();
+        let translation = Vector3::from_column_slice(&result.parameters[3..]);
+        assert!((translation - shift).norm() < 0.3);
+        assert!(result.parameters[..3].iter().all(|r| r.abs() < 0.02));
+    }
+}
@@ -0,0 +1,100 @@
+use nalgebra::{Matrix3, Matrix4, Rotation3, Vector3};
+
+/// Rotation matrix for rotations (radians) about the x, y and z axes,
+/// applied in that order.
+pub fn rotation_matrix(angles: &[f64; 3]) -> Matrix3<f64> {
+    Rotation3::from_euler_angles(angles[0], angles[1], angles[2]).into_inner()
+}
+
+/// Rigid world space transform `p -> R (p - c) + c + t`.
+///
+/// `params` holds the rotations about the x, y and z axes (radians) followed
+/// by the translation `t` (mm). The rotation is applied about `center`.
+pub fn rigid_matrix(params: &[f64; 6], center: &Vector3<f64>) -> Matrix4<f64> {
+    let r = rotation_matrix(&[params[0], params[1], params[2]]);
+    let t = Vector3::new(params[3], params[4], params[5]);
+    linear_about_center(&r, &t, center)
+}
+
+/// Affine world space transform `p -> R K S (p - c) + c + t`.
+///
+/// `params` holds the rotations about the x, y and z axes (radians), the
+/// translation `t` (mm), the scaling factors `S` along x, y and z and the
+/// shears `K` in the xy, xz and yz planes, i.e. the upper triangle of the
+/// shear matrix. All transforms are applied about `center`.
+pub fn affine_matrix(params: &[f64; 12], center: &Vector3<f64>) -> Matrix4<f64> {
+    let r = rotation_matrix(&[params[0], params[1], params[2]]);
+    let t = Vector3::new(params[3], params[4], params[5]);
+    let s = Matrix3::from_diagonal(&Vector3::new(params[6], params[7], params[8]));
+    #[rustfmt::skip]
+    let k = Matrix3::new(
+        1.0, params[9], params[10],
+        0.0, 1.0, params[11],
+        0.0, 0.0, 1.0,
+    );
+    linear_about_center(&(r * k * s), &t, center)
+}
+
+/// Homogeneous matrix of `p -> A (p - c) + c + t`.
+pub(crate) fn linear_about_center(
+    a: &Matrix3<f64>,
+    t: &Vector3<f64>,
+    center: &Vector3<f64>,
+) -> Matrix4<f64> {
+    let offset = center + t - a * center;
+    let mut m = a.to_homogeneous();
+    m[(0, 3)] = offset.x;
+    m[(1, 3)] = offset.y;
+    m[(2, 3)] = offset.z;
+    m
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn test_rigid_matrix() {
+        let center = Vector3::new(10.0, 0.0, 0.0);
+        let m = rigid_matrix(
+            &[0.0, 0.0, std::f64::consts::FRAC_PI_2, 1.0, 2.0, 3.0],
+            &center,
+        );
+        // the center is only translated
+        assert_relative_eq!((m * center.push(1.0)).xyz(), Vector3::new(11.0, 2.0, 3.0));
+        // rotation of 90° about z
+        let p = Vector3::new(11.0, 0.0, 0.0);
+        assert_relative_eq!(
+            (m * p.push(1.0)).xyz(),
+            Vector3::new(11.0, 3.0, 3.0),
+            epsilon = 1e-12
+        );
+    }
+
+    #[test]
+    fn test_affine_matrix() {
+        let center = Vector3::new(1.0, 2.0, 3.0);
+        let rigid = [0.1, -0.2, 0.3, 1.0, 2.0, 3.0];
+        let mut params = [0.0; 12];
+        params[..6].copy_from_slice(&rigid);
+        params[6..9].copy_from_slice(&[1.0; 3]);
+        assert_relative_eq!(
+            affine_matrix(&params, &center),
+            rigid_matrix(&rigid, &center),
+            epsilon = 1e-12
+        );
+
+        // scaling and shear about the center
+        let mut params = [0.0; 12];
+        params[6..9].copy_from_slice(&[2.0, 1.0, 0.5]);
+        params[9] = 0.5;
+        let m = affine_matrix(&params, &center);
+        assert_relative_eq!((m * center.push(1.0)).xyz(), center);
+        let p = center + Vector3::new(1.0, 1.0, 2.0);
+        assert_relative_eq!(
+            (m * p.push(1.0)).xyz(),
+            center + Vector3::new(2.5, 1.0, 1.0)
+        );
+    }
+}
@@ -0,0 +1,252 @@
+use crate::sanitize_im_shape;
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+use std::io::{self, Write};
+
+/// An 8 bit RGB image of shape (rows, columns, 3).
+pub type RgbImage = Array3<u8>;
+
+/// Color lookup for overlays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colormap {
+    /// Black to white.
+    Gray,
+
+    /// Black over red and yellow to white.
+    Hot,
+
+    /// Blue over cyan, yellow and red.
+    Jet,
+
+    /// A fixed categorical palette indexed by the (rounded) label value.
+    Labels,
+}
+
+/// Categorical colors, repeated for labels beyond its length.
+const LABEL_PALETTE: [[u8; 3]; 10] = [
+    [230, 25, 75],
+    [60, 180, 75],
+    [255, 225, 25],
+    [0, 130, 200],
+    [245, 130, 48],
+    [145, 30, 180],
+    [70, 240, 240],
+    [240, 50, 230],
+    [210, 245, 60],
+    [250, 190, 212],
+];
+
+impl Colormap {
+    /// Color of `value`, which is expected in [0, 1] (clamped) for the
+    /// continuous colormaps and to be a label for [`Colormap::Labels`].
+    pub fn color(&self, value: f64) -> [u8; 3] {
+        let t = value.clamp(0.0, 1.0);
+        let rgb = match self {
+            Colormap::Gray => [t, t, t],
+            Colormap::Hot => [3.0 * t, 3.0 * t - 1.0, 3.0 * t - 2.0],
+            Colormap::Jet => [
+                1.5 - (4.0 * t - 3.0).abs(),
+                1.5 - (4.0 * t - 2.0).abs(),
+                1.5 - (4.0 * t - 1.0).abs(),
+            ],
+            Colormap::Labels => {
+                let label = value.round().max(1.0) as usize;
+                return LABEL_PALETTE[(label - 1) % LABEL_PALETTE.len()];
+            }
+        };
+        rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+}
+
+/// Extract the slice `index` along `axis` of a 3D image.
+///
+/// The slice is oriented for display: columns run along the first remaining
+/// axis and rows along the second one, with its largest index at the top.
+/// For an image in RAS orientation an axial slice (`axis` 2) hence shows
+/// right to the right and anterior to the top.
+pub fn extract_slice<U>(
+    in_im: &Array<U, IxDyn>,
+    axis: usize,
+    index: usize,
+) -> Result<Array2<f64>, String>
+where
+    U: AsPrimitive<f64>,
+{
+    let im = sanitize_im_shape(in_im)?;
+    if axis > 2 {
+        return Err("slice axis has to be 0, 1 or 2".into());
+    }
+    if index >= im.shape()[axis] {
+        return Err("slice index out of bounds".into());
+    }
+    let slice = im.index_axis(Axis(axis), index);
+    let mut slice = slice.t().mapv(|x| x.as_());
+    slice.invert_axis(Axis(0));
+    Ok(slice
+        .into_dimensionality()
+        .expect("slice of a 3D image is 2D"))
+}
+
+/// Render a slice in grayscale, mapping `window` (low, high) to black and
+/// white. The window defaults to the finite intensity range of the slice.
+pub fn grayscale(slice: &Array2<f64>, window: Option<(f64, f64)>) -> RgbImage {
+    let (low, high) = window.unwrap_or_else(|| finite_range(slice));
+    let scale = if high > low { 1.0 / (high - low) } else { 0.0 };
+    let (rows, cols) = slice.dim();
+    Array3::from_shape_fn((rows, cols, 3), |(r, c, _)| {
+        let t = (slice[[r, c]] - low) * scale;
+        Colormap::Gray.color(if t.is_finite() { t } else { 0.0 })[0]
+    })
+}
+
+/// Parameters of an [`overlay`].
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Overlay {
+    /// Colormap of the overlaid map.
+    pub colormap: Colormap,
+
+    /// Opacity of the overlay, from 0 (transparent) to 1 (opaque).
+    pub alpha: f64,
+
+    /// Values mapped to the ends of a continuous colormap; defaults to the
+    /// finite range of the map. Ignored for [`Colormap::Labels`].
+    pub range: Option<(f64, f64)>,
+
+    /// Values at or below the threshold (e.g. background label 0 or low
+    /// probabilities) are not drawn.
+    pub threshold: f64,
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Self {
+            colormap: Colormap::Hot,
+            alpha: 0.5,
+            range: None,
+            threshold: 0.0,
+        }
+    }
+}
+
+/// Blend a label or probability map slice onto a rendered slice, e.g. for
+/// segmentation QC figures.
+pub fn overlay(base: &RgbImage, map: &Array2<f64>, params: &Overlay) -> Result<RgbImage, String> {
+    let (rows, cols, _) = base.dim();
+    if map.dim() != (rows, cols) {
+        return Err("overlay and base image shapes do not match".into());
+    }
+    if !(0.0..=1.0).contains(&params.alpha) {
+        return Err("alpha has to be within [0, 1]".into());
+    }
+    let (low, high) = params.range.unwrap_or_else(|| finite_range(map));
+    let scale = if high > low { 1.0 / (high - low) } else { 0.0 };
+
+    let mut blended = base.clone();
+    for ((r, c), value) in map.indexed_iter() {
+        if !value.is_finite() || *value <= params.threshold {
+            continue;
+        }
+        let color = match params.colormap {
+            Colormap::Labels => params.colormap.color(*value),
+            colormap => colormap.color((value - low) * scale),
+        };
+        for (channel, overlay) in color.iter().enumerate() {
+            let b = blended[[r, c, channel]] as f64;
+            let mixed = (1.0 - params.alpha) * b + params.alpha * *overlay as f64;
+            blended[[r, c, channel]] = mixed.round() as u8;
+        }
+    }
+    Ok(blended)
+}
+
+/// Tile rendered slices of equal size into a grid with `columns` columns,
+/// row by row. Unused tiles remain black.
+pub fn montage(images: &[RgbImage], columns: usize) -> Result<RgbImage, String> {
+    let first = images
+        .first()
+        .ok_or("montage requires at least one image")?;
+    if columns == 0 {
+        return Err("number of columns has to be at least 1".into());
+    }
+    let (rows, cols, _) = first.dim();
+    if images.iter().any(|im| im.dim() != first.dim()) {
+        return Err("montage images differ in shape".into());
+    }
+    let grid_rows = images.len().div_ceil(columns);
+    let mut out = Array3::zeros((grid_rows * rows, columns * cols, 3));
+    for (n, im) in images.iter().enumerate() {
+        let (r, c) = (n / columns * rows, n % columns * cols);
+        out.slice_mut(s![r..r + rows, c..c + cols, ..]).assign(im);
+    }
+    Ok(out)
+}
+
+/// Write an image as binary PPM (P6), which most image tools can convert.
+pub fn write_ppm<W: Write>(image: &RgbImage, writer: &mut W) -> io::Result<()> {
+    let (rows, cols, _) = image.dim();
+    write!(writer, "P6\n{cols} {rows}\n255\n")?;
+    match image.as_slice() {
+        Some(data) => writer.write_all(data),
+        None => writer.write_all(&image.iter().copied().collect::<Vec<u8>>()),
+    }
+}
+
+/// Range of the finite values, (0, 0) if there are none.
+fn finite_range(values: &Array2<f64>) -> (f64, f64) {
+    let (low, high) = values
+        .iter()
+        .filter(|x| x.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), x| {
+            (lo.min(*x), hi.max(*x))
+        });
+    if low <= high {
+        (low, high)
+    } else {
+        (0.0, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_slice() {
+        let im = Array::from_shape_fn(IxDyn(&[3, 2, 4]), |idx| (idx[0] * 10 + idx[1]) as f64);
+        let slice = extract_slice(&im, 2, 1).unwrap();
+        assert_eq!(slice.dim(), (2, 3));
+        // top left is the first x and the last y
+        assert_eq!(slice[[0, 0]], 1.0);
+        assert_eq!(slice[[1, 2]], 20.0);
+        assert!(extract_slice(&im, 2, 4).is_err());
+    }
+
+    #[test]
+    fn test_overlay_and_montage() {
+        let anatomy = Array2::from_shape_fn((2, 2), |(r, c)| (r * 2 + c) as f64);
+        let base = grayscale(&anatomy, None);
+        assert_eq!(base[[0, 0, 0]], 0);
+        assert_eq!(base[[1, 1, 2]], 255);
+
+        let labels = Array2::from_shape_vec((2, 2), vec![0.0, 1.0, 2.0, 0.0]).unwrap();
+        let params = Overlay {
+            colormap: Colormap::Labels,
+            alpha: 1.0,
+            ..Default::default()
+        };
+        let blended = overlay(&base, &labels, &params).unwrap();
+        assert_eq!(blended.slice(s![0, 0, ..]), base.slice(s![0, 0, ..]));
+        assert_eq!(blended.slice(s![0, 1, ..]).to_vec(), LABEL_PALETTE[0]);
+        assert_eq!(blended.slice(s![1, 0, ..]).to_vec(), LABEL_PALETTE[1]);
+
+        let grid = montage(&[base.clone(), blended, base], 2).unwrap();
+        assert_eq!(grid.dim(), (4, 4, 3));
+        assert!(grid.slice(s![2.., 2.., ..]).iter().all(|x| *x == 0));
+
+        let mut ppm = Vec::new();
+        write_ppm(&grid, &mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n4 4\n255\n"));
+        assert_eq!(ppm.len(), 11 + 4 * 4 * 3);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::sampler::traits::ReSample;
+use crate::{resample_tiled, sanitize_im_shape, vox2out_vox, Tiling};
+use nalgebra::{Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::{AsPrimitive, Num};
+use std::fmt::Display;
+
+/// Output grid of a [`Resample`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target<T>
+where
+    T: Scalar,
+{
+    Spacing([f32; 3]),
+    Grid {
+        shape: [usize; 3],
+        affine: Matrix4<T>,
+    },
+}
+
+/// Fluent configuration of a resampling, e.g.
+/// `Resample::to_spacing([1.0; 3]).with_sampler(NearestNeighbor::default())`,
+/// applied with [`Resample::apply`].
+///
+/// The sampler has to be set with [`Resample::with_sampler`]. Unless
+/// configured otherwise, images are resampled without a transform and with
+/// [`Tiling::Auto`], i.e. as [`resample_to_output`](crate::resample_to_output)
+/// and [`resample_from_to`](crate::resample_from_to) do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Resample<T, S>
+where
+    T: Scalar,
+{
+    target: Target<T>,
+    sampler: S,
+    transform: Option<Matrix4<T>>,
+    tiling: Tiling,
+}
+
+impl<T> Resample<T, ()>
+where
+    T: Scalar,
+{
+    /// Resample to world space with the given voxel sizes, on a grid covering
+    /// the (transformed) image.
+    pub fn to_spacing(voxel_sizes: [f32; 3]) -> Self {
+        Self::new(Target::Spacing(voxel_sizes))
+    }
+
+    /// Resample to the voxel space defined by shape and affine.
+    pub fn to_grid(shape: [usize; 3], affine: Matrix4<T>) -> Self {
+        Self::new(Target::Grid { shape, affine })
+    }
+
+    fn new(target: Target<T>) -> Self {
+        Self {
+            target,
+            sampler: (),
+            transform: None,
+            tiling: Tiling::Auto,
+        }
+    }
+}
+
+impl<T, S> Resample<T, S>
+where
+    T: Scalar,
+{
+    pub fn with_sampler<R>(self, sampler: R) -> Resample<T, R> {
+        Resample {
+            target: self.target,
+            sampler,
+            transform: self.transform,
+            tiling: self.tiling,
+        }
+    }
+
+    /// Move the image by a world space transform, mapping output world
+    /// coordinates to input world coordinates, see
+    /// [`resample_with_transform`](crate::resample_with_transform).
+    pub fn with_transform(mut self, transform: Matrix4<T>) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
+    pub fn with_tiling(mut self, tiling: Tiling) -> Self {
+        self.tiling = tiling;
+        self
+    }
+
+    /// Resample in_im (2D to 5D, see [`resample_from_to`](crate::resample_from_to)).
+    /// Returns the resampled image and its affine.
+    pub fn apply<U>(
+        &self,
+        in_im: &Array<U, IxDyn>,
+        in_affine: &Matrix4<T>,
+    ) -> Result<(Array<U, IxDyn>, Matrix4<T>), String>
+    where
+        T: Num + RealField + AsPrimitive<usize> + AsPrimitive<U> + Copy + Display,
+        U: Num + Copy + Send + Sync + 'static,
+        S: ReSample<T, U> + 'static,
+        f32: AsPrimitive<T>,
+        usize: AsPrimitive<T>,
+    {
+        let sanitized;
+        let in_im = if in_im.ndim() >= 4 {
+            in_im
+        } else {
+            sanitized = sanitize_im_shape(in_im)?;
+            &sanitized
+        };
+        let transform = self.transform.unwrap_or_else(Matrix4::identity);
+        let (out_shape, out_affine) = match self.target {
+            Target::Grid { shape, affine } => (shape, affine),
+            Target::Spacing(voxel_sizes) => {
+                let inv_transform = transform
+                    .try_inverse()
+                    .ok_or("no valid matrix inverse found for transform")?;
+                let in_shape = Vector3::from_row_slice(&in_im.shape()[..3]);
+                let voxel_sizes = Vector3::from(voxel_sizes.map(|x| x.as_()));
+                let (shape, affine) =
+                    vox2out_vox(&in_shape, &(inv_transform * in_affine), &voxel_sizes)?;
+                (shape.into(), affine)
+            }
+        };
+        let out_im = resample_tiled(
+            in_im,
+            in_affine,
+            &out_shape,
+            &(transform * out_affine),
+            &self.sampler,
+            self.tiling,
+        )?;
+        Ok((out_im, out_affine))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registration::transform::rigid_matrix;
+    use crate::sampler::trilinear::TriLinear;
+    use crate::{resample_to_output, resample_with_transform, NearestNeighbor, SamplingMode};
+
+    #[test]
+    fn test_resample_builder() {
+        let im = Array::from_shape_fn(IxDyn(&[6, 5, 4]), |idx| {
+            (idx[0] * 3 + idx[1] * 5 + idx[2] * 7) as f64
+        });
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 2.0, 1.5));
+        let sampler = TriLinear::builder().mode(SamplingMode::Nearest).build();
+
+        let (out, out_affine) = Resample::to_spacing([0.75, 1.0, 1.25])
+            .with_sampler(sampler)
+            .apply(&im, &affine)
+            .unwrap();
+        let expected = resample_to_output(&im, &affine, &[0.75, 1.0, 1.25], &sampler).unwrap();
+        assert_eq!((out, out_affine), expected);
+
+        let transform = rigid_matrix(&[0.2, 0.0, -0.1, 1.0, 0.0, 0.5], &Vector3::zeros());
+        let nearest = NearestNeighbor::builder().cval(-1.0).build();
+        let (out, out_affine) = Resample::to_grid([4, 4, 4], affine)
+            .with_sampler(nearest)
+            .with_transform(transform)
+            .with_tiling(Tiling::Tiles([2, 2, 2]))
+            .apply(&im, &affine)
+            .unwrap();
+        let expected =
+            resample_with_transform(&im, &affine, &transform, &[4, 4, 4], &affine, &nearest)
+                .unwrap();
+        assert_eq!(out, expected);
+        assert_eq!(out_affine, affine);
+    }
+}
@@ -0,0 +1,348 @@
+use crate::sample_world_points;
+use crate::sampler::traits::ReSample;
+use nalgebra::{Matrix3, Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::{AsPrimitive, Num};
+
+/// Right handed orthonormal frame (u, v, w) as columns, with w along
+/// `normal`. u is the projection of the x axis onto the plane (the y axis
+/// for normals close to x), so axial planes map u, v to x, y and sagittal
+/// planes to y, z.
+pub(crate) fn orthonormal_frame<T>(normal: &Vector3<T>) -> Option<Matrix3<T>>
+where
+    T: Scalar + RealField + Copy,
+{
+    let w = normal.try_normalize(T::default_epsilon())?;
+    let helper = if w.x.abs() < nalgebra::convert(0.9) {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+    let u = (helper - w * helper.dot(&w)).normalize();
+    let v = w.cross(&u);
+    Some(Matrix3::from_columns(&[u, v, w]))
+}
+
+/// Reslice a 3D image along an arbitrary (oblique) plane for multiplanar
+/// reformatting.
+///
+/// The plane is centered on `plane_origin` (world coordinates) and sampled
+/// on a `size` grid with `in_plane_spacing` mm, its axes forming a right
+/// handed frame with `plane_normal`, see [`orthonormal_frame`]. Returns the
+/// slice and its affine, mapping (column, row, 0) to world coordinates; the
+/// third column is the unit normal, so the slice can be used as a 3D image
+/// of shape (size, 1).
+pub fn extract_slice<T, U, S>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    plane_origin: &Vector3<T>,
+    plane_normal: &Vector3<T>,
+    in_plane_spacing: &[T; 2],
+    size: &[usize; 2],
+    sampler: &S,
+) -> Result<(Array2<U>, Matrix4<T>), String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<U> + Copy,
+    U: Num + Copy + Send + Sync + 'static,
+    S: ReSample<T, U> + ?Sized + 'static,
+    usize: AsPrimitive<T>,
+{
+    let frame = orthonormal_frame(plane_normal).ok_or("plane normal must not be zero")?;
+    if in_plane_spacing.iter().any(|s| *s <= T::zero()) {
+        return Err("in-plane spacing has to be positive".into());
+    }
+    if size.contains(&0) {
+        return Err("slice size has to be at least 1".into());
+    }
+    let affine = plane_affine(&frame, plane_origin, in_plane_spacing, size);
+
+    let (aff, tra) = (
+        affine.fixed_slice::<3, 3>(0, 0),
+        affine.fixed_slice::<3, 1>(0, 3),
+    );
+    let points: Vec<Vector3<T>> = (0..size[0])
+        .flat_map(|i| (0..size[1]).map(move |j| (i, j)))
+        .map(|(i, j)| aff * Vector3::new(i.as_(), j.as_(), T::zero()) + tra)
+        .collect();
+    let values = sample_world_points(in_im, in_affine, &points, sampler)?;
+    let slice = Array2::from_shape_vec((size[0], size[1]), values)
+        .expect("one value per point of the plane");
+    Ok((slice, affine))
+}
+
+/// Affine of a plane grid centered on `origin`.
+fn plane_affine<T>(
+    frame: &Matrix3<T>,
+    origin: &Vector3<T>,
+    spacing: &[T; 2],
+    size: &[usize; 2],
+) -> Matrix4<T>
+where
+    T: Scalar + RealField + Copy,
+    usize: AsPrimitive<T>,
+{
+    let two: T = nalgebra::convert(2.0);
+    let mut affine = Matrix4::identity();
+    let mut translation = *origin;
+    for d in 0..2 {
+        let axis = frame.column(d) * spacing[d];
+        affine.fixed_slice_mut::<3, 1>(0, d).copy_from(&axis);
+        translation -= axis * ((size[d] - 1).as_() / two);
+    }
+    affine
+        .fixed_slice_mut::<3, 1>(0, 2)
+        .copy_from(&frame.column(2));
+    affine.fixed_slice_mut::<3, 1>(0, 3).copy_from(&translation);
+    affine
+}
+
+/// Parameters of a [`curved_reformation`].
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurvedReformation {
+    /// Distance (mm) between consecutive planes along the path.
+    pub step: f64,
+
+    /// Pixel spacing (mm) within the planes.
+    pub in_plane_spacing: [f64; 2],
+
+    /// Number of pixels of each plane.
+    pub size: [usize; 2],
+}
+
+impl Default for CurvedReformation {
+    fn default() -> Self {
+        Self {
+            step: 1.0,
+            in_plane_spacing: [1.0, 1.0],
+            size: [32, 32],
+        }
+    }
+}
+
+/// Result of a [`curved_reformation`].
+#[derive(Debug, Clone)]
+pub struct Reformation<U> {
+    /// The straightened volume of shape (size, number of planes): plane
+    /// pixels along the first two axes, the path along the third one. Its
+    /// central slice along axis 0 or 1 is the stretched curved planar
+    /// reformation (CPR) image.
+    pub volume: Array<U, IxDyn>,
+
+    /// World position of the center of each plane.
+    pub positions: Vec<Vector3<f64>>,
+
+    /// Frame of each plane: in-plane axes and the path tangent as columns.
+    pub frames: Vec<Matrix3<f64>>,
+}
+
+/// Straighten a 3D image along a centerline (world points, e.g. of a vessel
+/// or the spine) by sampling planes orthogonal to it.
+///
+/// The polyline is resampled at equal arc length steps. The plane axes are
+/// propagated along the path with minimal rotation, so the straightened
+/// volume does not twist around the centerline.
+pub fn curved_reformation<T, U, S>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    centerline: &[Vector3<f64>],
+    params: &CurvedReformation,
+    sampler: &S,
+) -> Result<Reformation<U>, String>
+where
+    T: Scalar + AsPrimitive<f64>,
+    U: Num + Copy + Send + Sync + 'static,
+    S: ReSample<f64, U> + ?Sized + 'static,
+    f64: AsPrimitive<U>,
+{
+    if params.step <= 0.0 || params.in_plane_spacing.iter().any(|s| *s <= 0.0) {
+        return Err("step and in-plane spacing have to be positive".into());
+    }
+    if params.size.contains(&0) {
+        return Err("plane size has to be at least 1".into());
+    }
+    let positions = resample_path(centerline, params.step)?;
+    let n = positions.len();
+
+    // tangents by central differences, frames by projecting the previous
+    // in-plane axis onto the next plane
+    let tangents: Vec<Vector3<f64>> = (0..n)
+        .map(|i| (positions[(i + 1).min(n - 1)] - positions[i.saturating_sub(1)]).normalize())
+        .collect();
+    let mut frames = Vec::with_capacity(n);
+    let mut frame = orthonormal_frame(&tangents[0]).expect("tangents are unit vectors");
+    for t in &tangents {
+        let u = frame.column(0).into_owned();
+        let u = (u - t * t.dot(&u))
+            .try_normalize(1e-12)
+            .unwrap_or_else(|| orthonormal_frame(t).expect("unit vector").column(0).into());
+        frame = Matrix3::from_columns(&[u, t.cross(&u), *t]);
+        frames.push(frame);
+    }
+
+    let [nu, nv] = params.size;
+    let offset = |k: usize, d: usize| {
+        (k as f64 - (params.size[d] - 1) as f64 / 2.0) * params.in_plane_spacing[d]
+    };
+    let mut points = Vec::with_capacity(nu * nv * n);
+    for a in 0..nu {
+        for b in 0..nv {
+            for (p, f) in positions.iter().zip(&frames) {
+                points.push(p + f.column(0) * offset(a, 0) + f.column(1) * offset(b, 1));
+            }
+        }
+    }
+    let in_affine: Matrix4<f64> = in_affine.map(|x| x.as_());
+    let values = sample_world_points(in_im, &in_affine, &points, sampler)?;
+    let volume =
+        Array::from_shape_vec(IxDyn(&[nu, nv, n]), values).expect("one value per plane pixel");
+    Ok(Reformation {
+        volume,
+        positions,
+        frames,
+    })
+}
+
+/// Points along a polyline at equal arc length `step`, starting at its
+/// first point. The last point is included if it is not closer than half a
+/// step to the previous sample.
+fn resample_path(path: &[Vector3<f64>], step: f64) -> Result<Vec<Vector3<f64>>, String> {
+    let segments: Vec<(Vector3<f64>, Vector3<f64>, f64)> = path
+        .windows(2)
+        .map(|w| (w[0], w[1], (w[1] - w[0]).norm()))
+        .filter(|(_, _, length)| *length > 0.0)
+        .collect();
+    let length: f64 = segments.iter().map(|(_, _, l)| l).sum();
+    if segments.is_empty() || !length.is_finite() {
+        return Err("centerline requires at least two distinct finite points".into());
+    }
+
+    let n = (length / step + 0.5).floor() as usize + 1;
+    let mut points = Vec::with_capacity(n);
+    let (mut segment, mut start) = (0, 0.0);
+    for i in 0..n {
+        let s = (i as f64 * step).min(length);
+        while segment + 1 < segments.len() && s > start + segments[segment].2 {
+            start += segments[segment].2;
+            segment += 1;
+        }
+        let (a, b, l) = segments[segment];
+        points.push(a + (b - a) * ((s - start) / l).min(1.0));
+    }
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TriLinear;
+    use approx::*;
+
+    #[test]
+    fn test_extract_slice() {
+        let im = Array::from_shape_fn(IxDyn(&[5, 5, 5]), |idx| {
+            (idx[0] + 10 * idx[1] + 100 * idx[2]) as f64
+        });
+        let affine = Matrix4::<f64>::identity();
+        let sampler = TriLinear::<f64>::default();
+
+        // an axial plane reproduces the voxels of the slice
+        let center = Vector3::new(2.0, 2.0, 3.0);
+        let (axial, axial_affine) = extract_slice(
+            &im,
+            &affine,
+            &center,
+            &Vector3::z(),
+            &[1.0, 1.0],
+            &[5, 5],
+            &sampler,
+        )
+        .unwrap();
+        assert_eq!(axial.into_dyn(), im.index_axis(Axis(2), 3));
+        assert_relative_eq!(
+            axial_affine,
+            Matrix4::new_translation(&Vector3::new(0.0, 0.0, 3.0))
+        );
+
+        // an oblique plane through the center, sampled at half a voxel
+        let normal = Vector3::new(1.0, 0.0, 1.0);
+        let (oblique, oblique_affine) = extract_slice(
+            &im,
+            &affine,
+            &center,
+            &normal,
+            &[0.5, 0.5],
+            &[3, 3],
+            &sampler,
+        )
+        .unwrap();
+        assert_relative_eq!(oblique[[1, 1]], 322.0, epsilon = 1e-9);
+        let corner = oblique_affine * nalgebra::Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let expected = frame_offset(&normal, &center);
+        assert_relative_eq!(corner.xyz(), expected, epsilon = 1e-12);
+        assert_relative_eq!(
+            oblique[[0, 0]],
+            expected.x + 10.0 * expected.y + 100.0 * expected.z,
+            epsilon = 1e-9
+        );
+
+        assert!(extract_slice(
+            &im,
+            &affine,
+            &center,
+            &Vector3::zeros(),
+            &[1.0, 1.0],
+            &[5, 5],
+            &sampler
+        )
+        .is_err());
+    }
+
+    /// World position of the first pixel of a 3 x 3 plane with 0.5 mm spacing.
+    fn frame_offset(normal: &Vector3<f64>, center: &Vector3<f64>) -> Vector3<f64> {
+        let frame = orthonormal_frame(normal).unwrap();
+        center - (frame.column(0) + frame.column(1)) * 0.5
+    }
+
+    #[test]
+    fn test_curved_reformation() {
+        let im = Array::from_shape_fn(IxDyn(&[9, 9, 9]), |idx| {
+            (idx[0] + 10 * idx[1] + 100 * idx[2]) as f64
+        });
+        let affine = Matrix4::<f64>::identity();
+        let sampler = TriLinear::<f64>::default();
+        let params = CurvedReformation {
+            size: [3, 3],
+            ..Default::default()
+        };
+
+        // a straight path along z reproduces the volume around it
+        let line = [Vector3::new(4.0, 4.0, 1.0), Vector3::new(4.0, 4.0, 7.0)];
+        let straight = curved_reformation(&im, &affine, &line, &params, &sampler).unwrap();
+        assert_eq!(straight.volume.shape(), &[3, 3, 7]);
+        assert_eq!(straight.volume, im.slice(s![3..6, 3..6, 1..8]).into_dyn());
+
+        // a bent path is sampled at equal distances and without twist
+        let bent = [
+            Vector3::new(2.0, 4.0, 1.0),
+            Vector3::new(2.0, 4.0, 4.0),
+            Vector3::new(6.0, 4.0, 4.0),
+        ];
+        let result = curved_reformation(&im, &affine, &bent, &params, &sampler).unwrap();
+        assert_eq!(result.positions.len(), 8);
+        for w in result.positions.windows(2) {
+            assert!((w[1] - w[0]).norm() <= 1.0 + 1e-12);
+        }
+        for f in &result.frames {
+            assert_relative_eq!(f.column(1).into_owned(), Vector3::y(), epsilon = 1e-12);
+        }
+        let centers = result.volume.slice(s![1, 1, ..]).to_vec();
+        let expected: Vec<f64> = result
+            .positions
+            .iter()
+            .map(|p| p.x + 10.0 * p.y + 100.0 * p.z)
+            .collect();
+        assert_relative_eq!(&centers[..], &expected[..], epsilon = 1e-9);
+        assert!(curved_reformation(&im, &affine, &bent[..1], &params, &sampler).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use super::common::SamplingMode;
+
+/// Settings shared by the samplers, independent of the coordinate type of
+/// [`ReSample`](super::traits::ReSample).
+pub trait SamplerSettings<U> {
+    fn set_mode(&mut self, mode: SamplingMode);
+    fn set_constant(&mut self, cval: U);
+}
+
+/// Fluent configuration of a sampler, starting from its defaults, e.g.
+/// `TriLinear::builder().mode(SamplingMode::Nearest).cval(-1024.0).build()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerBuilder<S> {
+    pub(crate) sampler: S,
+}
+
+impl<S> SamplerBuilder<S> {
+    pub fn new(sampler: S) -> Self {
+        Self { sampler }
+    }
+
+    /// Strategy for points outside of the input.
+    pub fn mode<U>(mut self, mode: SamplingMode) -> Self
+    where
+        S: SamplerSettings<U>,
+    {
+        self.sampler.set_mode(mode);
+        self
+    }
+
+    /// Value of points outside of the input in [`SamplingMode::Constant`].
+    pub fn cval<U>(mut self, cval: U) -> Self
+    where
+        S: SamplerSettings<U>,
+    {
+        self.sampler.set_constant(cval);
+        self
+    }
+
+    pub fn build(self) -> S {
+        self.sampler
+    }
+}
+
+/// Implement [`SamplerSettings`] and a `builder` constructor for a sampler
+/// with `mode` and `cval` fields, generic over its voxel type. Samplers
+/// without a meaningful default pass `without_builder` and provide their own
+/// `builder`.
+macro_rules! impl_sampler_settings {
+    ($sampler:ident) => {
+        $crate::sampler::builder::impl_sampler_settings!($sampler, without_builder);
+
+        impl<U> $sampler<U>
+        where
+            U: Num + Copy,
+        {
+            pub fn builder() -> $crate::sampler::builder::SamplerBuilder<Self> {
+                $crate::sampler::builder::SamplerBuilder::new(Self::default())
+            }
+        }
+    };
+    ($sampler:ident, without_builder) => {
+        impl<U> $crate::sampler::builder::SamplerSettings<U> for $sampler<U>
+        where
+            U: Num + Copy,
+        {
+            fn set_mode(&mut self, mode: SamplingMode) {
+                self.mode = mode;
+            }
+
+            fn set_constant(&mut self, cval: U) {
+                self.cval = cval;
+            }
+        }
+    };
+}
+
+pub(crate) use impl_sampler_settings;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::signed_distance::SignedDistance;
+    use crate::sampler::traits::ReSample;
+    use crate::sampler::trilinear::TriLinear;
+
+    #[test]
+    fn test_sampler_builder() {
+        let sampler = TriLinear::builder()
+            .mode(SamplingMode::Nearest)
+            .cval(-1024.0)
+            .build();
+        assert_eq!(
+            ReSample::<f64, f64>::get_sampling_mode(&sampler),
+            SamplingMode::Nearest
+        );
+        assert_eq!(ReSample::<f64, f64>::get_cval(&sampler), -1024.0);
+        let sampler = SignedDistance::<u8>::builder([2.0, 1.0, 1.0])
+            .cval(3)
+            .build();
+        assert_eq!(sampler, {
+            let mut expected = SignedDistance::new([2.0, 1.0, 1.0]);
+            ReSample::<f64, u8>::set_cval(&mut expected, 3);
+            expected
+        });
+    }
+}
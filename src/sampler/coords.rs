@@ -0,0 +1,145 @@
+#[cfg(feature = "nalgebra")]
+use nalgebra::{MatrixXx3, Scalar, Vector3};
+use ndarray::prelude::*;
+
+/// Containers of voxel coordinates (one row or point per coordinate) which
+/// can be sampled with [`ReSample::sample_coords`](super::traits::ReSample::sample_coords).
+///
+/// Borrowed `Array2`s, `ArrayView2`s, slices of `[T; 3]` and (with the
+/// `nalgebra` feature) `MatrixXx3`s are viewed as (n, 3) arrays without a
+/// copy, owned `Array2`s and `MatrixXx3`s are taken over. Only slices of
+/// `Vector3` are copied.
+pub trait IntoCoords<'a, T> {
+    fn into_coords(self) -> Result<CowArray<'a, T, Ix2>, String>;
+}
+
+impl<'a, T> IntoCoords<'a, T> for Array2<T> {
+    fn into_coords(self) -> Result<CowArray<'a, T, Ix2>, String> {
+        if self.ncols() != 3 {
+            return Err("coordinates have to be of shape (n, 3)".into());
+        }
+        Ok(self.into())
+    }
+}
+
+impl<'a, T> IntoCoords<'a, T> for &'a Array2<T> {
+    fn into_coords(self) -> Result<CowArray<'a, T, Ix2>, String> {
+        self.view().into_coords()
+    }
+}
+
+impl<'a, T> IntoCoords<'a, T> for ArrayView2<'a, T> {
+    fn into_coords(self) -> Result<CowArray<'a, T, Ix2>, String> {
+        if self.ncols() != 3 {
+            return Err("coordinates have to be of shape (n, 3)".into());
+        }
+        Ok(self.into())
+    }
+}
+
+impl<'a, T> IntoCoords<'a, T> for &'a [[T; 3]] {
+    fn into_coords(self) -> Result<CowArray<'a, T, Ix2>, String> {
+        Ok(ArrayView2::from_shape((self.len(), 3), self.as_flattened())
+            .expect("n rows of 3 columns")
+            .into())
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<'a, T> IntoCoords<'a, T> for MatrixXx3<T>
+where
+    T: Scalar,
+{
+    fn into_coords(self) -> Result<CowArray<'a, T, Ix2>, String> {
+        // both are column-major
+        let n = self.nrows();
+        let values: Vec<T> = self.data.into();
+        Ok(Array2::from_shape_vec((n, 3).f(), values)
+            .expect("n rows of 3 columns")
+            .into())
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<'a, T> IntoCoords<'a, T> for &'a MatrixXx3<T>
+where
+    T: Scalar,
+{
+    fn into_coords(self) -> Result<CowArray<'a, T, Ix2>, String> {
+        Ok(
+            ArrayView2::from_shape((self.nrows(), 3).f(), self.as_slice())
+                .expect("n rows of 3 columns")
+                .into(),
+        )
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<'a, T> IntoCoords<'a, T> for &'a [Vector3<T>]
+where
+    T: Scalar,
+{
+    fn into_coords(self) -> Result<CowArray<'a, T, Ix2>, String> {
+        Ok(Array2::from_shape_fn((self.len(), 3).f(), |(i, d)| self[i][d].clone()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::common::SamplingMode;
+    use crate::sampler::traits::ReSample;
+    use crate::sampler::trilinear::TriLinear;
+
+    #[test]
+    fn test_into_coords() {
+        let points = [[0.5, 1.0, 0.0], [2.0, 0.25, 1.5], [3.5, 0.0, 0.0]];
+        let expected = Array2::from_shape_fn((3, 3), |(i, d)| points[i][d]);
+        assert_eq!(points.as_slice().into_coords().unwrap(), expected);
+        assert_eq!((&expected).into_coords().unwrap(), expected);
+        // borrowed containers are viewed in place
+        let view = points.as_slice().into_coords().unwrap();
+        assert_eq!((view.is_view(), view.as_ptr()), (true, points[0].as_ptr()));
+        let view = (&expected).into_coords().unwrap();
+        assert_eq!((view.is_view(), view.as_ptr()), (true, expected.as_ptr()));
+        let f_order = expected.t().as_standard_layout().t().to_owned();
+        let ptr = f_order.as_ptr();
+        let coords = f_order.into_coords().unwrap();
+        assert!(!coords.is_view());
+        assert_eq!((coords.as_ptr(), coords), (ptr, expected.view().into()));
+        // arrays sliced in place are taken over as they are
+        let mut sliced = Array2::from_shape_fn((3, 5).f(), |(i, d)| {
+            [0.0, points[i][0], points[i][1], points[i][2], 0.0][d]
+        });
+        sliced.slice_collapse(s![.., 1..4]);
+        assert_eq!(sliced.into_coords().unwrap(), expected);
+        assert!(Array2::<f64>::zeros((2, 2)).into_coords().is_err());
+        #[cfg(feature = "nalgebra")]
+        {
+            let matrix = MatrixXx3::from_fn(3, |i, d| points[i][d]);
+            let ptr = matrix.as_ptr();
+            let borrowed = (&matrix).into_coords().unwrap();
+            assert_eq!((borrowed.is_view(), borrowed.as_ptr()), (true, ptr));
+            assert_eq!(borrowed, expected);
+            let coords = matrix.into_coords().unwrap();
+            assert!(!coords.is_view());
+            assert_eq!((coords.as_ptr(), coords), (ptr, expected.view().into()));
+        }
+
+        let im = Array::from_shape_fn(IxDyn(&[4, 2, 2]), |idx| (idx[0] + idx[1] + idx[2]) as f64);
+        let sampler = TriLinear::default();
+        let values = sampler.sample_coords(&im, points.as_slice()).unwrap();
+        // neighbors beyond the last voxel take the constant value
+        assert_eq!(values.as_slice().unwrap(), &[1.5, 1.625, 1.5]);
+        assert_eq!(sampler.sample_coords(&im, expected.view()).unwrap(), values);
+        assert_eq!(
+            sampler.sample_coords(&im, expected.clone()).unwrap(),
+            values
+        );
+        // the borrowed coordinates are clamped per point, not in place
+        let clamped = TriLinear::builder().mode(SamplingMode::Nearest).build();
+        let values = clamped.sample_coords(&im, points.as_slice()).unwrap();
+        assert_eq!(values.as_slice().unwrap(), &[1.5, 3.25, 3.0]);
+        assert_eq!(points[2], [3.5, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,150 @@
+use super::builder::impl_sampler_settings;
+use super::common::{within_bounds, SamplingMode};
+use super::traits::ReSample;
+use crate::par::*;
+use ndarray::prelude::*;
+use nifti_processing_core::{corners, trilinear_weights};
+use num_traits::float::FloatCore;
+use num_traits::{AsPrimitive, Num};
+
+/// A sampler for label maps, interpolating the one-hot encoding of the labels
+/// trilinearly and returning the label with the largest weight (argmax).
+///
+/// Compared to [`NearestNeighbor`](super::nearest_neighbor::NearestNeighbor)
+/// this produces smoother label boundaries, while, unlike
+/// [`TriLinear`](super::trilinear::TriLinear), only ever returning labels
+/// present in the input. Only the (at most 8) labels of the neighboring voxels
+/// are considered, hence no per-class channels are allocated. Ties are broken
+/// in favour of the smaller label.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabelTriLinear<U>
+where
+    U: Num + Copy,
+{
+    mode: SamplingMode,
+    cval: U,
+}
+
+impl<U> Default for LabelTriLinear<U>
+where
+    U: Num + Copy,
+{
+    fn default() -> Self {
+        Self {
+            mode: SamplingMode::Constant,
+            cval: U::zero(),
+        }
+    }
+}
+
+impl_sampler_settings!(LabelTriLinear);
+
+impl<T, U> ReSample<T, U> for LabelTriLinear<U>
+where
+    T: FloatCore + AsPrimitive<usize> + AsPrimitive<U> + Send + Sync,
+    U: Num + AsPrimitive<T> + PartialOrd + Copy + Send + Sync,
+    usize: AsPrimitive<T>,
+{
+    fn set_sampling_mode(&mut self, mode: SamplingMode) {
+        self.mode = mode;
+    }
+
+    fn get_sampling_mode(&self) -> SamplingMode {
+        self.mode
+    }
+
+    fn set_cval(&mut self, cval: U) {
+        self.cval = cval;
+    }
+
+    fn get_cval(&self) -> U {
+        self.cval
+    }
+
+    fn sample(
+        &self,
+        in_im: &Array<U, IxDyn>,
+        in_coords: &mut Array2<T>,
+        out_shape: &[usize],
+    ) -> Result<Array<U, IxDyn>, String> {
+        self.sample_view(in_im, in_coords.view(), out_shape)
+    }
+
+    fn sample_view(
+        &self,
+        in_im: &Array<U, IxDyn>,
+        in_coords: ArrayView2<T>,
+        out_shape: &[usize],
+    ) -> Result<Array<U, IxDyn>, String> {
+        let in_shape = in_im.shape();
+        let t_zero = T::zero();
+        let upper: [T; 3] = [in_shape[0].as_(), in_shape[1].as_(), in_shape[2].as_()];
+
+        let values: Vec<U> = (0..in_coords.nrows())
+            .into_par_iter()
+            .map(|i| {
+                let p = [in_coords[[i, 0]], in_coords[[i, 1]], in_coords[[i, 2]]];
+                let p = self.apply_sampling_mode_point(in_im, p);
+
+                // check if index is out of bounds (or not a number)
+                if !within_bounds(&p, &upper) {
+                    return self.get_cval();
+                }
+
+                let p0 = p.map(|x| x.floor());
+                let weights = trilinear_weights(&p, &p0);
+
+                // accumulate the weights of the distinct neighboring labels
+                let mut labels: [(U, T); 8] = [(self.get_cval(), t_zero); 8];
+                let mut n_labels = 0;
+                for (c, weight) in corners(p0.map(|x| x.as_())).iter().zip(weights) {
+                    let label = self.get_val(in_im, c[0], c[1], c[2]);
+                    match labels[..n_labels].iter_mut().find(|(l, _)| *l == label) {
+                        Some((_, w)) => *w = *w + weight,
+                        None => {
+                            labels[n_labels] = (label, weight);
+                            n_labels += 1;
+                        }
+                    }
+                }
+
+                labels[..n_labels]
+                    .iter()
+                    .fold(None, |best: Option<(U, T)>, &(label, weight)| match best {
+                        Some((l, w)) if w > weight || (w == weight && l < label) => best,
+                        _ => Some((label, weight)),
+                    })
+                    .map(|(label, _)| label)
+                    .unwrap_or_else(|| self.get_cval())
+            })
+            .collect();
+
+        if let Ok(r) = Array::from_shape_vec(out_shape, values) {
+            Ok(r.into_dyn())
+        } else {
+            Err("number of elements is not compatible with out_shape shape".into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_trilinear() {
+        // labels 0 and 2 side by side: linear interpolation would create label 1
+        let im = Array::from_shape_fn(IxDyn(&[2, 2, 2]), |idx| if idx[0] == 0 { 0 } else { 2 });
+        let mut coords = array![
+            [0.4, 0.5, 0.5],
+            [0.6, 0.5, 0.5],
+            [0.5, 0.5, 0.5],
+            [3.0, 0.0, 0.0],
+        ];
+        let mut sampler = LabelTriLinear::default();
+        ReSample::<f64, i32>::set_cval(&mut sampler, -1);
+        let out = sampler.sample(&im, &mut coords, &[4]).unwrap();
+        assert_eq!(out.as_slice().unwrap(), &[0, 2, 0, -1]);
+    }
+}
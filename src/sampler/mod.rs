@@ -1,6 +1,11 @@
+pub mod builder;
 pub mod common;
+pub mod coords;
 pub mod traits;
 
 // sampling implementations:
+pub mod label_trilinear;
 pub mod nearest_neighbor;
+pub mod signed_distance;
 pub mod trilinear;
+pub mod widened;
@@ -1,9 +1,11 @@
+use super::builder::impl_sampler_settings;
 use super::common::SamplingMode;
 use super::traits::ReSample;
-use nalgebra::{MatrixXx3, RealField};
+use crate::par::*;
 use ndarray::prelude::*;
+use nifti_processing_core::nearest;
+use num_traits::float::FloatCore;
 use num_traits::{AsPrimitive, Num};
-use rayon::prelude::*;
 
 /// A sampler employing a nearest neighbor strategy.
 ///
@@ -30,9 +32,11 @@ where
     }
 }
 
+impl_sampler_settings!(NearestNeighbor);
+
 impl<T, U> ReSample<T, U> for NearestNeighbor<U>
 where
-    T: Num + AsPrimitive<usize> + AsPrimitive<U> + RealField + PartialOrd + Copy,
+    T: FloatCore + AsPrimitive<usize> + AsPrimitive<U> + Send + Sync,
     U: Num + AsPrimitive<T> + Copy + Send + Sync,
     usize: AsPrimitive<T>,
 {
@@ -55,43 +59,27 @@ where
     fn sample(
         &self,
         in_im: &Array<U, IxDyn>,
-        in_coords: &mut MatrixXx3<T>,
+        in_coords: &mut Array2<T>,
         out_shape: &[usize],
     ) -> Result<Array<U, IxDyn>, String> {
-        self.apply_sampling_mode(in_im, in_coords);
-        let in_coords =
-            MatrixXx3::from_iterator(in_coords.nrows(), in_coords.iter_mut().map(|x| x.ceil()));
-        let in_coords_u: MatrixXx3<usize> =
-            MatrixXx3::from_iterator(in_coords.nrows(), in_coords.iter().map(|x| x.as_()));
+        self.sample_view(in_im, in_coords.view(), out_shape)
+    }
 
-        let in_shape = in_im.shape();
-        let t_zero = T::zero();
-        let x_upper = T::from_usize(in_shape[0]).expect("failed to determine upper X");
-        let y_upper = T::from_usize(in_shape[1]).expect("failed to determine upper Y");
-        let z_upper = T::from_usize(in_shape[2]).expect("failed to determine upper Z");
+    fn sample_view(
+        &self,
+        in_im: &Array<U, IxDyn>,
+        in_coords: ArrayView2<T>,
+        out_shape: &[usize],
+    ) -> Result<Array<U, IxDyn>, String> {
+        let im = in_im
+            .view()
+            .into_dimensionality::<Ix3>()
+            .map_err(|_| "in_im has to be 3D")?;
+        let (mode, cval) = (self.get_sampling_mode(), self.get_cval());
 
         let values: Vec<U> = (0..in_coords.nrows())
             .into_par_iter()
-            .map(|i| {
-                let (x, y, z) = (in_coords[(i, 0)], in_coords[(i, 1)], in_coords[(i, 2)]);
-                let (x_u, y_u, z_u) = (
-                    in_coords_u[(i, 0)],
-                    in_coords_u[(i, 1)],
-                    in_coords_u[(i, 2)],
-                );
-
-                // check if index is out of bounds
-                if
-                // check if any of the coordinates are out of lower bounds
-                (x < t_zero)  | (y < t_zero)  | (z < t_zero) |
-                // check if any of the coordinates are out of upper bounds
-                (x > x_upper) | (y > y_upper) | (z > z_upper)
-                {
-                    return self.get_cval();
-                };
-
-                self.get_val(in_im, x_u, y_u, z_u)
-            })
+            .map(|i| nearest(&im, [0, 1, 2].map(|d| in_coords[[i, d]]), mode, cval))
             .collect();
 
         if let Ok(r) = Array::from_shape_vec(out_shape, values) {
@@ -101,3 +89,28 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearest_neighbor_rounding() {
+        // coordinates round to the nearest voxel, halves away from zero
+        let im = array![[[10]], [[20]], [[30]]].into_dyn();
+        let x = [0.49, 0.5, 0.51, 1.5, 2.49, 2.5, -0.49, -0.5];
+        let mut coords =
+            Array2::from_shape_fn((x.len(), 3), |(i, d)| if d == 0 { x[i] } else { 0.0 });
+
+        let mut sampler = NearestNeighbor::default();
+        ReSample::<f64, i32>::set_cval(&mut sampler, -1);
+        let out = sampler
+            .sample(&im, &mut coords.clone(), &[x.len()])
+            .unwrap();
+        assert_eq!(out.as_slice().unwrap(), &[10, 20, 20, 30, 30, -1, 10, -1]);
+
+        ReSample::<f64, i32>::set_sampling_mode(&mut sampler, SamplingMode::Nearest);
+        let out = sampler.sample(&im, &mut coords, &[x.len()]).unwrap();
+        assert_eq!(out.as_slice().unwrap(), &[10, 20, 20, 30, 30, 30, 10, 10]);
+    }
+}
@@ -0,0 +1,295 @@
+use super::builder::{impl_sampler_settings, SamplerBuilder};
+use super::common::{within_bounds, SamplingMode};
+use super::traits::ReSample;
+use super::trilinear::TriLinear;
+use crate::distance::euclidean_distance_transform;
+use ndarray::prelude::*;
+use num_traits::float::FloatCore;
+use num_traits::{AsPrimitive, Num};
+use std::cmp::Ordering;
+
+/// A sampler for label maps, converting each label to a signed distance map,
+/// interpolating the distances trilinearly and returning the label with the
+/// smallest distance.
+///
+/// This is the most topology preserving of the label samplers, as thin
+/// structures remain connected as long as their distance map is sampled
+/// finely enough. It computes one distance transform per label of the input,
+/// hence it is considerably slower than
+/// [`LabelTriLinear`](super::label_trilinear::LabelTriLinear). Ties are broken
+/// in favour of the smaller label.
+///
+/// Distances are computed in the units of `voxel_sizes`, which have to be the
+/// voxel sizes of the input, e.g. [`voxel_sizes`](crate::voxel_sizes) of its
+/// affine. The distance maps are computed anew on every call of
+/// [`sample`](ReSample::sample); to sample the same label map repeatedly, e.g.
+/// slice by slice, compute them once with [`SignedDistance::precompute`].
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignedDistance<U>
+where
+    U: Num + Copy,
+{
+    mode: SamplingMode,
+    cval: U,
+    voxel_sizes: [f64; 3],
+}
+
+impl<U> SignedDistance<U>
+where
+    U: Num + Copy,
+{
+    /// A sampler for inputs with the given voxel sizes.
+    pub fn new(voxel_sizes: [f64; 3]) -> Self {
+        Self {
+            mode: SamplingMode::Constant,
+            cval: U::zero(),
+            voxel_sizes,
+        }
+    }
+
+    /// Fluent configuration of a sampler for inputs with the given voxel
+    /// sizes, see [`SignedDistance::new`].
+    pub fn builder(voxel_sizes: [f64; 3]) -> SamplerBuilder<Self> {
+        SamplerBuilder::new(Self::new(voxel_sizes))
+    }
+
+    /// The signed distance maps of all labels of in_im, as a sampler of
+    /// in_im with the settings of this sampler.
+    pub fn precompute(&self, in_im: &Array<U, IxDyn>) -> Result<SignedDistanceMaps<U>, String>
+    where
+        U: PartialOrd,
+    {
+        let labels = sorted_labels(in_im);
+        let fields = signed_distance_fields(in_im, &labels, &self.voxel_sizes)?;
+        Ok(SignedDistanceMaps {
+            mode: self.mode,
+            cval: self.cval,
+            image: in_im.to_owned(),
+            labels,
+            fields,
+        })
+    }
+}
+
+impl_sampler_settings!(SignedDistance, without_builder);
+
+impl<T, U> ReSample<T, U> for SignedDistance<U>
+where
+    T: FloatCore + AsPrimitive<usize> + AsPrimitive<U> + AsPrimitive<f64> + Send + Sync,
+    U: Num + AsPrimitive<T> + PartialOrd + Copy + Send + Sync,
+    usize: AsPrimitive<T>,
+{
+    fn set_sampling_mode(&mut self, mode: SamplingMode) {
+        self.mode = mode;
+    }
+
+    fn get_sampling_mode(&self) -> SamplingMode {
+        self.mode
+    }
+
+    fn set_cval(&mut self, cval: U) {
+        self.cval = cval;
+    }
+
+    fn get_cval(&self) -> U {
+        self.cval
+    }
+
+    fn sample(
+        &self,
+        in_im: &Array<U, IxDyn>,
+        in_coords: &mut Array2<T>,
+        out_shape: &[usize],
+    ) -> Result<Array<U, IxDyn>, String> {
+        let labels = sorted_labels(in_im);
+        let fields = signed_distance_fields(in_im, &labels, &self.voxel_sizes)?;
+        sample_fields(self, in_im, &labels, &fields, in_coords, out_shape)
+    }
+}
+
+/// [`SignedDistance`] with the distance maps of one label map computed in
+/// advance by [`SignedDistance::precompute`].
+///
+/// Sampling any other image than the one the maps were computed of is an
+/// error, e.g. the volumes of a 4D label image.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedDistanceMaps<U>
+where
+    U: Num + Copy,
+{
+    mode: SamplingMode,
+    cval: U,
+    image: Array<U, IxDyn>,
+    labels: Vec<U>,
+    fields: Vec<Array<f64, IxDyn>>,
+}
+
+impl_sampler_settings!(SignedDistanceMaps, without_builder);
+
+impl<T, U> ReSample<T, U> for SignedDistanceMaps<U>
+where
+    T: FloatCore + AsPrimitive<usize> + AsPrimitive<U> + AsPrimitive<f64> + Send + Sync,
+    U: Num + AsPrimitive<T> + PartialOrd + Copy + Send + Sync,
+    usize: AsPrimitive<T>,
+{
+    fn set_sampling_mode(&mut self, mode: SamplingMode) {
+        self.mode = mode;
+    }
+
+    fn get_sampling_mode(&self) -> SamplingMode {
+        self.mode
+    }
+
+    fn set_cval(&mut self, cval: U) {
+        self.cval = cval;
+    }
+
+    fn get_cval(&self) -> U {
+        self.cval
+    }
+
+    fn sample(
+        &self,
+        in_im: &Array<U, IxDyn>,
+        in_coords: &mut Array2<T>,
+        out_shape: &[usize],
+    ) -> Result<Array<U, IxDyn>, String> {
+        if *in_im != self.image {
+            return Err("in_im is not the image the distance maps were computed of".into());
+        }
+        sample_fields(
+            self,
+            in_im,
+            &self.labels,
+            &self.fields,
+            in_coords,
+            out_shape,
+        )
+    }
+}
+
+/// The distinct labels of in_im in ascending order.
+fn sorted_labels<U>(in_im: &Array<U, IxDyn>) -> Vec<U>
+where
+    U: PartialOrd + Copy,
+{
+    let mut labels: Vec<U> = in_im.iter().copied().collect();
+    labels.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    labels.dedup();
+    labels
+}
+
+/// The signed distance maps (negative inside) of the labels of in_im. A
+/// single label has no map, it is at -inf everywhere.
+fn signed_distance_fields<U>(
+    in_im: &Array<U, IxDyn>,
+    labels: &[U],
+    voxel_sizes: &[f64; 3],
+) -> Result<Vec<Array<f64, IxDyn>>, String>
+where
+    U: Num + PartialOrd + Copy,
+{
+    if labels.len() < 2 {
+        return Ok(Vec::new());
+    }
+    labels
+        .iter()
+        .map(|label| {
+            let mask = in_im.mapv(|x| x == *label);
+            let distance_inside = euclidean_distance_transform(&mask, voxel_sizes)?;
+            let distance_outside = euclidean_distance_transform(&mask.mapv(|x| !x), voxel_sizes)?;
+            Ok(distance_outside - distance_inside)
+        })
+        .collect()
+}
+
+/// Sample the label with the smallest interpolated signed distance.
+fn sample_fields<T, U, S>(
+    sampler: &S,
+    in_im: &Array<U, IxDyn>,
+    labels: &[U],
+    fields: &[Array<f64, IxDyn>],
+    in_coords: &mut Array2<T>,
+    out_shape: &[usize],
+) -> Result<Array<U, IxDyn>, String>
+where
+    T: FloatCore + AsPrimitive<usize> + AsPrimitive<U> + AsPrimitive<f64>,
+    U: Num + Copy + 'static,
+    S: ReSample<T, U>,
+    usize: AsPrimitive<T>,
+{
+    sampler.apply_sampling_mode(in_im, in_coords);
+
+    let in_shape = in_im.shape();
+    let upper = [0, 1, 2].map(|d| in_shape[d] as f64);
+    let coords: Array2<f64> = in_coords.mapv(|x| x.as_());
+    let outside: Vec<bool> = coords
+        .rows()
+        .into_iter()
+        .map(|p| !within_bounds(&[p[0], p[1], p[2]], &upper))
+        .collect();
+
+    // smallest distance and its label
+    let mut best: Vec<(f64, U)> = vec![(f64::INFINITY, sampler.get_cval()); coords.nrows()];
+    if let [label] = labels[..] {
+        best.iter_mut().for_each(|b| b.1 = label);
+    } else {
+        let mut trilinear = TriLinear::<f64>::default();
+        ReSample::<f64, f64>::set_sampling_mode(&mut trilinear, SamplingMode::Nearest);
+        for (label, sdf) in labels.iter().zip(fields) {
+            let distances = ReSample::<f64, f64>::sample_view(
+                &trilinear,
+                sdf,
+                coords.view(),
+                &[coords.nrows()],
+            )?;
+            for (b, d) in best.iter_mut().zip(distances.iter()) {
+                if *d < b.0 {
+                    *b = (*d, *label);
+                }
+            }
+        }
+    }
+
+    let values: Vec<U> = best
+        .into_iter()
+        .zip(outside)
+        .map(|((_, label), out)| if out { sampler.get_cval() } else { label })
+        .collect();
+
+    if let Ok(r) = Array::from_shape_vec(out_shape, values) {
+        Ok(r.into_dyn())
+    } else {
+        Err("number of elements is not compatible with out_shape shape".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_distance() {
+        // one voxel thick slab of label 3 at x = 2
+        let im = Array::from_shape_fn(IxDyn(&[6, 4, 4]), |idx| if idx[0] == 2 { 3 } else { 0 });
+        let mut coords = array![
+            [2.4, 1.0, 1.0],
+            [2.6, 1.0, 1.0],
+            [1.5, 2.0, 2.0],
+            [-1.0, 0.0, 0.0],
+        ];
+        let mut sampler = SignedDistance::new([1.0, 1.0, 1.0]);
+        ReSample::<f64, i32>::set_cval(&mut sampler, -1);
+        let out = sampler.sample(&im, &mut coords, &[4]).unwrap();
+        assert_eq!(out.as_slice().unwrap(), &[3, 0, 0, -1]);
+
+        let maps = sampler.precompute(&im).unwrap();
+        let mut coords = array![[2.4, 1.0, 1.0], [2.6, 1.0, 1.0]];
+        let out = maps.sample(&im, &mut coords, &[2]).unwrap();
+        assert_eq!(out.as_slice().unwrap(), &[3, 0]);
+        let other = im.mapv(|x| 3 - x);
+        assert!(maps.sample(&other, &mut coords, &[2]).is_err());
+    }
+}
@@ -1,11 +1,14 @@
 use super::common::SamplingMode;
-use nalgebra::{clamp, MatrixXx3};
+use super::coords::IntoCoords;
 use ndarray::prelude::*;
+use nifti_processing_core::clamp_coordinate;
 use num_traits::{AsPrimitive, Num};
 
 /// This trait has to be implented by all valid samplers.
 ///
-pub trait ReSample<T, U>
+/// Samplers are shared between threads, e.g. to resample the volumes of a 4D
+/// image in parallel.
+pub trait ReSample<T, U>: Sync
 where
     T: Num + AsPrimitive<usize> + AsPrimitive<U> + PartialOrd + Copy,
     U: Num + Copy + 'static,
@@ -17,33 +20,97 @@ where
     fn set_cval(&mut self, cval: U);
     fn get_cval(&self) -> U;
 
+    /// Sample in_im at the voxel coordinates in_coords, one row of shape
+    /// (n, 3) per point, into an image of out_shape (of n voxels). Samplers
+    /// may clamp the coordinates in place.
     fn sample(
         &self,
         in_im: &Array<U, IxDyn>,
-        in_coords: &mut MatrixXx3<T>,
+        in_coords: &mut Array2<T>,
         out_shape: &[usize],
     ) -> Result<Array<U, IxDyn>, String>;
 
-    fn apply_sampling_mode(&self, in_im: &Array<U, IxDyn>, in_coords: &mut MatrixXx3<T>) {
+    /// Sample in_im at the borrowed voxel coordinates in_coords, like
+    /// [`sample`](ReSample::sample) but without modifying them.
+    ///
+    /// The default implementation copies the coordinates; the nearest
+    /// neighbor and (label) trilinear samplers clamp each point as they go,
+    /// without a copy.
+    fn sample_view(
+        &self,
+        in_im: &Array<U, IxDyn>,
+        in_coords: ArrayView2<T>,
+        out_shape: &[usize],
+    ) -> Result<Array<U, IxDyn>, String> {
+        self.sample(in_im, &mut in_coords.to_owned(), out_shape)
+    }
+
+    /// Sample at the voxel coordinates of any [`IntoCoords`] container,
+    /// e.g. an `Array2` of shape (n, 3) or a slice of `[T; 3]`, returning
+    /// one value per coordinate. Borrowed containers are sampled with
+    /// [`sample_view`](ReSample::sample_view).
+    fn sample_coords<'a, C>(
+        &self,
+        in_im: &Array<U, IxDyn>,
+        coords: C,
+    ) -> Result<Array<U, IxDyn>, String>
+    where
+        Self: Sized,
+        T: 'a,
+        C: IntoCoords<'a, T>,
+    {
+        let coords = coords.into_coords()?;
+        let n = coords.nrows();
+        if coords.is_view() {
+            self.sample_view(in_im, coords.view(), &[n])
+        } else {
+            self.sample(in_im, &mut coords.into_owned(), &[n])
+        }
+    }
+
+    /// Whether sampling exactly at voxel centers reproduces the voxel values.
+    ///
+    /// This holds for all interpolating samplers and allows resampling onto
+    /// grids aligned with the input grid (e.g. the identity or integer voxel
+    /// shifts) without sampling at all. Samplers which e.g. smooth have to
+    /// return false.
+    fn interpolates(&self) -> bool {
+        true
+    }
+
+    fn apply_sampling_mode(&self, in_im: &Array<U, IxDyn>, in_coords: &mut Array2<T>) {
         let in_shape = in_im.shape();
 
         let caps: [T; 3] = [
-            (in_shape[0] - 1).as_(),
-            (in_shape[1] - 1).as_(),
-            (in_shape[2] - 1).as_(),
+            in_shape[0].saturating_sub(1).as_(),
+            in_shape[1].saturating_sub(1).as_(),
+            in_shape[2].saturating_sub(1).as_(),
         ];
 
         match self.get_sampling_mode() {
             SamplingMode::Constant => (), // leave idxs as is
             SamplingMode::Nearest => {
-                for (i, mut col) in in_coords.column_iter_mut().enumerate() {
+                for (i, mut col) in in_coords.columns_mut().into_iter().enumerate() {
                     col.iter_mut()
-                        .for_each(|x| x.clone_from(&clamp(*x, T::zero(), caps[i])))
+                        .for_each(|x| *x = clamp_coordinate(*x, caps[i]))
                 }
             }
         }
     }
 
+    /// The voxel coordinate p after applying the sampling mode, as
+    /// [`apply_sampling_mode`](ReSample::apply_sampling_mode) does for all
+    /// coordinates in place.
+    fn apply_sampling_mode_point(&self, in_im: &Array<U, IxDyn>, p: [T; 3]) -> [T; 3] {
+        match self.get_sampling_mode() {
+            SamplingMode::Constant => p,
+            SamplingMode::Nearest => {
+                let in_shape = in_im.shape();
+                [0, 1, 2].map(|d| clamp_coordinate(p[d], in_shape[d].saturating_sub(1).as_()))
+            }
+        }
+    }
+
     fn get_val(&self, im: &Array<U, IxDyn>, x: usize, y: usize, z: usize) -> U {
         match im.get([x, y, z]) {
             Some(val) => *val,
@@ -1,9 +1,11 @@
+use super::builder::impl_sampler_settings;
 use super::common::SamplingMode;
 use super::traits::ReSample;
-use nalgebra::{MatrixXx3, RealField};
+use crate::par::*;
 use ndarray::prelude::*;
+use nifti_processing_core::trilinear;
+use num_traits::float::FloatCore;
 use num_traits::{AsPrimitive, Num};
-use rayon::prelude::*;
 
 /// A sampler employing a trilinear interpolation strategy.
 ///
@@ -30,9 +32,11 @@ where
     }
 }
 
+impl_sampler_settings!(TriLinear);
+
 impl<T, U> ReSample<T, U> for TriLinear<U>
 where
-    T: Num + AsPrimitive<usize> + AsPrimitive<U> + RealField + PartialOrd + Copy,
+    T: FloatCore + AsPrimitive<usize> + AsPrimitive<U> + Send + Sync,
     U: Num + AsPrimitive<T> + Copy + Send + Sync,
     usize: AsPrimitive<T>,
 {
@@ -52,100 +56,30 @@ where
         self.cval
     }
 
-    #[allow(non_snake_case)]
     fn sample(
         &self,
         in_im: &Array<U, IxDyn>,
-        in_coords: &mut MatrixXx3<T>,
+        in_coords: &mut Array2<T>,
         out_shape: &[usize],
     ) -> Result<Array<U, IxDyn>, String> {
-        self.apply_sampling_mode(in_im, in_coords);
-
-        let in_shape = in_im.shape();
-        let t_zero = T::zero();
-        let t_one = T::one();
-        let x_upper = T::from_usize(in_shape[0]).expect("failed to determine upper X");
-        let y_upper = T::from_usize(in_shape[1]).expect("failed to determine upper Y");
-        let z_upper = T::from_usize(in_shape[2]).expect("failed to determine upper Z");
+        self.sample_view(in_im, in_coords.view(), out_shape)
+    }
 
-        let in_coords_0 = MatrixXx3::from_vec(
-            in_coords
-                .as_slice()
-                .into_par_iter()
-                .map(|x| x.floor())
-                .collect(),
-        );
-        let in_coords_1 = MatrixXx3::from_vec(
-            in_coords_0
-                .as_slice()
-                .into_par_iter()
-                .map(|x| *x + t_one)
-                .collect(),
-        );
+    fn sample_view(
+        &self,
+        in_im: &Array<U, IxDyn>,
+        in_coords: ArrayView2<T>,
+        out_shape: &[usize],
+    ) -> Result<Array<U, IxDyn>, String> {
+        let im = in_im
+            .view()
+            .into_dimensionality::<Ix3>()
+            .map_err(|_| "in_im has to be 3D")?;
+        let (mode, cval) = (self.get_sampling_mode(), self.get_cval());
 
         let values: Vec<U> = (0..in_coords.nrows())
             .into_par_iter()
-            .map(|i| {
-                let (x, y, z) = (in_coords[(i, 0)], in_coords[(i, 1)], in_coords[(i, 2)]);
-
-                // check if index is out of bounds
-                if
-                // check if any of the coordinates are out of lower bounds
-                (x < t_zero)  | (y < t_zero)  | (z < t_zero) |
-                // check if any of the coordinates are out of upper bounds
-                (x > x_upper) | (y > y_upper) | (z > z_upper)
-                {
-                    return self.get_cval();
-                };
-
-                let (x0, y0, z0) = (
-                    in_coords_0[(i, 0)],
-                    in_coords_0[(i, 1)],
-                    in_coords_0[(i, 2)],
-                );
-                let (x1, y1, z1) = (
-                    in_coords_1[(i, 0)],
-                    in_coords_1[(i, 1)],
-                    in_coords_1[(i, 2)],
-                );
-
-                // simd does not play nice with num_traits
-                // we convert to usize here instead of in
-                // advance to leaverage multiprocessing.
-                // if implemented with simd there would be
-                // a total expected speedup of 5 % for
-                // overall resampling
-                let (x0_u, y0_u, z0_u) = (
-                    in_coords_0[(i, 0)].as_(),
-                    in_coords_0[(i, 1)].as_(),
-                    in_coords_0[(i, 2)].as_(),
-                );
-                let (x1_u, y1_u, z1_u) = (
-                    in_coords_1[(i, 0)].as_(),
-                    in_coords_1[(i, 1)].as_(),
-                    in_coords_1[(i, 2)].as_(),
-                );
-
-                let Ia = self.get_val(in_im, x0_u, y0_u, z0_u);
-                let Ib = self.get_val(in_im, x0_u, y0_u, z1_u);
-                let Ic = self.get_val(in_im, x0_u, y1_u, z0_u);
-                let Id = self.get_val(in_im, x0_u, y1_u, z1_u);
-                let Ie = self.get_val(in_im, x1_u, y0_u, z0_u);
-                let If = self.get_val(in_im, x1_u, y0_u, z1_u);
-                let Ig = self.get_val(in_im, x1_u, y1_u, z0_u);
-                let Ih = self.get_val(in_im, x1_u, y1_u, z1_u);
-
-                let wa: U = ((x1 - x) * (y1 - y) * (z1 - z)).as_();
-                let wb: U = ((x1 - x) * (y1 - y) * (z - z0)).as_();
-                let wc: U = ((x1 - x) * (y - y0) * (z1 - z)).as_();
-                let wd: U = ((x1 - x) * (y - y0) * (z - z0)).as_();
-                let we: U = ((x - x0) * (y1 - y) * (z1 - z)).as_();
-                let wf: U = ((x - x0) * (y1 - y) * (z - z0)).as_();
-                let wg: U = ((x - x0) * (y - y0) * (z1 - z)).as_();
-                let wh: U = ((x - x0) * (y - y0) * (z - z0)).as_();
-
-                wa * Ia + wb * Ib + wc * Ic + wd * Id + we * Ie + wf * If + wg * Ig + wh * Ih
-            })
+            .map(|i| trilinear(&im, [0, 1, 2].map(|d| in_coords[[i, d]]), mode, cval))
             .collect();
 
         if let Ok(r) = Array::from_shape_vec(out_shape, values) {
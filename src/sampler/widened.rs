@@ -0,0 +1,165 @@
+use super::builder::{SamplerBuilder, SamplerSettings};
+use super::common::SamplingMode;
+use super::traits::ReSample;
+#[cfg(feature = "half")]
+use super::trilinear::TriLinear;
+use ndarray::prelude::*;
+use num_traits::{AsPrimitive, Num};
+use std::marker::PhantomData;
+
+/// A sampler adapter interpolating in the wider voxel type `W`.
+///
+/// The wrapped sampler operates on a `W` copy of the input volume and its
+/// result is converted back to the voxel type. This keeps e.g. half
+/// precision volumes from accumulating rounding errors in every
+/// interpolation step, see [`HalfTriLinear`].
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Widened<S, W> {
+    inner: S,
+    wide: PhantomData<W>,
+}
+
+impl<S, W> Widened<S, W> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            wide: PhantomData,
+        }
+    }
+
+    /// The wrapped sampler.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S, W> Default for Widened<S, W>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::new(S::default())
+    }
+}
+
+impl<S, W> Widened<S, W>
+where
+    S: Default,
+{
+    pub fn builder() -> SamplerBuilder<Self> {
+        SamplerBuilder::new(Self::default())
+    }
+}
+
+impl<S, W, U> SamplerSettings<U> for Widened<S, W>
+where
+    S: SamplerSettings<W>,
+    U: AsPrimitive<W>,
+    W: Copy + 'static,
+{
+    fn set_mode(&mut self, mode: SamplingMode) {
+        self.inner.set_mode(mode);
+    }
+
+    fn set_constant(&mut self, cval: U) {
+        self.inner.set_constant(cval.as_());
+    }
+}
+
+/// Trilinear interpolation of `half::f16` and `half::bf16` volumes, computed
+/// in f32.
+#[cfg(feature = "half")]
+pub type HalfTriLinear = Widened<TriLinear<f32>, f32>;
+
+impl<T, U, W, S> ReSample<T, U> for Widened<S, W>
+where
+    T: Num + AsPrimitive<usize> + AsPrimitive<U> + AsPrimitive<W> + PartialOrd + Copy,
+    U: Num + AsPrimitive<W> + Copy + Send + Sync + 'static,
+    W: Num + AsPrimitive<U> + Copy + Send + Sync + 'static,
+    S: ReSample<T, W>,
+    usize: AsPrimitive<T>,
+{
+    fn set_sampling_mode(&mut self, mode: SamplingMode) {
+        self.inner.set_sampling_mode(mode);
+    }
+
+    fn get_sampling_mode(&self) -> SamplingMode {
+        self.inner.get_sampling_mode()
+    }
+
+    fn set_cval(&mut self, cval: U) {
+        self.inner.set_cval(cval.as_());
+    }
+
+    fn get_cval(&self) -> U {
+        self.inner.get_cval().as_()
+    }
+
+    fn interpolates(&self) -> bool {
+        self.inner.interpolates()
+    }
+
+    fn sample(
+        &self,
+        in_im: &Array<U, IxDyn>,
+        in_coords: &mut Array2<T>,
+        out_shape: &[usize],
+    ) -> Result<Array<U, IxDyn>, String> {
+        let wide: Array<W, IxDyn> = in_im.mapv(|x| x.as_());
+        let values = self.inner.sample(&wide, in_coords, out_shape)?;
+        Ok(values.mapv(|x| x.as_()))
+    }
+
+    fn sample_view(
+        &self,
+        in_im: &Array<U, IxDyn>,
+        in_coords: ArrayView2<T>,
+        out_shape: &[usize],
+    ) -> Result<Array<U, IxDyn>, String> {
+        let wide: Array<W, IxDyn> = in_im.mapv(|x| x.as_());
+        let values = self.inner.sample_view(&wide, in_coords, out_shape)?;
+        Ok(values.mapv(|x| x.as_()))
+    }
+}
+
+#[cfg(all(test, feature = "nalgebra"))]
+mod tests {
+    use super::*;
+    use crate::resample_from_to;
+    use crate::sampler::trilinear::TriLinear;
+    use half::{bf16, f16};
+    use nalgebra::{Matrix4, Vector3};
+
+    #[test]
+    fn test_half_trilinear() {
+        let im = Array::from_shape_fn(IxDyn(&[4, 4, 4]), |idx| {
+            (idx[0] * 16 + idx[1] * 4 + idx[2]) as f32
+        });
+        let affine = Matrix4::<f32>::identity();
+        let out_affine = Matrix4::new_translation(&Vector3::new(0.5, 0.25, 0.75));
+        let reference =
+            resample_from_to(&im, &affine, &[3, 3, 3], &out_affine, &TriLinear::default()).unwrap();
+
+        let half_im = im.mapv(f16::from_f32);
+        let mut sampler = Widened::<TriLinear<f32>, f32>::default();
+        ReSample::<f32, f16>::set_cval(&mut sampler, f16::from_f32(-1.0));
+        assert_eq!(
+            ReSample::<f32, f16>::get_cval(&sampler),
+            f16::from_f32(-1.0)
+        );
+        let resampled =
+            resample_from_to(&half_im, &affine, &[3, 3, 3], &out_affine, &sampler).unwrap();
+        for (a, b) in resampled.iter().zip(reference.iter()) {
+            // only the final rounding to f16 remains
+            assert_eq!(*a, f16::from_f32(*b));
+        }
+
+        let bf16_im = im.mapv(bf16::from_f32);
+        let resampled =
+            resample_from_to(&bf16_im, &affine, &[3, 3, 3], &out_affine, &sampler).unwrap();
+        for (a, b) in resampled.iter().zip(reference.iter()) {
+            assert_eq!(*a, bf16::from_f32(*b));
+        }
+    }
+}
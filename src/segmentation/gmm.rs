@@ -0,0 +1,278 @@
+use crate::neighborhood::{flat_index, offset_index, unflat_index, Connectivity};
+use crate::par::*;
+use crate::{sanitize_im_shape, sanitize_mask, shape3};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+use std::f64::consts::PI;
+
+/// Parameters of the expectation maximization (EM) Gaussian mixture segmentation.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GaussianMixture {
+    /// Number of tissue classes, e.g. 3 for CSF / GM / WM.
+    pub n_classes: usize,
+
+    /// Maximum number of EM iterations.
+    pub max_iter: usize,
+
+    /// Convergence threshold on the relative change of the log-likelihood.
+    pub tolerance: f64,
+
+    /// Strength of the Markov random field (MRF) spatial prior.
+    /// `None` disables the prior, i.e. voxels are classified independently.
+    pub mrf_beta: Option<f64>,
+}
+
+impl Default for GaussianMixture {
+    fn default() -> Self {
+        Self {
+            n_classes: 3,
+            max_iter: 100,
+            tolerance: 1e-5,
+            mrf_beta: None,
+        }
+    }
+}
+
+/// Result of a [`gaussian_mixture_segmentation`].
+///
+/// All per-class quantities are ordered by increasing class mean, i.e. for a
+/// T1 weighted image the classes are CSF, GM and WM.
+#[derive(Debug, Clone)]
+pub struct MixtureSegmentation {
+    /// Posterior probability map of each class. Voxels outside of the mask are 0.
+    pub probabilities: Vec<Array<f64, IxDyn>>,
+
+    /// Hard label map. Voxels outside of the mask are 0, class `k` is labeled `k + 1`.
+    pub labels: Array<usize, IxDyn>,
+
+    /// Mean intensity of each class.
+    pub means: Vec<f64>,
+
+    /// Intensity variance of each class.
+    pub variances: Vec<f64>,
+
+    /// Mixing proportion of each class.
+    pub weights: Vec<f64>,
+
+    /// Number of EM iterations performed.
+    pub n_iter: usize,
+}
+
+/// Segment in_im into `n_classes` Gaussian intensity classes.
+///
+/// The mixture parameters are estimated by expectation maximization. If
+/// `mrf_beta` is set, the class prior of each voxel is modulated by the
+/// posteriors of its 6-connected neighbors (mean field approximation of a
+/// Potts model), which suppresses isolated misclassified voxels.
+///
+/// The image should be bias corrected. Voxels outside of `mask` and non-finite
+/// voxels are ignored.
+pub fn gaussian_mixture_segmentation<U>(
+    in_im: &Array<U, IxDyn>,
+    mask: Option<&Array<bool, IxDyn>>,
+    params: &GaussianMixture,
+) -> Result<MixtureSegmentation, String>
+where
+    U: AsPrimitive<f64>,
+{
+    let k = params.n_classes;
+    if k == 0 {
+        return Err("n_classes has to be at least 1".into());
+    }
+
+    let in_im = sanitize_im_shape(in_im)?;
+    let shape = shape3(&in_im);
+    let mask = sanitize_mask(mask, in_im.shape())?;
+
+    // gather all voxels taking part in the estimation
+    let mut voxels: Vec<usize> = Vec::new();
+    let mut values: Vec<f64> = Vec::new();
+    let mut position = vec![usize::MAX; in_im.len()];
+    let inside: Vec<bool> = match &mask {
+        Some(m) => m.iter().copied().collect(),
+        None => vec![true; in_im.len()],
+    };
+    for (i, (val, inside)) in in_im.iter().zip(inside).enumerate() {
+        let val: f64 = val.as_();
+        if inside && val.is_finite() {
+            position[i] = voxels.len();
+            voxels.push(i);
+            values.push(val);
+        }
+    }
+    let n = values.len();
+    if n < k {
+        return Err("not enough voxels for the requested number of classes".into());
+    }
+
+    // initialize the classes at evenly spaced intensity quantiles
+    let mut sorted = values.clone();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let global_mean = values.iter().sum::<f64>() / n as f64;
+    let global_var = values
+        .iter()
+        .map(|x| (x - global_mean).powi(2))
+        .sum::<f64>()
+        / n as f64;
+    let var_floor = (global_var * 1e-6).max(1e-12);
+
+    let mut means: Vec<f64> = (0..k)
+        .map(|c| sorted[((c as f64 + 0.5) / k as f64 * n as f64) as usize])
+        .collect();
+    let mut variances = vec![(global_var / (k * k) as f64).max(var_floor); k];
+    let mut weights = vec![1.0 / k as f64; k];
+
+    let offsets = Connectivity::Six.offsets();
+    let mut posteriors = vec![1.0 / k as f64; n * k];
+    let mut prev_ll = f64::NEG_INFINITY;
+    let mut n_iter = 0;
+
+    for _ in 0..params.max_iter {
+        n_iter += 1;
+
+        // E-step
+        let log_norm: Vec<f64> = (0..k)
+            .map(|c| weights[c].ln() - 0.5 * (2.0 * PI * variances[c]).ln())
+            .collect();
+        let prior = params.mrf_beta.map(|_| posteriors.clone());
+        let ll: f64 = posteriors
+            .par_chunks_mut(k)
+            .enumerate()
+            .map(|(j, post)| {
+                let x = values[j];
+                for c in 0..k {
+                    post[c] = log_norm[c] - (x - means[c]).powi(2) / (2.0 * variances[c]);
+                }
+                if let (Some(beta), Some(prior)) = (params.mrf_beta, &prior) {
+                    let idx = unflat_index(voxels[j], &shape);
+                    for offset in &offsets {
+                        if let Some(nb) = offset_index(idx, offset, &shape) {
+                            let p = position[flat_index(nb, &shape)];
+                            if p != usize::MAX {
+                                for c in 0..k {
+                                    post[c] += beta * prior[p * k + c];
+                                }
+                            }
+                        }
+                    }
+                }
+                // normalize using log-sum-exp
+                let mx = post.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                let s: f64 = post.iter().map(|x| (x - mx).exp()).sum();
+                post.iter_mut().for_each(|x| *x = (*x - mx).exp() / s);
+                mx + s.ln()
+            })
+            .sum();
+
+        // M-step
+        let mut sum_w = vec![0.0; k];
+        let mut sum_wx = vec![0.0; k];
+        for (j, post) in posteriors.chunks(k).enumerate() {
+            for c in 0..k {
+                sum_w[c] += post[c];
+                sum_wx[c] += post[c] * values[j];
+            }
+        }
+        for c in 0..k {
+            if sum_w[c] > 0.0 {
+                means[c] = sum_wx[c] / sum_w[c];
+            }
+        }
+        let mut sum_wd = vec![0.0; k];
+        for (j, post) in posteriors.chunks(k).enumerate() {
+            for c in 0..k {
+                sum_wd[c] += post[c] * (values[j] - means[c]).powi(2);
+            }
+        }
+        for c in 0..k {
+            if sum_w[c] > 0.0 {
+                variances[c] = (sum_wd[c] / sum_w[c]).max(var_floor);
+                weights[c] = sum_w[c] / n as f64;
+            }
+        }
+
+        if (ll - prev_ll).abs() <= params.tolerance * ll.abs() {
+            break;
+        }
+        prev_ll = ll;
+    }
+
+    // order classes by increasing mean
+    let mut order: Vec<usize> = (0..k).collect();
+    order.sort_by(|a, b| means[*a].partial_cmp(&means[*b]).unwrap());
+
+    let mut probabilities = vec![Array::<f64, _>::zeros(IxDyn(&shape)); k];
+    let mut labels = Array::<usize, _>::zeros(IxDyn(&shape));
+    for (j, post) in posteriors.chunks(k).enumerate() {
+        let idx = unflat_index(voxels[j], &shape);
+        let mut best = 0;
+        for (new_c, old_c) in order.iter().enumerate() {
+            probabilities[new_c][IxDyn(&idx)] = post[*old_c];
+            if post[*old_c] > post[order[best]] {
+                best = new_c;
+            }
+        }
+        labels[IxDyn(&idx)] = best + 1;
+    }
+
+    Ok(MixtureSegmentation {
+        probabilities,
+        labels,
+        means: order.iter().map(|c| means[*c]).collect(),
+        variances: order.iter().map(|c| variances[*c]).collect(),
+        weights: order.iter().map(|c| weights[*c]).collect(),
+        n_iter,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_class_image() -> Array<f32, IxDyn> {
+        Array::from_shape_fn(IxDyn(&[6, 4, 4]), |idx| {
+            let noise = ((idx[0] * 7 + idx[1] * 3 + idx[2]) % 5) as f32 - 2.0;
+            if idx[0] < 3 {
+                10.0 + noise
+            } else {
+                100.0 + noise
+            }
+        })
+    }
+
+    #[test]
+    fn test_gaussian_mixture_two_classes() {
+        let im = two_class_image();
+        let params = GaussianMixture {
+            n_classes: 2,
+            ..Default::default()
+        };
+        let seg = gaussian_mixture_segmentation(&im, None, &params).unwrap();
+        assert!((seg.means[0] - 10.0).abs() < 1.0);
+        assert!((seg.means[1] - 100.0).abs() < 1.0);
+        for (idx, label) in seg.labels.indexed_iter() {
+            assert_eq!(*label, if idx[0] < 3 { 1 } else { 2 });
+        }
+    }
+
+    #[test]
+    fn test_gaussian_mixture_mask() {
+        let im = two_class_image();
+        let mask = Array::from_shape_fn(IxDyn(&[6, 4, 4]), |idx| idx[2] > 0);
+        let params = GaussianMixture {
+            n_classes: 2,
+            mrf_beta: Some(0.5),
+            ..Default::default()
+        };
+        let seg = gaussian_mixture_segmentation(&im, Some(&mask), &params).unwrap();
+        for (idx, label) in seg.labels.indexed_iter() {
+            if idx[2] == 0 {
+                assert_eq!(*label, 0);
+                assert_eq!(seg.probabilities[0][&idx], 0.0);
+            } else {
+                assert_eq!(*label, if idx[0] < 3 { 1 } else { 2 });
+            }
+        }
+    }
+}
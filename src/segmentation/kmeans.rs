@@ -0,0 +1,286 @@
+use crate::neighborhood::unflat_index;
+use crate::par::*;
+use crate::{sanitize_im_shape, sanitize_mask, shape3};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Parameters of the k-means intensity clustering.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KMeans {
+    /// Number of clusters.
+    pub n_clusters: usize,
+
+    /// Maximum number of Lloyd iterations.
+    pub max_iter: usize,
+
+    /// Iteration stops once no centroid moves further than this distance.
+    pub tolerance: f64,
+
+    /// If set, the voxel coordinates are used as additional features. The
+    /// weight converts a distance of one voxel into intensity units.
+    pub spatial_weight: Option<f64>,
+}
+
+impl Default for KMeans {
+    fn default() -> Self {
+        Self {
+            n_clusters: 3,
+            max_iter: 100,
+            tolerance: 1e-4,
+            spatial_weight: None,
+        }
+    }
+}
+
+/// Result of a [`kmeans_segmentation`].
+///
+/// Clusters are ordered by increasing centroid intensity.
+#[derive(Debug, Clone)]
+pub struct KMeansSegmentation {
+    /// Label map. Voxels outside of the mask are 0, cluster `k` is labeled `k + 1`.
+    pub labels: Array<usize, IxDyn>,
+
+    /// Cluster centroids. The first feature is the intensity, followed by the
+    /// weighted voxel coordinates if `spatial_weight` is set.
+    pub centroids: Vec<Vec<f64>>,
+
+    /// Number of iterations performed.
+    pub n_iter: usize,
+}
+
+/// Cluster the voxels of in_im into `n_clusters` groups using k-means.
+///
+/// The centroids are initialized deterministically from equally sized
+/// intensity quantile bins, hence repeated runs yield identical results.
+/// Voxels outside of `mask` and non-finite voxels are ignored.
+pub fn kmeans_segmentation<U>(
+    in_im: &Array<U, IxDyn>,
+    mask: Option<&Array<bool, IxDyn>>,
+    params: &KMeans,
+) -> Result<KMeansSegmentation, String>
+where
+    U: AsPrimitive<f64>,
+{
+    let k = params.n_clusters;
+    if k == 0 {
+        return Err("n_clusters has to be at least 1".into());
+    }
+
+    let in_im = sanitize_im_shape(in_im)?;
+    let shape = shape3(&in_im);
+    let mask = sanitize_mask(mask, in_im.shape())?;
+
+    let inside: Vec<bool> = match &mask {
+        Some(m) => m.iter().copied().collect(),
+        None => vec![true; in_im.len()],
+    };
+    let n_features = if params.spatial_weight.is_some() {
+        4
+    } else {
+        1
+    };
+
+    // build the feature matrix (row-major, one row per voxel)
+    let mut voxels: Vec<usize> = Vec::new();
+    let mut features: Vec<f64> = Vec::new();
+    for (i, (val, inside)) in in_im.iter().zip(inside).enumerate() {
+        let val: f64 = val.as_();
+        if !inside || !val.is_finite() {
+            continue;
+        }
+        voxels.push(i);
+        features.push(val);
+        if let Some(w) = params.spatial_weight {
+            let idx = unflat_index(i, &shape);
+            features.extend(idx.iter().map(|x| w * *x as f64));
+        }
+    }
+    let n = voxels.len();
+    if n < k {
+        return Err("not enough voxels for the requested number of clusters".into());
+    }
+
+    // initialize centroids as the means of equally sized intensity bins
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|a, b| {
+        features[a * n_features]
+            .partial_cmp(&features[b * n_features])
+            .unwrap()
+    });
+    let mut centroids = vec![0.0; k * n_features];
+    for c in 0..k {
+        let bin = &order[c * n / k..(c + 1) * n / k];
+        for j in bin {
+            for f in 0..n_features {
+                centroids[c * n_features + f] += features[j * n_features + f] / bin.len() as f64;
+            }
+        }
+    }
+
+    let mut assignment = vec![0usize; n];
+    let mut n_iter = 0;
+    for _ in 0..params.max_iter {
+        n_iter += 1;
+
+        // assignment step
+        assignment.par_iter_mut().enumerate().for_each(|(j, a)| {
+            let row = &features[j * n_features..(j + 1) * n_features];
+            let mut best = (0, f64::INFINITY);
+            for c in 0..k {
+                let d: f64 = row
+                    .iter()
+                    .zip(&centroids[c * n_features..(c + 1) * n_features])
+                    .map(|(x, m)| (x - m).powi(2))
+                    .sum();
+                if d < best.1 {
+                    best = (c, d);
+                }
+            }
+            *a = best.0;
+        });
+
+        // update step
+        let zeros = || vec![0.0; k * (n_features + 1)];
+        let accumulate = |mut acc: Vec<f64>, j: usize| {
+            let c = assignment[j];
+            for f in 0..n_features {
+                acc[c * (n_features + 1) + f] += features[j * n_features + f];
+            }
+            acc[c * (n_features + 1) + n_features] += 1.0;
+            acc
+        };
+        #[cfg(feature = "parallel")]
+        let sums = (0..n)
+            .into_par_iter()
+            .fold(zeros, accumulate)
+            .reduce(zeros, |a, b| a.iter().zip(&b).map(|(x, y)| x + y).collect());
+        #[cfg(not(feature = "parallel"))]
+        let sums = (0..n).fold(zeros(), accumulate);
+
+        let mut max_shift: f64 = 0.0;
+        for c in 0..k {
+            let count = sums[c * (n_features + 1) + n_features];
+            if count == 0.0 {
+                // keep empty clusters in place
+                continue;
+            }
+            let mut shift = 0.0;
+            for f in 0..n_features {
+                let new = sums[c * (n_features + 1) + f] / count;
+                shift += (new - centroids[c * n_features + f]).powi(2);
+                centroids[c * n_features + f] = new;
+            }
+            max_shift = max_shift.max(shift.sqrt());
+        }
+        if max_shift <= params.tolerance {
+            break;
+        }
+    }
+
+    // order clusters by increasing centroid intensity
+    let mut cluster_order: Vec<usize> = (0..k).collect();
+    cluster_order.sort_by(|a, b| {
+        centroids[a * n_features]
+            .partial_cmp(&centroids[b * n_features])
+            .unwrap()
+    });
+    let mut rank = vec![0; k];
+    for (r, c) in cluster_order.iter().enumerate() {
+        rank[*c] = r;
+    }
+
+    let mut labels = Array::<usize, _>::zeros(IxDyn(&shape));
+    for (j, c) in assignment.iter().enumerate() {
+        labels[IxDyn(&unflat_index(voxels[j], &shape))] = rank[*c] + 1;
+    }
+
+    Ok(KMeansSegmentation {
+        labels,
+        centroids: cluster_order
+            .iter()
+            .map(|c| centroids[c * n_features..(c + 1) * n_features].to_vec())
+            .collect(),
+        n_iter,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kmeans_intensity_clusters() {
+        let im = Array::from_shape_fn(IxDyn(&[6, 3, 3]), |idx| match idx[0] {
+            0 | 1 => 5.0 + idx[2] as f32,
+            2 | 3 => 50.0 + idx[2] as f32,
+            _ => 200.0 + idx[2] as f32,
+        });
+        let seg = kmeans_segmentation(&im, None, &KMeans::default()).unwrap();
+        assert_eq!(seg.centroids.len(), 3);
+        assert!((seg.centroids[1][0] - 51.0).abs() < 1e-6);
+        for (idx, label) in seg.labels.indexed_iter() {
+            assert_eq!(*label, idx[0] / 2 + 1);
+        }
+    }
+
+    #[test]
+    fn test_kmeans_mask() {
+        // bright voxels outside of the mask must neither be labeled nor
+        // shift the centroids
+        let im = Array::from_shape_fn(IxDyn(&[6, 2, 2]), |idx| match idx[0] {
+            0 | 1 => 10.0,
+            2 | 3 => 100.0,
+            _ => 1e4,
+        });
+        let mask = im.mapv(|x| x < 1e3);
+        let params = KMeans {
+            n_clusters: 2,
+            ..Default::default()
+        };
+        let seg = kmeans_segmentation(&im, Some(&mask), &params).unwrap();
+        assert_eq!(seg.centroids, vec![vec![10.0], vec![100.0]]);
+        for (idx, label) in seg.labels.indexed_iter() {
+            assert_eq!(*label, [1, 1, 2, 2, 0, 0][idx[0]]);
+        }
+    }
+
+    #[test]
+    fn test_kmeans_spatial_weight() {
+        // two blobs of equal intensity at both ends along x
+        let im = Array::from_shape_fn(IxDyn(&[8, 3, 3]), |idx| {
+            if idx[0] < 2 || idx[0] >= 6 {
+                100.0
+            } else {
+                0.0
+            }
+        });
+        let mask = im.mapv(|x| x > 50.0);
+        let params = KMeans {
+            n_clusters: 2,
+            ..Default::default()
+        };
+        let labels_of = |seg: &KMeansSegmentation, x: usize| {
+            let mut labels: Vec<usize> =
+                seg.labels.index_axis(Axis(0), x).iter().copied().collect();
+            labels.dedup();
+            labels
+        };
+
+        // intensity alone cannot separate them
+        let seg = kmeans_segmentation(&im, Some(&mask), &params).unwrap();
+        assert_eq!(labels_of(&seg, 0), labels_of(&seg, 7));
+
+        let params = KMeans {
+            spatial_weight: Some(1.0),
+            ..params
+        };
+        let seg = kmeans_segmentation(&im, Some(&mask), &params).unwrap();
+        for (a, b) in [(0, 1), (6, 7)] {
+            assert_eq!(labels_of(&seg, a).len(), 1);
+            assert_eq!(labels_of(&seg, a), labels_of(&seg, b));
+        }
+        assert_ne!(labels_of(&seg, 0), labels_of(&seg, 7));
+        assert_eq!(labels_of(&seg, 3), vec![0]);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::par::*;
+use crate::{resample_with_transform, ReSample};
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::float::FloatCore;
+use num_traits::AsPrimitive;
+
+/// An atlas label map together with its alignment to the target.
+#[derive(Debug, Clone, Copy)]
+pub struct Atlas<'a, T>
+where
+    T: Scalar,
+{
+    /// Label map of the atlas. 0 denotes background.
+    pub labels: &'a Array<usize, IxDyn>,
+
+    /// Affine of the atlas label map.
+    pub affine: Matrix4<T>,
+
+    /// World space transform mapping target world coordinates to atlas world
+    /// coordinates, e.g. as estimated by a registration of the atlas
+    /// intensity image to the target.
+    pub transform: Matrix4<T>,
+}
+
+/// Strategy used to fuse the propagated atlas labels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LabelFusion {
+    /// Each atlas casts one vote per voxel. Ties are resolved in favor of the
+    /// smaller label.
+    MajorityVote,
+
+    /// Simplified STAPLE: the performance of each atlas is estimated from its
+    /// agreement with the current consensus and votes are weighted by the
+    /// log-odds of that performance. Iterated up to `max_iter` times.
+    Staple { max_iter: usize },
+}
+
+/// Result of [`propagate_labels`].
+#[derive(Debug, Clone)]
+pub struct PropagatedLabels {
+    /// Fused label map in target space.
+    pub labels: Array<usize, IxDyn>,
+
+    /// Vote weight of each atlas used for the final fusion.
+    pub atlas_weights: Vec<f64>,
+}
+
+/// Propagate one or more atlas label maps to a target grid and fuse them.
+///
+/// Each atlas is warped into target space with `sampler`, which should be a
+/// label sampler such as [`NearestNeighbor`](crate::NearestNeighbor),
+/// [`LabelTriLinear`](crate::LabelTriLinear) or
+/// [`SignedDistance`](crate::SignedDistance), so that no artificial labels
+/// are introduced by interpolation.
+pub fn propagate_labels<T, S>(
+    atlases: &[Atlas<T>],
+    out_shape: &[usize; 3],
+    out_affine: &Matrix4<T>,
+    fusion: LabelFusion,
+    sampler: &S,
+) -> Result<PropagatedLabels, String>
+where
+    T: Scalar + RealField + FloatCore + AsPrimitive<usize> + Copy,
+    S: ReSample<T, usize> + ?Sized + 'static,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    if atlases.is_empty() {
+        return Err("at least one atlas is required".into());
+    }
+
+    let warped = atlases
+        .iter()
+        .map(|atlas| {
+            resample_with_transform(
+                atlas.labels,
+                &atlas.affine,
+                &atlas.transform,
+                out_shape,
+                out_affine,
+                sampler,
+            )
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let warped: Vec<&[usize]> = warped
+        .iter()
+        .map(|w| w.as_slice().expect("resampled labels are contiguous"))
+        .collect();
+
+    let mut weights = vec![1.0; atlases.len()];
+    let mut fused = weighted_vote(&warped, &weights);
+
+    if let LabelFusion::Staple { max_iter } = fusion {
+        for _ in 0..max_iter {
+            // agreement with the consensus, evaluated where any atlas has a label
+            let new_weights: Vec<f64> = warped
+                .iter()
+                .map(|w| {
+                    let (mut agree, mut total) = (0usize, 0usize);
+                    for (i, f) in fused.iter().enumerate() {
+                        if *f != 0 || w[i] != 0 {
+                            total += 1;
+                            agree += (*f == w[i]) as usize;
+                        }
+                    }
+                    let p = if total == 0 {
+                        0.5
+                    } else {
+                        (agree as f64 / total as f64).clamp(1e-3, 1.0 - 1e-3)
+                    };
+                    (p / (1.0 - p)).ln().max(0.0)
+                })
+                .collect();
+            let converged = new_weights
+                .iter()
+                .zip(&weights)
+                .all(|(a, b)| (a - b).abs() < 1e-6);
+            weights = new_weights;
+            if weights.iter().all(|w| *w == 0.0) {
+                // no atlas performs better than chance, fall back to plain voting
+                weights = vec![1.0; atlases.len()];
+            }
+            fused = weighted_vote(&warped, &weights);
+            if converged {
+                break;
+            }
+        }
+    }
+
+    Ok(PropagatedLabels {
+        labels: Array::from_shape_vec(IxDyn(out_shape), fused)
+            .map_err(|_| "number of elements is not compatible with out_shape")?,
+        atlas_weights: weights,
+    })
+}
+
+/// Voxel-wise weighted vote. Ties are resolved in favor of the smaller label.
+fn weighted_vote(warped: &[&[usize]], weights: &[f64]) -> Vec<usize> {
+    let n = warped[0].len();
+    (0..n)
+        .into_par_iter()
+        .map(|i| {
+            let mut votes: Vec<(usize, f64)> = Vec::with_capacity(warped.len());
+            for (w, weight) in warped.iter().zip(weights) {
+                match votes.iter_mut().find(|(l, _)| *l == w[i]) {
+                    Some((_, v)) => *v += weight,
+                    None => votes.push((w[i], *weight)),
+                }
+            }
+            votes
+                .iter()
+                .fold((0, f64::NEG_INFINITY), |best, (l, v)| {
+                    if *v > best.1 || (*v == best.1 && *l < best.0) {
+                        (*l, *v)
+                    } else {
+                        best
+                    }
+                })
+                .0
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LabelTriLinear, NearestNeighbor};
+
+    #[test]
+    fn test_propagate_labels() {
+        let truth = Array::from_shape_fn(IxDyn(&[6, 6, 6]), |idx| (idx[0] >= 3) as usize + 1);
+        let mut noisy = truth.clone();
+        noisy[[0, 0, 0]] = 2;
+        let wrong = Array::from_elem(IxDyn(&[6, 6, 6]), 3usize);
+
+        let identity = Matrix4::<f32>::identity();
+        let atlas = |labels| Atlas {
+            labels,
+            affine: identity,
+            transform: identity,
+        };
+        let atlases = [atlas(&truth), atlas(&noisy), atlas(&wrong)];
+
+        let nn = NearestNeighbor::default();
+        let vote = propagate_labels(
+            &atlases,
+            &[6, 6, 6],
+            &identity,
+            LabelFusion::MajorityVote,
+            &nn,
+        )
+        .unwrap();
+        assert_eq!(vote.labels, truth);
+
+        let staple = propagate_labels(
+            &atlases,
+            &[6, 6, 6],
+            &identity,
+            LabelFusion::Staple { max_iter: 10 },
+            &nn,
+        )
+        .unwrap();
+        assert_eq!(staple.labels, truth);
+        assert!(staple.atlas_weights[2] < staple.atlas_weights[0]);
+    }
+
+    #[test]
+    fn test_propagate_labels_label_trilinear() {
+        // atlases shifted by 0.6 voxels: the one-hot encodings are
+        // interpolated and the boundary moves to the nearer voxel
+        let labels = Array::from_shape_fn(IxDyn(&[6, 6, 6]), |idx| (idx[0] >= 3) as usize * 4);
+        let identity = Matrix4::<f64>::identity();
+        let shift = Matrix4::new_translation(&[0.6, 0.0, 0.0].into());
+        let atlases = [
+            Atlas {
+                labels: &labels,
+                affine: identity,
+                transform: shift,
+            },
+            Atlas {
+                labels: &labels,
+                affine: identity,
+                transform: shift,
+            },
+        ];
+        let fused = propagate_labels(
+            &atlases,
+            &[6, 6, 6],
+            &identity,
+            LabelFusion::MajorityVote,
+            &LabelTriLinear::default(),
+        )
+        .unwrap();
+        let expected = Array::from_shape_fn(IxDyn(&[6, 6, 6]), |idx| {
+            // beyond the last voxel the constant 0 outweighs label 4
+            (2..5).contains(&idx[0]) as usize * 4
+        });
+        assert_eq!(fused.labels, expected);
+    }
+}
@@ -0,0 +1,7 @@
+// segmentation implementations:
+pub mod brain_extraction;
+pub mod gmm;
+pub mod kmeans;
+pub mod label_fusion;
+pub mod region_growing;
+pub mod watershed;
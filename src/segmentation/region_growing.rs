@@ -0,0 +1,178 @@
+use crate::neighborhood::{offset_index, Connectivity};
+use crate::{sanitize_im_shape, shape3};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+use std::collections::VecDeque;
+
+/// Inclusion criterion for [`region_growing`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GrowthCriterion {
+    /// Voxels with `lower <= value <= upper` are included.
+    Range { lower: f64, upper: f64 },
+
+    /// Voxels within `mean ± k·std` of the region are included (confidence
+    /// connected). The statistics are initialized from a box of
+    /// `initial_radius` voxels around each seed and re-estimated from the
+    /// grown region `iterations` times.
+    Adaptive {
+        k: f64,
+        iterations: usize,
+        initial_radius: usize,
+    },
+}
+
+/// Grow a region from `seeds` over all connected voxels satisfying `criterion`.
+///
+/// Seeds are given as voxel indices. Seeds which do not satisfy the criterion
+/// themselves are not part of the region. Returns a binary mask.
+pub fn region_growing<U>(
+    in_im: &Array<U, IxDyn>,
+    seeds: &[[usize; 3]],
+    criterion: &GrowthCriterion,
+    connectivity: Connectivity,
+) -> Result<Array<bool, IxDyn>, String>
+where
+    U: AsPrimitive<f64>,
+{
+    let in_im = sanitize_im_shape(in_im)?;
+    let im: Array<f64, IxDyn> = in_im.mapv(|x| x.as_());
+    let shape = shape3(&im);
+
+    if seeds.is_empty() {
+        return Err("at least one seed point is required".into());
+    }
+    if seeds
+        .iter()
+        .any(|s| s.iter().zip(&shape).any(|(i, n)| i >= n))
+    {
+        return Err("seed point out of bounds".into());
+    }
+
+    match *criterion {
+        GrowthCriterion::Range { lower, upper } => {
+            Ok(flood_fill(&im, seeds, lower, upper, connectivity))
+        }
+        GrowthCriterion::Adaptive {
+            k,
+            iterations,
+            initial_radius,
+        } => {
+            // initial statistics from the neighborhood of the seeds
+            let r = initial_radius as isize;
+            let mut values = Vec::new();
+            for seed in seeds {
+                for dx in -r..=r {
+                    for dy in -r..=r {
+                        for dz in -r..=r {
+                            if let Some(idx) = offset_index(*seed, &[dx, dy, dz], &shape) {
+                                values.push(im[IxDyn(&idx)]);
+                            }
+                        }
+                    }
+                }
+            }
+            let (mut mean, mut std) = mean_std(values.iter());
+
+            let mut region = flood_fill(&im, seeds, mean - k * std, mean + k * std, connectivity);
+            for _ in 0..iterations {
+                let inside = im.iter().zip(region.iter()).filter(|(_, m)| **m);
+                (mean, std) = mean_std(inside.map(|(v, _)| v));
+                if !mean.is_finite() {
+                    break;
+                }
+                region = flood_fill(&im, seeds, mean - k * std, mean + k * std, connectivity);
+            }
+            Ok(region)
+        }
+    }
+}
+
+fn mean_std<'a>(values: impl Iterator<Item = &'a f64>) -> (f64, f64) {
+    let (mut n, mut sum, mut sum_sq) = (0usize, 0.0, 0.0);
+    for v in values {
+        n += 1;
+        sum += v;
+        sum_sq += v * v;
+    }
+    let mean = sum / n as f64;
+    let var = (sum_sq / n as f64 - mean * mean).max(0.0);
+    (mean, var.sqrt())
+}
+
+/// Breadth first flood fill over all voxels in [lower, upper].
+fn flood_fill(
+    im: &Array<f64, IxDyn>,
+    seeds: &[[usize; 3]],
+    lower: f64,
+    upper: f64,
+    connectivity: Connectivity,
+) -> Array<bool, IxDyn> {
+    let shape = shape3(im);
+    let offsets = connectivity.offsets();
+    let included = |idx: &[usize; 3]| {
+        let v = im[IxDyn(idx)];
+        v >= lower && v <= upper
+    };
+
+    let mut region = Array::from_elem(im.raw_dim(), false);
+    let mut queue: VecDeque<[usize; 3]> = VecDeque::new();
+    for seed in seeds {
+        if included(seed) && !region[IxDyn(seed)] {
+            region[IxDyn(seed)] = true;
+            queue.push_back(*seed);
+        }
+    }
+    while let Some(idx) = queue.pop_front() {
+        for offset in &offsets {
+            if let Some(nb) = offset_index(idx, offset, &shape) {
+                if !region[IxDyn(&nb)] && included(&nb) {
+                    region[IxDyn(&nb)] = true;
+                    queue.push_back(nb);
+                }
+            }
+        }
+    }
+    region
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube_image() -> Array<f32, IxDyn> {
+        Array::from_shape_fn(IxDyn(&[8, 8, 8]), |idx| {
+            let inside =
+                (2..5).contains(&idx[0]) && (2..5).contains(&idx[1]) && (2..5).contains(&idx[2]);
+            let noise = ((idx[0] + 2 * idx[1] + 3 * idx[2]) % 3) as f32;
+            if inside {
+                100.0 + noise
+            } else {
+                noise
+            }
+        })
+    }
+
+    #[test]
+    fn test_region_growing_range() {
+        let im = cube_image();
+        let criterion = GrowthCriterion::Range {
+            lower: 50.0,
+            upper: 150.0,
+        };
+        let region = region_growing(&im, &[[3, 3, 3]], &criterion, Connectivity::Six).unwrap();
+        assert_eq!(region.iter().filter(|x| **x).count(), 27);
+        assert!(region[[2, 2, 2]] && !region[[1, 2, 2]]);
+    }
+
+    #[test]
+    fn test_region_growing_adaptive() {
+        let im = cube_image();
+        let criterion = GrowthCriterion::Adaptive {
+            k: 2.5,
+            iterations: 3,
+            initial_radius: 1,
+        };
+        let region = region_growing(&im, &[[3, 3, 3]], &criterion, Connectivity::Six).unwrap();
+        assert_eq!(region.iter().filter(|x| **x).count(), 27);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::distance::euclidean_distance_transform;
+use crate::neighborhood::{offset_index, Connectivity};
+use crate::{sanitize_im_shape, sanitize_mask, shape3};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Queue entry of the priority flood. Lower elevations and, for equal
+/// elevations, older entries are popped first.
+struct FloodEntry {
+    elevation: f64,
+    age: usize,
+    idx: [usize; 3],
+}
+
+impl PartialEq for FloodEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FloodEntry {}
+
+impl PartialOrd for FloodEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FloodEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed, as BinaryHeap is a max-heap
+        other
+            .elevation
+            .total_cmp(&self.elevation)
+            .then_with(|| other.age.cmp(&self.age))
+    }
+}
+
+/// Marker-controlled watershed segmentation.
+///
+/// Starting from the labeled `markers` (0 denotes unlabeled voxels), the
+/// `elevation` image is flooded in order of increasing elevation, until all
+/// voxels within `mask` are assigned to one of the markers. Typical elevation
+/// images are the gradient magnitude of an intensity image or the negated
+/// distance transform of a binary mask.
+pub fn watershed<U>(
+    elevation: &Array<U, IxDyn>,
+    markers: &Array<usize, IxDyn>,
+    mask: Option<&Array<bool, IxDyn>>,
+    connectivity: Connectivity,
+) -> Result<Array<usize, IxDyn>, String>
+where
+    U: AsPrimitive<f64>,
+{
+    let elevation = sanitize_im_shape(elevation)?;
+    let shape = shape3(&elevation);
+    let mut labels = sanitize_im_shape(markers)?;
+    if labels.shape() != elevation.shape() {
+        return Err("markers shape does not match elevation shape".into());
+    }
+    let mask = sanitize_mask(mask, elevation.shape())?;
+    let in_mask = |idx: &[usize; 3]| mask.as_ref().map_or(true, |m| m[IxDyn(idx)]);
+
+    let offsets = connectivity.offsets();
+    let mut heap = BinaryHeap::new();
+    let mut age = 0;
+    for (idx, label) in labels.indexed_iter() {
+        if *label != 0 {
+            let idx = [idx[0], idx[1], idx[2]];
+            heap.push(FloodEntry {
+                elevation: elevation[IxDyn(&idx)].as_(),
+                age,
+                idx,
+            });
+            age += 1;
+        }
+    }
+
+    while let Some(entry) = heap.pop() {
+        let label = labels[IxDyn(&entry.idx)];
+        for offset in &offsets {
+            if let Some(nb) = offset_index(entry.idx, offset, &shape) {
+                if labels[IxDyn(&nb)] == 0 && in_mask(&nb) {
+                    labels[IxDyn(&nb)] = label;
+                    heap.push(FloodEntry {
+                        elevation: elevation[IxDyn(&nb)].as_(),
+                        age,
+                        idx: nb,
+                    });
+                    age += 1;
+                }
+            }
+        }
+    }
+
+    // markers placed outside of the mask are not part of the result
+    if let Some(mask) = &mask {
+        labels.zip_mut_with(mask, |l, m| {
+            if !m {
+                *l = 0
+            }
+        });
+    }
+    Ok(labels)
+}
+
+/// Split touching objects of a binary mask.
+///
+/// The mask is flooded from `markers` on the negated (spacing aware) distance
+/// transform, so that the objects are separated along their narrowest
+/// connections.
+pub fn split_objects(
+    mask: &Array<bool, IxDyn>,
+    voxel_sizes: &[f64; 3],
+    markers: &Array<usize, IxDyn>,
+    connectivity: Connectivity,
+) -> Result<Array<usize, IxDyn>, String> {
+    let distance = euclidean_distance_transform(mask, voxel_sizes)?;
+    let elevation = distance.mapv(|x| -x);
+    watershed(&elevation, markers, Some(mask), connectivity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_objects() {
+        // two touching spheres with centers at x = 4 and x = 11
+        let shape = [16, 9, 9];
+        let mask = Array::from_shape_fn(IxDyn(&shape), |idx| {
+            let d = |cx: f64| {
+                ((idx[0] as f64 - cx).powi(2)
+                    + (idx[1] as f64 - 4.0).powi(2)
+                    + (idx[2] as f64 - 4.0).powi(2))
+                .sqrt()
+            };
+            d(4.0) <= 4.0 || d(11.0) <= 4.0
+        });
+        let mut markers = Array::zeros(IxDyn(&shape));
+        markers[[4, 4, 4]] = 1;
+        markers[[11, 4, 4]] = 2;
+
+        let labels = split_objects(&mask, &[1.0; 3], &markers, Connectivity::Six).unwrap();
+        for (idx, label) in labels.indexed_iter() {
+            if !mask[&idx] {
+                assert_eq!(*label, 0);
+            } else if idx[0] < 7 {
+                assert_eq!(*label, 1);
+            } else if idx[0] > 8 {
+                assert_eq!(*label, 2);
+            }
+        }
+    }
+}
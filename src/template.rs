@@ -0,0 +1,135 @@
+use crate::{resample_with_transform, ReSample};
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::{AsPrimitive, Num};
+
+/// Voxel size of the MNI152 template grids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MniResolution {
+    /// 1 mm isotropic, 182 x 218 x 182 voxels.
+    OneMm,
+    /// 2 mm isotropic, 91 x 109 x 91 voxels.
+    #[default]
+    TwoMm,
+}
+
+/// Shape of the FSL MNI152 1 mm template (`MNI152_T1_1mm.nii.gz`).
+pub const MNI152_1MM_SHAPE: [usize; 3] = [182, 218, 182];
+
+/// Shape of the FSL MNI152 2 mm template (`MNI152_T1_2mm.nii.gz`).
+pub const MNI152_2MM_SHAPE: [usize; 3] = [91, 109, 91];
+
+/// The voxel grid of a standard space: shape and voxel to world affine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TemplateGrid {
+    pub shape: [usize; 3],
+    pub affine: Matrix4<f64>,
+}
+
+impl TemplateGrid {
+    /// The FSL MNI152 1 mm grid. The world origin (the anterior commissure)
+    /// is at voxel (90, 126, 72) and the first axis runs from right to left,
+    /// as in the template files.
+    pub fn mni152_1mm() -> Self {
+        Self {
+            shape: MNI152_1MM_SHAPE,
+            affine: Matrix4::new(
+                -1.0, 0.0, 0.0, 90.0, //
+                0.0, 1.0, 0.0, -126.0, //
+                0.0, 0.0, 1.0, -72.0, //
+                0.0, 0.0, 0.0, 1.0,
+            ),
+        }
+    }
+
+    /// The FSL MNI152 2 mm grid, with the world origin at voxel (45, 63, 36).
+    pub fn mni152_2mm() -> Self {
+        Self {
+            shape: MNI152_2MM_SHAPE,
+            affine: Matrix4::new(
+                -2.0, 0.0, 0.0, 90.0, //
+                0.0, 2.0, 0.0, -126.0, //
+                0.0, 0.0, 2.0, -72.0, //
+                0.0, 0.0, 0.0, 1.0,
+            ),
+        }
+    }
+
+    /// The MNI152 grid of the given resolution.
+    pub fn mni152(resolution: MniResolution) -> Self {
+        match resolution {
+            MniResolution::OneMm => Self::mni152_1mm(),
+            MniResolution::TwoMm => Self::mni152_2mm(),
+        }
+    }
+
+    /// The affine in the coordinate type of the resampling functions.
+    pub fn affine_as<T>(&self) -> Matrix4<T>
+    where
+        T: Scalar + Copy,
+        f64: AsPrimitive<T>,
+    {
+        self.affine.map(|x| x.as_())
+    }
+}
+
+/// Resample in_im onto the MNI152 grid of the given resolution, returning the
+/// resampled image and the grid affine.
+///
+/// `transform` maps MNI world coordinates to the world coordinates of in_im,
+/// e.g. the inverse of a subject to template registration, as in
+/// [`resample_with_transform`]. Use the identity for images already aligned
+/// to MNI space.
+pub fn resample_to_mni<T, U, S>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    transform: &Matrix4<T>,
+    resolution: MniResolution,
+    sampler: &S,
+) -> Result<(Array<U, IxDyn>, Matrix4<T>), String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<U> + Copy,
+    U: Num + Copy + Send + Sync + 'static,
+    S: ReSample<T, U> + ?Sized + 'static,
+    f32: AsPrimitive<T>,
+    f64: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    let grid = TemplateGrid::mni152(resolution);
+    let out_affine = grid.affine_as::<T>();
+    let out_im = resample_with_transform(
+        in_im,
+        in_affine,
+        transform,
+        &grid.shape,
+        &out_affine,
+        sampler,
+    )?;
+    Ok((out_im, out_affine))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NearestNeighbor;
+
+    #[test]
+    fn test_resample_to_mni() {
+        // the input voxel (0, 0, 0) is at the world origin
+        let im = Array::from_shape_fn(IxDyn(&[4, 4, 4]), |idx| {
+            (idx[0] * 16 + idx[1] * 4 + idx[2]) as f32
+        });
+        let identity = Matrix4::<f64>::identity();
+        let nn = NearestNeighbor::default();
+        let (out, affine) =
+            resample_to_mni(&im, &identity, &identity, MniResolution::TwoMm, &nn).unwrap();
+        assert_eq!(out.shape(), &MNI152_2MM_SHAPE);
+        assert_eq!(affine, TemplateGrid::mni152_2mm().affine);
+        assert_eq!(out[[45, 63, 36]], 0.0);
+        // +y in world is +y in the template, 2 mm per voxel
+        assert_eq!(out[[45, 64, 36]], 8.0);
+        // +x in world is -x in the template
+        assert_eq!(out[[44, 63, 36]], 32.0);
+        assert_eq!(out[[46, 63, 36]], 0.0);
+    }
+}
@@ -0,0 +1,113 @@
+use super::{map_time_series, sanitize_series};
+use crate::sanitize_mask;
+use nalgebra::DMatrix;
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Trend removed by [`detrend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trend {
+    /// The mean.
+    Constant,
+
+    /// A straight line.
+    Linear,
+
+    /// A polynomial of the given order.
+    Polynomial { order: usize },
+}
+
+impl Trend {
+    fn order(&self) -> usize {
+        match self {
+            Trend::Constant => 0,
+            Trend::Linear => 1,
+            Trend::Polynomial { order } => *order,
+        }
+    }
+}
+
+/// Remove a polynomial trend from the time series of every voxel in the
+/// (spatial) `mask` by least squares; other voxels are copied unchanged.
+///
+/// The residuals have zero mean, which may have to be added back, e.g. for
+/// computing the tSNR afterwards. Voxels are processed in parallel.
+pub fn detrend<U>(
+    in_im: &Array<U, IxDyn>,
+    trend: Trend,
+    mask: Option<&Array<bool, IxDyn>>,
+) -> Result<Array<f64, IxDyn>, String>
+where
+    U: AsPrimitive<f64>,
+{
+    let series = sanitize_series(in_im)?;
+    let mask = sanitize_mask(mask, &series.shape()[..3])?;
+    let n = series.shape()[3];
+    let n_regressors = trend.order() + 1;
+    if n_regressors > n {
+        return Err("polynomial order too high for the number of volumes".into());
+    }
+
+    // orthonormal basis of the polynomials, with time scaled to [-1, 1] for
+    // a well conditioned design matrix
+    let design = DMatrix::from_fn(n, n_regressors, |t, p| {
+        let x = if n > 1 {
+            2.0 * t as f64 / (n - 1) as f64 - 1.0
+        } else {
+            0.0
+        };
+        x.powi(p as i32)
+    });
+    let q = design.qr().q();
+
+    Ok(map_time_series(&series, n, |voxel, input, out| {
+        out.copy_from_slice(input);
+        if mask.as_ref().is_some_and(|m| !m[IxDyn(&voxel)]) {
+            return;
+        }
+        for column in q.column_iter() {
+            let c: f64 = column.iter().zip(input).map(|(q, x)| q * x).sum();
+            out.iter_mut()
+                .zip(column.iter())
+                .for_each(|(o, q)| *o -= c * q);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn test_detrend() {
+        let quadratic = |t: f64| 5.0 + 0.3 * t - 0.02 * t * t;
+        let alternating = Array::from_shape_fn(IxDyn(&[2, 1, 1, 20]), |idx| {
+            if idx[3] % 2 == 0 {
+                1.0
+            } else {
+                -1.0
+            }
+        });
+        let series = Array::from_shape_fn(IxDyn(&[2, 1, 1, 20]), |idx| {
+            quadratic(idx[3] as f64) + alternating[&idx]
+        });
+        let mask = Array::from_shape_fn(IxDyn(&[2, 1, 1]), |idx| idx[0] == 0);
+
+        // the quadratic trend is removed entirely
+        let trend = Trend::Polynomial { order: 2 };
+        let out = detrend(&series, trend, Some(&mask)).unwrap();
+        let expected = detrend(&alternating, trend, None).unwrap();
+        for t in 0..20 {
+            assert_abs_diff_eq!(out[[0, 0, 0, t]], expected[[0, 0, 0, t]], epsilon = 1e-9);
+        }
+        assert_eq!(
+            out.slice(s![1, .., .., ..]),
+            series.slice(s![1, .., .., ..])
+        );
+
+        let out = detrend(&series, Trend::Constant, None).unwrap();
+        assert_abs_diff_eq!(out.sum(), 0.0, epsilon = 1e-9);
+        assert!(detrend(&series, Trend::Polynomial { order: 20 }, None).is_err());
+    }
+}
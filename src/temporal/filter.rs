@@ -0,0 +1,233 @@
+use super::{map_time_series, sanitize_series};
+use crate::sanitize_mask;
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+use std::f64::consts::PI;
+
+/// Pass band of a [`temporal_filter`], with cutoff frequencies in Hz.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Band {
+    /// Keep frequencies below `cutoff`.
+    LowPass { cutoff: f64 },
+
+    /// Keep frequencies above `cutoff`, e.g. to remove scanner drifts.
+    HighPass { cutoff: f64 },
+
+    /// Keep frequencies between `low` and `high`.
+    BandPass { low: f64, high: f64 },
+}
+
+/// Parameters of the [`temporal_filter`].
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TemporalFilter {
+    /// Frequencies to keep; defaults to the common resting-state band of
+    /// 0.01 - 0.1 Hz.
+    pub band: Band,
+
+    /// Order of each Butterworth filter. As the filter is applied forwards
+    /// and backwards, the effective order doubles.
+    pub order: usize,
+}
+
+impl Default for TemporalFilter {
+    fn default() -> Self {
+        Self {
+            band: Band::BandPass {
+                low: 0.01,
+                high: 0.1,
+            },
+            order: 2,
+        }
+    }
+}
+
+/// A first (`b2 = a2 = 0`) or second order section in transposed direct form
+/// II, with coefficients normalized to `a0 = 1`.
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Section {
+    /// Filter `x` in place, starting from the steady state of a constant
+    /// input equal to `x[0]`.
+    fn apply(&self, x: &mut [f64]) {
+        let Some(&x0) = x.first() else {
+            return;
+        };
+        let [b0, b1, b2] = self.b;
+        let [a1, a2] = self.a;
+        let y0 = x0 * (b0 + b1 + b2) / (1.0 + a1 + a2);
+        let mut z2 = b2 * x0 - a2 * y0;
+        let mut z1 = b1 * x0 - a1 * y0 + z2;
+        for v in x.iter_mut() {
+            let y = b0 * *v + z1;
+            z1 = b1 * *v - a1 * y + z2;
+            z2 = b2 * *v - a2 * y;
+            *v = y;
+        }
+    }
+}
+
+/// Sections of a digital Butterworth filter (bilinear transform) of the
+/// given order and cutoff, relative to the sampling frequency.
+fn butterworth(order: usize, cutoff: f64, highpass: bool) -> Vec<Section> {
+    let k = (PI * cutoff).tan();
+    let mut sections: Vec<Section> = (0..order / 2)
+        .map(|i| {
+            let theta = PI * (2 * i + 1) as f64 / (2 * order) as f64;
+            let q = 1.0 / (2.0 * theta.cos());
+            let norm = 1.0 / (1.0 + k / q + k * k);
+            let a = [2.0 * (k * k - 1.0) * norm, (1.0 - k / q + k * k) * norm];
+            let b = if highpass {
+                [norm, -2.0 * norm, norm]
+            } else {
+                let b0 = k * k * norm;
+                [b0, 2.0 * b0, b0]
+            };
+            Section { b, a }
+        })
+        .collect();
+    if order % 2 == 1 {
+        let norm = 1.0 / (1.0 + k);
+        let a = [(k - 1.0) * norm, 0.0];
+        let b = if highpass {
+            [norm, -norm, 0.0]
+        } else {
+            [k * norm, k * norm, 0.0]
+        };
+        sections.push(Section { b, a });
+    }
+    sections
+}
+
+/// Zero-phase filtering of `x`: forwards and backwards over the series padded
+/// by odd reflection, which suppresses transients at both ends.
+fn filtfilt(sections: &[Section], x: &[f64], out: &mut [f64]) {
+    let n = x.len();
+    let pad = (6 * sections.len()).min(n.saturating_sub(1));
+    let mut padded: Vec<f64> = (1..=pad).rev().map(|i| 2.0 * x[0] - x[i]).collect();
+    padded.extend_from_slice(x);
+    padded.extend((1..=pad).map(|i| 2.0 * x[n - 1] - x[n - 1 - i]));
+
+    for s in sections {
+        s.apply(&mut padded);
+    }
+    padded.reverse();
+    for s in sections {
+        s.apply(&mut padded);
+    }
+    padded.reverse();
+    out.copy_from_slice(&padded[pad..pad + n]);
+}
+
+/// Voxelwise temporal Butterworth filtering of a 4D series with repetition
+/// time `tr` (s).
+///
+/// The filter is applied forwards and backwards, hence it does not shift the
+/// signal in time. Only voxels within the (spatial) `mask` are filtered, the
+/// time series of all other voxels are copied unchanged.
+pub fn temporal_filter<U>(
+    in_im: &Array<U, IxDyn>,
+    tr: f64,
+    params: &TemporalFilter,
+    mask: Option<&Array<bool, IxDyn>>,
+) -> Result<Array<f64, IxDyn>, String>
+where
+    U: AsPrimitive<f64>,
+{
+    let series = sanitize_series(in_im)?;
+    if tr <= 0.0 {
+        return Err("repetition time has to be positive".into());
+    }
+    if params.order == 0 {
+        return Err("filter order has to be at least 1".into());
+    }
+    let mask = sanitize_mask(mask, &series.shape()[..3])?;
+
+    let nyquist = 0.5 / tr;
+    let relative = |f: f64| {
+        if f > 0.0 && f < nyquist {
+            Ok(f * tr)
+        } else {
+            Err(format!(
+                "cutoff frequency {f} Hz outside of (0, {nyquist}) Hz"
+            ))
+        }
+    };
+    let sections = match params.band {
+        Band::LowPass { cutoff } => butterworth(params.order, relative(cutoff)?, false),
+        Band::HighPass { cutoff } => butterworth(params.order, relative(cutoff)?, true),
+        Band::BandPass { low, high } => {
+            if low >= high {
+                return Err("lower cutoff has to be below the upper cutoff".into());
+            }
+            let mut sections = butterworth(params.order, relative(low)?, true);
+            sections.extend(butterworth(params.order, relative(high)?, false));
+            sections
+        }
+    };
+
+    let n_volumes = series.shape()[3];
+    Ok(map_time_series(
+        &series,
+        n_volumes,
+        |voxel, input, out| match &mask {
+            Some(mask) if !mask[IxDyn(&voxel)] => out.copy_from_slice(input),
+            _ => filtfilt(&sections, input, out),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temporal_filter() {
+        // slow (0.02 Hz) and fast (0.2 Hz) oscillation around an offset
+        let tr = 1.0;
+        let slow = |t: f64| (2.0 * PI * 0.02 * t).sin();
+        let fast = |t: f64| 0.5 * (2.0 * PI * 0.2 * t).sin();
+        let series = Array::from_shape_fn(IxDyn(&[2, 1, 1, 200]), |idx| {
+            let t = idx[3] as f64 * tr;
+            3.0 + slow(t) + fast(t)
+        });
+        let mask = Array::from_shape_fn(IxDyn(&[2, 1, 1]), |idx| idx[0] == 0);
+        let max_error = |out: &Array<f64, IxDyn>, f: &dyn Fn(f64) -> f64| {
+            (50..150)
+                .map(|t| (out[[0, 0, 0, t]] - f(t as f64 * tr)).abs())
+                .fold(0.0, f64::max)
+        };
+
+        let lowpass = TemporalFilter {
+            band: Band::LowPass { cutoff: 0.08 },
+            order: 4,
+        };
+        let out = temporal_filter(&series, tr, &lowpass, Some(&mask)).unwrap();
+        assert!(max_error(&out, &|t| 3.0 + slow(t)) < 0.05);
+        // voxels outside of the mask are untouched
+        assert_eq!(
+            out.slice(s![1, .., .., ..]),
+            series.slice(s![1, .., .., ..])
+        );
+
+        let bandpass = TemporalFilter {
+            band: Band::BandPass {
+                low: 0.1,
+                high: 0.3,
+            },
+            order: 4,
+        };
+        let out = temporal_filter(&series, tr, &bandpass, None).unwrap();
+        assert!(max_error(&out, &fast) < 0.05);
+
+        let invalid = TemporalFilter {
+            band: Band::HighPass { cutoff: 0.6 },
+            ..Default::default()
+        };
+        assert!(temporal_filter(&series, tr, &invalid, None).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::par::*;
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+// temporal processing of 4D series:
+pub mod detrend;
+pub mod filter;
+pub mod resample;
+pub mod roi;
+pub mod series;
+pub mod slice_timing;
+pub mod stats;
+
+/// Regular sampling times of the volumes of a 4D series (x, y, z, t).
+///
+/// This corresponds to `pixdim[4]` (`tr`) and `toffset` (`offset`) of the
+/// NIFTI header, in seconds. Functions changing the time grid return the new
+/// one, which has to be written back into the header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeGrid {
+    /// Number of volumes.
+    pub n_volumes: usize,
+
+    /// Repetition time (s) between consecutive volumes.
+    pub tr: f64,
+
+    /// Acquisition time (s) of the first volume.
+    pub offset: f64,
+}
+
+impl TimeGrid {
+    /// A grid of `n_volumes` volumes, `tr` seconds apart, starting at `offset`.
+    pub fn new(n_volumes: usize, tr: f64, offset: f64) -> Self {
+        Self {
+            n_volumes,
+            tr,
+            offset,
+        }
+    }
+
+    /// Acquisition time (s) of each volume.
+    pub fn times(&self) -> Vec<f64> {
+        (0..self.n_volumes)
+            .map(|t| self.offset + t as f64 * self.tr)
+            .collect()
+    }
+
+    /// Fractional volume index of the time `time` (s).
+    pub fn index_of(&self, time: f64) -> f64 {
+        (time - self.offset) / self.tr
+    }
+}
+
+/// Check that in_im is a 4D series and convert it to f64 in standard layout.
+pub(crate) fn sanitize_series<U>(in_im: &Array<U, IxDyn>) -> Result<Array<f64, IxDyn>, String>
+where
+    U: AsPrimitive<f64>,
+{
+    if in_im.ndim() != 4 {
+        return Err("expected a 4D series (x, y, z, t)".into());
+    }
+    if in_im.shape()[3] == 0 {
+        return Err("series does not contain any volumes".into());
+    }
+    Ok(in_im.mapv(|x| x.as_()))
+}
+
+/// Apply `f(voxel, in_series, out_series)` to the time series of every voxel
+/// in parallel, producing a series with `n_out` volumes. `voxel` is the
+/// spatial index (x, y, z).
+pub(crate) fn map_time_series<F>(
+    series: &Array<f64, IxDyn>,
+    n_out: usize,
+    f: F,
+) -> Array<f64, IxDyn>
+where
+    F: Fn([usize; 3], &[f64], &mut [f64]) + Sync,
+{
+    let shape = series.shape();
+    let (n_in, spatial) = (shape[3], [shape[0], shape[1], shape[2]]);
+    let series = series.as_standard_layout();
+    let data = series.as_slice().expect("standard layout is contiguous");
+    let mut out = vec![0.0; spatial.iter().product::<usize>() * n_out];
+    if n_out > 0 {
+        out.par_chunks_mut(n_out)
+            .zip(data.par_chunks(n_in))
+            .enumerate()
+            .for_each(|(i, (out, input))| {
+                let voxel = [
+                    i / (spatial[1] * spatial[2]),
+                    i / spatial[2] % spatial[1],
+                    i % spatial[2],
+                ];
+                f(voxel, input, out)
+            });
+    }
+    Array::from_shape_vec(IxDyn(&[spatial[0], spatial[1], spatial[2], n_out]), out)
+        .expect("output size matches its shape")
+}
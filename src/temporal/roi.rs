@@ -0,0 +1,198 @@
+use crate::sampler::nearest_neighbor::NearestNeighbor;
+use crate::{resample_from_to, same_grid, sanitize_im_shape};
+use nalgebra::{DMatrix, Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::float::FloatCore;
+use num_traits::AsPrimitive;
+use std::collections::BTreeMap;
+
+/// Summary of the voxel time series within a region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoiSummary {
+    /// Mean over all voxels of the region.
+    #[default]
+    Mean,
+
+    /// First eigenvariate (as in SPM): the dominant temporal component of
+    /// the demeaned voxel time series, scaled by its singular value over the
+    /// square root of the number of voxels and signed to correlate positively
+    /// with the mean. Added back is the mean over all voxels and volumes.
+    FirstEigenvariate,
+}
+
+/// Region time series, see [`roi_time_series`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoiTimeSeries {
+    /// Non-zero labels of the atlas within the image, sorted.
+    pub labels: Vec<usize>,
+
+    /// Number of voxels of each label.
+    pub n_voxels: Vec<usize>,
+
+    /// Time series of each label, of shape (labels, time).
+    pub series: Array2<f64>,
+}
+
+/// Extract one time series per atlas label from a 4D series (x, y, z, t).
+///
+/// If the atlas is defined on another grid than the series, it is resampled
+/// onto the grid of the series with a nearest neighbor sampler.
+pub fn roi_time_series<T, U>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    atlas: &Array<usize, IxDyn>,
+    atlas_affine: &Matrix4<T>,
+    summary: RoiSummary,
+) -> Result<RoiTimeSeries, String>
+where
+    T: Scalar + RealField + FloatCore + AsPrimitive<usize> + Copy,
+    U: AsPrimitive<f64>,
+    f32: AsPrimitive<T>,
+    usize: AsPrimitive<T>,
+{
+    if in_im.ndim() != 4 {
+        return Err("expected a 4D series (x, y, z, t)".into());
+    }
+    let shape = [in_im.shape()[0], in_im.shape()[1], in_im.shape()[2]];
+    let atlas = sanitize_im_shape(atlas)?;
+    let tolerance: T = 1e-4f32.as_();
+    let atlas = if same_grid(&shape, in_affine, atlas.shape(), atlas_affine, tolerance) {
+        atlas
+    } else {
+        resample_from_to(
+            &atlas,
+            atlas_affine,
+            &shape,
+            in_affine,
+            &NearestNeighbor::<usize>::default(),
+        )?
+    };
+
+    // voxel time series of each label
+    let mut regions: BTreeMap<usize, Vec<Vec<f64>>> = BTreeMap::new();
+    for (idx, label) in atlas.indexed_iter() {
+        if *label == 0 {
+            continue;
+        }
+        let lane = in_im.slice(s![idx[0], idx[1], idx[2], ..]);
+        regions
+            .entry(*label)
+            .or_default()
+            .push(lane.iter().map(|x| x.as_()).collect());
+    }
+
+    let n_volumes = in_im.shape()[3];
+    let mut series = Array2::zeros((regions.len(), n_volumes));
+    for (mut row, voxels) in series.outer_iter_mut().zip(regions.values()) {
+        let mean: Vec<f64> = (0..n_volumes)
+            .map(|t| voxels.iter().map(|v| v[t]).sum::<f64>() / voxels.len() as f64)
+            .collect();
+        let values = match summary {
+            RoiSummary::Mean => mean,
+            RoiSummary::FirstEigenvariate => first_eigenvariate(voxels, &mean),
+        };
+        row.iter_mut().zip(values).for_each(|(r, v)| *r = v);
+    }
+
+    Ok(RoiTimeSeries {
+        n_voxels: regions.values().map(|v| v.len()).collect(),
+        labels: regions.into_keys().collect(),
+        series,
+    })
+}
+
+/// First eigenvariate of the voxel time series of a region, computed from
+/// the eigen decomposition of the (time x time) scatter matrix.
+fn first_eigenvariate(voxels: &[Vec<f64>], mean: &[f64]) -> Vec<f64> {
+    let n = mean.len();
+    let demeaned: Vec<Vec<f64>> = voxels
+        .iter()
+        .map(|v| {
+            let m = v.iter().sum::<f64>() / n as f64;
+            v.iter().map(|x| x - m).collect()
+        })
+        .collect();
+    let scatter = DMatrix::from_fn(n, n, |i, j| {
+        demeaned.iter().map(|v| v[i] * v[j]).sum::<f64>()
+    });
+    let eigen = scatter.symmetric_eigen();
+    let (k, lambda) =
+        eigen
+            .eigenvalues
+            .iter()
+            .enumerate()
+            .fold((0, f64::NEG_INFINITY), |best, (k, l)| {
+                if *l > best.1 {
+                    (k, *l)
+                } else {
+                    best
+                }
+            });
+    let grand_mean = mean.iter().sum::<f64>() / n as f64;
+    let u: Vec<f64> = eigen.eigenvectors.column(k).iter().copied().collect();
+    let sign = if u
+        .iter()
+        .zip(mean)
+        .map(|(u, m)| u * (m - grand_mean))
+        .sum::<f64>()
+        < 0.0
+    {
+        -1.0
+    } else {
+        1.0
+    };
+    let scale = sign * lambda.max(0.0).sqrt() / (voxels.len() as f64).sqrt();
+    u.iter().map(|u| grand_mean + scale * u).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn test_roi_time_series() {
+        // label 1: x < 2 with signal t, label 2: x >= 2 with signal -t at
+        // voxel-dependent amplitudes
+        let series = Array::from_shape_fn(IxDyn(&[4, 2, 1, 5]), |idx| {
+            let t = idx[3] as f64;
+            let amplitude = 1.0 + idx[1] as f64;
+            if idx[0] < 2 {
+                10.0 + amplitude * t
+            } else {
+                20.0 - amplitude * t
+            }
+        });
+        let affine = Matrix4::<f64>::identity();
+        // coarser atlas on another grid
+        let atlas = Array::from_shape_vec(IxDyn(&[2, 1, 1]), vec![1, 2]).unwrap();
+        let atlas_affine = Matrix4::new_nonuniform_scaling(&[2.0, 2.0, 1.0].into())
+            .append_translation(&[0.5, 0.5, 0.0].into());
+
+        let mean =
+            roi_time_series(&series, &affine, &atlas, &atlas_affine, RoiSummary::Mean).unwrap();
+        assert_eq!(mean.labels, vec![1, 2]);
+        assert_eq!(mean.n_voxels, vec![4, 4]);
+        assert_relative_eq!(mean.series[[0, 2]], 13.0);
+        assert_relative_eq!(mean.series[[1, 4]], 14.0);
+
+        let eig = roi_time_series(
+            &series,
+            &affine,
+            &atlas,
+            &atlas_affine,
+            RoiSummary::FirstEigenvariate,
+        )
+        .unwrap();
+        // a single temporal component: the eigenvariate follows the mean
+        let slope = eig.series[[1, 1]] - eig.series[[1, 0]];
+        assert!(slope < 0.0);
+        for t in 1..5 {
+            assert_relative_eq!(
+                eig.series[[1, t]] - eig.series[[1, t - 1]],
+                slope,
+                epsilon = 1e-9
+            );
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use super::TimeGrid;
+use crate::{same_grid, sanitize_im_shape};
+use nalgebra::{Matrix4, RealField, Scalar};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Split a 4D series (x, y, z, t) into its 3D volumes.
+pub fn split_volumes<U>(in_im: &Array<U, IxDyn>) -> Result<Vec<Array<U, IxDyn>>, String>
+where
+    U: Clone,
+{
+    if in_im.ndim() != 4 {
+        return Err("expected a 4D series (x, y, z, t)".into());
+    }
+    Ok(in_im
+        .axis_iter(Axis(3))
+        .map(|volume| volume.to_owned())
+        .collect())
+}
+
+/// Merge 3D volumes on a common grid into a 4D series (x, y, z, t).
+///
+/// All volumes have to share their shape and (within a tolerance of 1e-4)
+/// their affine, which is also the affine of the series.
+pub fn merge_volumes<T, U>(
+    volumes: &[Array<U, IxDyn>],
+    affines: &[Matrix4<T>],
+) -> Result<Array<U, IxDyn>, String>
+where
+    T: Scalar + RealField + Copy,
+    U: Clone,
+    f32: AsPrimitive<T>,
+{
+    if volumes.is_empty() {
+        return Err("no volumes to merge".into());
+    }
+    if volumes.len() != affines.len() {
+        return Err("number of volumes and affines do not match".into());
+    }
+    let volumes = volumes
+        .iter()
+        .map(sanitize_im_shape)
+        .collect::<Result<Vec<_>, _>>()?;
+    check_grids(&volumes, affines, |im| im.shape())?;
+    let views: Vec<_> = volumes.iter().map(|v| v.view()).collect();
+    ndarray::stack(Axis(3), &views).map_err(|e| e.to_string())
+}
+
+/// Concatenate 4D runs along time.
+///
+/// The runs have to share their spatial shape, their affine (within a
+/// tolerance of 1e-4) and their repetition time. The time grid of the
+/// result starts at the offset of the first run.
+pub fn concatenate_runs<T, U>(
+    runs: &[Array<U, IxDyn>],
+    affines: &[Matrix4<T>],
+    grids: &[TimeGrid],
+) -> Result<(Array<U, IxDyn>, TimeGrid), String>
+where
+    T: Scalar + RealField + Copy,
+    U: Clone,
+    f32: AsPrimitive<T>,
+{
+    if runs.is_empty() {
+        return Err("no runs to concatenate".into());
+    }
+    if runs.len() != affines.len() || runs.len() != grids.len() {
+        return Err("number of runs, affines and time grids do not match".into());
+    }
+    if runs.iter().any(|run| run.ndim() != 4) {
+        return Err("expected 4D series (x, y, z, t)".into());
+    }
+    if runs
+        .iter()
+        .zip(grids)
+        .any(|(run, grid)| run.shape()[3] != grid.n_volumes)
+    {
+        return Err("number of volumes does not match the time grid".into());
+    }
+    if grids
+        .iter()
+        .any(|grid| (grid.tr - grids[0].tr).abs() > 1e-6 * grids[0].tr.abs())
+    {
+        return Err("repetition times of the runs differ".into());
+    }
+    check_grids(runs, affines, |im| &im.shape()[..3])?;
+
+    let views: Vec<_> = runs.iter().map(|run| run.view()).collect();
+    let series = ndarray::concatenate(Axis(3), &views).map_err(|e| e.to_string())?;
+    let grid = TimeGrid::new(series.shape()[3], grids[0].tr, grids[0].offset);
+    Ok((series, grid))
+}
+
+/// Check that all images share the (spatial) shape and affine of the first.
+fn check_grids<T, U, F>(
+    images: &[Array<U, IxDyn>],
+    affines: &[Matrix4<T>],
+    spatial_shape: F,
+) -> Result<(), String>
+where
+    T: Scalar + RealField + Copy,
+    f32: AsPrimitive<T>,
+    F: Fn(&Array<U, IxDyn>) -> &[usize],
+{
+    let tolerance: T = 1e-4f32.as_();
+    let shape = spatial_shape(&images[0]);
+    for (im, affine) in images.iter().zip(affines) {
+        if !same_grid(shape, &affines[0], spatial_shape(im), affine, tolerance) {
+            return Err("images are not defined on the same grid".into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_merge_concatenate() {
+        let series = Array::from_shape_fn(IxDyn(&[2, 3, 4, 5]), |idx| idx[3] * 100 + idx[0]);
+        let affine = Matrix4::<f32>::identity();
+        let volumes = split_volumes(&series).unwrap();
+        assert_eq!(volumes.len(), 5);
+        assert_eq!(volumes[3].shape(), &[2, 3, 4]);
+
+        let merged = merge_volumes(&volumes, &[affine; 5]).unwrap();
+        assert_eq!(merged, series);
+        let mut shifted = affine;
+        shifted[(0, 3)] = 1.0;
+        assert!(merge_volumes(&volumes[..2], &[affine, shifted]).is_err());
+
+        let grid = TimeGrid::new(5, 2.0, 0.0);
+        let (runs, runs_grid) = concatenate_runs(
+            &[series.clone(), series.clone()],
+            &[affine; 2],
+            &[grid, TimeGrid::new(5, 2.0, 30.0)],
+        )
+        .unwrap();
+        assert_eq!(runs.shape(), &[2, 3, 4, 10]);
+        assert_eq!(runs[[1, 0, 0, 7]], 201);
+        assert_eq!(runs_grid, TimeGrid::new(10, 2.0, 0.0));
+        assert!(concatenate_runs(
+            &[series.clone(), series],
+            &[affine; 2],
+            &[grid, TimeGrid::new(5, 2.5, 0.0)],
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use super::resample::TemporalInterpolation;
+use super::{map_time_series, sanitize_series, TimeGrid};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Common slice acquisition orders, see [`slice_times`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceOrder {
+    /// 0, 1, 2, ...
+    Ascending,
+
+    /// n - 1, n - 2, ...
+    Descending,
+
+    /// 0, 2, 4, ..., 1, 3, 5, ...
+    InterleavedAscending,
+
+    /// n - 1, n - 3, ..., n - 2, n - 4, ...
+    InterleavedDescending,
+}
+
+/// Acquisition times (s, relative to the start of each volume) of `n_slices`
+/// slices acquired evenly spaced within `tr` in the given order, as found in
+/// the `SliceTiming` field of a BIDS sidecar.
+pub fn slice_times(order: SliceOrder, n_slices: usize, tr: f64) -> Vec<f64> {
+    let acquisition: Vec<usize> = match order {
+        SliceOrder::Ascending => (0..n_slices).collect(),
+        SliceOrder::Descending => (0..n_slices).rev().collect(),
+        SliceOrder::InterleavedAscending => (0..n_slices)
+            .step_by(2)
+            .chain((1..n_slices).step_by(2))
+            .collect(),
+        SliceOrder::InterleavedDescending => (0..n_slices)
+            .rev()
+            .step_by(2)
+            .chain((0..n_slices.saturating_sub(1)).rev().step_by(2))
+            .collect(),
+    };
+    let mut times = vec![0.0; n_slices];
+    for (n, slice) in acquisition.into_iter().enumerate() {
+        times[slice] = n as f64 * tr / n_slices as f64;
+    }
+    times
+}
+
+/// Parameters of the [`slice_timing_correction`].
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SliceTiming {
+    /// Spatial axis along which the slices were acquired.
+    pub slice_axis: usize,
+
+    /// Time (s, relative to the start of each volume) all slices are
+    /// interpolated to; `None` for the middle of the TR.
+    pub reference_time: Option<f64>,
+
+    /// Interpolation along the time axis.
+    pub interpolation: TemporalInterpolation,
+}
+
+impl Default for SliceTiming {
+    fn default() -> Self {
+        Self {
+            slice_axis: 2,
+            reference_time: None,
+            interpolation: TemporalInterpolation::default(),
+        }
+    }
+}
+
+/// Slice timing correction of a 4D fMRI series.
+///
+/// `slice_times` holds the acquisition time (s) of each slice relative to the
+/// start of the volume (e.g. BIDS `SliceTiming`, or [`slice_times`]). The time
+/// series of every slice is interpolated to `reference_time`, as if all
+/// slices of a volume had been acquired at once. The time grid of the series
+/// is unchanged; its `offset` should be understood as the start of each
+/// volume.
+pub fn slice_timing_correction<U>(
+    in_im: &Array<U, IxDyn>,
+    grid: &TimeGrid,
+    slice_times: &[f64],
+    params: &SliceTiming,
+) -> Result<Array<f64, IxDyn>, String>
+where
+    U: AsPrimitive<f64>,
+{
+    let series = sanitize_series(in_im)?;
+    let n_volumes = series.shape()[3];
+    if n_volumes != grid.n_volumes {
+        return Err("number of volumes does not match the time grid".into());
+    }
+    if grid.tr <= 0.0 {
+        return Err("repetition time has to be positive".into());
+    }
+    if params.slice_axis > 2 {
+        return Err("slice axis has to be 0, 1 or 2".into());
+    }
+    if slice_times.len() != series.shape()[params.slice_axis] {
+        return Err("number of slice times does not match the number of slices".into());
+    }
+    let reference = params.reference_time.unwrap_or(0.5 * grid.tr);
+
+    // interpolation weights of each slice and volume
+    let weights: Vec<Vec<Vec<(usize, f64)>>> = slice_times
+        .iter()
+        .map(|st| {
+            let shift = (reference - st) / grid.tr;
+            (0..n_volumes)
+                .map(|t| params.interpolation.weights(t as f64 + shift, n_volumes))
+                .collect()
+        })
+        .collect();
+    Ok(map_time_series(&series, n_volumes, |voxel, input, out| {
+        let slice_weights = &weights[voxel[params.slice_axis]];
+        for (o, w) in out.iter_mut().zip(slice_weights) {
+            *o = w.iter().map(|(i, w)| w * input[*i]).sum();
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_slice_times() {
+        assert_eq!(
+            slice_times(SliceOrder::InterleavedAscending, 4, 2.0),
+            vec![0.0, 1.0, 0.5, 1.5]
+        );
+        assert_eq!(
+            slice_times(SliceOrder::InterleavedDescending, 5, 2.5),
+            vec![1.0, 2.0, 0.5, 1.5, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_slice_timing_correction() {
+        let (tr, n_slices) = (2.0, 4);
+        let times = slice_times(SliceOrder::InterleavedAscending, n_slices, tr);
+        let signal = |t: f64| (2.0 * PI * t / 30.0).sin();
+        let grid = TimeGrid::new(30, tr, 0.0);
+        let series = Array::from_shape_fn(IxDyn(&[1, 1, n_slices, 30]), |idx| {
+            signal(idx[3] as f64 * tr + times[idx[2]])
+        });
+        let corrected =
+            slice_timing_correction(&series, &grid, &times, &SliceTiming::default()).unwrap();
+
+        // all slices are aligned to the middle of the TR
+        for t in 5..25 {
+            let expected = signal(t as f64 * tr + 1.0);
+            for z in 0..n_slices {
+                assert!((corrected[[0, 0, z, t]] - expected).abs() < 0.01);
+            }
+        }
+    }
+}
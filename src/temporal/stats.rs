@@ -0,0 +1,110 @@
+use ndarray::prelude::*;
+use ndarray::Zip;
+use num_traits::AsPrimitive;
+
+/// Voxelwise statistics along the time axis, see [`temporal_stats`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemporalStats {
+    /// Mean over time.
+    pub mean: Array<f64, IxDyn>,
+
+    /// Sample standard deviation, 0 for a single volume.
+    pub std: Array<f64, IxDyn>,
+
+    /// Minimum over time.
+    pub min: Array<f64, IxDyn>,
+
+    /// Maximum over time.
+    pub max: Array<f64, IxDyn>,
+
+    /// Temporal signal to noise ratio `mean / std`, 0 where the standard
+    /// deviation is 0.
+    pub tsnr: Array<f64, IxDyn>,
+}
+
+/// Mean, standard deviation, minimum, maximum and tSNR volumes of a 4D series
+/// (x, y, z, t).
+///
+/// The series is processed one volume at a time with Welford's algorithm, so
+/// only a few 3D accumulators are held in f64, never the whole series.
+pub fn temporal_stats<U>(in_im: &Array<U, IxDyn>) -> Result<TemporalStats, String>
+where
+    U: AsPrimitive<f64>,
+{
+    if in_im.ndim() != 4 {
+        return Err("expected a 4D series (x, y, z, t)".into());
+    }
+    let n_volumes = in_im.shape()[3];
+    if n_volumes == 0 {
+        return Err("series does not contain any volumes".into());
+    }
+    let shape = IxDyn(&in_im.shape()[..3]);
+    let mut mean = Array::zeros(shape.clone());
+    let mut m2: Array<f64, IxDyn> = Array::zeros(shape.clone());
+    let mut min = Array::from_elem(shape.clone(), f64::INFINITY);
+    let mut max = Array::from_elem(shape, f64::NEG_INFINITY);
+
+    for (t, volume) in in_im.axis_iter(Axis(3)).enumerate() {
+        let n = (t + 1) as f64;
+        Zip::from(&mut mean)
+            .and(&mut m2)
+            .and(&mut min)
+            .and(&mut max)
+            .and(&volume)
+            .for_each(|mean, m2, min, max, x| {
+                let x: f64 = x.as_();
+                let delta = x - *mean;
+                *mean += delta / n;
+                *m2 += delta * (x - *mean);
+                *min = min.min(x);
+                *max = max.max(x);
+            });
+    }
+
+    let ddof = (n_volumes - 1).max(1) as f64;
+    let std = m2.mapv(|m2| (m2 / ddof).sqrt());
+    let tsnr = Zip::from(&mean)
+        .and(&std)
+        .map_collect(|m, s| if *s > 0.0 { m / s } else { 0.0 });
+    Ok(TemporalStats {
+        mean,
+        std,
+        min,
+        max,
+        tsnr,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn test_temporal_stats() {
+        let values = [2.0f32, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        let series = Array::from_shape_fn(IxDyn(&[1, 2, 1, 8]), |idx| {
+            if idx[1] == 0 {
+                values[idx[3]]
+            } else {
+                3.0
+            }
+        });
+        let stats = temporal_stats(&series).unwrap();
+        assert_eq!(stats.mean.shape(), &[1, 2, 1]);
+        assert_relative_eq!(stats.mean[[0, 0, 0]], 5.0);
+        assert_relative_eq!(
+            stats.std[[0, 0, 0]],
+            (32.0f64 / 7.0).sqrt(),
+            epsilon = 1e-12
+        );
+        assert_relative_eq!(
+            stats.tsnr[[0, 0, 0]],
+            5.0 / (32.0f64 / 7.0).sqrt(),
+            epsilon = 1e-12
+        );
+        assert_eq!((stats.min[[0, 0, 0]], stats.max[[0, 0, 0]]), (2.0, 9.0));
+        // constant voxel
+        assert_eq!((stats.std[[0, 1, 0]], stats.tsnr[[0, 1, 0]]), (0.0, 0.0));
+    }
+}
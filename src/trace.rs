@@ -0,0 +1,113 @@
+/// A debug span which, when dropped at the end of the instrumented
+/// operation, emits a debug event with the elapsed time.
+#[cfg(feature = "tracing")]
+pub(crate) struct Timed {
+    span: tracing::Span,
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "tracing")]
+impl Timed {
+    pub(crate) fn new(span: tracing::Span) -> Self {
+        Self {
+            span,
+            start: std::time::Instant::now(),
+        }
+    }
+
+    pub(crate) fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Timed {
+    fn drop(&mut self) {
+        let elapsed_ms = self.start.elapsed().as_secs_f64() * 1e3;
+        self.span
+            .in_scope(|| tracing::debug!(elapsed_ms, "finished"));
+    }
+}
+
+/// Instrument the rest of the enclosing block with a timed debug span of
+/// the given name and fields (in the syntax of `tracing::debug_span!`).
+/// Without the `tracing` feature, neither the span nor its fields are
+/// evaluated.
+macro_rules! timed_span {
+    ($name:expr $(, $($fields:tt)*)?) => {
+        #[cfg(feature = "tracing")]
+        let _timed = $crate::trace::Timed::new(tracing::debug_span!($name $(, $($fields)*)?));
+        #[cfg(feature = "tracing")]
+        let _entered = _timed.span().enter();
+    };
+}
+
+/// Emit a debug event (in the syntax of `tracing::debug!`) with the
+/// `tracing` feature, nothing otherwise.
+macro_rules! debug_event {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($args)*);
+    };
+}
+
+pub(crate) use debug_event;
+pub(crate) use timed_span;
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use crate::{resample_from_to, TriLinear};
+    use nalgebra::Matrix4;
+    use ndarray::prelude::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Mutex;
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// Records the names of spans and events.
+    #[derive(Default)]
+    struct Recorder {
+        n_spans: AtomicU64,
+        names: Mutex<Vec<String>>,
+    }
+
+    impl Subscriber for &'static Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let name = span.metadata().name();
+            self.names.lock().unwrap().push(format!("span {}", name));
+            Id::from_u64(self.n_spans.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let name = event.metadata().name();
+            self.names.lock().unwrap().push(format!("event {}", name));
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_timed_span() {
+        let recorder: &'static Recorder = Box::leak(Box::default());
+        let im = Array::from_elem(IxDyn(&[4, 4, 4]), 1.0);
+        let affine = Matrix4::<f64>::identity();
+        tracing::subscriber::with_default(recorder, || {
+            let sampler = TriLinear::<f64>::default();
+            let out_affine = Matrix4::new_scaling(1.5);
+            resample_from_to(&im, &affine, &[3, 3, 3], &out_affine, &sampler).unwrap();
+        });
+        let names = recorder.names.lock().unwrap();
+        assert_eq!(names.first().map(|s| s.as_str()), Some("span resample"));
+        assert_eq!(names.iter().filter(|s| s.starts_with("event")).count(), 2);
+    }
+}
@@ -0,0 +1,344 @@
+use crate::sampler::common::SamplingMode;
+use crate::sampler::traits::ReSample;
+use crate::sampler::trilinear::TriLinear;
+use crate::{afftra_to_aff_tra, sanitize_im_shape};
+use nalgebra::{Matrix3, Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::{AsPrimitive, Num};
+
+// Displacement fields are stored as arrays of shape (x, y, z, 3) holding the
+// world space displacement (mm) of each voxel of the grid defined by the field
+// affine. A field `u` defines the transform `p -> p + u(p)`, which maps
+// reference (fixed) world coordinates to input (moving) world coordinates, in
+// accordance with `resample_with_transform`.
+
+/// Validate a displacement field and return its spatial shape.
+pub(crate) fn field_shape(field: &Array<f64, IxDyn>) -> Result<[usize; 3], String> {
+    match field.shape() {
+        [x, y, z, 3] => Ok([*x, *y, *z]),
+        _ => Err("displacement field has to be of shape (x, y, z, 3)".into()),
+    }
+}
+
+/// The displacement vectors of a field in row-major voxel order.
+pub(crate) fn field_vectors(field: &Array<f64, IxDyn>) -> Vec<Vector3<f64>> {
+    field
+        .as_standard_layout()
+        .as_slice()
+        .expect("standard layout is contiguous")
+        .chunks(3)
+        .map(Vector3::from_column_slice)
+        .collect()
+}
+
+/// Assemble a displacement field from vectors in row-major voxel order.
+pub(crate) fn field_from_vectors(
+    shape: &[usize; 3],
+    vectors: &[Vector3<f64>],
+) -> Array<f64, IxDyn> {
+    let data = vectors.iter().flat_map(|v| v.iter().copied()).collect();
+    Array::from_shape_vec(IxDyn(&[shape[0], shape[1], shape[2], 3]), data)
+        .expect("number of vectors matches the field shape")
+}
+
+/// World coordinates of all voxels of a grid in row-major order.
+pub(crate) fn grid_points(shape: &[usize; 3], affine: &Matrix4<f64>) -> Vec<Vector3<f64>> {
+    let (aff, tra) = afftra_to_aff_tra(affine);
+    let mut points = Vec::with_capacity(shape.iter().product());
+    for i in 0..shape[0] {
+        for j in 0..shape[1] {
+            for k in 0..shape[2] {
+                points.push(aff * Vector3::new(i as f64, j as f64, k as f64) + tra);
+            }
+        }
+    }
+    points
+}
+
+/// Voxel coordinates of world points with respect to `affine`.
+fn voxel_coords<T>(points: &[Vector3<f64>], affine: &Matrix4<f64>) -> Result<Array2<T>, String>
+where
+    T: Scalar + Copy,
+    f64: AsPrimitive<T>,
+{
+    let inv = affine
+        .try_inverse()
+        .ok_or("no valid matrix inverse found for the affine")?;
+    let (aff, tra) = afftra_to_aff_tra(&inv);
+    let coords: Vec<Vector3<f64>> = points.iter().map(|p| aff * p + tra).collect();
+    Ok(Array2::from_shape_fn((coords.len(), 3).f(), |(i, j)| {
+        coords[i][j].as_()
+    }))
+}
+
+/// Trilinearly interpolate a displacement field at world points. Points
+/// outside of the field take the displacement of the nearest border voxel.
+pub(crate) fn sample_field(
+    field: &Array<f64, IxDyn>,
+    affine: &Matrix4<f64>,
+    points: &[Vector3<f64>],
+) -> Result<Vec<Vector3<f64>>, String> {
+    field_shape(field)?;
+    let mut sampler = TriLinear::<f64>::default();
+    ReSample::<f64, f64>::set_sampling_mode(&mut sampler, SamplingMode::Nearest);
+    let mut coords = voxel_coords::<f64>(points, affine)?;
+    let mut sampled = Vec::with_capacity(3);
+    for c in 0..3 {
+        let component = field.index_axis(Axis(3), c).to_owned();
+        sampled.push(sampler.sample(&component, &mut coords, &[points.len()])?);
+    }
+    Ok((0..points.len())
+        .map(|i| Vector3::new(sampled[0][i], sampled[1][i], sampled[2][i]))
+        .collect())
+}
+
+/// Convert a displacement field stored as NIFTI vector image of shape
+/// (x, y, z, 1, 3) (`dim[5]` holding the vector components, intent
+/// `NIFTI_INTENT_DISPVECT`) into a field of shape (x, y, z, 3).
+///
+/// Fields written by ITK based tools hold LPS displacements, whose x and y
+/// components have to be negated.
+pub fn displacement_from_nifti(im: &Array<f64, IxDyn>) -> Result<Array<f64, IxDyn>, String> {
+    match im.shape() {
+        [x, y, z, 1, 3] => Ok(im
+            .as_standard_layout()
+            .into_owned()
+            .into_shape(IxDyn(&[*x, *y, *z, 3]))
+            .expect("number of elements is preserved")),
+        _ => Err("expected a NIFTI vector image of shape (x, y, z, 1, 3)".into()),
+    }
+}
+
+/// Convert a displacement field of shape (x, y, z, 3) into a NIFTI vector
+/// image of shape (x, y, z, 1, 3), see [`displacement_from_nifti`].
+pub fn displacement_to_nifti(field: &Array<f64, IxDyn>) -> Result<Array<f64, IxDyn>, String> {
+    let [x, y, z] = field_shape(field)?;
+    Ok(field
+        .as_standard_layout()
+        .into_owned()
+        .into_shape(IxDyn(&[x, y, z, 1, 3]))
+        .expect("number of elements is preserved"))
+}
+
+/// Displacement field of a world space `transform` on the grid given by
+/// `shape` and `affine`.
+pub fn affine_to_displacement<T>(
+    transform: &Matrix4<T>,
+    shape: &[usize; 3],
+    affine: &Matrix4<T>,
+) -> Array<f64, IxDyn>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+{
+    let transform: Matrix4<f64> = transform.map(|x| x.as_());
+    let (aff, tra) = afftra_to_aff_tra(&transform);
+    let vectors: Vec<Vector3<f64>> = grid_points(shape, &affine.map(|x| x.as_()))
+        .iter()
+        .map(|p| aff * p + tra - p)
+        .collect();
+    field_from_vectors(shape, &vectors)
+}
+
+/// Warp in_im with a displacement field onto the grid of the field.
+///
+/// The result has the shape of the field and is aligned by field_affine.
+pub fn warp_image<T, U, S>(
+    in_im: &Array<U, IxDyn>,
+    in_affine: &Matrix4<T>,
+    field: &Array<f64, IxDyn>,
+    field_affine: &Matrix4<T>,
+    sampler: &S,
+) -> Result<Array<U, IxDyn>, String>
+where
+    T: Num + Scalar + RealField + AsPrimitive<usize> + AsPrimitive<U> + AsPrimitive<f64> + Copy,
+    U: Num + Copy + 'static,
+    S: ReSample<T, U> + ?Sized + 'static,
+    usize: AsPrimitive<T>,
+    f64: AsPrimitive<T>,
+{
+    let in_im = sanitize_im_shape(in_im)?;
+    let shape = field_shape(field)?;
+    let points: Vec<Vector3<f64>> = grid_points(&shape, &field_affine.map(|x| x.as_()))
+        .iter()
+        .zip(field_vectors(field))
+        .map(|(p, u)| p + u)
+        .collect();
+    let mut coords = voxel_coords::<T>(&points, &in_affine.map(|x| x.as_()))?;
+    sampler.sample(&in_im, &mut coords, &shape)
+}
+
+/// Compose two displacement fields on the same grid.
+///
+/// The result first applies `first` and then `second`, i.e. it displaces
+/// `p` to `q + second(q)` with `q = p + first(p)`. Warping an image with the
+/// result is equivalent to warping it with `second` and warping that result
+/// with `first`.
+pub fn compose_displacements<T>(
+    first: &Array<f64, IxDyn>,
+    second: &Array<f64, IxDyn>,
+    affine: &Matrix4<T>,
+) -> Result<Array<f64, IxDyn>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+{
+    let shape = field_shape(first)?;
+    if field_shape(second)? != shape {
+        return Err("displacement field shapes do not match".into());
+    }
+    let affine: Matrix4<f64> = affine.map(|x| x.as_());
+    let first = field_vectors(first);
+    let points: Vec<Vector3<f64>> = grid_points(&shape, &affine)
+        .iter()
+        .zip(&first)
+        .map(|(p, u)| p + u)
+        .collect();
+    let second = sample_field(second, &affine, &points)?;
+    let vectors: Vec<Vector3<f64>> = first.iter().zip(&second).map(|(a, b)| a + b).collect();
+    Ok(field_from_vectors(&shape, &vectors))
+}
+
+/// Exponentiate a stationary velocity field by scaling and squaring, which
+/// yields a diffeomorphic displacement field for smooth velocities. The field
+/// is scaled by `2^-steps` and composed with itself `steps` times.
+pub fn exponentiate<T>(
+    velocity: &Array<f64, IxDyn>,
+    affine: &Matrix4<T>,
+    steps: usize,
+) -> Result<Array<f64, IxDyn>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+{
+    field_shape(velocity)?;
+    let scale = 0.5f64.powi(steps as i32);
+    let mut field = velocity.mapv(|x| x * scale);
+    for _ in 0..steps {
+        field = compose_displacements(&field, &field, affine)?;
+    }
+    Ok(field)
+}
+
+/// Jacobian matrices `I + du/dp` of the transform of a displacement field,
+/// with derivatives with respect to world coordinates, in row-major voxel
+/// order.
+///
+/// The derivatives are approximated by central differences (one-sided at
+/// the volume border).
+pub fn jacobian_matrices<T>(
+    field: &Array<f64, IxDyn>,
+    affine: &Matrix4<T>,
+) -> Result<Vec<Matrix3<f64>>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+{
+    let shape = field_shape(field)?;
+    let (aff, _) = afftra_to_aff_tra(&affine.map(|x| x.as_()));
+    let inv = aff
+        .try_inverse()
+        .ok_or("no valid matrix inverse found for the affine")?;
+
+    let mut jacobians = Vec::with_capacity(shape.iter().product());
+    for i in 0..shape[0] {
+        for j in 0..shape[1] {
+            for k in 0..shape[2] {
+                let idx = [i, j, k];
+                let mut d = Matrix3::zeros();
+                for axis in 0..3 {
+                    if shape[axis] < 2 {
+                        continue;
+                    }
+                    let (mut lo, mut hi) = (idx, idx);
+                    lo[axis] = idx[axis].saturating_sub(1);
+                    hi[axis] = (idx[axis] + 1).min(shape[axis] - 1);
+                    let h = (hi[axis] - lo[axis]) as f64;
+                    for c in 0..3 {
+                        d[(c, axis)] =
+                            (field[[hi[0], hi[1], hi[2], c]] - field[[lo[0], lo[1], lo[2], c]]) / h;
+                    }
+                }
+                jacobians.push(Matrix3::identity() + d * inv);
+            }
+        }
+    }
+    Ok(jacobians)
+}
+
+/// Determinant of the Jacobian of the transform of a displacement field.
+///
+/// Values above 1 denote local expansion, values below 1 local compression
+/// and non-positive values folding of the transform.
+pub fn jacobian_determinant<T>(
+    field: &Array<f64, IxDyn>,
+    affine: &Matrix4<T>,
+) -> Result<Array<f64, IxDyn>, String>
+where
+    T: Scalar + RealField + AsPrimitive<f64> + Copy,
+{
+    let shape = field_shape(field)?;
+    let determinants = jacobian_matrices(field, affine)?
+        .iter()
+        .map(|j| j.determinant())
+        .collect();
+    Array::from_shape_vec(IxDyn(&shape), determinants)
+        .map_err(|_| "number of elements is not compatible with the field shape".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{resample_with_transform, TriLinear};
+    use approx::*;
+
+    #[test]
+    fn test_affine_field() {
+        let shape = [6, 5, 4];
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 1.0, 1.5));
+        let transform = Matrix4::new_nonuniform_scaling(&Vector3::new(1.1, 0.9, 1.0))
+            .append_translation(&Vector3::new(0.5, -1.0, 0.25));
+        let field = affine_to_displacement(&transform, &shape, &affine);
+
+        // warping with the field equals resampling with the transform
+        let im = Array::from_shape_fn(IxDyn(&shape), |idx| {
+            (idx[0] * 7 + idx[1] * 3 + idx[2]) as f64
+        });
+        let sampler = TriLinear::default();
+        let warped = warp_image(&im, &affine, &field, &affine, &sampler).unwrap();
+        let resampled =
+            resample_with_transform(&im, &affine, &transform, &shape, &affine, &sampler).unwrap();
+        assert_relative_eq!(
+            warped.as_slice().unwrap(),
+            resampled.as_slice().unwrap(),
+            epsilon = 1e-9
+        );
+
+        // the jacobian determinant of an affine transform is constant
+        let det = jacobian_determinant(&field, &affine).unwrap();
+        det.iter()
+            .for_each(|d| assert_relative_eq!(*d, 0.99, epsilon = 1e-9));
+
+        // NIFTI vector image round trip
+        let nifti = displacement_to_nifti(&field).unwrap();
+        assert_eq!(nifti.shape(), &[6, 5, 4, 1, 3]);
+        assert_eq!(displacement_from_nifti(&nifti).unwrap(), field);
+    }
+
+    #[test]
+    fn test_compose_and_exponentiate() {
+        let shape = [8, 8, 8];
+        let affine = Matrix4::<f64>::identity();
+        let shift = |t: Vector3<f64>| {
+            affine_to_displacement(&Matrix4::new_translation(&t), &shape, &affine)
+        };
+        let composed = compose_displacements(
+            &shift(Vector3::new(1.0, 0.0, 0.0)),
+            &shift(Vector3::new(0.0, 2.0, 0.0)),
+            &affine,
+        )
+        .unwrap();
+        assert_relative_eq!(composed, shift(Vector3::new(1.0, 2.0, 0.0)), epsilon = 1e-9);
+
+        // the exponential of a constant velocity is a translation
+        let velocity = shift(Vector3::new(0.5, 0.25, -1.0));
+        let field = exponentiate(&velocity, &affine, 4).unwrap();
+        assert_relative_eq!(field, velocity, epsilon = 1e-9);
+    }
+}
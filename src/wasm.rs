@@ -0,0 +1,158 @@
+use crate::render::{extract_slice, grayscale, RgbImage};
+use crate::reslice::extract_slice as extract_oblique_slice;
+use crate::{NearestNeighbor, TriLinear};
+use nalgebra::{Matrix4, Vector3};
+use ndarray::prelude::*;
+use ndarray::ShapeBuilder;
+use wasm_bindgen::prelude::*;
+
+/// A volume for browser-based viewers, e.g. decoded from a NIFTI file in
+/// JavaScript.
+///
+/// Slices are returned in display orientation (see
+/// [`render::extract_slice`](crate::render::extract_slice)), row by row from
+/// the top, as expected by canvas `ImageData`.
+#[wasm_bindgen]
+pub struct Volume {
+    image: Array<f32, IxDyn>,
+    affine: Matrix4<f64>,
+}
+
+#[wasm_bindgen]
+impl Volume {
+    /// A volume of the given dims (3 values) from data in NIFTI order, i.e.
+    /// with the first axis varying fastest, and the affine as 16 row-major
+    /// values (e.g. the NIFTI srow_x, srow_y and srow_z rows and 0, 0, 0, 1).
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: Vec<f32>, dims: Vec<usize>, affine: Vec<f64>) -> Result<Volume, JsError> {
+        volume(data, &dims, &affine).map_err(|e| JsError::new(&e))
+    }
+
+    pub fn dims(&self) -> Vec<usize> {
+        self.image.shape().to_vec()
+    }
+
+    /// Slice index along axis (0, 1 or 2).
+    pub fn slice(&self, axis: usize, index: usize) -> Result<Vec<f32>, JsError> {
+        let slice = extract_slice(&self.image, axis, index).map_err(|e| JsError::new(&e))?;
+        Ok(slice.iter().map(|x| *x as f32).collect())
+    }
+
+    /// Oblique slice of width x height pixels of spacing mm through origin
+    /// (world coordinates) with the plane normal, interpolated trilinearly or
+    /// by nearest neighbor, see [`reslice::extract_slice`](crate::reslice::extract_slice).
+    pub fn reslice(
+        &self,
+        origin: Vec<f64>,
+        normal: Vec<f64>,
+        spacing: f64,
+        width: usize,
+        height: usize,
+        interpolate: bool,
+    ) -> Result<Vec<f32>, JsError> {
+        oblique_slice(
+            self,
+            &origin,
+            &normal,
+            spacing,
+            [width, height],
+            interpolate,
+        )
+        .map_err(|e| JsError::new(&e))
+    }
+}
+
+/// Map the values of a slice of the given width from the window [low, high]
+/// to gray, as RGBA bytes for canvas `ImageData`.
+#[wasm_bindgen]
+pub fn window(values: &[f32], width: usize, low: f64, high: f64) -> Result<Vec<u8>, JsError> {
+    rgba(values, width, low, high).map_err(|e| JsError::new(&e))
+}
+
+fn volume(data: Vec<f32>, dims: &[usize], affine: &[f64]) -> Result<Volume, String> {
+    if dims.len() != 3 || affine.len() != 16 {
+        return Err("expected 3 dims and 16 affine values".into());
+    }
+    let image = Array::from_shape_vec(IxDyn(dims).f(), data).map_err(|e| e.to_string())?;
+    Ok(Volume {
+        image,
+        affine: Matrix4::from_row_slice(affine),
+    })
+}
+
+fn oblique_slice(
+    volume: &Volume,
+    origin: &[f64],
+    normal: &[f64],
+    spacing: f64,
+    size: [usize; 2],
+    interpolate: bool,
+) -> Result<Vec<f32>, String> {
+    if origin.len() != 3 || normal.len() != 3 {
+        return Err("origin and normal have to be 3D".into());
+    }
+    let (origin, normal) = (
+        Vector3::from_row_slice(origin),
+        Vector3::from_row_slice(normal),
+    );
+    let (image, affine, spacing) = (&volume.image, &volume.affine, &[spacing; 2]);
+    let (slice, _) = if interpolate {
+        let sampler = TriLinear::default();
+        extract_oblique_slice(image, affine, &origin, &normal, spacing, &size, &sampler)?
+    } else {
+        let sampler = NearestNeighbor::default();
+        extract_oblique_slice(image, affine, &origin, &normal, spacing, &size, &sampler)?
+    };
+    // columns along the first plane axis, the top row at the end of the second
+    let [width, height] = size;
+    Ok((0..height)
+        .flat_map(|r| (0..width).map(move |c| (c, height - 1 - r)))
+        .map(|(c, r)| slice[[c, r]])
+        .collect())
+}
+
+fn rgba(values: &[f32], width: usize, low: f64, high: f64) -> Result<Vec<u8>, String> {
+    if width == 0 || values.len() % width != 0 {
+        return Err("number of values is not a multiple of the width".into());
+    }
+    let slice = Array2::from_shape_fn((values.len() / width, width), |(r, c)| {
+        values[r * width + c] as f64
+    });
+    let gray: RgbImage = grayscale(&slice, Some((low, high)));
+    let (rows, cols, _) = gray.dim();
+    Ok(itertools::iproduct!(0..rows, 0..cols)
+        .flat_map(|(r, c)| [gray[[r, c, 0]], gray[[r, c, 1]], gray[[r, c, 2]], 255])
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_slices() {
+        // x varies fastest
+        let data: Vec<f32> = (0..24).map(|x| x as f32).collect();
+        let affine = [
+            1.0, 0.0, 0.0, -2.0, //
+            0.0, 1.0, 0.0, 0.0, //
+            0.0, 0.0, 1.0, 0.0, //
+            0.0, 0.0, 0.0, 1.0,
+        ];
+        let volume = volume(data, &[2, 3, 4], &affine).unwrap();
+        assert_eq!(volume.dims(), vec![2, 3, 4]);
+        assert_eq!(volume.image[[1, 2, 3]], (1 + 2 * 2 + 3 * 6) as f32);
+
+        // the axial plane through z = 3 shows the axial slice 3
+        let origin = [-1.5, 1.0, 3.0];
+        let slice = oblique_slice(&volume, &origin, &[0.0, 0.0, 1.0], 1.0, [2, 3], true).unwrap();
+        let axial = volume.slice(2, 3).unwrap();
+        assert_eq!(slice, axial);
+
+        let pixels = rgba(&[0.0, 5.0, 10.0, 20.0], 2, 0.0, 10.0).unwrap();
+        assert_eq!(pixels.len(), 16);
+        assert_eq!(&pixels[4..8], &[128, 128, 128, 255]);
+        assert_eq!(&pixels[12..16], &[255, 255, 255, 255]);
+        assert!(rgba(&[0.0; 3], 2, 0.0, 1.0).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use crate::{sanitize_im_shape, shape3};
+use nalgebra::{Matrix4, RealField, Scalar, Vector3};
+use ndarray::prelude::*;
+use num_traits::AsPrimitive;
+
+/// Affine of the image downsampled by `factors`: voxels are enlarged by the
+/// factors and the first voxel is centered on the first block, like the
+/// levels of a [`gaussian_pyramid`](crate::pyramid::gaussian_pyramid).
+pub fn block_affine<T>(affine: &Matrix4<T>, factors: &[usize; 3]) -> Matrix4<T>
+where
+    T: Scalar + RealField + Copy,
+    f64: AsPrimitive<T>,
+{
+    let f = factors.map(|f| f as f64);
+    let scaling = Matrix4::new_nonuniform_scaling(&Vector3::new(f[0], f[1], f[2]))
+        .append_translation(&Vector3::from(f.map(|f| (f - 1.0) / 2.0)));
+    affine * scaling.map(|x| x.as_())
+}
+
+/// Downsample a 3D image by integer `factors` by averaging blocks of
+/// voxels, without any coordinate based sampling.
+///
+/// The shape of the result is the input shape divided by the factors and
+/// rounded up; incomplete blocks at the upper borders are averaged over the
+/// voxels they contain. The affine is the [`block_affine`].
+pub fn block_mean<T, U>(
+    in_im: &Array<U, IxDyn>,
+    affine: &Matrix4<T>,
+    factors: &[usize; 3],
+) -> Result<(Array<f64, IxDyn>, Matrix4<T>), String>
+where
+    T: Scalar + RealField + Copy,
+    U: AsPrimitive<f64>,
+    f64: AsPrimitive<T>,
+{
+    let in_im = sanitize_im_shape(in_im)?;
+    let out_shape = block_shape(&in_im, factors)?;
+    let mut sum = Array::<f64, _>::zeros(out_shape);
+    let mut count = Array::<usize, _>::zeros(out_shape);
+    for ((i, j, k), x) in in_im
+        .view()
+        .into_dimensionality::<Ix3>()
+        .expect("sanitized image is 3D")
+        .indexed_iter()
+    {
+        let block = (i / factors[0], j / factors[1], k / factors[2]);
+        sum[block] += x.as_();
+        count[block] += 1;
+    }
+    sum.zip_mut_with(&count, |s, n| *s /= *n as f64);
+    Ok((sum.into_dyn(), block_affine(affine, factors)))
+}
+
+/// Downsample a 3D label map by integer `factors`, assigning each block its
+/// most frequent label (ties are broken in favour of the smaller label), see
+/// [`block_mean`].
+pub fn block_majority<T, U>(
+    in_im: &Array<U, IxDyn>,
+    affine: &Matrix4<T>,
+    factors: &[usize; 3],
+) -> Result<(Array<U, IxDyn>, Matrix4<T>), String>
+where
+    T: Scalar + RealField + Copy,
+    U: PartialOrd + Copy,
+    f64: AsPrimitive<T>,
+{
+    let in_im = sanitize_im_shape(in_im)?;
+    let out_shape = block_shape(&in_im, factors)?;
+    let in_im = in_im
+        .into_dimensionality::<Ix3>()
+        .expect("sanitized image is 3D");
+
+    let mut counts: Vec<(U, usize)> = Vec::new();
+    let out = Array::from_shape_fn(out_shape, |(i, j, k)| {
+        let block = in_im.slice(s![
+            i * factors[0]..((i + 1) * factors[0]).min(in_im.shape()[0]),
+            j * factors[1]..((j + 1) * factors[1]).min(in_im.shape()[1]),
+            k * factors[2]..((k + 1) * factors[2]).min(in_im.shape()[2]),
+        ]);
+        counts.clear();
+        for label in block.iter() {
+            match counts.iter_mut().find(|(l, _)| l == label) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((*label, 1)),
+            }
+        }
+        counts
+            .iter()
+            .copied()
+            .reduce(|best, c| {
+                if c.1 > best.1 || (c.1 == best.1 && c.0 < best.0) {
+                    c
+                } else {
+                    best
+                }
+            })
+            .expect("blocks are not empty")
+            .0
+    });
+    Ok((out.into_dyn(), block_affine(affine, factors)))
+}
+
+/// Affine of the image upsampled by `factors`, the inverse of
+/// [`block_affine`]: each voxel is split into `factors` smaller voxels
+/// covering the same extent.
+pub fn subdivided_affine<T>(affine: &Matrix4<T>, factors: &[usize; 3]) -> Matrix4<T>
+where
+    T: Scalar + RealField + Copy,
+    f64: AsPrimitive<T>,
+{
+    let f = factors.map(|f| f as f64);
+    let scaling =
+        Matrix4::new_nonuniform_scaling(&Vector3::new(1.0 / f[0], 1.0 / f[1], 1.0 / f[2]))
+            .prepend_translation(&Vector3::from(f.map(|f| -(f - 1.0) / 2.0)));
+    affine * scaling.map(|x| x.as_())
+}
+
+/// Upsample a 3D image (e.g. a low resolution label map) by integer
+/// `factors` by replicating each voxel, see [`subdivided_affine`].
+pub fn upsample_nearest<T, U>(
+    in_im: &Array<U, IxDyn>,
+    affine: &Matrix4<T>,
+    factors: &[usize; 3],
+) -> Result<(Array<U, IxDyn>, Matrix4<T>), String>
+where
+    T: Scalar + RealField + Copy,
+    U: Clone,
+    f64: AsPrimitive<T>,
+{
+    let in_im = sanitize_im_shape(in_im)?;
+    let out_shape = subdivided_shape(&in_im, factors)?;
+    let out = Array::from_shape_fn(out_shape, |(i, j, k)| {
+        in_im[[i / factors[0], j / factors[1], k / factors[2]]].clone()
+    });
+    Ok((out.into_dyn(), subdivided_affine(affine, factors)))
+}
+
+/// Upsample a 3D image by integer `factors` with trilinear interpolation,
+/// see [`subdivided_affine`].
+///
+/// The interpolation weights are the same for every lane of an axis, hence
+/// they are computed once per axis and applied separably; no coordinates
+/// are computed per voxel. Beyond the outermost voxel centers the border
+/// values are repeated.
+pub fn upsample_linear<T, U>(
+    in_im: &Array<U, IxDyn>,
+    affine: &Matrix4<T>,
+    factors: &[usize; 3],
+) -> Result<(Array<f64, IxDyn>, Matrix4<T>), String>
+where
+    T: Scalar + RealField + Copy,
+    U: AsPrimitive<f64>,
+    f64: AsPrimitive<T>,
+{
+    let in_im = sanitize_im_shape(in_im)?;
+    let (nx, ny, nz) = subdivided_shape(&in_im, factors)?;
+    let mut im: Array<f64, IxDyn> = in_im.mapv(|x| x.as_());
+    for (axis, n_out) in [nx, ny, nz].into_iter().enumerate() {
+        let weights = axis_weights(im.shape()[axis], factors[axis]);
+        let mut shape = im.shape().to_vec();
+        shape[axis] = n_out;
+        let mut out = Array::zeros(shape);
+        for (lane, mut out_lane) in im
+            .lanes(Axis(axis))
+            .into_iter()
+            .zip(out.lanes_mut(Axis(axis)))
+        {
+            for (x, (i0, i1, w)) in out_lane.iter_mut().zip(&weights) {
+                *x = (1.0 - w) * lane[*i0] + w * lane[*i1];
+            }
+        }
+        im = out;
+    }
+    Ok((im, subdivided_affine(affine, factors)))
+}
+
+/// Neighbors and weight of the second neighbor of each output position when
+/// upsampling an axis of length `n` by `factor`.
+fn axis_weights(n: usize, factor: usize) -> Vec<(usize, usize, f64)> {
+    (0..n * factor)
+        .map(|j| {
+            let pos = ((j as f64 + 0.5) / factor as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            let i0 = pos.floor() as usize;
+            let i1 = (i0 + 1).min(n - 1);
+            (i0, i1, pos - i0 as f64)
+        })
+        .collect()
+}
+
+/// Shape of a 3D image upsampled by `factors`.
+fn subdivided_shape<U>(
+    in_im: &Array<U, IxDyn>,
+    factors: &[usize; 3],
+) -> Result<(usize, usize, usize), String> {
+    if factors.contains(&0) {
+        return Err("zoom factors have to be at least 1".into());
+    }
+    let shape = shape3(in_im);
+    Ok((
+        shape[0] * factors[0],
+        shape[1] * factors[1],
+        shape[2] * factors[2],
+    ))
+}
+
+/// Shape of a 3D image downsampled by `factors`.
+fn block_shape<U>(
+    in_im: &Array<U, IxDyn>,
+    factors: &[usize; 3],
+) -> Result<(usize, usize, usize), String> {
+    if factors.contains(&0) {
+        return Err("zoom factors have to be at least 1".into());
+    }
+    let shape = shape3(in_im);
+    Ok((
+        shape[0].div_ceil(factors[0]),
+        shape[1].div_ceil(factors[1]),
+        shape[2].div_ceil(factors[2]),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn test_block_mean() {
+        let im = Array::from_shape_fn(IxDyn(&[5, 4, 2]), |idx| idx[0] as f64);
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 1.0, 1.0));
+        let (mean, mean_affine) = block_mean(&im, &affine, &[2, 2, 1]).unwrap();
+        assert_eq!(mean.shape(), &[3, 2, 2]);
+        assert_eq!(mean.slice(s![.., 0, 0]).to_vec(), vec![0.5, 2.5, 4.0]);
+        // the block centers map to the centers of the original voxels
+        let center = mean_affine * nalgebra::Vector4::new(1.0, 0.0, 0.0, 1.0);
+        assert_relative_eq!(center.x, 2.0 * 2.5);
+        assert_relative_eq!(center.y, 0.5);
+        assert!(block_mean(&im, &affine, &[0, 1, 1]).is_err());
+    }
+
+    #[test]
+    fn test_block_majority() {
+        let labels =
+            Array::from_shape_vec(IxDyn(&[4, 2, 1]), vec![1u8, 1, 2, 0, 3, 3, 2, 2]).unwrap();
+        let affine = Matrix4::<f64>::identity();
+        let (majority, _) = block_majority(&labels, &affine, &[2, 2, 1]).unwrap();
+        assert_eq!(majority.shape(), &[2, 1, 1]);
+        // a tie of 2 and 3 is resolved to the smaller label
+        assert_eq!(majority.as_slice().unwrap(), &[1, 2]);
+    }
+
+    #[test]
+    fn test_upsample() {
+        let labels = Array::from_shape_vec(IxDyn(&[2, 1, 1]), vec![3u8, 5]).unwrap();
+        let affine = Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 2.0, 2.0));
+        let (up, up_affine) = upsample_nearest(&labels, &affine, &[2, 1, 1]).unwrap();
+        assert_eq!(up.as_slice().unwrap(), &[3, 3, 5, 5]);
+        assert_relative_eq!(up_affine[(0, 0)], 1.0);
+        assert_relative_eq!(up_affine[(0, 3)], -0.5);
+        // downsampling restores the original grid
+        assert_relative_eq!(block_affine(&up_affine, &[2, 1, 1]), affine);
+
+        let im = Array::from_shape_fn(IxDyn(&[3, 2, 1]), |idx| (idx[0] * 4 + idx[1]) as f64);
+        let (up, _) = upsample_linear(&im, &affine, &[2, 2, 1]).unwrap();
+        assert_eq!(up.shape(), &[6, 4, 1]);
+        assert_eq!(
+            up.slice(s![.., 0, 0]).to_vec(),
+            vec![0.0, 1.0, 3.0, 5.0, 7.0, 8.0]
+        );
+        assert_eq!(up.slice(s![2, .., 0]).to_vec(), vec![3.0, 3.25, 3.75, 4.0]);
+        assert_relative_eq!(up.mean().unwrap(), im.mean().unwrap());
+    }
+}